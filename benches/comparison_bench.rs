@@ -100,6 +100,43 @@ fn minibit_decode(buf: &[u8]) -> TradeMessage {
     }
 }
 
+// MiniBit through the serde data format; `seq` travels in the frame header
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct TradeBody<'a> {
+    timestamp_ns: u64,
+    price: i64,
+    quantity: u32,
+    symbol: Option<&'a str>,
+    note: Option<&'a str>,
+}
+
+#[cfg(feature = "serde")]
+fn minibit_serde_encode(trade: &TradeMessage, buf: &mut [u8]) -> usize {
+    let header = minibit::FrameHeader::new(minibit::messages::msg_types::TRADE_V1, trade.seq, 0);
+    let body = TradeBody {
+        timestamp_ns: trade.timestamp_ns,
+        price: trade.price,
+        quantity: trade.quantity,
+        symbol: trade.symbol.as_deref(),
+        note: trade.note.as_deref(),
+    };
+    minibit::serde::to_frame(buf, &header, &body).unwrap()
+}
+
+#[cfg(feature = "serde")]
+fn minibit_serde_decode(buf: &[u8]) -> TradeMessage {
+    let (header, body): (_, TradeBody<'_>) = minibit::serde::from_frame(buf).unwrap();
+    TradeMessage {
+        seq: header.seq,
+        timestamp_ns: body.timestamp_ns,
+        price: body.price,
+        quantity: body.quantity,
+        symbol: body.symbol.map(str::to_owned),
+        note: body.note.map(str::to_owned),
+    }
+}
+
 fn bench_encoding_comparison(c: &mut Criterion) {
     let mut group = c.benchmark_group("encoding_comparison");
 
@@ -119,6 +156,20 @@ fn bench_encoding_comparison(c: &mut Criterion) {
             });
         });
 
+        // MiniBit via serde
+        #[cfg(feature = "serde")]
+        group.bench_with_input(
+            BenchmarkId::new("minibit_serde", name),
+            trade,
+            |b, trade| {
+                let mut buf = vec![0u8; 1024];
+                b.iter(|| {
+                    let size = minibit_serde_encode(black_box(trade), black_box(&mut buf));
+                    black_box(size);
+                });
+            },
+        );
+
         // Bincode
         group.bench_with_input(BenchmarkId::new("bincode", name), trade, |b, trade| {
             b.iter(|| {
@@ -198,6 +249,19 @@ fn bench_decoding_comparison(c: &mut Criterion) {
             },
         );
 
+        // MiniBit via serde
+        #[cfg(feature = "serde")]
+        group.bench_with_input(
+            BenchmarkId::new("minibit_serde", name),
+            minibit_data,
+            |b, data| {
+                b.iter(|| {
+                    let decoded = minibit_serde_decode(black_box(data));
+                    black_box(decoded);
+                });
+            },
+        );
+
         // Bincode
         group.bench_with_input(
             BenchmarkId::new("bincode", name),
@@ -209,7 +209,7 @@ fn bench_variable_message_sizes(c: &mut Criterion) {
         b"AAPL",
         b"BITCOIN_USD",
         b"VERY_LONG_SYMBOL_NAME_FOR_TESTING_PERFORMANCE",
-        &vec![b'X'; 100], // 100 byte symbol
+        &[b'X'; 100],     // 100 byte symbol
         &vec![b'Y'; 255], // Max reasonable symbol
     ];
 
@@ -5,17 +5,19 @@ fn main() {
     #[cfg(feature = "std")]
     {
         // Generate protobuf files if protoc is available
-        if let Ok(_) = std::process::Command::new("protoc")
+        if std::process::Command::new("protoc")
             .arg("--version")
             .output()
+            .is_ok()
         {
             generate_protobuf_schema();
         }
 
         // Generate Cap'n Proto files if capnp is available
-        if let Ok(_) = std::process::Command::new("capnp")
+        if std::process::Command::new("capnp")
             .arg("--version")
             .output()
+            .is_ok()
         {
             generate_capnp_schema();
         }
@@ -52,12 +54,13 @@ message TradeMessage {
     fs::write(format!("{}/trade.proto", proto_dir), proto_content).unwrap();
 
     // Generate Rust code (if protobuf-codegen is available)
-    if let Err(_) = protobuf_codegen::Codegen::new()
+    if protobuf_codegen::Codegen::new()
         .pure()
         .out_dir("src/generated")
         .inputs(&[format!("{}/trade.proto", proto_dir)])
         .include(proto_dir)
         .run()
+        .is_err()
     {
         // Silently ignore if codegen fails
         eprintln!("Warning: Failed to generate protobuf code");
@@ -0,0 +1,444 @@
+//! Line arbitration for feeds published redundantly (A/B lines)
+//!
+//! An [`Arbiter`] takes frames from any number of lines carrying the same
+//! data and emits each seq once, in order, from whichever line delivered it
+//! first. Frames that arrive ahead of a missing seq wait in a bounded
+//! buffer for another line to fill the hole; when the buffer is full the
+//! hole is given up as lost on every line. Seqs are arbitrated per channel.
+//!
+//! Each frame's CRC is checked before it takes part: a corrupt copy is
+//! dropped and counted, so the good copy on another line is emitted instead.
+//!
+//! [`LineStats`] tell how each line is doing: how many frames it won, how
+//! many arrived after the other line's copy or corrupt, and the seqs it
+//! never carried.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::decoder::FrameDecoder;
+use crate::error::Result;
+use crate::frame::FrameHeader;
+use crate::sequence::{SeqStatus, SeqTracker};
+
+/// Default number of out-of-order frames buffered across all channels
+pub const DEFAULT_BUFFER_LIMIT: usize = 1024;
+
+/// Something the arbiter decided
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// The next frame of `channel`, emitted once
+    Frame {
+        /// Line that delivered it first
+        line: usize,
+        /// Channel from the frame header
+        channel: u16,
+        /// Seq from the frame header
+        seq: u32,
+        /// Verbatim frame bytes
+        frame: &'a [u8],
+    },
+    /// Seqs `expected..received` of `channel` were given up as lost on
+    /// every line; reported just before the frame with seq `received`
+    Lost {
+        /// Channel of the lost frames
+        channel: u16,
+        /// First lost seq
+        expected: u32,
+        /// Seq emitted next
+        received: u32,
+    },
+}
+
+/// Counters kept per line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineStats {
+    /// Frames received on the line
+    pub frames: u64,
+    /// Frames emitted from this line because it delivered them first
+    pub won: u64,
+    /// Frames already emitted or buffered from another line
+    pub late: u64,
+    /// Frames dropped because their CRC did not match
+    pub corrupt: u64,
+    /// Gaps in the seqs the line carried
+    pub gaps: u64,
+    /// Seqs missing from the line across all gaps
+    pub missing: u64,
+}
+
+#[derive(Debug, Default)]
+struct Line {
+    tracker: SeqTracker,
+    stats: LineStats,
+}
+
+/// Merges redundant lines into one gap-aware stream
+#[derive(Debug)]
+pub struct Arbiter {
+    lines: Vec<Line>,
+    /// Next seq to emit per channel
+    next: BTreeMap<u16, u32>,
+    /// Frames ahead of the next seq: (channel, seq) -> (line, frame)
+    pending: BTreeMap<(u16, u32), (usize, Vec<u8>)>,
+    buffer_limit: usize,
+    lost: u64,
+}
+
+/// Position of `seq` relative to `next`, treating seqs as wrapping
+#[inline]
+fn ahead(seq: u32, next: u32) -> i32 {
+    seq.wrapping_sub(next) as i32
+}
+
+impl Arbiter {
+    /// Arbitrate between `lines` lines, numbered from 0
+    pub fn new(lines: usize) -> Self {
+        Self {
+            lines: (0..lines).map(|_| Line::default()).collect(),
+            next: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            buffer_limit: DEFAULT_BUFFER_LIMIT,
+            lost: 0,
+        }
+    }
+
+    /// Set how many out-of-order frames may wait for a missing seq
+    pub fn with_buffer_limit(mut self, frames: usize) -> Self {
+        self.buffer_limit = frames;
+        self
+    }
+
+    /// Number of lines
+    #[inline]
+    pub fn lines(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Counters of `line`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `line` is out of range.
+    #[inline]
+    pub fn stats(&self, line: usize) -> LineStats {
+        self.lines[line].stats
+    }
+
+    /// Seqs given up as lost on every line
+    #[inline]
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    /// Frames waiting for a missing seq
+    #[inline]
+    pub fn buffered(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Next seq to be emitted on `channel`
+    #[inline]
+    pub fn expected(&self, channel: u16) -> Option<u32> {
+        self.next.get(&channel).copied()
+    }
+
+    /// Take a frame received on `line`
+    ///
+    /// The first frame of a channel sets where its sequence starts. A frame
+    /// whose CRC does not match is only counted in [`LineStats::corrupt`].
+    /// Fails if the frame header cannot be decoded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `line` is out of range.
+    pub fn push(
+        &mut self,
+        line: usize,
+        frame: &[u8],
+        mut on_event: impl FnMut(Event<'_>),
+    ) -> Result<()> {
+        let header = FrameHeader::decode(frame)?;
+        let (channel, seq) = (header.channel, header.seq);
+
+        let state = &mut self.lines[line];
+        state.stats.frames += 1;
+        if FrameDecoder::new(frame).verify_crc32c().is_err() {
+            state.stats.corrupt += 1;
+            return Ok(());
+        }
+        if let SeqStatus::Gap { expected, received } = state.tracker.observe(channel, seq) {
+            state.stats.gaps += 1;
+            state.stats.missing += received.wrapping_sub(expected) as u64;
+        }
+
+        let next = *self.next.entry(channel).or_insert(seq);
+        let position = ahead(seq, next);
+        if position < 0 || self.pending.contains_key(&(channel, seq)) {
+            self.lines[line].stats.late += 1;
+            return Ok(());
+        }
+        self.lines[line].stats.won += 1;
+        if position > 0 {
+            self.pending.insert((channel, seq), (line, frame.to_vec()));
+            if self.pending.len() > self.buffer_limit {
+                self.skip_gap(channel, &mut on_event);
+            }
+            return Ok(());
+        }
+        on_event(Event::Frame {
+            line,
+            channel,
+            seq,
+            frame,
+        });
+        self.next.insert(channel, seq.wrapping_add(1));
+        self.drain(channel, &mut on_event);
+        Ok(())
+    }
+
+    /// Give up on every missing seq, emitting all buffered frames in order
+    ///
+    /// Call when the lines have gone quiet or at the end of input.
+    pub fn flush(&mut self, mut on_event: impl FnMut(Event<'_>)) {
+        while let Some(&(channel, _)) = self.pending.keys().next() {
+            self.skip_gap(channel, &mut on_event);
+        }
+    }
+
+    /// Declare the seqs before the earliest buffered frame of `channel` lost
+    /// and emit what follows
+    fn skip_gap(&mut self, channel: u16, on_event: &mut impl FnMut(Event<'_>)) {
+        let Some(&expected) = self.next.get(&channel) else {
+            return;
+        };
+        let earliest = self
+            .pending
+            .range((channel, 0)..=(channel, u32::MAX))
+            .map(|(&(_, seq), _)| seq)
+            .min_by_key(|&seq| ahead(seq, expected) as u32);
+        let Some(received) = earliest else {
+            return;
+        };
+        self.lost += received.wrapping_sub(expected) as u64;
+        on_event(Event::Lost {
+            channel,
+            expected,
+            received,
+        });
+        self.next.insert(channel, received);
+        self.drain(channel, on_event);
+    }
+
+    /// Emit buffered frames of `channel` that are now in order
+    fn drain(&mut self, channel: u16, on_event: &mut impl FnMut(Event<'_>)) {
+        let mut next = self.next[&channel];
+        while let Some((line, frame)) = self.pending.remove(&(channel, next)) {
+            on_event(Event::Frame {
+                line,
+                channel,
+                seq: next,
+                frame: &frame,
+            });
+            next = next.wrapping_add(1);
+        }
+        self.next.insert(channel, next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::test_util::{channel_frame, trade_frame};
+    use alloc::{format, vec};
+
+    /// Emitted `(line, seq)` pairs and lost `(expected, received)` ranges
+    type Outcome = (Vec<(usize, u32)>, Vec<(u32, u32)>);
+
+    /// Feed `(line, seq)` arrivals, then flush
+    fn run(arbiter: &mut Arbiter, arrivals: &[(usize, u32)]) -> Outcome {
+        let (mut frames, mut lost) = (Vec::new(), Vec::new());
+        let mut on_event = |event: Event<'_>| match event {
+            Event::Frame {
+                line, seq, frame, ..
+            } => {
+                assert_eq!(FrameHeader::decode(frame).unwrap().seq, seq);
+                frames.push((line, seq));
+            }
+            Event::Lost {
+                expected, received, ..
+            } => lost.push((expected, received)),
+        };
+        for &(line, seq) in arrivals {
+            arbiter
+                .push(line, &trade_frame(seq), &mut on_event)
+                .unwrap();
+        }
+        arbiter.flush(&mut on_event);
+        (frames, lost)
+    }
+
+    #[test]
+    fn test_ab_arbitration() {
+        let mut arbiter = Arbiter::new(2);
+        // A drops 2 and 3, B drops 1 and is late with 2; 4 arrives on A
+        // before anyone has 3, and only B ever sends 3
+        let arrivals = [
+            (0, 0),
+            (1, 0),
+            (0, 1),
+            (1, 2),
+            (0, 4),
+            (1, 3),
+            (1, 4),
+            (0, 5),
+            (1, 5),
+        ];
+        let (frames, lost) = run(&mut arbiter, &arrivals);
+        assert_eq!(frames, [(0, 0), (0, 1), (1, 2), (1, 3), (0, 4), (0, 5)]);
+        assert!(lost.is_empty());
+        assert_eq!(
+            arbiter.stats(0),
+            LineStats {
+                frames: 4,
+                won: 4,
+                late: 0,
+                corrupt: 0,
+                gaps: 1,
+                missing: 2,
+            }
+        );
+        assert_eq!(
+            arbiter.stats(1),
+            LineStats {
+                frames: 5,
+                won: 2,
+                late: 3,
+                corrupt: 0,
+                gaps: 1,
+                missing: 1,
+            }
+        );
+        assert_eq!(arbiter.expected(0), Some(6));
+    }
+
+    #[test]
+    fn test_buffer_limit_and_loss_on_every_line() {
+        let mut arbiter = Arbiter::new(3).with_buffer_limit(2);
+        // Seq 1 never arrives: after 2 and 3 wait, 4 overflows the buffer
+        let arrivals = [(0, 0), (1, 2), (2, 3), (0, 4), (1, 5), (2, 7)];
+        let (frames, lost) = run(&mut arbiter, &arrivals);
+        let seqs: Vec<u32> = frames.iter().map(|&(_, seq)| seq).collect();
+        assert_eq!(seqs, [0, 2, 3, 4, 5, 7]);
+        // 6 is only given up by the final flush
+        assert_eq!(lost, [(1, 2), (6, 7)]);
+        assert_eq!(arbiter.lost(), 2);
+        assert_eq!(arbiter.buffered(), 0);
+
+        // Frames of other channels are arbitrated separately, and a seq
+        // already given up is late
+        let frame = channel_frame(3, 0);
+        let mut events = vec![];
+        arbiter
+            .push(1, &frame, |event| events.push(format!("{:?}", event)))
+            .unwrap();
+        arbiter
+            .push(0, &trade_frame(1), |_| unreachable!())
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(arbiter.expected(3), Some(1));
+        assert_eq!(arbiter.stats(0).late, 1);
+        assert!(arbiter.push(0, &[0; 4], |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_copy_does_not_win() {
+        let mut arbiter = Arbiter::new(2);
+        let mut emitted = Vec::new();
+        let mut on_event = |event: Event<'_>| {
+            if let Event::Frame {
+                line, seq, frame, ..
+            } = event
+            {
+                FrameDecoder::new(frame).verify_crc32c().unwrap();
+                emitted.push((line, seq));
+            }
+        };
+
+        // A delivers every seq first, but its copy of 1 has a flipped bit
+        for seq in 0..3 {
+            let mut frame = trade_frame(seq);
+            if seq == 1 {
+                let last = frame.len() - 5;
+                frame[last] ^= 0x40;
+            }
+            arbiter.push(0, &frame, &mut on_event).unwrap();
+            arbiter.push(1, &trade_frame(seq), &mut on_event).unwrap();
+        }
+        assert_eq!(emitted, [(0, 0), (1, 1), (0, 2)]);
+        let stats = arbiter.stats(0);
+        assert_eq!((stats.frames, stats.won, stats.corrupt), (3, 2, 1));
+        // A never carried a good copy of 1
+        assert_eq!((stats.gaps, stats.missing), (1, 1));
+        assert_eq!(arbiter.stats(1).won, 1);
+        assert_eq!(arbiter.stats(1).late, 2);
+    }
+
+    #[test]
+    fn test_seq_wraparound() {
+        // A drops u32::MAX, which B fills in after A has moved on to 0
+        let mut arbiter = Arbiter::new(2);
+        let arrivals = [
+            (0, u32::MAX - 1),
+            (1, u32::MAX - 1),
+            (0, 0),
+            (1, u32::MAX),
+            (1, 0),
+            (0, 1),
+            (1, 1),
+        ];
+        let (frames, lost) = run(&mut arbiter, &arrivals);
+        assert_eq!(frames, [(0, u32::MAX - 1), (1, u32::MAX), (0, 0), (0, 1)]);
+        assert!(lost.is_empty());
+        assert_eq!((arbiter.stats(0).gaps, arbiter.stats(0).missing), (1, 1));
+        assert_eq!(arbiter.stats(1).late, 3);
+        assert_eq!(arbiter.expected(0), Some(2));
+
+        // Seqs lost across the wrap are counted once each
+        let mut arbiter = Arbiter::new(1).with_buffer_limit(0);
+        let (frames, lost) = run(&mut arbiter, &[(0, u32::MAX - 1), (0, 1)]);
+        assert_eq!(frames, [(0, u32::MAX - 1), (0, 1)]);
+        assert_eq!(lost, [(u32::MAX, 1)]);
+        assert_eq!(arbiter.lost(), 2);
+    }
+
+    #[test]
+    fn test_torn_frames() {
+        let mut arbiter = Arbiter::new(1);
+        let frame = trade_frame(0);
+        // Too short for a header: rejected without counting
+        assert_eq!(
+            arbiter.push(0, &frame[..10], |_| unreachable!()),
+            Err(Error::UnexpectedEof)
+        );
+        assert_eq!(arbiter.stats(0).frames, 0);
+
+        // A whole header with the body cut short fails its CRC check
+        arbiter
+            .push(0, &frame[..frame.len() - 3], |_| unreachable!())
+            .unwrap();
+        assert_eq!(arbiter.stats(0).corrupt, 1);
+        assert_eq!(arbiter.expected(0), None);
+
+        let mut emitted = 0;
+        arbiter.push(0, &frame, |_| emitted += 1).unwrap();
+        assert_eq!((emitted, arbiter.stats(0).frames), (1, 2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_push_on_unknown_line() {
+        let _ = Arbiter::new(2).push(2, &trade_frame(0), |_| {});
+    }
+}
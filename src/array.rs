@@ -0,0 +1,210 @@
+//! Repeated fixed-width fields
+//!
+//! Arrays are encoded as a varint element count followed by the elements
+//! back to back, each exactly [`FixedWidth::SIZE`] bytes. Decoding returns an
+//! [`ArrayView`] that borrows the frame buffer and decodes elements lazily.
+
+use core::marker::PhantomData;
+
+use crate::error::{Error, Result};
+
+/// Default upper bound on decoded array element counts
+///
+/// Guards against malformed counts even when elements are tiny.
+pub const MAX_ARRAY_LEN: usize = 65_536;
+
+/// A record with a fixed encoded size (e.g. a price level or a fill)
+pub trait FixedWidth: Sized {
+    /// Encoded size in bytes
+    const SIZE: usize;
+
+    /// Encode into `buf`, which is exactly `SIZE` bytes long
+    fn encode_fixed(&self, buf: &mut [u8]);
+
+    /// Decode from `buf`, which is exactly `SIZE` bytes long
+    fn decode_fixed(buf: &[u8]) -> Self;
+}
+
+macro_rules! impl_fixed_width_int {
+    ($($ty:ty),*) => {
+        $(
+            impl FixedWidth for $ty {
+                const SIZE: usize = core::mem::size_of::<$ty>();
+
+                #[inline]
+                fn encode_fixed(&self, buf: &mut [u8]) {
+                    buf.copy_from_slice(&self.to_le_bytes());
+                }
+
+                #[inline]
+                fn decode_fixed(buf: &[u8]) -> Self {
+                    let mut bytes = [0u8; core::mem::size_of::<$ty>()];
+                    bytes.copy_from_slice(buf);
+                    <$ty>::from_le_bytes(bytes)
+                }
+            }
+        )*
+    };
+}
+
+impl_fixed_width_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+/// Zero-copy view over an encoded array
+#[derive(Debug, Clone, Copy)]
+pub struct ArrayView<'a, T> {
+    bytes: &'a [u8],
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<'a, T: FixedWidth> ArrayView<'a, T> {
+    /// Create a view over `len` elements stored in `bytes`
+    ///
+    /// `bytes` must be exactly `len * T::SIZE` bytes long.
+    #[inline]
+    pub fn new(bytes: &'a [u8], len: usize) -> Result<Self> {
+        let expected = len.checked_mul(T::SIZE).ok_or(Error::Overflow)?;
+        if bytes.len() != expected {
+            return Err(Error::DecodeInvariant);
+        }
+        Ok(Self {
+            bytes,
+            len,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Number of elements
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if the array has no elements
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Decode the element at `idx`, or `None` if out of bounds
+    #[inline]
+    pub fn get(&self, idx: usize) -> Option<T> {
+        if idx >= self.len {
+            return None;
+        }
+        let start = idx * T::SIZE;
+        Some(T::decode_fixed(&self.bytes[start..start + T::SIZE]))
+    }
+
+    /// Raw encoded elements
+    #[inline]
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Iterate over decoded elements
+    #[inline]
+    pub fn iter(&self) -> ArrayIter<'a, T> {
+        ArrayIter {
+            bytes: self.bytes,
+            remaining: self.len,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T: FixedWidth> IntoIterator for ArrayView<'a, T> {
+    type Item = T;
+    type IntoIter = ArrayIter<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the elements of an [`ArrayView`]
+#[derive(Debug, Clone)]
+pub struct ArrayIter<'a, T> {
+    bytes: &'a [u8],
+    remaining: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: FixedWidth> Iterator for ArrayIter<'_, T> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let (head, tail) = self.bytes.split_at(T::SIZE);
+        self.bytes = tail;
+        self.remaining -= 1;
+        Some(T::decode_fixed(head))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T: FixedWidth> ExactSizeIterator for ArrayIter<'_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Level {
+        price: i64,
+        qty: u32,
+    }
+
+    impl FixedWidth for Level {
+        const SIZE: usize = 12;
+
+        fn encode_fixed(&self, buf: &mut [u8]) {
+            self.price.encode_fixed(&mut buf[0..8]);
+            self.qty.encode_fixed(&mut buf[8..12]);
+        }
+
+        fn decode_fixed(buf: &[u8]) -> Self {
+            Self {
+                price: i64::decode_fixed(&buf[0..8]),
+                qty: u32::decode_fixed(&buf[8..12]),
+            }
+        }
+    }
+
+    #[test]
+    fn test_array_view_access() {
+        let levels = [Level { price: 100, qty: 5 }, Level { price: 99, qty: 7 }];
+        let mut bytes = [0u8; 24];
+        for (level, chunk) in levels.iter().zip(bytes.chunks_mut(Level::SIZE)) {
+            level.encode_fixed(chunk);
+        }
+
+        let view = ArrayView::<Level>::new(&bytes, 2).unwrap();
+        assert_eq!(view.len(), 2);
+        assert_eq!(view.get(1), Some(levels[1]));
+        assert_eq!(view.get(2), None);
+        assert_eq!(view.iter().len(), 2);
+        assert!(view.iter().eq(levels.iter().copied()));
+    }
+
+    #[test]
+    fn test_array_view_length_mismatch() {
+        let bytes = [0u8; 7];
+        assert_eq!(
+            ArrayView::<u32>::new(&bytes, 2).unwrap_err(),
+            Error::DecodeInvariant
+        );
+        assert_eq!(
+            ArrayView::<u64>::new(&bytes, usize::MAX).unwrap_err(),
+            Error::Overflow
+        );
+    }
+}
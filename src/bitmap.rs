@@ -1,9 +1,16 @@
 //! Presence bitmap utilities for optional fields
 //!
 //! Supports 8-bit and 16-bit presence bitmaps for tracking which optional
-//! fields are present in a message.
+//! fields are present in a message, plus a variable-length bitmap for
+//! messages with more optional fields.
+//!
+//! The variable-length form uses continuation bits (LEB128): each byte carries
+//! seven field bits, low fields first, and the high bit marks that another
+//! byte follows. Trailing empty bytes are omitted, so a message using only its
+//! first seven optional fields pays a single byte.
 
 use crate::error::{Error, Result};
+use crate::varint;
 
 /// Maximum fields supported by 8-bit bitmap
 pub const BITMAP_8_MAX_FIELDS: usize = 8;
@@ -11,10 +18,13 @@ pub const BITMAP_8_MAX_FIELDS: usize = 8;
 /// Maximum fields supported by 16-bit bitmap  
 pub const BITMAP_16_MAX_FIELDS: usize = 16;
 
+/// Maximum fields supported by variable-length bitmap
+pub const BITMAP_VAR_MAX_FIELDS: usize = 128;
+
 /// Presence bitmap helper
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PresenceBitmap {
-    bits: u16,
+    bits: u128,
     size: BitmapSize,
 }
 
@@ -25,15 +35,18 @@ pub enum BitmapSize {
     U8,
     /// 16-bit bitmap (2 bytes)
     U16,
+    /// Variable-length bitmap (1 to 19 bytes, continuation-bit encoded)
+    Var,
 }
 
 impl BitmapSize {
-    /// Size in bytes
+    /// Size in bytes (maximum size for variable-length bitmaps)
     #[inline]
     pub const fn bytes(&self) -> usize {
         match self {
             BitmapSize::U8 => 1,
             BitmapSize::U16 => 2,
+            BitmapSize::Var => varint::MAX_VARINT_U128_SIZE,
         }
     }
 
@@ -43,6 +56,7 @@ impl BitmapSize {
         match self {
             BitmapSize::U8 => BITMAP_8_MAX_FIELDS,
             BitmapSize::U16 => BITMAP_16_MAX_FIELDS,
+            BitmapSize::Var => BITMAP_VAR_MAX_FIELDS,
         }
     }
 }
@@ -57,12 +71,28 @@ impl PresenceBitmap {
     /// Create from raw bits value
     #[inline]
     pub const fn from_bits(bits: u16, size: BitmapSize) -> Self {
+        Self::from_bits_u128(bits as u128, size)
+    }
+
+    /// Create from raw bits value, for variable-length bitmaps with more
+    /// than 16 fields
+    #[inline]
+    pub const fn from_bits_u128(bits: u128, size: BitmapSize) -> Self {
         Self { bits, size }
     }
 
     /// Get raw bits value
+    ///
+    /// Only the first 16 fields; use [`bits_u128`](Self::bits_u128) for
+    /// variable-length bitmaps.
     #[inline]
     pub const fn bits(&self) -> u16 {
+        self.bits as u16
+    }
+
+    /// Get raw bits value of all fields
+    #[inline]
+    pub const fn bits_u128(&self) -> u128 {
         self.bits
     }
 
@@ -78,7 +108,7 @@ impl PresenceBitmap {
         if field_idx >= self.size.max_fields() {
             return Err(Error::Overflow);
         }
-        self.bits |= 1 << field_idx;
+        self.bits |= 1u128 << field_idx;
         Ok(())
     }
 
@@ -88,7 +118,7 @@ impl PresenceBitmap {
         if field_idx >= self.size.max_fields() {
             return Err(Error::Overflow);
         }
-        self.bits &= !(1 << field_idx);
+        self.bits &= !(1u128 << field_idx);
         Ok(())
     }
 
@@ -113,6 +143,18 @@ impl PresenceBitmap {
         self.bits == 0
     }
 
+    /// Number of bytes [`encode`](Self::encode) will write
+    #[inline]
+    pub fn encoded_len(&self) -> usize {
+        match self.size {
+            BitmapSize::U8 | BitmapSize::U16 => self.size.bytes(),
+            BitmapSize::Var => {
+                let significant = 128 - self.bits.leading_zeros() as usize;
+                significant.div_ceil(7).max(1)
+            }
+        }
+    }
+
     /// Encode bitmap to buffer
     #[inline]
     pub fn encode(&self, buf: &mut [u8]) -> Result<usize> {
@@ -128,9 +170,10 @@ impl PresenceBitmap {
                 if buf.len() < 2 {
                     return Err(Error::ShortBuffer);
                 }
-                buf[0..2].copy_from_slice(&self.bits.to_le_bytes());
+                buf[0..2].copy_from_slice(&(self.bits as u16).to_le_bytes());
                 Ok(2)
             }
+            BitmapSize::Var => varint::encode_u128(self.bits, buf),
         }
     }
 
@@ -153,6 +196,10 @@ impl PresenceBitmap {
                 let bitmap = Self::from_bits(bits, size);
                 Ok((bitmap, 2))
             }
+            BitmapSize::Var => {
+                let (bits, len) = varint::decode_u128(buf)?;
+                Ok((Self::from_bits_u128(bits, size), len))
+            }
         }
     }
 
@@ -283,4 +330,56 @@ mod tests {
         assert!(!bitmap.is_set(1));
         assert_eq!(bitmap.count_set(), 3);
     }
+
+    #[test]
+    fn test_bitmap_var_sizes() {
+        let mut bitmap = PresenceBitmap::new(BitmapSize::Var);
+        let mut buf = [0u8; BitmapSize::Var.bytes()];
+
+        // Empty and low-field bitmaps take a single byte
+        assert_eq!(bitmap.encode(&mut buf).unwrap(), 1);
+        bitmap.set(6).unwrap();
+        assert_eq!(bitmap.encoded_len(), 1);
+        assert_eq!(bitmap.encode(&mut buf).unwrap(), 1);
+
+        bitmap.set(7).unwrap();
+        assert_eq!(bitmap.encoded_len(), 2);
+
+        bitmap.set(127).unwrap();
+        assert_eq!(bitmap.encoded_len(), 19);
+        assert_eq!(bitmap.set(128), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn test_bitmap_var_roundtrip() {
+        let mut bitmap = PresenceBitmap::new(BitmapSize::Var);
+        for idx in [0, 13, 63, 64, 100] {
+            bitmap.set(idx).unwrap();
+        }
+
+        let mut buf = [0u8; 32];
+        let encoded_len = bitmap.encode(&mut buf).unwrap();
+        assert_eq!(encoded_len, bitmap.encoded_len());
+
+        let (decoded, decoded_len) = PresenceBitmap::decode(&buf, BitmapSize::Var).unwrap();
+        assert_eq!(decoded_len, encoded_len);
+        assert_eq!(decoded, bitmap);
+
+        let set_fields: std::vec::Vec<usize> = decoded.iter_set().collect();
+        assert_eq!(set_fields, std::vec![0, 13, 63, 64, 100]);
+
+        // The 16-bit accessors see the first 16 fields only
+        let bits = 1 | 1 << 13 | 1 << 63 | 1 << 64 | 1 << 100;
+        assert_eq!(decoded.bits_u128(), bits);
+        assert_eq!(decoded.bits(), 1 | 1 << 13);
+        assert_eq!(
+            PresenceBitmap::from_bits_u128(bits, BitmapSize::Var),
+            bitmap
+        );
+
+        assert_eq!(
+            PresenceBitmap::decode(&buf[..encoded_len - 1], BitmapSize::Var),
+            Err(Error::UnexpectedEof)
+        );
+    }
 }
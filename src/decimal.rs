@@ -0,0 +1,161 @@
+//! Fixed-point decimal values
+//!
+//! Prices travel as a signed 64-bit mantissa with a scale fixed by the
+//! schema, so `Decimal { mantissa: 12345, scale: 2 }` is `123.45`. Only the
+//! mantissa is written to the wire (8 bytes, little-endian).
+
+use core::fmt;
+use core::str::FromStr;
+
+use crate::error::{Error, Result};
+
+/// Largest supported scale (10^18 is the largest power of ten in an i64)
+pub const MAX_SCALE: u8 = 18;
+
+/// Signed fixed-point number: `mantissa / 10^scale`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Decimal {
+    mantissa: i64,
+    scale: u8,
+}
+
+impl Decimal {
+    /// Create a decimal, rejecting scales above [`MAX_SCALE`]
+    #[inline]
+    pub const fn new(mantissa: i64, scale: u8) -> Result<Self> {
+        if scale > MAX_SCALE {
+            return Err(Error::Overflow);
+        }
+        Ok(Self { mantissa, scale })
+    }
+
+    /// Raw mantissa as written to the wire
+    #[inline]
+    pub const fn mantissa(&self) -> i64 {
+        self.mantissa
+    }
+
+    /// Number of fractional digits
+    #[inline]
+    pub const fn scale(&self) -> u8 {
+        self.scale
+    }
+
+    /// Re-express the value with `scale` fractional digits
+    ///
+    /// Returns `Error::Overflow` if the mantissa does not fit or digits would
+    /// be lost.
+    pub fn rescale(&self, scale: u8) -> Result<Self> {
+        if scale > MAX_SCALE {
+            return Err(Error::Overflow);
+        }
+        let mantissa = if scale >= self.scale {
+            let factor = 10i64.pow((scale - self.scale) as u32);
+            self.mantissa.checked_mul(factor).ok_or(Error::Overflow)?
+        } else {
+            let factor = 10i64.pow((self.scale - scale) as u32);
+            if self.mantissa % factor != 0 {
+                return Err(Error::Overflow);
+            }
+            self.mantissa / factor
+        };
+        Ok(Self { mantissa, scale })
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.scale == 0 {
+            return write!(f, "{}", self.mantissa);
+        }
+        let factor = 10u64.pow(self.scale as u32);
+        let abs = self.mantissa.unsigned_abs();
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            abs / factor,
+            abs % factor,
+            width = self.scale as usize
+        )
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = Error;
+
+    /// Parse `[-]digits[.digits]`; the scale is the number of fractional digits
+    fn from_str(s: &str) -> Result<Self> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+        if int_part.is_empty() || (digits.contains('.') && frac_part.is_empty()) {
+            return Err(Error::DecodeInvariant);
+        }
+        let scale = u8::try_from(frac_part.len()).map_err(|_| Error::Overflow)?;
+        if scale > MAX_SCALE {
+            return Err(Error::Overflow);
+        }
+
+        // Accumulate negatively so that i64::MIN parses
+        let mut mantissa: i64 = 0;
+        for c in int_part.chars().chain(frac_part.chars()) {
+            let digit = c.to_digit(10).ok_or(Error::DecodeInvariant)? as i64;
+            mantissa = mantissa
+                .checked_mul(10)
+                .and_then(|m| m.checked_sub(digit))
+                .ok_or(Error::Overflow)?;
+        }
+        if !negative {
+            mantissa = mantissa.checked_neg().ok_or(Error::Overflow)?;
+        }
+
+        Ok(Self { mantissa, scale })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn test_display_and_parse() {
+        for (mantissa, scale, text) in [
+            (12345, 2, "123.45"),
+            (-5, 3, "-0.005"),
+            (7, 0, "7"),
+            (i64::MIN, 4, "-922337203685477.5808"),
+        ] {
+            let d = Decimal::new(mantissa, scale).unwrap();
+            assert_eq!(d.to_string(), text);
+            assert_eq!(text.parse::<Decimal>(), Ok(d));
+        }
+
+        assert_eq!("1.".parse::<Decimal>(), Err(Error::DecodeInvariant));
+        assert_eq!("x".parse::<Decimal>(), Err(Error::DecodeInvariant));
+        assert_eq!(
+            "9223372036854775808".parse::<Decimal>(),
+            Err(Error::Overflow)
+        );
+        assert_eq!(Decimal::new(1, MAX_SCALE + 1), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn test_rescale() {
+        let d = Decimal::new(12345, 2).unwrap();
+        assert_eq!(d.rescale(4).unwrap().mantissa(), 1_234_500);
+        assert_eq!(d.rescale(1), Err(Error::Overflow));
+        assert_eq!(
+            Decimal::new(1200, 2)
+                .unwrap()
+                .rescale(0)
+                .unwrap()
+                .mantissa(),
+            12
+        );
+    }
+}
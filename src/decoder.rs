@@ -3,9 +3,13 @@
 //! The decoder operates on borrowed slices and provides zero-copy access
 //! to frame contents where possible.
 
+use crate::array::{ArrayView, FixedWidth, MAX_ARRAY_LEN};
+use crate::bitmap::{BitmapSize, PresenceBitmap};
 use crate::crc32c;
 use crate::error::{Error, Result};
 use crate::frame::FrameHeader;
+use crate::tagged::{self, TaggedFields, TaggedValue, WireType};
+use crate::timestamp::Timestamp;
 use crate::varint;
 
 /// Zero-copy frame decoder
@@ -224,6 +228,14 @@ impl<'a> BodyCursor<'a> {
         self.get_u16()
     }
 
+    /// Read a presence bitmap of the given size
+    #[inline]
+    pub fn get_presence_bitmap(&mut self, size: BitmapSize) -> Result<PresenceBitmap> {
+        let (bitmap, len) = PresenceBitmap::decode(&self.buf[self.pos..], size)?;
+        self.pos += len;
+        Ok(bitmap)
+    }
+
     /// Read variable-length bytes with length prefix
     ///
     /// Returns a zero-copy slice into the original buffer
@@ -243,6 +255,98 @@ impl<'a> BodyCursor<'a> {
         Ok(bytes)
     }
 
+    /// Read an array of fixed-width elements with a varint count prefix
+    ///
+    /// Counts above [`MAX_ARRAY_LEN`] are rejected with `Error::Overflow`.
+    #[inline]
+    pub fn get_array<T: FixedWidth>(&mut self) -> Result<ArrayView<'a, T>> {
+        self.get_array_with_limit(MAX_ARRAY_LEN)
+    }
+
+    /// Read an array of fixed-width elements, rejecting counts above `max_len`
+    ///
+    /// Returns a zero-copy view into the original buffer. The cursor is not
+    /// advanced if the count or data is invalid.
+    #[inline]
+    pub fn get_array_with_limit<T: FixedWidth>(
+        &mut self,
+        max_len: usize,
+    ) -> Result<ArrayView<'a, T>> {
+        let remaining_buf = &self.buf[self.pos..];
+        let (count, varint_size) = varint::decode_u32(remaining_buf)?;
+
+        let count = count as usize;
+        if count > max_len {
+            return Err(Error::Overflow);
+        }
+
+        let data_len = count.checked_mul(T::SIZE).ok_or(Error::Overflow)?;
+        let start = self.pos + varint_size;
+        if start + data_len > self.buf.len() {
+            return Err(Error::UnexpectedEof);
+        }
+
+        let view = ArrayView::new(&self.buf[start..start + data_len], count)?;
+        self.pos = start + data_len;
+        Ok(view)
+    }
+
+    /// Read a tagged optional field
+    ///
+    /// Returns the field index and value; callers skip a field simply by
+    /// ignoring it, since the value has already been consumed.
+    #[inline]
+    pub fn get_tagged(&mut self) -> Result<(u32, TaggedValue<'a>)> {
+        let start = self.pos;
+        let result = self.get_varint_u32().and_then(|key| {
+            let (field_idx, wire_type) = tagged::decode_key(key)?;
+            let value = match wire_type {
+                WireType::Varint => TaggedValue::Varint(self.get_varint_u64()?),
+                WireType::Fixed64 => TaggedValue::Fixed64(self.get_u64()?),
+                WireType::Bytes => TaggedValue::Bytes(self.get_varbytes()?),
+                WireType::Fixed32 => TaggedValue::Fixed32(self.get_u32()?),
+                WireType::Fixed16 => TaggedValue::Fixed16(self.get_u16()?),
+                WireType::Fixed8 => TaggedValue::Fixed8(self.get_u8()?),
+            };
+            Ok((field_idx, value))
+        });
+        if result.is_err() {
+            self.pos = start;
+        }
+        result
+    }
+
+    /// Iterate over the tagged fields from here to the end of the body
+    #[inline]
+    pub fn tagged_fields(&mut self) -> TaggedFields<'a> {
+        let rest = BodyCursor {
+            buf: &self.buf[self.pos..],
+            pos: 0,
+        };
+        self.pos = self.buf.len();
+        TaggedFields::new(rest)
+    }
+
+    /// Split off a cursor over the next `len` bytes and advance past them
+    ///
+    /// The returned cursor cannot read beyond those bytes, so nested decoding
+    /// stays within its group.
+    #[inline]
+    pub fn sub_cursor(&mut self, len: usize) -> Result<BodyCursor<'a>> {
+        let bytes = self.get_bytes(len)?;
+        Ok(BodyCursor { buf: bytes, pos: 0 })
+    }
+
+    /// Read a nested group written by `FrameEncoder::begin_group`/`end_group`
+    ///
+    /// Returns a bounded cursor over the group's sub-body.
+    #[inline]
+    pub fn get_group(&mut self) -> Result<BodyCursor<'a>> {
+        let start = self.pos;
+        let len = self.get_u32()? as usize;
+        self.sub_cursor(len).inspect_err(|_| self.pos = start)
+    }
+
     /// Read raw bytes without length prefix
     #[inline]
     pub fn get_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
@@ -272,6 +376,54 @@ impl<'a> BodyCursor<'a> {
         Ok(value)
     }
 
+    /// Read a ZigZag varint-encoded i32
+    #[inline]
+    pub fn get_varint_i32(&mut self) -> Result<i32> {
+        let remaining_buf = &self.buf[self.pos..];
+        let (value, varint_size) = varint::decode_i32(remaining_buf)?;
+        self.pos += varint_size;
+        Ok(value)
+    }
+
+    /// Read a ZigZag varint-encoded i64
+    #[inline]
+    pub fn get_varint_i64(&mut self) -> Result<i64> {
+        let remaining_buf = &self.buf[self.pos..];
+        let (value, varint_size) = varint::decode_i64(remaining_buf)?;
+        self.pos += varint_size;
+        Ok(value)
+    }
+
+    /// Read a ZigZag varint delta and add it to `base`
+    #[inline]
+    pub fn get_varint_delta_i64(&mut self, base: i64) -> Result<i64> {
+        Ok(base.wrapping_add(self.get_varint_i64()?))
+    }
+
+    /// Read a timestamp encoded as 8-byte nanoseconds
+    #[inline]
+    pub fn get_timestamp(&mut self) -> Result<Timestamp> {
+        let (ts, len) = Timestamp::decode(&self.buf[self.pos..])?;
+        self.pos += len;
+        Ok(ts)
+    }
+
+    /// Read a timestamp encoded as a varint delta from `base`
+    #[inline]
+    pub fn get_timestamp_delta(&mut self, base: Timestamp) -> Result<Timestamp> {
+        let (ts, len) = Timestamp::decode_delta(&self.buf[self.pos..], base)?;
+        self.pos += len;
+        Ok(ts)
+    }
+
+    /// Read a timestamp encoded as 4-byte seconds + 4-byte nanos
+    #[inline]
+    pub fn get_timestamp_secs_nanos(&mut self) -> Result<Timestamp> {
+        let (ts, len) = Timestamp::decode_secs_nanos(&self.buf[self.pos..])?;
+        self.pos += len;
+        Ok(ts)
+    }
+
     /// Peek at bytes without advancing cursor
     #[inline]
     pub fn peek_bytes(&self, len: usize) -> Result<&'a [u8]> {
@@ -372,6 +524,31 @@ mod tests {
         assert_eq!(body.get_u64().unwrap(), 999);
     }
 
+    #[test]
+    fn test_decoder_presence_bitmap_var() {
+        let mut buf = [0u8; 128];
+        let mut encoder = FrameEncoder::new(&mut buf);
+
+        let mut bitmap = PresenceBitmap::new(BitmapSize::Var);
+        bitmap.set(2).unwrap();
+        bitmap.set(90).unwrap();
+
+        let mut header = FrameHeader::new(3, 1, 0);
+        header.set_flag(FrameFlags::PRESENCE_BITMAP);
+        encoder.begin(&header).unwrap();
+        encoder.put_presence_bitmap(&bitmap).unwrap();
+        encoder.put_u32(42).unwrap();
+        let frame_size = encoder.finish_crc32c().unwrap();
+
+        let decoder = FrameDecoder::new(&buf[..frame_size]);
+        let mut body = decoder.body().unwrap();
+        let decoded = body.get_presence_bitmap(BitmapSize::Var).unwrap();
+        assert_eq!(decoded, bitmap);
+        assert!(decoded.is_set(90));
+        assert_eq!(body.get_u32().unwrap(), 42);
+        assert!(body.is_at_end());
+    }
+
     #[test]
     fn test_decoder_error_cases() {
         // Test with invalid frame
@@ -404,6 +581,158 @@ mod tests {
         assert_eq!(decoder.verify_crc32c(), Err(Error::CrcMismatch));
     }
 
+    #[test]
+    fn test_decoder_signed_varints() {
+        let mut buf = [0u8; 128];
+        let mut encoder = FrameEncoder::new(&mut buf);
+
+        encoder.begin(&FrameHeader::new(1, 1, 0)).unwrap();
+        encoder.put_varint_i32(-5).unwrap();
+        encoder.put_varint_i64(i64::MIN).unwrap();
+        encoder
+            .put_varint_delta_i64(49_999_990, 50_000_000)
+            .unwrap();
+        encoder.put_varint_delta_i64(i64::MIN, i64::MAX).unwrap();
+        let frame_size = encoder.finish_crc32c().unwrap();
+
+        let decoder = FrameDecoder::new(&buf[..frame_size]);
+        // -5, the -10 delta and the wrapped delta of 1 take one byte; i64::MIN takes 10
+        assert_eq!(decoder.header().unwrap().len, 1 + 10 + 1 + 1);
+
+        let mut body = decoder.body().unwrap();
+        assert_eq!(body.get_varint_i32().unwrap(), -5);
+        assert_eq!(body.get_varint_i64().unwrap(), i64::MIN);
+        assert_eq!(body.get_varint_delta_i64(50_000_000).unwrap(), 49_999_990);
+        assert_eq!(body.get_varint_delta_i64(i64::MAX).unwrap(), i64::MIN);
+        assert!(body.is_at_end());
+    }
+
+    #[test]
+    fn test_decoder_arrays() {
+        let mut buf = [0u8; 128];
+        let mut encoder = FrameEncoder::new(&mut buf);
+
+        let prices: [i64; 3] = [100_000_000, 99_990_000, -5];
+        encoder.begin(&FrameHeader::new(1, 1, 0)).unwrap();
+        encoder.put_array(&prices).unwrap();
+        encoder.put_array::<u16>(&[]).unwrap();
+        encoder.put_u8(7).unwrap();
+        let frame_size = encoder.finish_crc32c().unwrap();
+
+        let decoder = FrameDecoder::new(&buf[..frame_size]);
+        let mut body = decoder.body().unwrap();
+
+        let view = body.get_array::<i64>().unwrap();
+        assert_eq!(view.len(), 3);
+        assert!(view.iter().eq(prices.iter().copied()));
+        assert!(body.get_array::<u16>().unwrap().is_empty());
+        assert_eq!(body.get_u8().unwrap(), 7);
+        assert!(body.is_at_end());
+    }
+
+    #[test]
+    fn test_decoder_array_limits() {
+        // Count of 1000 u64 elements with only 8 bytes of data behind it
+        let data = [0xE8, 0x07, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut cursor = BodyCursor { buf: &data, pos: 0 };
+        assert_eq!(cursor.get_array::<u64>().unwrap_err(), Error::UnexpectedEof);
+        assert_eq!(
+            cursor.get_array_with_limit::<u8>(999).unwrap_err(),
+            Error::Overflow
+        );
+        assert_eq!(cursor.pos, 0);
+    }
+
+    #[test]
+    fn test_decoder_nested_groups() {
+        // ExecutionReport { order_id, fills: [Fill { price, qty, venue? }] }
+        let fills: [(i64, u32, Option<&[u8]>); 2] =
+            [(100_000_000, 10, Some(b"XNAS")), (100_010_000, 5, None)];
+
+        let mut buf = [0u8; 256];
+        let mut encoder = FrameEncoder::new(&mut buf);
+        encoder.begin(&FrameHeader::new(10, 1, 0)).unwrap();
+        encoder.put_u64(42).unwrap();
+        encoder.put_varint_u32(fills.len() as u32).unwrap();
+        for (price, qty, venue) in fills {
+            let group = encoder.begin_group().unwrap();
+            encoder.put_bitmap(venue.is_some() as u16).unwrap();
+            encoder.put_i64(price).unwrap();
+            encoder.put_u32(qty).unwrap();
+            if let Some(venue) = venue {
+                encoder.put_varbytes(venue).unwrap();
+            }
+            encoder.end_group(group).unwrap();
+        }
+        encoder.put_u8(0xAA).unwrap();
+        let frame_size = encoder.finish_crc32c().unwrap();
+
+        let decoder = FrameDecoder::new(&buf[..frame_size]);
+        decoder.verify_crc32c().unwrap();
+        let mut body = decoder.body().unwrap();
+        assert_eq!(body.get_u64().unwrap(), 42);
+
+        let count = body.get_varint_u32().unwrap();
+        assert_eq!(count, 2);
+        for (price, qty, venue) in fills {
+            let mut fill = body.get_group().unwrap();
+            let bitmap = fill.get_bitmap().unwrap();
+            assert_eq!(fill.get_i64().unwrap(), price);
+            assert_eq!(fill.get_u32().unwrap(), qty);
+            let decoded_venue = if bitmap & 1 != 0 {
+                Some(fill.get_varbytes().unwrap())
+            } else {
+                None
+            };
+            assert_eq!(decoded_venue, venue);
+            assert!(fill.is_at_end());
+        }
+        assert_eq!(body.get_u8().unwrap(), 0xAA);
+        assert!(body.is_at_end());
+    }
+
+    #[test]
+    fn test_sub_cursor_is_bounded() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8];
+        let mut cursor = BodyCursor { buf: &data, pos: 0 };
+
+        let mut sub = cursor.sub_cursor(3).unwrap();
+        assert_eq!(cursor.pos, 3);
+        assert_eq!(sub.get_u16().unwrap(), 0x0201);
+        assert_eq!(sub.get_u16(), Err(Error::UnexpectedEof));
+        assert_eq!(sub.get_u8().unwrap(), 3);
+
+        assert_eq!(cursor.sub_cursor(6).unwrap_err(), Error::UnexpectedEof);
+
+        // Group length prefix claims more than is available
+        let data = [0xFF, 0, 0, 0, 1, 2];
+        let mut cursor = BodyCursor { buf: &data, pos: 0 };
+        assert_eq!(cursor.get_group().unwrap_err(), Error::UnexpectedEof);
+        assert_eq!(cursor.pos, 0);
+    }
+
+    #[test]
+    fn test_decoder_timestamps() {
+        let mut buf = [0u8; 128];
+        let mut encoder = FrameEncoder::new(&mut buf);
+
+        let base = Timestamp::from_secs(1_700_000_000);
+        let ts = Timestamp::from_nanos(1_700_000_000_000_250_000);
+
+        encoder.begin(&FrameHeader::new(1, 1, 0)).unwrap();
+        encoder.put_timestamp(ts).unwrap();
+        encoder.put_timestamp_delta(ts, base).unwrap();
+        encoder.put_timestamp_secs_nanos(ts).unwrap();
+        let frame_size = encoder.finish_crc32c().unwrap();
+
+        let decoder = FrameDecoder::new(&buf[..frame_size]);
+        let mut body = decoder.body().unwrap();
+        assert_eq!(body.get_timestamp().unwrap(), ts);
+        assert_eq!(body.get_timestamp_delta(base).unwrap(), ts);
+        assert_eq!(body.get_timestamp_secs_nanos().unwrap(), ts);
+        assert!(body.is_at_end());
+    }
+
     #[test]
     fn test_body_cursor_operations() {
         let data = [1, 2, 3, 4, 5, 6, 7, 8];
@@ -0,0 +1,483 @@
+//! Schema-driven decoding without compile-time knowledge of the message
+//!
+//! [`DynamicMessage`] walks a frame body using a [`MessageSchema`] and
+//! returns each present field as a named [`Value`], recursing into nested
+//! groups. [`DynamicDecoder`] keeps a [`SchemaRegistry`] fed by schema
+//! announcement frames, so a stream that announces its schemas up front can
+//! be decoded by a reader that has never seen the code that wrote it.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::bitmap::BitmapSize;
+use crate::decimal::Decimal;
+use crate::decoder::{BodyCursor, FrameDecoder};
+use crate::error::{Error, Result};
+use crate::frame::{FrameFlags, FrameHeader};
+use crate::messages::msg_types;
+use crate::schema::{self, FieldDef, FieldType, MessageSchema, OptionalEncoding, SchemaRegistry};
+use crate::tagged::TaggedValue;
+use crate::timestamp::Timestamp;
+use crate::varint;
+
+/// Decoded field value
+///
+/// Names borrow from the schema (`'s`) and variable-length data from the
+/// frame (`'a`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value<'s, 'a> {
+    /// Any unsigned integer field
+    U64(u64),
+    /// Any signed integer field
+    I64(i64),
+    /// Fixed-point decimal field
+    Decimal(Decimal),
+    /// Timestamp field
+    Timestamp(Timestamp),
+    /// Bytes field (zero-copy)
+    Bytes(&'a [u8]),
+    /// Nested group
+    Group(DynamicMessage<'s, 'a>),
+}
+
+impl fmt::Display for Value<'_, '_> {
+    /// Integers and decimals in decimal notation, timestamps as RFC 3339,
+    /// bytes as a quoted string when valid UTF-8 and as hex otherwise
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::U64(v) => write!(f, "{}", v),
+            Value::I64(v) => write!(f, "{}", v),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::Timestamp(ts) => write!(f, "{}", ts),
+            Value::Bytes(bytes) => match core::str::from_utf8(bytes) {
+                Ok(s) => write!(f, "{:?}", s),
+                Err(_) => {
+                    f.write_str("0x")?;
+                    bytes.iter().try_for_each(|b| write!(f, "{:02x}", b))
+                }
+            },
+            Value::Group(group) => write!(f, "{}", group),
+        }
+    }
+}
+
+/// Where a body's optional fields come from
+#[derive(Clone, Copy)]
+enum OptionalMode {
+    None,
+    Bitmap(BitmapSize),
+    Tagged,
+}
+
+/// Named field values of one body or group, in wire order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DynamicMessage<'s, 'a> {
+    fields: Vec<(&'s str, Value<'s, 'a>)>,
+}
+
+impl<'s, 'a> DynamicMessage<'s, 'a> {
+    /// Decode a frame of `schema.msg_type` using `schema`
+    pub fn decode(schema: &'s MessageSchema, buf: &'a [u8]) -> Result<(FrameHeader, Self)> {
+        let decoder = FrameDecoder::new(buf);
+        let header = decoder.header()?;
+
+        if header.msg_type != schema.msg_type {
+            return Err(Error::UnsupportedMsgType);
+        }
+
+        decoder.verify_crc32c()?;
+
+        let message = Self::decode_body(schema, &header, decoder.body()?)?;
+        Ok((header, message))
+    }
+
+    /// Decode a body positioned at its first field
+    ///
+    /// Handles bitmap-gated and tagged optional fields as selected by the
+    /// header flags. Unknown tagged fields are skipped; unknown bitmap bits
+    /// are tolerated only after the last known field, since their length is
+    /// unknown. Delta bodies are relative to earlier frames and return
+    /// `Error::FlagConflict`.
+    pub fn decode_body(
+        schema: &'s MessageSchema,
+        header: &FrameHeader,
+        mut body: BodyCursor<'a>,
+    ) -> Result<Self> {
+        if header.has_flag(FrameFlags::DELTA) {
+            return Err(Error::FlagConflict);
+        }
+        let mode = if header.has_flag(FrameFlags::TAGGED) {
+            if header.has_flag(FrameFlags::PRESENCE_BITMAP) {
+                return Err(Error::FlagConflict);
+            }
+            OptionalMode::Tagged
+        } else if header.has_flag(FrameFlags::PRESENCE_BITMAP) {
+            let OptionalEncoding::Bitmap(size) = schema.optional_encoding else {
+                return Err(Error::FlagConflict);
+            };
+            OptionalMode::Bitmap(size)
+        } else {
+            OptionalMode::None
+        };
+        Self::read(schema, &mut body, mode)
+    }
+
+    /// Decode the sub-body of a nested group laid out as `layout`
+    pub fn decode_group(layout: &'s MessageSchema, mut group: BodyCursor<'a>) -> Result<Self> {
+        let mode = match layout.optional_encoding {
+            _ if !layout.has_optional() => OptionalMode::None,
+            OptionalEncoding::Bitmap(size) => OptionalMode::Bitmap(size),
+            OptionalEncoding::Tagged => OptionalMode::Tagged,
+        };
+        Self::read(layout, &mut group, mode)
+    }
+
+    fn read(
+        schema: &'s MessageSchema,
+        body: &mut BodyCursor<'a>,
+        mode: OptionalMode,
+    ) -> Result<Self> {
+        let mut fields = Vec::with_capacity(schema.fields.len());
+
+        for field in schema.required_fields() {
+            fields.push((field.name.as_str(), read_value(body, field)?));
+        }
+
+        let optional = schema.optional_fields();
+        match mode {
+            OptionalMode::None => {}
+            OptionalMode::Tagged => {
+                for entry in body.tagged_fields() {
+                    let (field_idx, value) = entry?;
+                    let known = optional.iter().find(|&&(i, _)| i as u32 == field_idx);
+                    if let Some(&(_, field)) = known {
+                        fields.push((field.name.as_str(), tagged_value(field, value)?));
+                    }
+                }
+            }
+            OptionalMode::Bitmap(size) => {
+                let bitmap = body.get_presence_bitmap(size)?;
+
+                let mut skipped_unknown = false;
+                for bit in bitmap.iter_set() {
+                    match optional.iter().find(|&&(i, _)| i as usize == bit) {
+                        Some(_) if skipped_unknown => return Err(Error::DecodeInvariant),
+                        Some(&(_, field)) => {
+                            fields.push((field.name.as_str(), read_value(body, field)?));
+                        }
+                        None => skipped_unknown = true,
+                    }
+                }
+            }
+        }
+
+        Ok(Self { fields })
+    }
+
+    /// Value of the field called `name`, if present
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&Value<'s, 'a>> {
+        self.fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Present fields in wire order
+    #[inline]
+    pub fn fields(&self) -> &[(&'s str, Value<'s, 'a>)] {
+        &self.fields
+    }
+
+    /// Number of present fields
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Check if no fields are present
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Take the fields out of the message
+    #[inline]
+    pub fn into_fields(self) -> Vec<(&'s str, Value<'s, 'a>)> {
+        self.fields
+    }
+}
+
+impl fmt::Display for DynamicMessage<'_, '_> {
+    /// `{name: value, ...}`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{")?;
+        for (i, (name, value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {}", name, value)?;
+        }
+        f.write_str("}")
+    }
+}
+
+/// Read one value of `field` from the cursor
+#[inline]
+pub fn read_value<'s, 'a>(body: &mut BodyCursor<'a>, field: &'s FieldDef) -> Result<Value<'s, 'a>> {
+    Ok(match field.ty {
+        FieldType::U8 => Value::U64(body.get_u8()? as u64),
+        FieldType::U16 => Value::U64(body.get_u16()? as u64),
+        FieldType::U32 => Value::U64(body.get_u32()? as u64),
+        FieldType::U64 => Value::U64(body.get_u64()?),
+        FieldType::I32 => Value::I64(body.get_i32()? as i64),
+        FieldType::I64 => Value::I64(body.get_i64()?),
+        FieldType::VarU64 => Value::U64(body.get_varint_u64()?),
+        FieldType::VarI64 => Value::I64(body.get_varint_i64()?),
+        FieldType::Timestamp => Value::Timestamp(body.get_timestamp()?),
+        FieldType::Bytes => Value::Bytes(body.get_varbytes()?),
+        FieldType::Decimal(scale) => Value::Decimal(Decimal::new(body.get_i64()?, scale)?),
+        FieldType::Group => {
+            let layout = field.group.as_deref().ok_or(Error::InvalidSchema)?;
+            Value::Group(DynamicMessage::decode_group(layout, body.get_group()?)?)
+        }
+    })
+}
+
+/// Convert a tagged value, checking its wire type against the field's
+fn tagged_value<'s, 'a>(field: &'s FieldDef, value: TaggedValue<'a>) -> Result<Value<'s, 'a>> {
+    if value.wire_type() != field.ty.wire_type() {
+        return Err(Error::DecodeInvariant);
+    }
+    Ok(match (field.ty, value) {
+        (FieldType::Bytes, TaggedValue::Bytes(bytes)) => Value::Bytes(bytes),
+        (FieldType::Group, TaggedValue::Bytes(bytes)) => {
+            let layout = field.group.as_deref().ok_or(Error::InvalidSchema)?;
+            let group = BodyCursor { buf: bytes, pos: 0 };
+            Value::Group(DynamicMessage::decode_group(layout, group)?)
+        }
+        (FieldType::I32, TaggedValue::Fixed32(v)) => Value::I64(v as i32 as i64),
+        (FieldType::I64, TaggedValue::Fixed64(v)) => Value::I64(v as i64),
+        (FieldType::VarI64, TaggedValue::Varint(v)) => Value::I64(varint::zigzag_decode_i64(v)),
+        (FieldType::Timestamp, TaggedValue::Fixed64(v)) => {
+            Value::Timestamp(Timestamp::from_nanos(v))
+        }
+        (FieldType::Decimal(scale), TaggedValue::Fixed64(v)) => {
+            Value::Decimal(Decimal::new(v as i64, scale)?)
+        }
+        (_, value) => Value::U64(value.as_u64()?),
+    })
+}
+
+/// Decoder for any message type whose schema it has been given or announced
+#[derive(Debug, Clone, Default)]
+pub struct DynamicDecoder {
+    registry: SchemaRegistry,
+}
+
+impl DynamicDecoder {
+    /// Create decoder that knows no schemas yet
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create decoder starting from an existing registry
+    #[inline]
+    pub fn with_registry(registry: SchemaRegistry) -> Self {
+        Self { registry }
+    }
+
+    /// Schemas known so far
+    #[inline]
+    pub fn registry(&self) -> &SchemaRegistry {
+        &self.registry
+    }
+
+    /// Register the schema carried by an announcement frame
+    ///
+    /// Returns `Ok(false)` for frames that are not schema announcements.
+    /// Re-announcing an identical schema is a no-op; a conflicting one
+    /// returns `Error::DuplicateMsgType`, and one for a session control id
+    /// `Error::InvalidSchema`.
+    pub fn learn(&mut self, buf: &[u8]) -> Result<bool> {
+        if FrameDecoder::new(buf).header()?.msg_type != msg_types::SCHEMA {
+            return Ok(false);
+        }
+
+        let (_, announced) = schema::decode_schema(buf)?;
+        if self.registry.get(announced.msg_type, announced.version) != Some(&announced) {
+            self.registry.register(announced)?;
+        }
+        Ok(true)
+    }
+
+    /// Decode a frame with the latest schema known for its message type
+    ///
+    /// Returns `Error::UnsupportedMsgType` if no schema has been learned.
+    pub fn decode<'s, 'a>(
+        &'s self,
+        buf: &'a [u8],
+    ) -> Result<(FrameHeader, DynamicMessage<'s, 'a>)> {
+        let msg_type = FrameDecoder::new(buf).header()?.msg_type;
+        let schema = self
+            .registry
+            .latest(msg_type)
+            .ok_or(Error::UnsupportedMsgType)?;
+        DynamicMessage::decode(schema, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::FrameEncoder;
+    use crate::messages::{quote, trade};
+    use std::string::ToString;
+
+    const TS_NS: u64 = 1_700_000_000_123_456_789;
+
+    #[test]
+    fn test_decode_builtin_trade() {
+        let schema = trade::schema();
+        let mut buf = [0u8; 128];
+
+        let size = trade::encode(&mut buf, 1, TS_NS, -5, 100, None, Some(b"hi")).unwrap();
+        let (header, message) = DynamicMessage::decode(&schema, &buf[..size]).unwrap();
+        assert_eq!(header.seq, 1);
+        assert_eq!(
+            message.fields(),
+            [
+                ("ts_ns", Value::U64(TS_NS)),
+                ("price", Value::I64(-5)),
+                ("qty", Value::U64(100)),
+                ("note", Value::Bytes(b"hi")),
+            ]
+        );
+        assert_eq!(message.get("symbol"), None);
+
+        let size = trade::encode_tagged(&mut buf, 2, TS_NS, 1, 2, Some(b"X"), None).unwrap();
+        let (_, message) = DynamicMessage::decode(&schema, &buf[..size]).unwrap();
+        assert_eq!(message.get("symbol"), Some(&Value::Bytes(b"X")));
+
+        assert_eq!(
+            DynamicMessage::decode(&quote::schema(), &buf[..size]).unwrap_err(),
+            Error::UnsupportedMsgType
+        );
+    }
+
+    #[test]
+    fn test_decoder_learns_announced_schema() {
+        let schema = MessageSchema::new(90, "Fill", 0)
+            .with_optional_encoding(OptionalEncoding::Tagged)
+            .required("ts", FieldType::Timestamp)
+            .required("delta", FieldType::VarI64)
+            .optional("venue", FieldType::Bytes, 3)
+            .optional("fee", FieldType::I32, 4);
+
+        let mut announcement = [0u8; 256];
+        let announcement_len = schema::encode_schema(&mut announcement, 0, &schema).unwrap();
+
+        let mut frame = [0u8; 128];
+        let mut encoder = FrameEncoder::new(&mut frame);
+        let mut header = FrameHeader::new(90, 1, 0);
+        header.set_flag(FrameFlags::TAGGED);
+        encoder.begin(&header).unwrap();
+        encoder.put_timestamp(Timestamp::from_nanos(TS_NS)).unwrap();
+        encoder.put_varint_i64(-42).unwrap();
+        encoder
+            .put_tagged(4, TaggedValue::Fixed32(-7i32 as u32))
+            .unwrap();
+        encoder.put_tagged(9, TaggedValue::Varint(1)).unwrap(); // unknown
+        encoder
+            .put_tagged(3, TaggedValue::Bytes(&[0xff, 0x00]))
+            .unwrap();
+        let frame_len = encoder.finish_crc32c().unwrap();
+
+        let mut decoder = DynamicDecoder::new();
+        assert_eq!(
+            decoder.decode(&frame[..frame_len]).unwrap_err(),
+            Error::UnsupportedMsgType
+        );
+        assert!(!decoder.learn(&frame[..frame_len]).unwrap());
+        assert!(decoder.learn(&announcement[..announcement_len]).unwrap());
+        assert!(decoder.learn(&announcement[..announcement_len]).unwrap());
+
+        let (_, message) = decoder.decode(&frame[..frame_len]).unwrap();
+        assert_eq!(
+            message.to_string(),
+            "{ts: 2023-11-14T22:13:20.123456789Z, delta: -42, fee: -7, venue: 0xff00}"
+        );
+    }
+
+    #[test]
+    fn test_unknown_bitmap_bit_before_known_field() {
+        let schema = MessageSchema::new(91, "Sparse", 0)
+            .required("a", FieldType::U8)
+            .optional("late", FieldType::Bytes, 5);
+
+        let mut buf = [0u8; 64];
+        let mut encoder = FrameEncoder::new(&mut buf);
+        let mut header = FrameHeader::new(91, 0, 0);
+        header.set_flag(FrameFlags::PRESENCE_BITMAP);
+        encoder.begin(&header).unwrap();
+        encoder.put_u8(1).unwrap();
+        encoder.put_bitmap((1 << 2) | (1 << 5)).unwrap();
+        encoder.put_u32(0).unwrap(); // bit 2, unknown to this schema
+        encoder.put_varbytes(b"x").unwrap();
+        let size = encoder.finish_crc32c().unwrap();
+
+        assert_eq!(
+            DynamicMessage::decode(&schema, &buf[..size]),
+            Err(Error::DecodeInvariant)
+        );
+    }
+
+    #[test]
+    fn test_nested_groups_and_decimals() {
+        let leg = MessageSchema::group()
+            .with_optional_encoding(OptionalEncoding::Bitmap(BitmapSize::U8))
+            .required("px", FieldType::Decimal(4))
+            .required("qty", FieldType::VarU64)
+            .optional("venue", FieldType::Bytes, 0);
+        let schema = MessageSchema::new(92, "Spread", 0)
+            .required("id", FieldType::U32)
+            .required_group("near", leg.clone())
+            .required_group("far", leg);
+
+        let mut buf = [0u8; 128];
+        let mut encoder = FrameEncoder::new(&mut buf);
+        encoder.begin(&FrameHeader::new(92, 0, 0)).unwrap();
+        encoder.put_u32(7).unwrap();
+        for (px, qty, venue) in [(1_005_000, 3, Some(&b"XCME"[..])), (-2_500, 4, None)] {
+            let group = encoder.begin_group().unwrap();
+            encoder.put_i64(px).unwrap();
+            encoder.put_varint_u64(qty).unwrap();
+            encoder.put_u8(venue.is_some() as u8).unwrap();
+            if let Some(venue) = venue {
+                encoder.put_varbytes(venue).unwrap();
+            }
+            encoder.end_group(group).unwrap();
+        }
+        let size = encoder.finish_crc32c().unwrap();
+
+        // Nested layouts survive a schema announcement
+        let mut announcement = [0u8; 256];
+        let len = schema::encode_schema(&mut announcement, 0, &schema).unwrap();
+        let mut decoder = DynamicDecoder::new();
+        decoder.learn(&announcement[..len]).unwrap();
+
+        let (_, message) = decoder.decode(&buf[..size]).unwrap();
+        assert_eq!(
+            message.to_string(),
+            "{id: 7, near: {px: 100.5000, qty: 3, venue: \"XCME\"}, far: {px: -0.2500, qty: 4}}"
+        );
+        let Some(Value::Group(far)) = message.get("far") else {
+            panic!("far leg missing");
+        };
+        assert_eq!(
+            far.get("px"),
+            Some(&Value::Decimal(Decimal::new(-2_500, 4).unwrap()))
+        );
+    }
+}
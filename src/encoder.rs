@@ -3,11 +3,25 @@
 //! The encoder writes directly into a user-provided buffer with careful
 //! bounds checking and optimal memory layout.
 
+use crate::array::{FixedWidth, MAX_ARRAY_LEN};
+use crate::bitmap::PresenceBitmap;
 use crate::crc32c;
 use crate::error::{Error, Result};
 use crate::frame::FrameHeader;
+use crate::tagged::{self, TaggedValue};
+use crate::timestamp::Timestamp;
 use crate::varint;
 
+/// Size of the length prefix written before a nested group
+pub const GROUP_LEN_SIZE: usize = 4;
+
+/// Marker for an open nested group, returned by [`FrameEncoder::begin_group`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[must_use = "a group must be closed with FrameEncoder::end_group"]
+pub struct GroupMarker {
+    len_offset: usize,
+}
+
 /// Frame encoder that writes into a user-provided buffer
 pub struct FrameEncoder<'a> {
     buf: &'a mut [u8],
@@ -99,13 +113,23 @@ impl<'a> FrameEncoder<'a> {
         self.put_u64(value as u64)
     }
 
-    /// Write a presence bitmap (8-bit or 16-bit)
+    /// Write a raw 16-bit presence bitmap
+    ///
+    /// See [`put_presence_bitmap`](Self::put_presence_bitmap) for 8-bit and
+    /// variable-length bitmaps.
     #[inline]
     pub fn put_bitmap(&mut self, bitmap: u16) -> Result<()> {
-        // For simplicity, always use 16-bit format
         self.put_u16(bitmap)
     }
 
+    /// Write a presence bitmap in its declared size (8-bit, 16-bit or variable)
+    #[inline]
+    pub fn put_presence_bitmap(&mut self, bitmap: &PresenceBitmap) -> Result<()> {
+        let len = bitmap.encode(&mut self.buf[self.pos..])?;
+        self.pos += len;
+        Ok(())
+    }
+
     /// Write variable-length bytes with length prefix
     #[inline]
     pub fn put_varbytes(&mut self, bytes: &[u8]) -> Result<()> {
@@ -124,6 +148,83 @@ impl<'a> FrameEncoder<'a> {
         Ok(())
     }
 
+    /// Write an array of fixed-width elements with a varint count prefix
+    ///
+    /// Arrays longer than [`MAX_ARRAY_LEN`] are rejected with
+    /// `Error::Overflow`, since the default decoder would refuse them.
+    #[inline]
+    pub fn put_array<T: FixedWidth>(&mut self, items: &[T]) -> Result<()> {
+        self.put_array_with_limit(items, MAX_ARRAY_LEN)
+    }
+
+    /// Write an array of fixed-width elements, rejecting more than `max_len`
+    ///
+    /// Pair with `BodyCursor::get_array_with_limit` using the same limit.
+    #[inline]
+    pub fn put_array_with_limit<T: FixedWidth>(
+        &mut self,
+        items: &[T],
+        max_len: usize,
+    ) -> Result<()> {
+        if items.len() > max_len {
+            return Err(Error::Overflow);
+        }
+        let count = u32::try_from(items.len()).map_err(|_| Error::Overflow)?;
+        let data_len = items.len().checked_mul(T::SIZE).ok_or(Error::Overflow)?;
+
+        let remaining = &mut self.buf[self.pos..];
+        let varint_len = varint::encode_u32(count, remaining)?;
+        if self.pos + varint_len + data_len > self.buf.len() {
+            return Err(Error::ShortBuffer);
+        }
+        self.pos += varint_len;
+
+        for item in items {
+            item.encode_fixed(&mut self.buf[self.pos..self.pos + T::SIZE]);
+            self.pos += T::SIZE;
+        }
+
+        Ok(())
+    }
+
+    /// Write a tagged optional field (varint key followed by the value)
+    #[inline]
+    pub fn put_tagged(&mut self, field_idx: u32, value: TaggedValue<'_>) -> Result<()> {
+        self.put_varint_u32(tagged::encode_key(field_idx, value.wire_type())?)?;
+        match value {
+            TaggedValue::Varint(v) => self.put_varint_u64(v),
+            TaggedValue::Fixed64(v) => self.put_u64(v),
+            TaggedValue::Bytes(bytes) => self.put_varbytes(bytes),
+            TaggedValue::Fixed32(v) => self.put_u32(v),
+            TaggedValue::Fixed16(v) => self.put_u16(v),
+            TaggedValue::Fixed8(v) => self.put_u8(v),
+        }
+    }
+
+    /// Begin a nested group
+    ///
+    /// Reserves a u32 length prefix; everything written until the matching
+    /// [`end_group`](Self::end_group) becomes the group's sub-body, which may
+    /// contain its own presence bitmap and further nested groups.
+    #[inline]
+    pub fn begin_group(&mut self) -> Result<GroupMarker> {
+        let len_offset = self.pos;
+        self.put_u32(0)?;
+        Ok(GroupMarker { len_offset })
+    }
+
+    /// Close a nested group, back-filling its length prefix
+    #[inline]
+    pub fn end_group(&mut self, marker: GroupMarker) -> Result<()> {
+        let group_start = marker.len_offset + GROUP_LEN_SIZE;
+        if group_start > self.pos {
+            return Err(Error::DecodeInvariant);
+        }
+        let group_len = u32::try_from(self.pos - group_start).map_err(|_| Error::Overflow)?;
+        self.buf[marker.len_offset..group_start].copy_from_slice(&group_len.to_le_bytes());
+        Ok(())
+    }
+
     /// Write raw bytes without length prefix
     #[inline]
     pub fn put_bytes(&mut self, bytes: &[u8]) -> Result<()> {
@@ -153,6 +254,57 @@ impl<'a> FrameEncoder<'a> {
         Ok(())
     }
 
+    /// Write a ZigZag varint-encoded i32
+    #[inline]
+    pub fn put_varint_i32(&mut self, value: i32) -> Result<()> {
+        let remaining = &mut self.buf[self.pos..];
+        let varint_len = varint::encode_i32(value, remaining)?;
+        self.pos += varint_len;
+        Ok(())
+    }
+
+    /// Write a ZigZag varint-encoded i64
+    #[inline]
+    pub fn put_varint_i64(&mut self, value: i64) -> Result<()> {
+        let remaining = &mut self.buf[self.pos..];
+        let varint_len = varint::encode_i64(value, remaining)?;
+        self.pos += varint_len;
+        Ok(())
+    }
+
+    /// Write `value - base` as a ZigZag varint (e.g. a price relative to the previous one)
+    ///
+    /// The subtraction wraps, so any pair of values round-trips through
+    /// [`BodyCursor::get_varint_delta_i64`](crate::decoder::BodyCursor::get_varint_delta_i64).
+    #[inline]
+    pub fn put_varint_delta_i64(&mut self, value: i64, base: i64) -> Result<()> {
+        self.put_varint_i64(value.wrapping_sub(base))
+    }
+
+    /// Write a timestamp as 8-byte nanoseconds
+    #[inline]
+    pub fn put_timestamp(&mut self, ts: Timestamp) -> Result<()> {
+        let len = ts.encode(&mut self.buf[self.pos..])?;
+        self.pos += len;
+        Ok(())
+    }
+
+    /// Write a timestamp as a varint delta from `base`
+    #[inline]
+    pub fn put_timestamp_delta(&mut self, ts: Timestamp, base: Timestamp) -> Result<()> {
+        let len = ts.encode_delta(base, &mut self.buf[self.pos..])?;
+        self.pos += len;
+        Ok(())
+    }
+
+    /// Write a timestamp as 4-byte seconds + 4-byte nanos
+    #[inline]
+    pub fn put_timestamp_secs_nanos(&mut self, ts: Timestamp) -> Result<()> {
+        let len = ts.encode_secs_nanos(&mut self.buf[self.pos..])?;
+        self.pos += len;
+        Ok(())
+    }
+
     /// Get current position in buffer
     #[inline]
     pub fn position(&self) -> usize {
@@ -211,6 +363,7 @@ impl<'a> FrameEncoder<'a> {
 mod tests {
     use super::*;
     use crate::{decoder::FrameDecoder, FRAME_MAGIC};
+    use alloc::vec;
 
     #[test]
     fn test_encoder_basic() {
@@ -276,6 +429,29 @@ mod tests {
         assert_eq!(decoded_header.len, 6); // 2 bytes bitmap + 4 bytes u32
     }
 
+    #[test]
+    fn test_encoder_presence_bitmap_sizes() {
+        use crate::bitmap::{BitmapSize, PresenceBitmap};
+
+        let mut buf = [0u8; 128];
+        let mut encoder = FrameEncoder::new(&mut buf);
+        encoder.begin(&FrameHeader::new(2, 1, 0)).unwrap();
+
+        let mut small = PresenceBitmap::new(BitmapSize::U8);
+        small.set(3).unwrap();
+        encoder.put_presence_bitmap(&small).unwrap();
+
+        let mut wide = PresenceBitmap::new(BitmapSize::Var);
+        wide.set(70).unwrap();
+        encoder.put_presence_bitmap(&wide).unwrap();
+
+        let frame_size = encoder.finish_crc32c().unwrap();
+
+        let decoder = FrameDecoder::new(&buf[..frame_size]);
+        // 1 byte for the 8-bit bitmap, 11 bytes for field 70 in the variable bitmap
+        assert_eq!(decoder.header().unwrap().len, 1 + 11);
+    }
+
     #[test]
     fn test_encoder_buffer_too_small() {
         let mut buf = [0u8; 10]; // Too small
@@ -285,6 +461,29 @@ mod tests {
         assert_eq!(encoder.begin(&header), Err(Error::ShortBuffer));
     }
 
+    #[test]
+    fn test_encoder_array_limits() {
+        let items = vec![0xA5u8; MAX_ARRAY_LEN + 1];
+        let mut buf = vec![0u8; MAX_ARRAY_LEN + 64];
+        let mut encoder = FrameEncoder::new(&mut buf);
+        encoder.begin(&FrameHeader::new(1, 1, 0)).unwrap();
+
+        assert_eq!(encoder.put_array(&items), Err(Error::Overflow));
+        assert_eq!(
+            encoder.put_array_with_limit(&items[..3], 2),
+            Err(Error::Overflow)
+        );
+        assert_eq!(encoder.position(), FrameHeader::SIZE);
+
+        encoder.put_array(&items[..MAX_ARRAY_LEN]).unwrap();
+        let frame_size = encoder.finish_crc32c().unwrap();
+
+        let decoder = FrameDecoder::new(&buf[..frame_size]);
+        let mut body = decoder.body().unwrap();
+        assert_eq!(body.get_array::<u8>().unwrap().len(), MAX_ARRAY_LEN);
+        assert!(body.is_at_end());
+    }
+
     #[test]
     fn test_encoder_reset() {
         let mut buf = [0u8; 256];
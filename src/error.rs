@@ -23,6 +23,26 @@ pub enum Error {
     UnsupportedMsgType,
     /// Invalid varint encoding
     InvalidVarint,
+    /// Delta frame received without the state it was encoded against
+    MissingDeltaBase,
+    /// Tagged field uses a wire type this decoder cannot skip
+    UnknownWireType,
+    /// Schema has duplicate field names or out-of-range optional indices
+    InvalidSchema,
+    /// Message type id already registered for another message or version
+    DuplicateMsgType,
+    /// Schema change breaks the required compatibility direction
+    IncompatibleSchema,
+    /// Value has a shape the serde data format cannot represent
+    UnsupportedType,
+    /// Error raised by a `Serialize` or `Deserialize` implementation
+    Serde,
+    /// Malformed JSON, or JSON that does not match the message schema
+    InvalidJson,
+    /// Input is not a pcap/pcapng capture, or a capture block is malformed
+    InvalidCapture,
+    /// Frame replays a sequence number that was already consumed
+    DuplicateSeq,
 }
 
 impl Error {
@@ -39,13 +59,22 @@ impl Error {
             Error::DecodeInvariant => "decode invariant violated",
             Error::UnsupportedMsgType => "unsupported message type",
             Error::InvalidVarint => "invalid varint encoding",
+            Error::MissingDeltaBase => "delta frame without a preceding keyframe",
+            Error::UnknownWireType => "unknown wire type in tagged field",
+            Error::InvalidSchema => "invalid message schema",
+            Error::DuplicateMsgType => "message type already registered",
+            Error::IncompatibleSchema => "incompatible schema change",
+            Error::UnsupportedType => "type cannot be represented on the wire",
+            Error::Serde => "serde error",
+            Error::InvalidJson => "invalid JSON frame description",
+            Error::InvalidCapture => "malformed packet capture",
+            Error::DuplicateSeq => "frame sequence number already seen",
         }
     }
 }
 
-#[cfg(feature = "std")]
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}", self.description())
     }
 }
@@ -1,9 +1,22 @@
 //! Frame header structures and utilities
 
 use crate::error::{Error, Result};
-use crate::{FRAME_MAGIC, MAX_FRAME_SIZE, MIN_FRAME_SIZE};
+use crate::{
+    FRAME_MAGIC, MAX_FRAME_SIZE, MAX_PROTOCOL_VERSION, MIN_FRAME_SIZE, MIN_PROTOCOL_VERSION,
+    PROTOCOL_VERSION,
+};
+
+/// Header layout version that leaves bytes 14..16 reserved
+pub const HEADER_V1: u8 = 1;
+
+/// Header layout version that carries a channel id in bytes 14..16
+pub const HEADER_V2: u8 = 2;
 
 /// Frame header structure (16 bytes, little-endian)
+///
+/// Both header versions are 16 bytes. Version 1 writes zeros to bytes 14..16
+/// and ignores them on decode; version 2 stores [`channel`](Self::channel)
+/// there so one connection can multiplex several streams.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FrameHeader {
     /// Magic number (0xFEED)
@@ -18,6 +31,8 @@ pub struct FrameHeader {
     pub seq: u32,
     /// Body length in bytes
     pub len: u32,
+    /// Channel/stream id (version 2 and later; always 0 in version 1)
+    pub channel: u16,
 }
 
 /// Frame flags bit definitions
@@ -34,19 +49,26 @@ impl FrameFlags {
     /// Body is AEAD encrypted (bit 2)
     pub const ENCRYPTED: u8 = 0x04;
 
+    /// Body is delta-encoded against a previous frame (bit 3)
+    pub const DELTA: u8 = 0x08;
+
+    /// Optional fields are tagged rather than bitmap-gated (bit 4)
+    pub const TAGGED: u8 = 0x10;
+
     /// Reserved flags mask
-    pub const RESERVED: u8 = 0xF8;
+    pub const RESERVED: u8 = 0xE0;
 }
 
 impl Default for FrameHeader {
     fn default() -> Self {
         Self {
             magic: FRAME_MAGIC,
-            ver: 1,
+            ver: PROTOCOL_VERSION,
             flags: 0,
             msg_type: 0,
             seq: 0,
             len: 0,
+            channel: 0,
         }
     }
 }
@@ -60,14 +82,32 @@ impl FrameHeader {
     pub fn new(msg_type: u16, seq: u32, len: u32) -> Self {
         Self {
             magic: FRAME_MAGIC,
-            ver: 1,
+            ver: PROTOCOL_VERSION,
             flags: 0,
             msg_type,
             seq,
             len,
+            channel: 0,
         }
     }
 
+    /// Set the header layout version
+    #[inline]
+    pub fn with_version(mut self, ver: u8) -> Self {
+        self.ver = ver;
+        self
+    }
+
+    /// Set the channel id, upgrading to header version 2 if needed
+    #[inline]
+    pub fn with_channel(mut self, channel: u16) -> Self {
+        self.channel = channel;
+        if self.ver < HEADER_V2 {
+            self.ver = HEADER_V2;
+        }
+        self
+    }
+
     /// Set a flag bit
     #[inline]
     pub fn set_flag(&mut self, flag: u8) {
@@ -93,7 +133,12 @@ impl FrameHeader {
             return Err(Error::InvalidMagic);
         }
 
-        if self.ver != 1 {
+        if !(MIN_PROTOCOL_VERSION..=MAX_PROTOCOL_VERSION).contains(&self.ver) {
+            return Err(Error::UnsupportedVersion);
+        }
+
+        // Version 1 has no room for a channel id
+        if self.ver < HEADER_V2 && self.channel != 0 {
             return Err(Error::UnsupportedVersion);
         }
 
@@ -108,7 +153,7 @@ impl FrameHeader {
             .and_then(|s| s.checked_add(4)) // CRC32C
             .ok_or(Error::Overflow)?;
 
-        if total_size < MIN_FRAME_SIZE || total_size > MAX_FRAME_SIZE {
+        if !(MIN_FRAME_SIZE..=MAX_FRAME_SIZE).contains(&total_size) {
             return Err(Error::Overflow);
         }
 
@@ -128,7 +173,14 @@ impl FrameHeader {
         buf[4..6].copy_from_slice(&self.msg_type.to_le_bytes());
         buf[6..10].copy_from_slice(&self.seq.to_le_bytes());
         buf[10..14].copy_from_slice(&self.len.to_le_bytes());
-        buf[14..16].fill(0); // Reserved bytes
+        if self.ver >= HEADER_V2 {
+            buf[14..16].copy_from_slice(&self.channel.to_le_bytes());
+        } else {
+            if self.channel != 0 {
+                return Err(Error::UnsupportedVersion);
+            }
+            buf[14..16].fill(0); // Reserved bytes
+        }
 
         Ok(())
     }
@@ -140,14 +192,20 @@ impl FrameHeader {
             return Err(Error::UnexpectedEof);
         }
 
+        let ver = buf[2];
         let header = Self {
             magic: u16::from_le_bytes([buf[0], buf[1]]),
-            ver: buf[2],
+            ver,
             flags: buf[3],
             msg_type: u16::from_le_bytes([buf[4], buf[5]]),
             seq: u32::from_le_bytes([buf[6], buf[7], buf[8], buf[9]]),
             len: u32::from_le_bytes([buf[10], buf[11], buf[12], buf[13]]),
-            // Skip reserved bytes [14..16]
+            // Bytes [14..16] are reserved in version 1
+            channel: if ver >= HEADER_V2 {
+                u16::from_le_bytes([buf[14], buf[15]])
+            } else {
+                0
+            },
         };
 
         header.validate()?;
@@ -174,6 +232,7 @@ mod tests {
             msg_type: 42,
             seq: 0x12345678,
             len: 100,
+            channel: 0,
         };
 
         let mut buf = [0u8; FrameHeader::SIZE];
@@ -196,7 +255,17 @@ mod tests {
         // Invalid version
         header.ver = 99;
         assert_eq!(header.validate(), Err(Error::UnsupportedVersion));
+        header.ver = 0;
+        assert_eq!(header.validate(), Err(Error::UnsupportedVersion));
+        header.ver = 1;
+
+        // Channel id needs a version 2 header
+        header.channel = 3;
+        assert_eq!(header.validate(), Err(Error::UnsupportedVersion));
+        header.ver = HEADER_V2;
+        assert!(header.validate().is_ok());
         header.ver = 1;
+        header.channel = 0;
 
         // Reserved flags
         header.flags = 0x80;
@@ -208,6 +277,40 @@ mod tests {
         assert_eq!(header.validate(), Err(Error::Overflow));
     }
 
+    #[test]
+    fn test_header_v2_channel() {
+        let header = FrameHeader::new(42, 7, 10).with_channel(0xBEEF);
+        assert_eq!(header.ver, HEADER_V2);
+
+        let mut buf = [0u8; FrameHeader::SIZE];
+        header.encode(&mut buf).unwrap();
+        assert_eq!(&buf[14..16], &0xBEEFu16.to_le_bytes());
+
+        let decoded = FrameHeader::decode(&buf).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(decoded.channel, 0xBEEF);
+    }
+
+    #[test]
+    fn test_header_v1_ignores_reserved_bytes() {
+        let header = FrameHeader::new(42, 7, 10);
+        let mut buf = [0u8; FrameHeader::SIZE];
+        header.encode(&mut buf).unwrap();
+        assert_eq!(&buf[14..16], &[0, 0]);
+
+        // Garbage in the reserved bytes of a v1 header is not a channel id
+        buf[14] = 0xAA;
+        buf[15] = 0x55;
+        let decoded = FrameHeader::decode(&buf).unwrap();
+        assert_eq!(decoded.ver, HEADER_V1);
+        assert_eq!(decoded.channel, 0);
+
+        // A v1 header cannot carry a channel id
+        let mut bad = header;
+        bad.channel = 1;
+        assert_eq!(bad.encode(&mut buf), Err(Error::UnsupportedVersion));
+    }
+
     #[test]
     fn test_flag_operations() {
         let mut header = FrameHeader::default();
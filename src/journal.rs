@@ -0,0 +1,1920 @@
+//! Append-only journal files for recording frames
+//!
+//! A journal is a directory of files named `journal-NNNNNN.mbj`, numbered
+//! in write order. Each file starts with a [`FileHeader`] followed by
+//! records, all integers little-endian:
+//!
+//! ```text
+//! record header (16 bytes): kind u8 | reserved [u8; 3] | len u32 | ts u64
+//! payload (len bytes):      frame bytes verbatim, or an index block
+//! ```
+//!
+//! `ts` is the receive timestamp of the frame in nanoseconds. An
+//! [`IndexBlock`] is written after every `index_interval` bytes of frame
+//! records and when a file is closed; it summarises the frames since the
+//! previous block (offset, count, seq and time range) and links back to
+//! it, so the final block of a cleanly closed file leads to all others.
+//! [`SparseIndex`] loads that chain (or rebuilds it by scanning a file that
+//! was never closed) and lets [`JournalReader::seek_seq`],
+//! [`JournalReader::seek_time`] and the zero-copy [`JournalView`] start
+//! reading anywhere after scanning at most one interval. [`Journal`] seeks
+//! across the rotated files of a directory, choosing the file to start in
+//! from the file headers and first frames.
+//!
+//! A crash can leave the last record of a file half written.
+//! [`JournalReader`] treats an incomplete final record, or an invalid one
+//! followed only by zero bytes, as a torn tail: reading stops cleanly and
+//! [`JournalReader::torn_tail`] reports where the damage starts.
+
+use core::fmt;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::vec::Vec;
+use std::{format, vec};
+
+use crate::crc32c::crc32c;
+use crate::decoder::FrameDecoder;
+use crate::error::{Error, Result};
+use crate::frame::FrameHeader;
+use crate::timestamp::Timestamp;
+use crate::MAX_FRAME_SIZE;
+
+/// Magic bytes at the start of every journal file
+pub const JOURNAL_MAGIC: [u8; 8] = *b"MINIBITJ";
+
+/// Current journal format version
+pub const JOURNAL_VERSION: u16 = 1;
+
+/// Size of the file header in bytes
+pub const FILE_HEADER_SIZE: usize = 24;
+
+/// Size of a record header in bytes
+pub const RECORD_HEADER_SIZE: usize = 16;
+
+/// Size of an index block payload in bytes
+pub const INDEX_BLOCK_SIZE: usize = 52;
+
+/// File name extension of journal files
+pub const FILE_EXTENSION: &str = "mbj";
+
+/// Default size at which the writer starts a new file
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 1 << 30;
+
+/// Default number of frame record bytes between index blocks
+pub const DEFAULT_INDEX_INTERVAL: u32 = 1 << 20;
+
+const FILE_PREFIX: &str = "journal-";
+const INDEX_TAG: [u8; 4] = *b"MBIX";
+const NO_PREV: u64 = u64::MAX;
+
+/// Record kinds
+pub mod record_kinds {
+    /// A frame stored verbatim
+    pub const FRAME: u8 = 1;
+    /// An [`IndexBlock`](super::IndexBlock)
+    pub const INDEX: u8 = 2;
+}
+
+/// Header at the start of a journal file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileHeader {
+    /// Format version
+    pub version: u16,
+    /// Frame record bytes between index blocks
+    pub index_interval: u32,
+    /// Receive timestamp of the first record in the file
+    pub opened: Timestamp,
+}
+
+impl FileHeader {
+    /// Encode to bytes
+    pub fn encode(&self) -> [u8; FILE_HEADER_SIZE] {
+        let mut buf = [0u8; FILE_HEADER_SIZE];
+        buf[..8].copy_from_slice(&JOURNAL_MAGIC);
+        buf[8..10].copy_from_slice(&self.version.to_le_bytes());
+        // [10..12] reserved
+        buf[12..16].copy_from_slice(&self.index_interval.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.opened.as_nanos().to_le_bytes());
+        buf
+    }
+
+    /// Decode from bytes
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        let buf = buf.get(..FILE_HEADER_SIZE).ok_or(Error::UnexpectedEof)?;
+        if buf[..8] != JOURNAL_MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+        let version = u16::from_le_bytes([buf[8], buf[9]]);
+        if version != JOURNAL_VERSION {
+            return Err(Error::UnsupportedVersion);
+        }
+        Ok(Self {
+            version,
+            index_interval: u32::from_le_bytes(buf[12..16].try_into().unwrap()),
+            opened: Timestamp::from_nanos(u64::from_le_bytes(buf[16..24].try_into().unwrap())),
+        })
+    }
+}
+
+/// Summary of the frame records written since the previous index block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexBlock {
+    /// File offset of the previous index record, if any
+    pub prev: Option<u64>,
+    /// File offset of the first frame record covered
+    pub start: u64,
+    /// Number of frame records covered
+    pub frames: u32,
+    /// Smallest frame seq covered
+    ///
+    /// The seq range only speaks for channel 0. A block holding other
+    /// channels records the whole range `0..=u32::MAX`, so that seeks scan
+    /// it, and one where channel 0 seqs go backwards (wrap) records the
+    /// inverted range `u32::MAX..=0`.
+    pub min_seq: u32,
+    /// Largest frame seq covered
+    pub max_seq: u32,
+    /// Receive timestamp of the first frame covered
+    pub first_ts: Timestamp,
+    /// Receive timestamp of the last frame covered
+    pub last_ts: Timestamp,
+}
+
+impl IndexBlock {
+    /// Encode to an index record payload
+    pub fn encode(&self) -> [u8; INDEX_BLOCK_SIZE] {
+        let mut buf = [0u8; INDEX_BLOCK_SIZE];
+        buf[0..8].copy_from_slice(&self.prev.unwrap_or(NO_PREV).to_le_bytes());
+        buf[8..16].copy_from_slice(&self.start.to_le_bytes());
+        buf[16..20].copy_from_slice(&self.frames.to_le_bytes());
+        buf[20..24].copy_from_slice(&self.min_seq.to_le_bytes());
+        buf[24..28].copy_from_slice(&self.max_seq.to_le_bytes());
+        buf[28..36].copy_from_slice(&self.first_ts.as_nanos().to_le_bytes());
+        buf[36..44].copy_from_slice(&self.last_ts.as_nanos().to_le_bytes());
+        let crc = crc32c(&buf[..44]);
+        buf[44..48].copy_from_slice(&crc.to_le_bytes());
+        // The trailing tag lets a reader spot the final index from the end
+        buf[48..52].copy_from_slice(&INDEX_TAG);
+        buf
+    }
+
+    /// Decode an index record payload
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() != INDEX_BLOCK_SIZE {
+            return Err(Error::UnexpectedEof);
+        }
+        if buf[48..52] != INDEX_TAG {
+            return Err(Error::InvalidMagic);
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(buf[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(buf[at..at + 8].try_into().unwrap());
+        if crc32c(&buf[..44]) != u32_at(44) {
+            return Err(Error::CrcMismatch);
+        }
+        Ok(Self {
+            prev: Some(u64_at(0)).filter(|&prev| prev != NO_PREV),
+            start: u64_at(8),
+            frames: u32_at(16),
+            min_seq: u32_at(20),
+            max_seq: u32_at(24),
+            first_ts: Timestamp::from_nanos(u64_at(28)),
+            last_ts: Timestamp::from_nanos(u64_at(36)),
+        })
+    }
+}
+
+fn encode_record_header(kind: u8, len: usize, ts: Timestamp) -> [u8; RECORD_HEADER_SIZE] {
+    let mut buf = [0u8; RECORD_HEADER_SIZE];
+    buf[0] = kind;
+    buf[4..8].copy_from_slice(&(len as u32).to_le_bytes());
+    buf[8..16].copy_from_slice(&ts.as_nanos().to_le_bytes());
+    buf
+}
+
+/// Decode a record header into (kind, payload length, timestamp)
+fn decode_record_header(buf: &[u8; RECORD_HEADER_SIZE]) -> Result<(u8, usize, Timestamp)> {
+    let kind = buf[0];
+    let len = u32::from_le_bytes(buf[4..8].try_into().unwrap()) as usize;
+    let valid_len = match kind {
+        record_kinds::FRAME => (FrameHeader::SIZE + 4..=MAX_FRAME_SIZE).contains(&len),
+        record_kinds::INDEX => len == INDEX_BLOCK_SIZE,
+        _ => return Err(Error::DecodeInvariant),
+    };
+    if buf[1..4] != [0; 3] || !valid_len {
+        return Err(Error::DecodeInvariant);
+    }
+    let ts = Timestamp::from_nanos(u64::from_le_bytes(buf[8..16].try_into().unwrap()));
+    Ok((kind, len, ts))
+}
+
+/// Check that `frame` is exactly one frame as described by its header
+fn check_frame(frame: &[u8]) -> Result<FrameHeader> {
+    let header = FrameHeader::decode(frame)?;
+    if header.total_size() != frame.len() {
+        return Err(Error::DecodeInvariant);
+    }
+    Ok(header)
+}
+
+/// One record read from a journal file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Record<'a> {
+    /// A recorded frame
+    Frame {
+        /// File offset of the record header
+        offset: u64,
+        /// Receive timestamp
+        ts: Timestamp,
+        /// Verbatim frame bytes
+        frame: &'a [u8],
+    },
+    /// An index block
+    Index {
+        /// File offset of the record header
+        offset: u64,
+        /// Block contents
+        block: IndexBlock,
+    },
+}
+
+impl Record<'_> {
+    /// Size of the record in the file, header included
+    #[inline]
+    pub fn size(&self) -> usize {
+        RECORD_HEADER_SIZE
+            + match self {
+                Record::Frame { frame, .. } => frame.len(),
+                Record::Index { .. } => INDEX_BLOCK_SIZE,
+            }
+    }
+}
+
+/// List the journal files in `dir` in write order
+pub fn journal_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if file_number(&path).is_some() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Number `N` of a file named `journal-N.mbj`
+fn file_number(path: &Path) -> Option<u32> {
+    if path.extension()? != FILE_EXTENSION {
+        return None;
+    }
+    path.file_stem()?
+        .to_str()?
+        .strip_prefix(FILE_PREFIX)?
+        .parse()
+        .ok()
+}
+
+fn seq_went_back(at: impl fmt::Display, channel: u16, from: u32, to: u32) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "{}: seq on channel {} went back from {} to {}",
+            at, channel, from, to
+        ),
+    )
+}
+
+fn invalid_data(offset: u64, err: Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("journal record at offset {}: {}", offset, err),
+    )
+}
+
+/// Frames since the last index block
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    start: u64,
+    bytes: u64,
+    frames: u32,
+    min_seq: u32,
+    max_seq: u32,
+    /// Every frame so far decodes and is on channel 0
+    ordered: bool,
+    /// A channel 0 seq went backwards
+    wrapped: bool,
+    first_ts: Timestamp,
+    last_ts: Timestamp,
+}
+
+impl Span {
+    /// Add a frame record to `span`, starting a new span if there is none
+    ///
+    /// `header` is `None` for a frame whose header does not decode.
+    fn observe<'a>(
+        span: &'a mut Option<Span>,
+        offset: u64,
+        size: u64,
+        header: Option<&FrameHeader>,
+        ts: Timestamp,
+    ) -> &'a mut Span {
+        let span = span.get_or_insert(Span {
+            start: offset,
+            bytes: 0,
+            frames: 0,
+            min_seq: u32::MAX,
+            max_seq: 0,
+            ordered: true,
+            wrapped: false,
+            first_ts: ts,
+            last_ts: ts,
+        });
+        match header {
+            Some(header) if header.channel == 0 => {
+                span.wrapped |= header.seq < span.max_seq;
+                span.min_seq = span.min_seq.min(header.seq);
+                span.max_seq = span.max_seq.max(header.seq);
+            }
+            _ => span.ordered = false,
+        }
+        span.bytes += size;
+        span.frames += 1;
+        span.last_ts = ts;
+        span
+    }
+
+    fn into_block(self, prev: Option<u64>) -> IndexBlock {
+        let (min_seq, max_seq) = if self.wrapped {
+            (u32::MAX, 0)
+        } else if self.ordered && self.min_seq <= self.max_seq {
+            (self.min_seq, self.max_seq)
+        } else {
+            (0, u32::MAX)
+        };
+        IndexBlock {
+            prev,
+            start: self.start,
+            frames: self.frames,
+            min_seq,
+            max_seq,
+            first_ts: self.first_ts,
+            last_ts: self.last_ts,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct OpenFile {
+    path: PathBuf,
+    out: BufWriter<File>,
+    opened: Timestamp,
+    size: u64,
+    last_index: Option<u64>,
+    span: Option<Span>,
+}
+
+impl OpenFile {
+    fn write_record(&mut self, kind: u8, ts: Timestamp, payload: &[u8]) -> io::Result<u64> {
+        let offset = self.size;
+        self.out
+            .write_all(&encode_record_header(kind, payload.len(), ts))?;
+        self.out.write_all(payload)?;
+        self.size += (RECORD_HEADER_SIZE + payload.len()) as u64;
+        Ok(offset)
+    }
+
+    /// Write an index block for the current span, if it has frames
+    fn write_index(&mut self) -> io::Result<()> {
+        let Some(span) = self.span.take() else {
+            return Ok(());
+        };
+        let block = span.into_block(self.last_index);
+        let offset = self.write_record(record_kinds::INDEX, span.last_ts, &block.encode())?;
+        self.last_index = Some(offset);
+        Ok(())
+    }
+}
+
+/// Appends frames to a directory of rotating journal files
+///
+/// Files are created lazily on the first append and never overwritten; a
+/// writer opened on an existing journal continues after its last file.
+/// Call [`close`](Self::close) to write the final index block.
+#[derive(Debug)]
+pub struct JournalWriter {
+    dir: PathBuf,
+    max_file_size: u64,
+    max_file_age: Option<Duration>,
+    index_interval: u32,
+    next_file: u32,
+    current: Option<OpenFile>,
+}
+
+impl JournalWriter {
+    /// Journal into `dir`, creating it if needed
+    pub fn create(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let next_file = journal_files(&dir)?
+            .iter()
+            .filter_map(|path| file_number(path))
+            .max()
+            .map_or(0, |n| n + 1);
+        Ok(Self {
+            dir,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_file_age: None,
+            index_interval: DEFAULT_INDEX_INTERVAL,
+            next_file,
+            current: None,
+        })
+    }
+
+    /// Start a new file once the current one would exceed `bytes`
+    ///
+    /// A single record larger than the limit still gets a file of its own.
+    pub fn with_max_file_size(mut self, bytes: u64) -> Self {
+        self.max_file_size = bytes;
+        self
+    }
+
+    /// Start a new file once a record arrives `age` after the file's first
+    /// record (by receive timestamp)
+    pub fn with_max_file_age(mut self, age: Duration) -> Self {
+        self.max_file_age = Some(age);
+        self
+    }
+
+    /// Write an index block after every `bytes` of frame records
+    pub fn with_index_interval(mut self, bytes: u32) -> Self {
+        self.index_interval = bytes.max(1);
+        self
+    }
+
+    /// Path of the file currently being written
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current.as_ref().map(|file| file.path.as_path())
+    }
+
+    /// Record `frame` with its receive timestamp
+    ///
+    /// `frame` must be exactly one frame with a valid header; its CRC is not
+    /// checked, so corrupt frames as received can be recorded too.
+    pub fn append(&mut self, frame: &[u8], ts: Timestamp) -> io::Result<()> {
+        let header =
+            check_frame(frame).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let record_size = (RECORD_HEADER_SIZE + frame.len()) as u64;
+
+        if let Some(file) = &self.current {
+            let full =
+                file.size + record_size > self.max_file_size && file.size > FILE_HEADER_SIZE as u64;
+            let expired = self.max_file_age.is_some_and(|age| {
+                ts.saturating_nanos_since(file.opened) as u128 >= age.as_nanos()
+            });
+            if full || expired {
+                self.finish_file()?;
+            }
+        }
+        let file = match self.current.take() {
+            Some(file) => file,
+            None => self.open_file(ts)?,
+        };
+        let file = self.current.insert(file);
+
+        let offset = file.write_record(record_kinds::FRAME, ts, frame)?;
+        let span = Span::observe(&mut file.span, offset, record_size, Some(&header), ts);
+        if span.bytes >= self.index_interval as u64 {
+            file.write_index()?;
+        }
+        Ok(())
+    }
+
+    /// Record `frame` stamped with the current wall-clock time
+    pub fn append_now(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.append(frame, Timestamp::now())
+    }
+
+    /// Push buffered records to the operating system
+    pub fn flush(&mut self) -> io::Result<()> {
+        match &mut self.current {
+            Some(file) => file.out.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Flush and wait until the current file's data is on disk
+    pub fn sync(&mut self) -> io::Result<()> {
+        match &mut self.current {
+            Some(file) => {
+                file.out.flush()?;
+                file.out.get_ref().sync_data()
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Write the final index block and flush the current file
+    pub fn close(mut self) -> io::Result<()> {
+        self.finish_file()
+    }
+
+    fn open_file(&mut self, opened: Timestamp) -> io::Result<OpenFile> {
+        let path = self.dir.join(format!(
+            "{}{:06}.{}",
+            FILE_PREFIX, self.next_file, FILE_EXTENSION
+        ));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        self.next_file += 1;
+
+        let mut out = BufWriter::new(file);
+        let header = FileHeader {
+            version: JOURNAL_VERSION,
+            index_interval: self.index_interval,
+            opened,
+        };
+        out.write_all(&header.encode())?;
+        Ok(OpenFile {
+            path,
+            out,
+            opened,
+            size: FILE_HEADER_SIZE as u64,
+            last_index: None,
+            span: None,
+        })
+    }
+
+    fn finish_file(&mut self) -> io::Result<()> {
+        if let Some(mut file) = self.current.take() {
+            file.write_index()?;
+            file.out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the records of one journal file in order
+#[derive(Debug)]
+pub struct JournalReader<R> {
+    inner: R,
+    header: FileHeader,
+    offset: u64,
+    buf: Vec<u8>,
+    torn: Option<u64>,
+    index: Option<SparseIndex>,
+}
+
+impl JournalReader<BufReader<File>> {
+    /// Open a journal file
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> JournalReader<R> {
+    /// Read the file header from `inner`
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut buf = [0u8; FILE_HEADER_SIZE];
+        inner.read_exact(&mut buf)?;
+        let header = FileHeader::decode(&buf).map_err(|e| invalid_data(0, e))?;
+        Ok(Self {
+            inner,
+            header,
+            offset: FILE_HEADER_SIZE as u64,
+            buf: Vec::new(),
+            torn: None,
+            index: None,
+        })
+    }
+
+    /// The file header
+    #[inline]
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    /// File offset of the next record
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Offset where an incomplete final write starts, once reading has
+    /// reached it
+    #[inline]
+    pub fn torn_tail(&self) -> Option<u64> {
+        self.torn
+    }
+
+    /// Read the next record, or `None` at the end of the file
+    ///
+    /// A torn tail also ends the file; corruption anywhere else is an
+    /// `InvalidData` error.
+    pub fn next_record(&mut self) -> io::Result<Option<Record<'_>>> {
+        if self.torn.is_some() {
+            return Ok(None);
+        }
+        let offset = self.offset;
+
+        let mut head = [0u8; RECORD_HEADER_SIZE];
+        match read_full(&mut self.inner, &mut head)? {
+            0 => return Ok(None),
+            RECORD_HEADER_SIZE => {}
+            _ => return self.torn_at(offset),
+        }
+        let (kind, len, ts) = match decode_record_header(&head) {
+            Ok(fields) => fields,
+            Err(err) => return self.bad_record(offset, err),
+        };
+        self.buf.resize(len, 0);
+        if read_full(&mut self.inner, &mut self.buf)? < len {
+            return self.torn_at(offset);
+        }
+
+        let checked = match kind {
+            record_kinds::FRAME => check_frame(&self.buf).map(|_| None),
+            _ => IndexBlock::decode(&self.buf).map(Some),
+        };
+        let index = match checked {
+            Ok(index) => index,
+            Err(err) => return self.bad_record(offset, err),
+        };
+        self.offset += (RECORD_HEADER_SIZE + len) as u64;
+        Ok(Some(match index {
+            Some(block) => Record::Index { offset, block },
+            None => Record::Frame {
+                offset,
+                ts,
+                frame: &self.buf,
+            },
+        }))
+    }
+
+    /// Iterate over the remaining frames, copying each out of the reader
+    pub fn frames(&mut self) -> ReaderFrames<'_, R> {
+        ReaderFrames {
+            reader: self,
+            failed: false,
+        }
+    }
+
+    /// Read up to the next frame record, skipping index blocks
+    pub fn next_frame(&mut self) -> io::Result<Option<(u64, Timestamp, &[u8])>> {
+        loop {
+            // Re-borrowing across loop iterations needs the record split off first
+            let (offset, ts, len) = match self.next_record()? {
+                None => return Ok(None),
+                Some(Record::Index { .. }) => continue,
+                Some(Record::Frame { offset, ts, frame }) => (offset, ts, frame.len()),
+            };
+            return Ok(Some((offset, ts, &self.buf[..len])));
+        }
+    }
+
+    fn torn_at<T>(&mut self, offset: u64) -> io::Result<Option<T>> {
+        self.torn = Some(offset);
+        Ok(None)
+    }
+
+    /// An invalid record is a torn tail only if nothing but zeros follows
+    fn bad_record<T>(&mut self, offset: u64, err: Error) -> io::Result<Option<T>> {
+        let mut chunk = vec![0u8; 4096];
+        loop {
+            match self.inner.read(&mut chunk) {
+                Ok(0) => return self.torn_at(offset),
+                Ok(n) if chunk[..n].iter().all(|&b| b == 0) => {}
+                Ok(_) => return Err(invalid_data(offset, err)),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// A frame read from a [`JournalReader`], owning its bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalFrameBuf {
+    /// File offset of the record header
+    pub offset: u64,
+    /// Receive timestamp
+    pub ts: Timestamp,
+    /// Verbatim frame bytes
+    pub frame: Vec<u8>,
+}
+
+impl JournalFrameBuf {
+    /// Decoder over the frame
+    #[inline]
+    pub fn decoder(&self) -> FrameDecoder<'_> {
+        FrameDecoder::new(&self.frame)
+    }
+}
+
+/// Iterator over the frames of a [`JournalReader`], skipping index blocks
+///
+/// Ends at the end of the file, at a torn tail and after the first error.
+#[derive(Debug)]
+pub struct ReaderFrames<'r, R> {
+    reader: &'r mut JournalReader<R>,
+    failed: bool,
+}
+
+impl<R> ReaderFrames<'_, R> {
+    /// Offset where an incomplete final write starts, once iteration has
+    /// reached it
+    #[inline]
+    pub fn torn_tail(&self) -> Option<u64> {
+        self.reader.torn
+    }
+}
+
+impl<R: Read> Iterator for ReaderFrames<'_, R> {
+    type Item = io::Result<JournalFrameBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.reader.next_frame() {
+            Ok(frame) => frame.map(|(offset, ts, frame)| {
+                Ok(JournalFrameBuf {
+                    offset,
+                    ts,
+                    frame: frame.to_vec(),
+                })
+            }),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Fill `buf` as far as the input allows, returning the bytes read
+fn read_full(inner: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match inner.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+impl<R: Read + Seek> JournalReader<R> {
+    /// Continue reading at `offset`, which must be the start of a record
+    pub fn seek_to(&mut self, offset: u64) -> io::Result<()> {
+        self.inner.seek(SeekFrom::Start(offset))?;
+        self.offset = offset;
+        self.torn = None;
+        Ok(())
+    }
+
+    /// The file's sparse index, loaded on first use
+    ///
+    /// See [`SparseIndex::load`].
+    pub fn index(&mut self) -> io::Result<&SparseIndex> {
+        if self.index.is_none() {
+            let (resume, torn) = (self.offset, self.torn);
+            let index = SparseIndex::load(&mut self.inner)?;
+            self.seek_to(resume)?;
+            self.torn = torn;
+            self.index = Some(index);
+        }
+        Ok(self.index.get_or_insert_with(SparseIndex::default))
+    }
+
+    /// Iterate over frames from the first on `channel` with a seq of at
+    /// least `seq`
+    ///
+    /// The reader is positioned at that frame (or at the end of the file if
+    /// there is none), so dropping the iterator unused leaves
+    /// [`next_frame`](Self::next_frame) to continue without copying. Seqs on
+    /// the channel must not go backwards: a wrap makes "at least `seq`"
+    /// ambiguous, so one shown by the index (channel 0 only) or met while
+    /// scanning to the match fails with
+    /// [`InvalidData`](io::ErrorKind::InvalidData).
+    pub fn seek_seq(&mut self, channel: u16, seq: u32) -> io::Result<ReaderFrames<'_, R>> {
+        self.seek_first(StartAt::seq(channel, seq))?;
+        Ok(self.frames())
+    }
+
+    /// Iterate over frames from the first received at or after `ts`
+    ///
+    /// Positions the reader like [`seek_seq`](Self::seek_seq).
+    pub fn seek_time(&mut self, ts: Timestamp) -> io::Result<ReaderFrames<'_, R>> {
+        self.seek_first(StartAt::Time(ts))?;
+        Ok(self.frames())
+    }
+
+    /// Scan forward from the index block for `at` to the first frame it
+    /// matches
+    fn seek_first(&mut self, mut at: StartAt) -> io::Result<bool> {
+        let Some(start) = at.locate(self.index()?)? else {
+            let end = self.inner.seek(SeekFrom::End(0))?;
+            self.offset = end;
+            return Ok(false);
+        };
+        self.seek_to(start)?;
+        loop {
+            let (offset, matched) = match self.next_record()? {
+                None => return Ok(false),
+                Some(Record::Index { .. }) => continue,
+                Some(Record::Frame { offset, ts, frame }) => {
+                    (offset, at.matches(offset, ts, frame)?)
+                }
+            };
+            if matched {
+                self.seek_to(offset)?;
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// The rotated files of a journal directory, read as one stream
+#[derive(Debug, Clone)]
+pub struct Journal {
+    files: Vec<PathBuf>,
+}
+
+impl Journal {
+    /// Find the files of the journal in `dir`
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            files: journal_files(dir.as_ref())?,
+        })
+    }
+
+    /// Journal files in write order
+    #[inline]
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// Iterate over the frames of every file
+    pub fn frames(&self) -> JournalFrames {
+        JournalFrames {
+            files: self.files.clone().into_iter(),
+            reader: None,
+            start: None,
+            failed: false,
+        }
+    }
+
+    /// Iterate over frames from the first on `channel` with a seq of at
+    /// least `seq`
+    ///
+    /// Starts in the last file not beginning with a frame on `channel` with
+    /// a seq above `seq`, found by reading the first frame of each file, and
+    /// seeks within each file by its index. Like
+    /// [`JournalReader::seek_seq`], fails if seqs on the channel wrap, which
+    /// includes files beginning with lower seqs than the file before.
+    pub fn seek_seq(&self, channel: u16, seq: u32) -> io::Result<JournalFrames> {
+        let (mut first, mut past, mut prev) = (0, false, None);
+        for (i, path) in self.files.iter().enumerate() {
+            let mut reader = JournalReader::open(path)?;
+            let Some((_, _, frame)) = reader.next_frame()? else {
+                continue;
+            };
+            let header = FrameHeader::decode(frame).ok();
+            if let Some(header) = header.filter(|header| header.channel == channel) {
+                if let Some(prev) = prev.filter(|&prev| header.seq < prev) {
+                    return Err(seq_went_back(path.display(), channel, prev, header.seq));
+                }
+                prev = Some(header.seq);
+                past |= header.seq > seq;
+            }
+            if !past {
+                first = i;
+            }
+        }
+        self.start_in(first, StartAt::seq(channel, seq))
+    }
+
+    /// Iterate over frames from the first received at or after `ts`
+    ///
+    /// Starts in the last file opened at or before `ts`, according to its
+    /// [`FileHeader`], and seeks within it by its index.
+    pub fn seek_time(&self, ts: Timestamp) -> io::Result<JournalFrames> {
+        let mut first = 0;
+        for (i, path) in self.files.iter().enumerate() {
+            if JournalReader::open(path)?.header().opened > ts {
+                break;
+            }
+            first = i;
+        }
+        self.start_in(first, StartAt::Time(ts))
+    }
+
+    /// Frames from file `first` on, skipped up to the first matching `start`
+    fn start_in(&self, first: usize, start: StartAt) -> io::Result<JournalFrames> {
+        let mut frames = JournalFrames {
+            files: self
+                .files
+                .get(first + 1..)
+                .unwrap_or_default()
+                .to_vec()
+                .into_iter(),
+            reader: None,
+            start: Some(start),
+            failed: false,
+        };
+        if let Some(path) = self.files.get(first) {
+            frames.reader = open_at(path, &start)?;
+        }
+        Ok(frames)
+    }
+}
+
+/// Open the file at `path` positioned at the index block to scan for
+/// `start`, or `None` if no block of the file can hold a match
+fn open_at(path: &Path, start: &StartAt) -> io::Result<Option<JournalReader<BufReader<File>>>> {
+    let mut reader = JournalReader::open(path)?;
+    let Some(offset) = start.locate(reader.index()?)? else {
+        return Ok(None);
+    };
+    reader.seek_to(offset)?;
+    Ok(Some(reader))
+}
+
+/// Iterator over the frames of a [`Journal`], file after file
+///
+/// A torn tail ends its file and reading goes on with the next one; the
+/// first error ends the iteration.
+#[derive(Debug)]
+pub struct JournalFrames {
+    files: vec::IntoIter<PathBuf>,
+    reader: Option<JournalReader<BufReader<File>>>,
+    /// Frames before the first match are skipped after a seek
+    start: Option<StartAt>,
+    failed: bool,
+}
+
+impl JournalFrames {
+    fn fail(&mut self, err: io::Error) -> Option<io::Result<JournalFrameBuf>> {
+        self.failed = true;
+        self.reader = None;
+        Some(Err(err))
+    }
+}
+
+impl Iterator for JournalFrames {
+    type Item = io::Result<JournalFrameBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed {
+            let reader = match &mut self.reader {
+                Some(reader) => reader,
+                None => {
+                    let path = self.files.next()?;
+                    // Still seeking: skip to the file's index block, or past
+                    // the file if it has no match
+                    let opened = match &self.start {
+                        Some(start) => open_at(&path, start),
+                        None => JournalReader::open(&path).map(Some),
+                    };
+                    match opened {
+                        Ok(Some(reader)) => self.reader.insert(reader),
+                        Ok(None) => continue,
+                        Err(err) => return self.fail(err),
+                    }
+                }
+            };
+            let (offset, ts, frame) = match reader.next_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    self.reader = None;
+                    continue;
+                }
+                Err(err) => return self.fail(err),
+            };
+            let matched = match &mut self.start {
+                None => Ok(true),
+                Some(start) => start.matches(offset, ts, frame),
+            };
+            match matched {
+                Ok(false) => continue,
+                Ok(true) => self.start = None,
+                Err(err) => return self.fail(err),
+            }
+            return Some(Ok(JournalFrameBuf {
+                offset,
+                ts,
+                frame: frame.to_vec(),
+            }));
+        }
+        None
+    }
+}
+
+/// Sparse index of one journal file, one [`IndexBlock`] per index interval
+///
+/// Maps a seq or receive time to the offset of a block of records to scan
+/// from, so seeking costs at most one interval of reading.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SparseIndex {
+    blocks: Vec<IndexBlock>,
+}
+
+impl SparseIndex {
+    /// Load the index blocks written by [`JournalWriter`]
+    ///
+    /// A cleanly closed file ends with an index block, and the chain of
+    /// blocks is followed back from there without reading any frames. Files
+    /// without a final block (the writer crashed) are [rebuilt](Self::rebuild).
+    pub fn load<R: Read + Seek>(src: &mut R) -> io::Result<Self> {
+        if let Some(blocks) = read_index_chain(src)? {
+            return Ok(Self { blocks });
+        }
+        src.seek(SeekFrom::Start(0))?;
+        Self::rebuild(&mut JournalReader::new(&mut *src)?)
+    }
+
+    /// Build the index by scanning every remaining frame of `reader`
+    ///
+    /// Blocks cover the file header's index interval, like those the writer
+    /// produces, but are not linked (`prev` is `None`).
+    pub fn rebuild<R: Read>(reader: &mut JournalReader<R>) -> io::Result<Self> {
+        let interval = reader.header().index_interval.max(1) as u64;
+        let (mut blocks, mut span) = (Vec::new(), None);
+        while let Some(record) = reader.next_record()? {
+            let Record::Frame { offset, ts, frame } = record else {
+                continue;
+            };
+            let header = FrameHeader::decode(frame).ok();
+            let size = (RECORD_HEADER_SIZE + frame.len()) as u64;
+            if Span::observe(&mut span, offset, size, header.as_ref(), ts).bytes >= interval {
+                blocks.extend(span.take().map(|span| span.into_block(None)));
+            }
+        }
+        blocks.extend(span.map(|span| span.into_block(None)));
+        Ok(Self { blocks })
+    }
+
+    /// Index blocks in file order
+    #[inline]
+    pub fn blocks(&self) -> &[IndexBlock] {
+        &self.blocks
+    }
+
+    /// Offset to scan from for the first frame on `channel` with a seq of at
+    /// least `seq`
+    ///
+    /// Only blocks of channel 0 frames narrow the seq range (see
+    /// [`IndexBlock::min_seq`]); any other block may hold the match.
+    pub fn locate_seq(&self, channel: u16, seq: u32) -> Option<u64> {
+        self.blocks
+            .iter()
+            .find(|block| {
+                let whole = block.min_seq == 0 && block.max_seq == u32::MAX;
+                let wrapped = block.min_seq > block.max_seq;
+                whole || wrapped || (channel == 0 && block.max_seq >= seq)
+            })
+            .map(|block| block.start)
+    }
+
+    /// Fail if channel 0 seqs go backwards within a block or from one block
+    /// to the next
+    fn check_seq_order(&self) -> io::Result<()> {
+        let mut prev: Option<&IndexBlock> = None;
+        for block in &self.blocks {
+            if block.min_seq == 0 && block.max_seq == u32::MAX {
+                continue;
+            }
+            let back = block.min_seq > block.max_seq
+                || prev.is_some_and(|prev| block.min_seq < prev.max_seq);
+            if back {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "journal block at offset {}: channel 0 seqs go backwards",
+                        block.start
+                    ),
+                ));
+            }
+            prev = Some(block);
+        }
+        Ok(())
+    }
+
+    /// Offset to scan from for the first frame received at or after `ts`
+    pub fn locate_time(&self, ts: Timestamp) -> Option<u64> {
+        self.blocks
+            .iter()
+            .find(|block| block.last_ts >= ts)
+            .map(|block| block.start)
+    }
+}
+
+/// Follow the index chain back from the end of the file
+///
+/// Returns `None` if the file does not end with an index record.
+fn read_index_chain<R: Read + Seek>(src: &mut R) -> io::Result<Option<Vec<IndexBlock>>> {
+    const RECORD: usize = RECORD_HEADER_SIZE + INDEX_BLOCK_SIZE;
+    let len = src.seek(SeekFrom::End(0))?;
+    if len < (FILE_HEADER_SIZE + RECORD) as u64 {
+        return Ok(None);
+    }
+
+    let mut blocks: Vec<IndexBlock> = Vec::new();
+    let mut offset = len - RECORD as u64;
+    loop {
+        let mut record = [0u8; RECORD];
+        src.seek(SeekFrom::Start(offset))?;
+        src.read_exact(&mut record)?;
+        let (head, payload) = record.split_at(RECORD_HEADER_SIZE);
+        let decoded = match decode_record_header(head.try_into().unwrap()) {
+            Ok((record_kinds::INDEX, ..)) => IndexBlock::decode(payload),
+            Ok(_) => Err(Error::DecodeInvariant),
+            Err(err) => Err(err),
+        };
+        let block = match decoded {
+            Ok(block) => block,
+            Err(_) if blocks.is_empty() => return Ok(None),
+            Err(err) => return Err(invalid_data(offset, err)),
+        };
+        blocks.push(block);
+        match block.prev {
+            None => break,
+            Some(prev) if prev >= FILE_HEADER_SIZE as u64 && prev + RECORD as u64 <= offset => {
+                offset = prev;
+            }
+            Some(_) => return Err(invalid_data(offset, Error::DecodeInvariant)),
+        }
+    }
+    blocks.reverse();
+    Ok(Some(blocks))
+}
+
+/// Journal file held in memory, read whole or memory-mapped
+///
+/// Records and frames borrow from the buffer, so iterating is zero-copy.
+#[derive(Debug, Clone, Copy)]
+pub struct JournalView<'a> {
+    buf: &'a [u8],
+    header: FileHeader,
+}
+
+impl<'a> JournalView<'a> {
+    /// Check the file header of `buf`
+    pub fn new(buf: &'a [u8]) -> io::Result<Self> {
+        let header = FileHeader::decode(buf).map_err(|e| invalid_data(0, e))?;
+        Ok(Self { buf, header })
+    }
+
+    /// The file header
+    #[inline]
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    /// The whole file
+    #[inline]
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.buf
+    }
+
+    /// Load the file's sparse index (see [`SparseIndex::load`])
+    pub fn index(&self) -> io::Result<SparseIndex> {
+        SparseIndex::load(&mut io::Cursor::new(self.buf))
+    }
+
+    /// Iterate over all records
+    pub fn records(&self) -> Records<'a> {
+        self.records_at(FILE_HEADER_SIZE as u64)
+    }
+
+    /// Iterate over records from `offset`, which must be the start of one
+    pub fn records_at(&self, offset: u64) -> Records<'a> {
+        Records {
+            buf: self.buf,
+            pos: usize::try_from(offset).map_or(self.buf.len(), |pos| pos.min(self.buf.len())),
+            torn: None,
+            failed: false,
+        }
+    }
+
+    /// Iterate over all frames
+    pub fn frames(&self) -> Frames<'a> {
+        Frames {
+            records: self.records(),
+            start: None,
+        }
+    }
+
+    /// Iterate over frames from the first on `channel` with a seq of at
+    /// least `seq`
+    ///
+    /// Fails like [`JournalReader::seek_seq`] if seqs on the channel wrap,
+    /// whether the index shows it or the frames scanned to the match do.
+    pub fn seek_seq(&self, index: &SparseIndex, channel: u16, seq: u32) -> io::Result<Frames<'a>> {
+        let start = StartAt::seq(channel, seq);
+        Ok(Frames {
+            records: self.records_at(start.locate(index)?.unwrap_or(u64::MAX)),
+            start: Some(start),
+        })
+    }
+
+    /// Iterate over frames from the first received at or after `ts`
+    pub fn seek_time(&self, index: &SparseIndex, ts: Timestamp) -> Frames<'a> {
+        Frames {
+            records: self.records_at(index.locate_time(ts).unwrap_or(u64::MAX)),
+            start: Some(StartAt::Time(ts)),
+        }
+    }
+
+    /// Split the frames into up to `n` iterators of whole index blocks
+    ///
+    /// The shards cover the file in order without overlap and can be read
+    /// on separate threads.
+    pub fn shards(&self, index: &SparseIndex, n: usize) -> Vec<Frames<'a>> {
+        let blocks = index.blocks();
+        let per_shard = blocks.len().div_ceil(n.max(1)).max(1);
+        let starts: Vec<usize> = blocks
+            .chunks(per_shard)
+            .map(|chunk| chunk[0].start as usize)
+            .collect();
+        starts
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| {
+                let end = starts.get(i + 1).map_or(self.buf.len(), |&end| end);
+                Frames {
+                    records: Records {
+                        buf: &self.buf[..end.min(self.buf.len())],
+                        pos: start.min(end),
+                        torn: None,
+                        failed: false,
+                    },
+                    start: None,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Decode the record at the start of `rest`
+///
+/// On failure, also returns how many bytes of `rest` the bad record spans;
+/// it is a torn tail if only zeros follow.
+fn parse_record(rest: &[u8], offset: u64) -> core::result::Result<Record<'_>, (Error, usize)> {
+    let head = rest
+        .get(..RECORD_HEADER_SIZE)
+        .ok_or((Error::UnexpectedEof, rest.len()))?;
+    let (kind, len, ts) =
+        decode_record_header(head.try_into().unwrap()).map_err(|e| (e, RECORD_HEADER_SIZE))?;
+    let end = RECORD_HEADER_SIZE + len;
+    let payload = rest
+        .get(RECORD_HEADER_SIZE..end)
+        .ok_or((Error::UnexpectedEof, rest.len()))?;
+    match kind {
+        record_kinds::FRAME => check_frame(payload).map(|_| Record::Frame {
+            offset,
+            ts,
+            frame: payload,
+        }),
+        _ => IndexBlock::decode(payload).map(|block| Record::Index { offset, block }),
+    }
+    .map_err(|e| (e, end))
+}
+
+/// Iterator over the records of a [`JournalView`]
+///
+/// Ends at a torn tail (see [`torn_tail`](Self::torn_tail)) and after the
+/// first corruption error.
+#[derive(Debug, Clone)]
+pub struct Records<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    torn: Option<u64>,
+    failed: bool,
+}
+
+impl Records<'_> {
+    /// File offset of the next record
+    #[inline]
+    pub fn offset(&self) -> u64 {
+        self.pos as u64
+    }
+
+    /// Offset where an incomplete final write starts, once iteration has
+    /// reached it
+    #[inline]
+    pub fn torn_tail(&self) -> Option<u64> {
+        self.torn
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = io::Result<Record<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.buf[self.pos..];
+        if rest.is_empty() || self.failed || self.torn.is_some() {
+            return None;
+        }
+        let offset = self.pos as u64;
+        match parse_record(rest, offset) {
+            Ok(record) => {
+                self.pos += record.size();
+                Some(Ok(record))
+            }
+            Err((_, seen)) if rest[seen..].iter().all(|&b| b == 0) => {
+                self.torn = Some(offset);
+                None
+            }
+            Err((err, _)) => {
+                self.failed = true;
+                Some(Err(invalid_data(offset, err)))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum StartAt {
+    /// The first frame on `channel` with a seq of at least `seq`; `last` is
+    /// the seq of the latest frame on the channel scanned past
+    Seq {
+        channel: u16,
+        seq: u32,
+        last: Option<u32>,
+    },
+    /// The first frame received at or after the time
+    Time(Timestamp),
+}
+
+impl StartAt {
+    fn seq(channel: u16, seq: u32) -> Self {
+        StartAt::Seq {
+            channel,
+            seq,
+            last: None,
+        }
+    }
+
+    /// Offset of the index block to scan from
+    ///
+    /// Fails for a seq on channel 0 if the index shows its seqs wrapping.
+    fn locate(&self, index: &SparseIndex) -> io::Result<Option<u64>> {
+        match *self {
+            StartAt::Seq { channel, seq, .. } => {
+                if channel == 0 {
+                    index.check_seq_order()?;
+                }
+                Ok(index.locate_seq(channel, seq))
+            }
+            StartAt::Time(ts) => Ok(index.locate_time(ts)),
+        }
+    }
+
+    /// Whether the frame record at `offset` is the first to read
+    ///
+    /// Fails if a seq on the channel goes backwards before the match.
+    fn matches(&mut self, offset: u64, ts: Timestamp, frame: &[u8]) -> io::Result<bool> {
+        let (channel, seq, last) = match self {
+            StartAt::Time(start) => return Ok(ts >= *start),
+            StartAt::Seq { channel, seq, last } => (*channel, *seq, last),
+        };
+        let Some(header) = FrameHeader::decode(frame)
+            .ok()
+            .filter(|h| h.channel == channel)
+        else {
+            return Ok(false);
+        };
+        if let Some(prev) = last.replace(header.seq).filter(|&prev| header.seq < prev) {
+            let at = format!("journal record at offset {}", offset);
+            return Err(seq_went_back(at, channel, prev, header.seq));
+        }
+        Ok(header.seq >= seq)
+    }
+}
+
+/// A frame from a [`JournalView`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JournalFrame<'a> {
+    /// File offset of the record header
+    pub offset: u64,
+    /// Receive timestamp
+    pub ts: Timestamp,
+    /// Verbatim frame bytes
+    pub frame: &'a [u8],
+}
+
+impl<'a> JournalFrame<'a> {
+    /// Decoder over the frame
+    #[inline]
+    pub fn decoder(&self) -> FrameDecoder<'a> {
+        FrameDecoder::new(self.frame)
+    }
+}
+
+/// Iterator over the frames of a [`JournalView`], skipping index blocks
+#[derive(Debug, Clone)]
+pub struct Frames<'a> {
+    records: Records<'a>,
+    /// Frames before the first match are skipped after a seek
+    start: Option<StartAt>,
+}
+
+impl Frames<'_> {
+    /// Offset where an incomplete final write starts, once iteration has
+    /// reached it
+    #[inline]
+    pub fn torn_tail(&self) -> Option<u64> {
+        self.records.torn_tail()
+    }
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = io::Result<JournalFrame<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (offset, ts, frame) = match self.records.next()? {
+                Err(err) => return Some(Err(err)),
+                Ok(Record::Index { .. }) => continue,
+                Ok(Record::Frame { offset, ts, frame }) => (offset, ts, frame),
+            };
+            let matched = match &mut self.start {
+                None => Ok(true),
+                Some(start) => start.matches(offset, ts, frame),
+            };
+            match matched {
+                Ok(false) => {}
+                Ok(true) => {
+                    self.start = None;
+                    return Some(Ok(JournalFrame { offset, ts, frame }));
+                }
+                Err(err) => {
+                    self.records.failed = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{channel_frame, trade_frame};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("minibit-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn read_all(path: &Path) -> (Vec<(u64, u32)>, Vec<IndexBlock>, Option<u64>) {
+        let mut reader = JournalReader::open(path).unwrap();
+        let (mut frames, mut indexes) = (Vec::new(), Vec::new());
+        while let Some(record) = reader.next_record().unwrap() {
+            match record {
+                Record::Frame { ts, frame, .. } => {
+                    frames.push((ts.as_nanos(), FrameHeader::decode(frame).unwrap().seq))
+                }
+                Record::Index { block, .. } => indexes.push(block),
+            }
+        }
+        (frames, indexes, reader.torn_tail())
+    }
+
+    #[test]
+    fn test_write_read_with_index_blocks() {
+        let dir = scratch_dir("journal-index");
+        let frame_record = (RECORD_HEADER_SIZE + trade_frame(0).len()) as u32;
+        let mut writer = JournalWriter::create(&dir)
+            .unwrap()
+            .with_index_interval(3 * frame_record);
+        for seq in 0..7 {
+            writer
+                .append(&trade_frame(seq), Timestamp::from_nanos(100 + seq as u64))
+                .unwrap();
+        }
+        let path = writer.current_path().unwrap().to_path_buf();
+        writer.close().unwrap();
+
+        let (frames, indexes, torn) = read_all(&path);
+        assert_eq!(
+            frames,
+            (0..7).map(|s| (100 + s as u64, s)).collect::<Vec<_>>()
+        );
+        assert_eq!(torn, None);
+        assert_eq!(
+            indexes
+                .iter()
+                .map(|b| (b.frames, b.min_seq, b.max_seq))
+                .collect::<Vec<_>>(),
+            [(3, 0, 2), (3, 3, 5), (1, 6, 6)]
+        );
+        assert_eq!(indexes[0].prev, None);
+        assert_eq!(indexes[0].start, FILE_HEADER_SIZE as u64);
+        assert!(indexes[2].prev.is_some());
+        assert_eq!(indexes[2].first_ts, Timestamp::from_nanos(106));
+        assert_eq!(
+            JournalReader::open(&path).unwrap().header().opened,
+            Timestamp::from_nanos(100)
+        );
+
+        // Rejects anything that is not exactly one frame
+        let mut writer = JournalWriter::create(&dir).unwrap();
+        assert!(writer
+            .append(&trade_frame(1)[..20], Timestamp::EPOCH)
+            .is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotation_by_size_and_age() {
+        let dir = scratch_dir("journal-rotate");
+        let frame_record = (RECORD_HEADER_SIZE + trade_frame(0).len()) as u64;
+        let mut writer = JournalWriter::create(&dir)
+            .unwrap()
+            .with_max_file_size(
+                FILE_HEADER_SIZE as u64 + 2 * frame_record + INDEX_BLOCK_SIZE as u64,
+            )
+            .with_max_file_age(Duration::from_secs(60));
+        let secs = |s: u64| Timestamp::from_secs(s);
+        for (seq, ts) in [(1, secs(0)), (2, secs(1)), (3, secs(2)), (4, secs(100))] {
+            writer.append(&trade_frame(seq), ts).unwrap();
+        }
+        writer.close().unwrap();
+
+        // Size splits after two frames, age after the third
+        let files = journal_files(&dir).unwrap();
+        let seqs: Vec<Vec<u32>> = files
+            .iter()
+            .map(|path| read_all(path).0.into_iter().map(|(_, seq)| seq).collect())
+            .collect();
+        assert_eq!(seqs, [vec![1, 2], vec![3], vec![4]]);
+
+        // A new writer continues numbering instead of overwriting
+        let mut writer = JournalWriter::create(&dir).unwrap();
+        writer.append(&trade_frame(5), secs(200)).unwrap();
+        assert_eq!(
+            writer.current_path().unwrap().file_name().unwrap(),
+            "journal-000003.mbj"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_tail_and_corruption() {
+        let dir = scratch_dir("journal-torn");
+        let mut writer = JournalWriter::create(&dir).unwrap();
+        for seq in 0..3 {
+            writer
+                .append(&trade_frame(seq), Timestamp::from_nanos(seq as u64))
+                .unwrap();
+        }
+        let path = writer.current_path().unwrap().to_path_buf();
+        writer.flush().unwrap();
+        drop(writer); // crash: no final index block
+        let data = fs::read(&path).unwrap();
+        let third = (FILE_HEADER_SIZE + 2 * (RECORD_HEADER_SIZE + trade_frame(0).len())) as u64;
+
+        let read = |bytes: &[u8]| {
+            let mut reader = JournalReader::new(bytes).unwrap();
+            let mut seqs = Vec::new();
+            while let Some((_, _, frame)) = reader.next_frame()? {
+                seqs.push(FrameHeader::decode(frame).unwrap().seq);
+            }
+            Ok::<_, io::Error>((seqs, reader.torn_tail()))
+        };
+        assert_eq!(read(&data).unwrap(), (vec![0, 1, 2], None));
+
+        // Every possible cut inside the last record is a torn tail
+        for cut in third as usize + 1..data.len() {
+            assert_eq!(
+                read(&data[..cut]).unwrap(),
+                (vec![0, 1], Some(third)),
+                "cut {}",
+                cut
+            );
+        }
+
+        // Zero-filled space after a crash is a torn tail too
+        let mut zeroed = data[..third as usize].to_vec();
+        zeroed.resize(data.len() + 100, 0);
+        assert_eq!(read(&zeroed).unwrap(), (vec![0, 1], Some(third)));
+
+        // Damage followed by more data is corruption
+        let mut corrupt = data.clone();
+        corrupt[FILE_HEADER_SIZE] = 0x7f;
+        assert_eq!(
+            read(&corrupt).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert!(JournalReader::new(&b"MINIBITX"[..]).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Journal `count` frames with seq `n` received at `1000 + n` ns
+    fn write_journal(dir: &Path, count: u32, close: bool) -> PathBuf {
+        let frame_record = (RECORD_HEADER_SIZE + trade_frame(0).len()) as u32;
+        let mut writer = JournalWriter::create(dir)
+            .unwrap()
+            .with_index_interval(4 * frame_record);
+        for seq in 0..count {
+            writer
+                .append(&trade_frame(seq), Timestamp::from_nanos(1000 + seq as u64))
+                .unwrap();
+        }
+        let path = writer.current_path().unwrap().to_path_buf();
+        if close {
+            writer.close().unwrap();
+        } else {
+            writer.flush().unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn test_seek_with_index_chain() {
+        let dir = scratch_dir("journal-seek");
+        let path = write_journal(&dir, 30, true);
+
+        let mut reader = JournalReader::open(&path).unwrap();
+        let index = reader.index().unwrap().clone();
+        assert_eq!(index.blocks().len(), 8);
+        assert_eq!(index.blocks()[7].frames, 2);
+
+        let seqs: Vec<(u64, u32)> = reader
+            .seek_seq(0, 13)
+            .unwrap()
+            .map(|frame| {
+                let frame = frame.unwrap();
+                (frame.ts.as_nanos(), frame.decoder().header().unwrap().seq)
+            })
+            .collect();
+        assert_eq!(
+            seqs,
+            (13..30)
+                .map(|seq| (1000 + seq as u64, seq))
+                .collect::<Vec<_>>()
+        );
+
+        // An unused iterator leaves the reader at the match
+        reader.seek_time(Timestamp::from_nanos(1027)).unwrap();
+        let (_, _, frame) = reader.next_frame().unwrap().unwrap();
+        assert_eq!(FrameHeader::decode(frame).unwrap().seq, 27);
+        assert!(reader.seek_seq(0, 30).unwrap().next().is_none());
+        assert!(reader.next_frame().unwrap().is_none());
+
+        // The in-memory view yields decoders from the same positions
+        let data = fs::read(&path).unwrap();
+        let view = JournalView::new(&data).unwrap();
+        assert_eq!(view.index().unwrap(), index);
+        let seqs: Vec<u32> = view
+            .seek_seq(&index, 0, 21)
+            .unwrap()
+            .map(|frame| frame.unwrap().decoder().header().unwrap().seq)
+            .collect();
+        assert_eq!(seqs, (21..30).collect::<Vec<_>>());
+        assert_eq!(
+            view.seek_time(&index, Timestamp::from_nanos(999)).count(),
+            30
+        );
+        assert_eq!(
+            view.seek_time(&index, Timestamp::from_nanos(2000)).count(),
+            0
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_journal_seek_across_files() {
+        let dir = scratch_dir("journal-files");
+        let frame_record = (RECORD_HEADER_SIZE + trade_frame(0).len()) as u64;
+        // Four frames per file, seq n received at 100n ns
+        let mut writer = JournalWriter::create(&dir).unwrap().with_max_file_size(
+            FILE_HEADER_SIZE as u64 + 4 * frame_record + INDEX_BLOCK_SIZE as u64,
+        );
+        for seq in 10..30 {
+            writer
+                .append(&trade_frame(seq), Timestamp::from_nanos(100 * seq as u64))
+                .unwrap();
+        }
+        writer.close().unwrap();
+
+        let journal = Journal::open(&dir).unwrap();
+        assert_eq!(journal.files().len(), 5);
+        let seqs = |frames: JournalFrames| -> Vec<u32> {
+            frames
+                .map(|frame| frame.unwrap().decoder().header().unwrap().seq)
+                .collect()
+        };
+        let all: Vec<u32> = (10..30).collect();
+        assert_eq!(seqs(journal.frames()), all);
+        assert_eq!(seqs(journal.seek_seq(0, 0).unwrap()), all);
+        assert_eq!(
+            seqs(journal.seek_time(Timestamp::from_nanos(0)).unwrap()),
+            all
+        );
+
+        // First, last and middle seqs of files, and times between frames
+        for start in [13, 14, 17, 29] {
+            let expected: Vec<u32> = (start..30).collect();
+            assert_eq!(seqs(journal.seek_seq(0, start).unwrap()), expected);
+            let ts = Timestamp::from_nanos(100 * start as u64 - 50);
+            assert_eq!(seqs(journal.seek_time(ts).unwrap()), expected);
+        }
+        assert!(journal.seek_seq(0, 30).unwrap().next().is_none());
+        let late = Timestamp::from_nanos(3000);
+        assert!(journal.seek_time(late).unwrap().next().is_none());
+
+        // Only the file holding the seq and those after it are read
+        let frames = journal.seek_seq(0, 21).unwrap();
+        assert_eq!(frames.files.len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_index_rebuilt_without_final_block() {
+        let dir = scratch_dir("journal-rebuild");
+        let closed = write_journal(&dir.join("closed"), 10, true);
+        let crashed = write_journal(&dir.join("crashed"), 10, false);
+
+        // Rebuilding matches the written blocks, except for the links
+        let written = JournalReader::open(&closed)
+            .unwrap()
+            .index()
+            .unwrap()
+            .clone();
+        let rebuilt = SparseIndex::rebuild(&mut JournalReader::open(&closed).unwrap()).unwrap();
+        let unlinked: Vec<_> = written
+            .blocks()
+            .iter()
+            .map(|block| IndexBlock {
+                prev: None,
+                ..*block
+            })
+            .collect();
+        assert_eq!(rebuilt.blocks(), unlinked);
+
+        // Frames after the last written block are still reachable
+        let mut reader = JournalReader::open(&crashed).unwrap();
+        assert_eq!(reader.index().unwrap().blocks().len(), 3);
+        let mut frames = reader.seek_seq(0, 9).unwrap();
+        let frame = frames.next().unwrap().unwrap();
+        assert_eq!(frame.decoder().header().unwrap().seq, 9);
+        assert!(frames.next().is_none());
+
+        // Torn tail in the view
+        let data = fs::read(&crashed).unwrap();
+        let torn = &data[..data.len() - 5];
+        let mut frames = JournalView::new(torn).unwrap().frames();
+        assert_eq!(frames.by_ref().count(), 9);
+        assert!(frames.torn_tail().is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Journal `frames` with four frame records per index block, or per file
+    /// if `rotate` is set
+    fn write_frames(dir: &Path, frames: &[Vec<u8>], rotate: bool) -> PathBuf {
+        let frame_record = (RECORD_HEADER_SIZE + trade_frame(0).len()) as u32;
+        let mut writer = JournalWriter::create(dir)
+            .unwrap()
+            .with_index_interval(4 * frame_record);
+        if rotate {
+            writer = writer.with_max_file_size(
+                FILE_HEADER_SIZE as u64 + 4 * frame_record as u64 + INDEX_BLOCK_SIZE as u64,
+            );
+        }
+        for (n, frame) in frames.iter().enumerate() {
+            writer
+                .append(frame, Timestamp::from_nanos(1000 + n as u64))
+                .unwrap();
+        }
+        let path = writer.current_path().unwrap().to_path_buf();
+        writer.close().unwrap();
+        path
+    }
+
+    fn channel_seq(frame: &[u8]) -> (u16, u32) {
+        let header = FrameHeader::decode(frame).unwrap();
+        (header.channel, header.seq)
+    }
+
+    fn channel_seqs<R: Read>(frames: ReaderFrames<'_, R>) -> Vec<(u16, u32)> {
+        frames
+            .map(|frame| channel_seq(&frame.unwrap().frame))
+            .collect()
+    }
+
+    #[test]
+    fn test_seek_seq_per_channel() {
+        let dir = scratch_dir("journal-channels");
+        // Channel 1 counts from 100 and channel 2 from 0, interleaved
+        let frames: Vec<Vec<u8>> = (0..12)
+            .flat_map(|n| [channel_frame(1, 100 + n), channel_frame(2, n)])
+            .collect();
+        let path = write_frames(&dir, &frames, false);
+
+        let mut reader = JournalReader::open(&path).unwrap();
+        let index = reader.index().unwrap().clone();
+        assert_eq!(index.blocks().len(), 6);
+        // Mixed channels leave the seq range open
+        assert!(index
+            .blocks()
+            .iter()
+            .all(|block| (block.min_seq, block.max_seq) == (0, u32::MAX)));
+
+        let firsts = |reader: &mut JournalReader<BufReader<File>>, channel, seq| {
+            let found: Vec<(u16, u32)> = reader
+                .seek_seq(channel, seq)
+                .unwrap()
+                .map(|frame| channel_seq(&frame.unwrap().frame))
+                .collect();
+            (found.first().copied(), found.len())
+        };
+        assert_eq!(firsts(&mut reader, 2, 5), (Some((2, 5)), 13));
+        assert_eq!(firsts(&mut reader, 1, 5), (Some((1, 100)), 24));
+        assert_eq!(firsts(&mut reader, 1, 111), (Some((1, 111)), 2));
+        assert_eq!(firsts(&mut reader, 2, 12), (None, 0));
+        assert_eq!(firsts(&mut reader, 0, 0), (None, 0));
+
+        let data = fs::read(&path).unwrap();
+        let view = JournalView::new(&data).unwrap();
+        let mut frames = view.seek_seq(&index, 2, 5).unwrap();
+        assert_eq!(channel_seq(frames.next().unwrap().unwrap().frame), (2, 5));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_seek_seq_rejects_wrap() {
+        let dir = scratch_dir("journal-wrap");
+        let wrapping: Vec<Vec<u8>> = (u32::MAX - 5..=u32::MAX)
+            .chain(0..6)
+            .map(trade_frame)
+            .collect();
+
+        // A wrap inside an index block is marked by an inverted range
+        let path = write_frames(&dir.join("block"), &wrapping, false);
+        let mut reader = JournalReader::open(&path).unwrap();
+        let index = reader.index().unwrap().clone();
+        let ranges: Vec<(u32, u32)> = index
+            .blocks()
+            .iter()
+            .map(|block| (block.min_seq, block.max_seq))
+            .collect();
+        assert_eq!(
+            ranges,
+            [(u32::MAX - 5, u32::MAX - 2), (u32::MAX, 0), (2, 5)]
+        );
+        let err = reader.seek_seq(0, 3).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let data = fs::read(&path).unwrap();
+        let view = JournalView::new(&data).unwrap();
+        assert!(view.seek_seq(&index, 0, 3).is_err());
+        // Seeking by time is unaffected
+        reader.seek_time(Timestamp::from_nanos(1009)).unwrap();
+        let (_, _, frame) = reader.next_frame().unwrap().unwrap();
+        assert_eq!(channel_seq(frame), (0, 3));
+
+        // A wrap from one block to the next
+        let blocks: Vec<Vec<u8>> = wrapping[2..10].to_vec();
+        let path = write_frames(&dir.join("blocks"), &blocks, false);
+        let mut reader = JournalReader::open(&path).unwrap();
+        assert!(reader.seek_seq(0, 1).is_err());
+
+        // A wrap from one file to the next
+        let files: Vec<Vec<u8>> = wrapping[2..10].to_vec();
+        write_frames(&dir.join("files"), &files, true);
+        let journal = Journal::open(dir.join("files")).unwrap();
+        assert_eq!(journal.files().len(), 2);
+        assert!(journal.seek_seq(0, 1).is_err());
+
+        // On other channels a wrap met while scanning to the match fails,
+        // and ends the iteration
+        let frames: Vec<Vec<u8>> = [10, 11, 12, 0, 1, 2]
+            .into_iter()
+            .map(|seq| channel_frame(2, seq))
+            .collect();
+        let path = write_frames(&dir.join("scan"), &frames, false);
+        let mut reader = JournalReader::open(&path).unwrap();
+        assert!(reader.seek_seq(2, 20).is_err());
+        assert_eq!(
+            channel_seqs(reader.seek_seq(2, 11).unwrap()),
+            [(2, 11), (2, 12), (2, 0), (2, 1), (2, 2)]
+        );
+        let data = fs::read(&path).unwrap();
+        let view = JournalView::new(&data).unwrap();
+        let index = view.index().unwrap();
+        let mut frames = view.seek_seq(&index, 2, 20).unwrap();
+        assert!(frames.next().unwrap().is_err());
+        assert!(frames.next().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,960 @@
+//! JSON transcoding of frames for inspection and hand-built test input
+//!
+//! [`to_json`] renders a frame as one line of JSON:
+//!
+//! ```text
+//! {"header":{"ver":1,"flags":1,"msg_type":1,"seq":9,"len":30},"type":"Trade",
+//!  "body":{"ts_ns":1700000000000000000,"price":-1,"qty":100,"symbol":"AAPL"},
+//!  "crc32c":"0x1b2c3d4e"}
+//! ```
+//!
+//! Body fields are rendered by schema type: integers as numbers, decimals as
+//! numbers with their schema scale, timestamps as RFC 3339 strings, bytes as
+//! a string when valid UTF-8 and as `{"hex":"..."}` otherwise, and groups as
+//! nested objects. `channel` appears for version 2 headers only.
+//!
+//! [`from_json`] builds the frame back. `len` and `crc32c` are recomputed, so
+//! edited JSON does not need them; `flags` defaults to whatever the schema's
+//! optional encoding needs for the fields given. A frame produced by the
+//! built-in encoders round-trips byte for byte; unknown optional fields
+//! (bits or tags the schema does not describe) are dropped by `to_json`.
+//!
+//! Frames no schema can render (unknown types, delta, compressed or
+//! encrypted bodies) go through [`raw_to_json`] instead, which keeps the
+//! header for reading and the whole frame as hex:
+//!
+//! ```text
+//! {"header":{"ver":1,"flags":8,"msg_type":3,"seq":4,"len":9},"raw":"edfe0108..."}
+//! ```
+//!
+//! `from_json` writes a `raw` frame back verbatim, ignoring the header
+//! object and the registry.
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::str::FromStr;
+
+use crate::bitmap::PresenceBitmap;
+use crate::decimal::Decimal;
+use crate::decoder::FrameDecoder;
+use crate::dynamic::{DynamicMessage, Value};
+use crate::encoder::FrameEncoder;
+use crate::error::{Error, Result};
+use crate::frame::{FrameFlags, FrameHeader, HEADER_V2};
+use crate::schema::{FieldDef, FieldType, MessageSchema, OptionalEncoding, SchemaRegistry};
+use crate::tagged::TaggedValue;
+use crate::timestamp::Timestamp;
+use crate::varint;
+use crate::PROTOCOL_VERSION;
+
+/// Flags whose bodies cannot be read through a schema
+const OPAQUE_FLAGS: u8 = FrameFlags::COMPRESSED | FrameFlags::ENCRYPTED | FrameFlags::DELTA;
+
+/// Render a frame using the latest schema registered for its message type
+///
+/// Returns `Error::UnsupportedMsgType` if the registry has no schema for it.
+pub fn to_json(registry: &SchemaRegistry, buf: &[u8]) -> Result<String> {
+    let msg_type = FrameDecoder::new(buf).header()?.msg_type;
+    let schema = registry.latest(msg_type).ok_or(Error::UnsupportedMsgType)?;
+    message_to_json(schema, buf)
+}
+
+/// Render a frame of `schema.msg_type` using `schema`
+///
+/// Compressed, encrypted and delta frames return `Error::FlagConflict`.
+pub fn message_to_json(schema: &MessageSchema, buf: &[u8]) -> Result<String> {
+    let decoder = FrameDecoder::new(buf);
+    let header = decoder.header()?;
+    if header.has_flag(OPAQUE_FLAGS) {
+        return Err(Error::FlagConflict);
+    }
+
+    let (header, message) = DynamicMessage::decode(schema, buf)?;
+    let crc = u32::from_le_bytes(
+        buf[header.total_size() - 4..header.total_size()]
+            .try_into()
+            .map_err(|_| Error::UnexpectedEof)?,
+    );
+
+    let mut out = String::new();
+    write_json(&mut out, &header, schema, &message, crc).map_err(|_| Error::Overflow)?;
+    Ok(out)
+}
+
+/// Build a frame from JSON using the latest schema for its `msg_type`
+///
+/// Returns the frame size written to `out`.
+pub fn from_json(registry: &SchemaRegistry, json: &str, out: &mut [u8]) -> Result<usize> {
+    let root = Parser::parse(json)?;
+    if let Some(raw) = root.get("raw") {
+        return raw_from_json(raw, out);
+    }
+    let header = header_from_json(&root)?;
+    let schema = registry
+        .latest(header.msg_type)
+        .ok_or(Error::UnsupportedMsgType)?;
+    encode_frame(schema, header, &root, out)
+}
+
+/// Build a frame of `schema.msg_type` from JSON
+pub fn message_from_json(schema: &MessageSchema, json: &str, out: &mut [u8]) -> Result<usize> {
+    let root = Parser::parse(json)?;
+    if let Some(raw) = root.get("raw") {
+        let len = raw_from_json(raw, out)?;
+        if FrameHeader::decode(out)?.msg_type != schema.msg_type {
+            return Err(Error::UnsupportedMsgType);
+        }
+        return Ok(len);
+    }
+    let header = header_from_json(&root)?;
+    if header.msg_type != schema.msg_type {
+        return Err(Error::UnsupportedMsgType);
+    }
+    encode_frame(schema, header, &root, out)
+}
+
+/// Render a frame as its header plus the whole frame in hex
+///
+/// Works for any frame with a readable header, whatever its type, flags or
+/// CRC, so a dump can keep frames that [`to_json`] cannot render.
+pub fn raw_to_json(buf: &[u8]) -> Result<String> {
+    let header = FrameHeader::decode(buf)?;
+    let frame = buf.get(..header.total_size()).ok_or(Error::UnexpectedEof)?;
+
+    let mut out = String::new();
+    write_raw(&mut out, &header, frame).map_err(|_| Error::Overflow)?;
+    Ok(out)
+}
+
+// ---------------------------------------------------------------------------
+// Rendering
+// ---------------------------------------------------------------------------
+
+fn write_json(
+    out: &mut String,
+    header: &FrameHeader,
+    schema: &MessageSchema,
+    message: &DynamicMessage<'_, '_>,
+    crc: u32,
+) -> core::fmt::Result {
+    write_header(out, header)?;
+    out.push_str(",\"type\":");
+    write_str(out, &schema.name)?;
+    out.push_str(",\"body\":");
+    write_message(out, message)?;
+    write!(out, ",\"crc32c\":\"0x{:08x}\"}}", crc)
+}
+
+fn write_header(out: &mut String, header: &FrameHeader) -> core::fmt::Result {
+    write!(
+        out,
+        "{{\"header\":{{\"ver\":{},\"flags\":{},\"msg_type\":{},\"seq\":{},\"len\":{}",
+        header.ver, header.flags, header.msg_type, header.seq, header.len
+    )?;
+    if header.ver >= HEADER_V2 {
+        write!(out, ",\"channel\":{}", header.channel)?;
+    }
+    out.push('}');
+    Ok(())
+}
+
+fn write_raw(out: &mut String, header: &FrameHeader, frame: &[u8]) -> core::fmt::Result {
+    write_header(out, header)?;
+    out.push_str(",\"raw\":\"");
+    frame.iter().try_for_each(|b| write!(out, "{:02x}", b))?;
+    out.push_str("\"}");
+    Ok(())
+}
+
+fn write_message(out: &mut String, message: &DynamicMessage<'_, '_>) -> core::fmt::Result {
+    out.push('{');
+    for (i, (name, value)) in message.fields().iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_str(out, name)?;
+        out.push(':');
+        match value {
+            Value::U64(v) => write!(out, "{}", v)?,
+            Value::I64(v) => write!(out, "{}", v)?,
+            Value::Decimal(d) => write!(out, "{}", d)?,
+            Value::Timestamp(ts) => write!(out, "\"{}\"", ts)?,
+            Value::Bytes(bytes) => match core::str::from_utf8(bytes) {
+                Ok(s) => write_str(out, s)?,
+                Err(_) => {
+                    out.push_str("{\"hex\":\"");
+                    bytes.iter().try_for_each(|b| write!(out, "{:02x}", b))?;
+                    out.push_str("\"}");
+                }
+            },
+            Value::Group(group) => write_message(out, group)?,
+        }
+    }
+    out.push('}');
+    Ok(())
+}
+
+/// Write a quoted, escaped JSON string
+fn write_str(out: &mut String, s: &str) -> core::fmt::Result {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Encoding
+// ---------------------------------------------------------------------------
+
+fn header_from_json(root: &Json) -> Result<FrameHeader> {
+    let header = root.get("header").ok_or(Error::InvalidJson)?;
+    let field = |name: &str| header.get(name).map(Json::as_u64).transpose();
+
+    let msg_type = field("msg_type")?.ok_or(Error::InvalidJson)?;
+    let mut frame_header =
+        FrameHeader::new(narrow(msg_type)?, narrow(field("seq")?.unwrap_or(0))?, 0)
+            .with_version(narrow(field("ver")?.unwrap_or(PROTOCOL_VERSION as u64))?);
+    frame_header.channel = narrow(field("channel")?.unwrap_or(0))?;
+    if let Some(flags) = field("flags")? {
+        frame_header.flags = narrow(flags)?;
+    }
+    Ok(frame_header)
+}
+
+/// Copy a `raw` hex frame to `out`, checking it holds exactly one frame
+fn raw_from_json(raw: &Json, out: &mut [u8]) -> Result<usize> {
+    let Json::String(hex) = raw else {
+        return Err(Error::InvalidJson);
+    };
+    let frame = decode_hex(hex)?;
+    if FrameHeader::decode(&frame)?.total_size() != frame.len() {
+        return Err(Error::InvalidJson);
+    }
+    out.get_mut(..frame.len())
+        .ok_or(Error::ShortBuffer)?
+        .copy_from_slice(&frame);
+    Ok(frame.len())
+}
+
+fn encode_frame(
+    schema: &MessageSchema,
+    mut header: FrameHeader,
+    root: &Json,
+    out: &mut [u8],
+) -> Result<usize> {
+    let body = root.get("body").ok_or(Error::InvalidJson)?.as_object()?;
+
+    let explicit_flags = root.get("header").and_then(|h| h.get("flags")).is_some();
+    if !explicit_flags && schema.has_optional() {
+        let any_optional = schema
+            .optional_fields()
+            .iter()
+            .any(|(_, field)| find(body, &field.name).is_some());
+        if any_optional {
+            header.set_flag(match schema.optional_encoding {
+                OptionalEncoding::Bitmap(_) => FrameFlags::PRESENCE_BITMAP,
+                OptionalEncoding::Tagged => FrameFlags::TAGGED,
+            });
+        }
+    }
+    if header.has_flag(OPAQUE_FLAGS) {
+        return Err(Error::FlagConflict);
+    }
+
+    let mode = if header.has_flag(FrameFlags::TAGGED) {
+        if header.has_flag(FrameFlags::PRESENCE_BITMAP) {
+            return Err(Error::FlagConflict);
+        }
+        Mode::Tagged
+    } else if header.has_flag(FrameFlags::PRESENCE_BITMAP) {
+        if schema.optional_encoding == OptionalEncoding::Tagged {
+            return Err(Error::FlagConflict);
+        }
+        Mode::Bitmap
+    } else {
+        Mode::None
+    };
+
+    let mut encoder = FrameEncoder::new(out);
+    encoder.begin(&header)?;
+    put_message(&mut encoder, schema, body, mode)?;
+    encoder.finish_crc32c()
+}
+
+/// How a body's optional fields are written
+#[derive(Clone, Copy)]
+enum Mode {
+    None,
+    Bitmap,
+    Tagged,
+}
+
+fn put_message(
+    encoder: &mut FrameEncoder<'_>,
+    schema: &MessageSchema,
+    body: &[(String, Json)],
+    mode: Mode,
+) -> Result<()> {
+    if body.iter().any(|(name, _)| schema.field(name).is_none()) {
+        return Err(Error::InvalidJson);
+    }
+
+    for field in schema.required_fields() {
+        let value = find(body, &field.name).ok_or(Error::InvalidJson)?;
+        put_value(encoder, field, value)?;
+    }
+
+    let optional = schema.optional_fields();
+    match mode {
+        Mode::None => {
+            if optional.iter().any(|(_, f)| find(body, &f.name).is_some()) {
+                return Err(Error::FlagConflict);
+            }
+        }
+        Mode::Bitmap => {
+            let OptionalEncoding::Bitmap(size) = schema.optional_encoding else {
+                return Err(Error::FlagConflict);
+            };
+            let mut bitmap = PresenceBitmap::new(size);
+            for &(index, field) in &optional {
+                if find(body, &field.name).is_some() {
+                    bitmap.set(index as usize)?;
+                }
+            }
+            encoder.put_presence_bitmap(&bitmap)?;
+            for &(_, field) in &optional {
+                if let Some(value) = find(body, &field.name) {
+                    put_value(encoder, field, value)?;
+                }
+            }
+        }
+        Mode::Tagged => {
+            // JSON order is wire order, so re-encoding keeps the original layout
+            for (name, value) in body {
+                if let Some(&(index, field)) = optional.iter().find(|(_, f)| f.name == *name) {
+                    put_tagged(encoder, index, field, value)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn group_mode(layout: &MessageSchema) -> Mode {
+    match layout.optional_encoding {
+        _ if !layout.has_optional() => Mode::None,
+        OptionalEncoding::Bitmap(_) => Mode::Bitmap,
+        OptionalEncoding::Tagged => Mode::Tagged,
+    }
+}
+
+fn put_value(encoder: &mut FrameEncoder<'_>, field: &FieldDef, value: &Json) -> Result<()> {
+    match field.ty {
+        FieldType::U8 => encoder.put_u8(narrow(value.as_u64()?)?),
+        FieldType::U16 => encoder.put_u16(narrow(value.as_u64()?)?),
+        FieldType::U32 => encoder.put_u32(narrow(value.as_u64()?)?),
+        FieldType::U64 => encoder.put_u64(value.as_u64()?),
+        FieldType::I32 => encoder.put_i32(narrow(value.as_i64()?)?),
+        FieldType::I64 => encoder.put_i64(value.as_i64()?),
+        FieldType::VarU64 => encoder.put_varint_u64(value.as_u64()?),
+        FieldType::VarI64 => encoder.put_varint_i64(value.as_i64()?),
+        FieldType::Timestamp => encoder.put_timestamp(value.as_timestamp()?),
+        FieldType::Bytes => encoder.put_varbytes(&value.as_bytes()?),
+        FieldType::Decimal(scale) => encoder.put_i64(value.as_decimal(scale)?),
+        FieldType::Group => {
+            let layout = field.group.as_deref().ok_or(Error::InvalidSchema)?;
+            let marker = encoder.begin_group()?;
+            put_message(encoder, layout, value.as_object()?, group_mode(layout))?;
+            encoder.end_group(marker)
+        }
+    }
+}
+
+fn put_tagged(
+    encoder: &mut FrameEncoder<'_>,
+    index: u8,
+    field: &FieldDef,
+    value: &Json,
+) -> Result<()> {
+    let index = index as u32;
+    let tagged = match field.ty {
+        FieldType::U8 => TaggedValue::Fixed8(narrow(value.as_u64()?)?),
+        FieldType::U16 => TaggedValue::Fixed16(narrow(value.as_u64()?)?),
+        FieldType::U32 => TaggedValue::Fixed32(narrow(value.as_u64()?)?),
+        FieldType::U64 => TaggedValue::Fixed64(value.as_u64()?),
+        FieldType::I32 => TaggedValue::Fixed32(narrow::<i32, _>(value.as_i64()?)? as u32),
+        FieldType::I64 => TaggedValue::Fixed64(value.as_i64()? as u64),
+        FieldType::VarU64 => TaggedValue::Varint(value.as_u64()?),
+        FieldType::VarI64 => TaggedValue::Varint(varint::zigzag_encode_i64(value.as_i64()?)),
+        FieldType::Timestamp => TaggedValue::Fixed64(value.as_timestamp()?.as_nanos()),
+        FieldType::Decimal(scale) => TaggedValue::Fixed64(value.as_decimal(scale)? as u64),
+        FieldType::Bytes => {
+            return encoder.put_tagged(index, TaggedValue::Bytes(&value.as_bytes()?))
+        }
+        FieldType::Group => {
+            // The sub-body is written as a bytes value, so encode it first
+            let layout = field.group.as_deref().ok_or(Error::InvalidSchema)?;
+            let mut scratch = vec![0u8; encoder.remaining()];
+            let mut group = FrameEncoder::new(&mut scratch);
+            put_message(&mut group, layout, value.as_object()?, group_mode(layout))?;
+            return encoder.put_tagged(index, TaggedValue::Bytes(group.as_slice()));
+        }
+    };
+    encoder.put_tagged(index, tagged)
+}
+
+#[inline]
+fn narrow<T: TryFrom<U>, U>(value: U) -> Result<T> {
+    T::try_from(value).map_err(|_| Error::Overflow)
+}
+
+#[inline]
+fn find<'j>(object: &'j [(String, Json)], name: &str) -> Option<&'j Json> {
+    object.iter().find(|(key, _)| key == name).map(|(_, v)| v)
+}
+
+// ---------------------------------------------------------------------------
+// Parsing
+// ---------------------------------------------------------------------------
+
+/// Parsed JSON value; numbers keep their text so that integers and decimals
+/// convert without going through floating point
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => find(fields, name),
+            _ => None,
+        }
+    }
+
+    fn as_object(&self) -> Result<&[(String, Json)]> {
+        match self {
+            Json::Object(fields) => Ok(fields),
+            _ => Err(Error::InvalidJson),
+        }
+    }
+
+    fn as_u64(&self) -> Result<u64> {
+        match self {
+            Json::Number(n) if !n.starts_with('-') => n.parse().map_err(|_| Error::Overflow),
+            _ => Err(Error::InvalidJson),
+        }
+    }
+
+    fn as_i64(&self) -> Result<i64> {
+        match self {
+            Json::Number(n) => n.parse().map_err(|_| Error::Overflow),
+            _ => Err(Error::InvalidJson),
+        }
+    }
+
+    /// Decimal mantissa at `scale`
+    fn as_decimal(&self, scale: u8) -> Result<i64> {
+        match self {
+            Json::Number(n) => Ok(Decimal::from_str(n)?.rescale(scale)?.mantissa()),
+            _ => Err(Error::InvalidJson),
+        }
+    }
+
+    /// RFC 3339 string or integer nanoseconds
+    fn as_timestamp(&self) -> Result<Timestamp> {
+        match self {
+            Json::String(s) => s.parse(),
+            Json::Number(_) => self.as_u64().map(Timestamp::from_nanos),
+            _ => Err(Error::InvalidJson),
+        }
+    }
+
+    /// UTF-8 string or `{"hex": "..."}`
+    fn as_bytes(&self) -> Result<Vec<u8>> {
+        match self {
+            Json::String(s) => Ok(s.as_bytes().to_vec()),
+            Json::Object(fields) if fields.len() == 1 => {
+                let Some(Json::String(hex)) = find(fields, "hex") else {
+                    return Err(Error::InvalidJson);
+                };
+                decode_hex(hex)
+            }
+            _ => Err(Error::InvalidJson),
+        }
+    }
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    let digits = hex.as_bytes();
+    if !digits.len().is_multiple_of(2) {
+        return Err(Error::InvalidJson);
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let s = core::str::from_utf8(pair).map_err(|_| Error::InvalidJson)?;
+            u8::from_str_radix(s, 16).map_err(|_| Error::InvalidJson)
+        })
+        .collect()
+}
+
+/// Nesting limit, so hostile input cannot exhaust the stack
+const MAX_DEPTH: usize = 64;
+
+/// Recursive-descent JSON parser
+struct Parser<'j> {
+    input: &'j [u8],
+    pos: usize,
+}
+
+impl<'j> Parser<'j> {
+    fn parse(json: &'j str) -> Result<Json> {
+        let mut parser = Parser {
+            input: json.as_bytes(),
+            pos: 0,
+        };
+        let value = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos != parser.input.len() {
+            return Err(Error::InvalidJson);
+        }
+        Ok(value)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.input.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<()> {
+        if self.peek() != Some(byte) {
+            return Err(Error::InvalidJson);
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, text: &str, value: Json) -> Result<Json> {
+        if !self.input[self.pos..].starts_with(text.as_bytes()) {
+            return Err(Error::InvalidJson);
+        }
+        self.pos += text.len();
+        Ok(value)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json> {
+        if depth > MAX_DEPTH {
+            return Err(Error::InvalidJson);
+        }
+        match self.peek().ok_or(Error::InvalidJson)? {
+            b'{' => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    if self.peek() != Some(b'"') {
+                        return Err(Error::InvalidJson);
+                    }
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    let value = self.value(depth + 1)?;
+                    if find(&fields, &key).is_some() {
+                        return Err(Error::InvalidJson);
+                    }
+                    fields.push((key, value));
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(fields));
+                        }
+                        _ => return Err(Error::InvalidJson),
+                    }
+                }
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => return Err(Error::InvalidJson),
+                    }
+                }
+            }
+            b'"' => self.string().map(Json::String),
+            b't' => self.literal("true", Json::Bool(true)),
+            b'f' => self.literal("false", Json::Bool(false)),
+            b'n' => self.literal("null", Json::Null),
+            b'-' | b'0'..=b'9' => self.number(),
+            _ => Err(Error::InvalidJson),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json> {
+        let start = self.pos;
+        if self.input[self.pos] == b'-' {
+            self.pos += 1;
+        }
+        let digits = |parser: &mut Self| {
+            let from = parser.pos;
+            while parser.input.get(parser.pos).is_some_and(u8::is_ascii_digit) {
+                parser.pos += 1;
+            }
+            parser.pos > from
+        };
+        if !digits(self) {
+            return Err(Error::InvalidJson);
+        }
+        if self.input.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            if !digits(self) {
+                return Err(Error::InvalidJson);
+            }
+        }
+        if let Some(b'e' | b'E') = self.input.get(self.pos) {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.input.get(self.pos) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return Err(Error::InvalidJson);
+            }
+        }
+        let text =
+            core::str::from_utf8(&self.input[start..self.pos]).map_err(|_| Error::InvalidJson)?;
+        Ok(Json::Number(text.to_string()))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.pos += 1; // opening quote
+        let mut out = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(&b) = self.input.get(self.pos) {
+                if b == b'"' || b == b'\\' || b < 0x20 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            // Input came from a &str and we only split at ASCII bytes
+            out.push_str(
+                core::str::from_utf8(&self.input[start..self.pos])
+                    .map_err(|_| Error::InvalidJson)?,
+            );
+
+            match self.input.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escape = *self.input.get(self.pos).ok_or(Error::InvalidJson)?;
+                    self.pos += 1;
+                    out.push(match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(Error::InvalidJson),
+                    });
+                }
+                _ => return Err(Error::InvalidJson),
+            }
+        }
+    }
+
+    /// `\uXXXX`, combining surrogate pairs
+    fn unicode_escape(&mut self) -> Result<char> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.input[self.pos..].starts_with(b"\\u") {
+                return Err(Error::InvalidJson);
+            }
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(Error::InvalidJson);
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or(Error::InvalidJson)
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self
+            .input
+            .get(self.pos..self.pos + 4)
+            .ok_or(Error::InvalidJson)?;
+        let s = core::str::from_utf8(digits).map_err(|_| Error::InvalidJson)?;
+        let value = u32::from_str_radix(s, 16).map_err(|_| Error::InvalidJson)?;
+        self.pos += 4;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{msg_types, quote, quote_delta, trade};
+    use crate::schema;
+    use alloc::format;
+
+    const TS_NS: u64 = 1_700_000_000_123_456_789;
+
+    fn assert_roundtrip(registry: &SchemaRegistry, frame: &[u8]) -> String {
+        let json = to_json(registry, frame).unwrap();
+        let mut out = [0u8; 512];
+        let len = from_json(registry, &json, &mut out).unwrap();
+        assert_eq!(&out[..len], frame, "{}", json);
+        json
+    }
+
+    #[test]
+    fn test_builtin_roundtrip() {
+        let registry = SchemaRegistry::with_builtin();
+        let mut buf = [0u8; 256];
+
+        let len = trade::encode(&mut buf, 9, TS_NS, -1, 100, Some(b"AAPL"), None).unwrap();
+        let json = assert_roundtrip(&registry, &buf[..len]);
+        assert!(json
+            .starts_with("{\"header\":{\"ver\":1,\"flags\":1,\"msg_type\":1,\"seq\":9,\"len\":"));
+        assert!(json.contains(
+            "\"type\":\"Trade\",\"body\":{\"ts_ns\":1700000000123456789,\"price\":-1,\"qty\":100,\"symbol\":\"AAPL\"}"
+        ));
+
+        let len = trade::encode(&mut buf, 1, TS_NS, 5, 1, None, Some(&[0xff, 0x00, b'"'])).unwrap();
+        let json = assert_roundtrip(&registry, &buf[..len]);
+        assert!(json.contains("\"note\":{\"hex\":\"ff0022\"}"));
+
+        let len =
+            trade::encode_tagged(&mut buf, 2, TS_NS, 1, 2, Some(b"a\"b\n"), Some(b"n")).unwrap();
+        assert_roundtrip(&registry, &buf[..len]);
+
+        let len = quote::encode(&mut buf, 3, TS_NS, 10, 11, 2, None).unwrap();
+        assert_roundtrip(&registry, &buf[..len]);
+
+        // Delta quote keyframes are schema-described; deltas are not
+        let mut encoder = quote_delta::DeltaEncoder::new(0);
+        let quote = quote_delta::Quote {
+            instrument: 300,
+            ts_ns: TS_NS,
+            bid: 10,
+            ask: 11,
+            level: 1,
+            symbol: Some(b"EUR/USD"),
+        };
+        let len = encoder.encode(&mut buf, 4, &quote).unwrap();
+        let json = assert_roundtrip(&registry, &buf[..len]);
+        assert!(json.contains("\"type\":\"QuoteDelta\",\"body\":{\"instrument\":300,"));
+        let len = encoder.encode(&mut buf, 5, &quote).unwrap();
+        assert_eq!(to_json(&registry, &buf[..len]), Err(Error::FlagConflict));
+    }
+
+    #[test]
+    fn test_schema_described_roundtrip() {
+        let leg = MessageSchema::group()
+            .required("px", FieldType::Decimal(4))
+            .optional("venue", FieldType::Bytes, 0);
+        let fill = MessageSchema::new(90, "Fill", 0)
+            .with_optional_encoding(OptionalEncoding::Tagged)
+            .required("ts", FieldType::Timestamp)
+            .required("delta", FieldType::VarI64)
+            .required_group("leg", leg.clone())
+            .optional("fee", FieldType::I32, 4)
+            .optional_group("hedge", leg, 7);
+        let mut registry = SchemaRegistry::new();
+        registry.register(fill.clone()).unwrap();
+
+        let json = concat!(
+            "{\"header\":{\"ver\":2,\"msg_type\":90,\"seq\":7,\"channel\":3},",
+            "\"body\":{\"ts\":\"2023-11-14T22:13:20.123456789Z\",\"delta\":-42,",
+            "\"leg\":{\"px\":101.5,\"venue\":\"XLON\"},",
+            "\"hedge\":{\"px\":-0.0001},\"fee\":-7}}"
+        );
+        let mut buf = [0u8; 256];
+        let len = from_json(&registry, json, &mut buf).unwrap();
+
+        let (header, message) = DynamicMessage::decode(&fill, &buf[..len]).unwrap();
+        assert!(header.has_flag(FrameFlags::TAGGED));
+        assert_eq!(header.channel, 3);
+        assert_eq!(
+            message.get("ts"),
+            Some(&Value::Timestamp(Timestamp::from_nanos(TS_NS)))
+        );
+        let Some(Value::Group(leg)) = message.get("leg") else {
+            panic!("leg missing");
+        };
+        assert_eq!(
+            leg.get("px"),
+            Some(&Value::Decimal(Decimal::new(1_015_000, 4).unwrap()))
+        );
+
+        let rendered = assert_roundtrip(&registry, &buf[..len]);
+        assert!(rendered.contains("\"leg\":{\"px\":101.5000,\"venue\":\"XLON\"}"));
+        assert!(rendered.contains("\"channel\":3"));
+        // Tagged fields keep their JSON order on the wire
+        assert!(rendered.find("\"hedge\"").unwrap() < rendered.find("\"fee\"").unwrap());
+
+        let announcement_len = schema::encode_schema(&mut buf, 0, &fill).unwrap();
+        assert_eq!(
+            to_json(&registry, &buf[..announcement_len]),
+            Err(Error::UnsupportedMsgType)
+        );
+    }
+
+    #[test]
+    fn test_raw_roundtrip() {
+        let registry = SchemaRegistry::with_builtin();
+        let mut buf = [0u8; 128];
+        let mut encoder = quote_delta::DeltaEncoder::new(0);
+        let quote = quote_delta::Quote {
+            instrument: 7,
+            ts_ns: TS_NS,
+            bid: 10,
+            ask: 11,
+            level: 1,
+            symbol: None,
+        };
+        encoder.encode(&mut buf, 0, &quote).unwrap();
+        let len = encoder.encode(&mut buf, 1, &quote).unwrap();
+        let frame = buf[..len].to_vec();
+
+        assert!(to_json(&registry, &frame).is_err());
+        let json = raw_to_json(&frame).unwrap();
+        assert!(json.starts_with("{\"header\":{\"ver\":1,\"flags\":8,\"msg_type\":3,\"seq\":1,"));
+        assert!(json.contains(",\"raw\":\"edfe0108"));
+
+        let len = from_json(&registry, &json, &mut buf).unwrap();
+        assert_eq!(&buf[..len], &frame[..]);
+        let trade = registry.latest(msg_types::TRADE_V1).unwrap();
+        assert_eq!(
+            message_from_json(trade, &json, &mut buf),
+            Err(Error::UnsupportedMsgType)
+        );
+
+        // A raw frame must hold exactly one whole frame
+        let hex: String = frame[..len - 1]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let truncated = format!("{{\"raw\":\"{}\"}}", hex);
+        assert_eq!(
+            from_json(&registry, &truncated, &mut buf),
+            Err(Error::InvalidJson)
+        );
+        assert_eq!(
+            from_json(&registry, "{\"raw\":1}", &mut buf),
+            Err(Error::InvalidJson)
+        );
+        assert_eq!(
+            from_json(&registry, &json, &mut buf[..len - 1]),
+            Err(Error::ShortBuffer)
+        );
+    }
+
+    #[test]
+    fn test_rejects_bad_input() {
+        let registry = SchemaRegistry::with_builtin();
+        let mut buf = [0u8; 128];
+        let trade = |body: &str| {
+            let mut json = String::from("{\"header\":{\"msg_type\":1},\"body\":");
+            json.push_str(body);
+            json.push('}');
+            json
+        };
+
+        for (json, err) in [
+            (String::from("{\"header\":"), Error::InvalidJson),
+            (trade("{\"ts_ns\":1,\"price\":2}"), Error::InvalidJson),
+            (
+                trade("{\"ts_ns\":1,\"price\":2,\"qty\":3,\"extra\":0}"),
+                Error::InvalidJson,
+            ),
+            (
+                trade("{\"ts_ns\":-1,\"price\":2,\"qty\":3}"),
+                Error::InvalidJson,
+            ),
+            (
+                trade("{\"ts_ns\":1,\"price\":2,\"qty\":4294967296}"),
+                Error::Overflow,
+            ),
+            (
+                trade("{\"ts_ns\":1,\"price\":2,\"qty\":3,\"note\":{\"hex\":\"f\"}}"),
+                Error::InvalidJson,
+            ),
+            (trade("{\"ts_ns\":1,\"ts_ns\":1}"), Error::InvalidJson),
+            (
+                String::from("{\"header\":{\"msg_type\":77},\"body\":{}}"),
+                Error::UnsupportedMsgType,
+            ),
+        ] {
+            assert_eq!(from_json(&registry, &json, &mut buf), Err(err), "{}", json);
+        }
+
+        // Optional field without the flag that would carry it
+        let json = "{\"header\":{\"msg_type\":1,\"flags\":0},\"body\":{\"ts_ns\":1,\"price\":2,\"qty\":3,\"note\":\"x\"}}";
+        assert_eq!(
+            from_json(&registry, json, &mut buf),
+            Err(Error::FlagConflict)
+        );
+
+        let parsed = Parser::parse(" [1.5e3, true, null, \"\\u00e9\\ud83d\\ude00\"] ").unwrap();
+        assert_eq!(
+            parsed,
+            Json::Array(vec![
+                Json::Number(String::from("1.5e3")),
+                Json::Bool(true),
+                Json::Null,
+                Json::String(String::from("é😀")),
+            ])
+        );
+        for bad in ["01x", "[1,]", "{\"a\" 1}", "\"\\ud83d\"", "-", "1."] {
+            assert_eq!(Parser::parse(bad), Err(Error::InvalidJson), "{}", bad);
+        }
+    }
+}
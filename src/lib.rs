@@ -7,8 +7,8 @@
 //!
 //! ```text
 //! +------------+---------+---------+-----------+-----------+---------+
-//! | Magic u16  | Ver u8  | Flags u8| MsgType u16| Seq u32  | Len u32 |
-//! +------------+---------+---------+-----------+-----------+---------+
+//! | Magic u16  | Ver u8  | Flags u8| MsgType u16| Seq u32  | Len u32 | Channel u16 (v2) |
+//! +------------+---------+---------+-----------+-----------+---------+------------------+
 //! | [HeaderExt? varint+bytes]                                        |
 //! | Body (Len bytes)                                                  |
 //! | CRC32C u32 (Castagnoli)                                           |
@@ -58,30 +58,70 @@ extern crate std;
 
 extern crate alloc;
 
+// Lets test fixtures shared with the binary name the crate the same way
+#[cfg(test)]
+extern crate self as minibit;
+
+pub mod arbiter;
+pub mod array;
 pub mod bitmap;
 pub mod crc32c;
+pub mod decimal;
 pub mod decoder;
+pub mod dynamic;
 pub mod encoder;
 pub mod error;
 pub mod frame;
+#[cfg(feature = "std")]
+pub mod journal;
+pub mod json;
 pub mod messages;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod pcap;
+#[cfg(feature = "std")]
+pub mod replay;
+pub mod schema;
+pub mod sequence;
+#[cfg(feature = "serde")]
+pub mod serde;
+#[cfg(feature = "mmap")]
+pub mod shm;
+pub mod tagged;
+#[cfg(feature = "std")]
+pub mod tcp;
+#[cfg(feature = "async")]
+pub mod tcp_async;
+pub mod timestamp;
+#[cfg(feature = "std")]
+pub mod udp;
 pub mod varint;
+pub mod version;
 
 #[cfg(all(feature = "std", test))]
 pub mod bench;
+#[cfg(all(feature = "std", test))]
+mod test_util;
 
 // Re-export main types
 pub use decoder::{BodyCursor, FrameDecoder};
 pub use encoder::FrameEncoder;
 pub use error::Error;
 pub use frame::{FrameFlags, FrameHeader};
+pub use timestamp::Timestamp;
 
 /// Magic number for frame identification
 pub const FRAME_MAGIC: u16 = 0xFEED;
 
-/// Current protocol version
+/// Current protocol version (used by default when encoding headers)
 pub const PROTOCOL_VERSION: u8 = 1;
 
+/// Oldest protocol version this crate can decode
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+
+/// Newest protocol version this crate can encode and decode
+pub const MAX_PROTOCOL_VERSION: u8 = 2;
+
 /// Minimum frame size (header + crc32c)
 pub const MIN_FRAME_SIZE: usize = 18; // 16 bytes header + 4 bytes crc32c
 
@@ -1,3 +1,895 @@
-fn main() {
-    println!("Hello, world!");
+//! `minibit` command-line tool for inspecting and building frame files
+//!
+//! A frame file is a plain concatenation of frames, as written by any of the
+//! encoders. Schema announcement frames in the input are learned as they are
+//! read, so later frames of announced types are decoded too.
+//!
+//! ```text
+//! minibit dump [--json] [--schemas FILE] [FILE]
+//! minibit validate [--schemas FILE] [FILE]
+//! minibit stats [FILE]
+//! minibit encode [--schemas FILE] [-o OUT] [FILE]
+//! minibit pcap [--port N] [--json] [--schemas FILE] [FILE]
+//! ```
+//!
+//! `FILE` defaults to stdin (also `-`). `encode` reads one JSON frame
+//! description per line (the format printed by `dump --json`) and writes
+//! frames to `OUT` or stdout; frames `dump --json` cannot decode are printed
+//! with their raw bytes in hex and written back unchanged. `pcap` reads a
+//! pcap or pcapng capture and dumps the frames carried over UDP or TCP,
+//! labelled with capture time and endpoints.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::process::ExitCode;
+
+use minibit::dynamic::DynamicDecoder;
+use minibit::messages::msg_types;
+use minibit::pcap::{Capture, Event, FrameExtractor};
+use minibit::schema::{self, SchemaRegistry};
+use minibit::sequence::{SeqStatus, SeqTracker};
+use minibit::{
+    json, Error, FrameDecoder, FrameFlags, FrameHeader, Timestamp, FRAME_MAGIC, MAX_FRAME_SIZE,
+};
+
+const USAGE: &str = "\
+usage: minibit <command> [options] [FILE]
+
+commands:
+  dump      print each frame's header, flags, CRC status and decoded fields
+  validate  report malformed frames with their byte offsets
+  stats     frame counts per message type, size histogram and sequence gaps
+  encode    build frames from JSON lines (as printed by `dump --json`)
+  pcap      dump frames carried in a pcap/pcapng capture
+
+options:
+  --json            dump, pcap: print one JSON object per frame
+  --port N          pcap: only traffic to or from UDP/TCP port N
+  --schemas FILE    load schema announcement frames from FILE first
+  -o, --output OUT  encode: write frames to OUT instead of stdout
+  -h, --help        show this help
+
+FILE defaults to stdin.";
+
+/// Gaps listed individually by `stats` before summarising the rest
+const MAX_LISTED_GAPS: usize = 20;
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(msg) => {
+            eprintln!("minibit: {}\n\n{}", msg, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match args.run() {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("minibit: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Dump,
+    Validate,
+    Stats,
+    Encode,
+    Pcap,
+}
+
+#[derive(Debug)]
+struct Args {
+    command: Command,
+    input: Option<String>,
+    output: Option<String>,
+    schemas: Option<String>,
+    port: Option<u16>,
+    json: bool,
+}
+
+impl Args {
+    /// Parse the command line; `Ok(None)` means help was requested
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let command = match args.next().as_deref() {
+            None | Some("-h" | "--help" | "help") => return Ok(None),
+            Some("dump") => Command::Dump,
+            Some("validate") => Command::Validate,
+            Some("stats") => Command::Stats,
+            Some("encode") => Command::Encode,
+            Some("pcap") => Command::Pcap,
+            Some(other) => return Err(format!("unknown command `{}`", other)),
+        };
+
+        let mut parsed = Args {
+            command,
+            input: None,
+            output: None,
+            schemas: None,
+            port: None,
+            json: false,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--json" if matches!(command, Command::Dump | Command::Pcap) => parsed.json = true,
+                "--schemas" if command != Command::Stats => {
+                    parsed.schemas = Some(args.next().ok_or("--schemas needs a file")?);
+                }
+                "--port" if command == Command::Pcap => {
+                    let port = args.next().ok_or("--port needs a number")?;
+                    let port = port
+                        .parse()
+                        .map_err(|_| format!("invalid port `{}`", port))?;
+                    parsed.port = Some(port);
+                }
+                "-o" | "--output" if command == Command::Encode => {
+                    parsed.output = Some(args.next().ok_or("--output needs a file")?);
+                }
+                opt if opt.starts_with('-') && opt != "-" => {
+                    return Err(format!("unexpected option `{}`", opt));
+                }
+                _ if parsed.input.is_some() => return Err("more than one input file".into()),
+                _ => parsed.input = Some(arg),
+            }
+        }
+        Ok(Some(parsed))
+    }
+
+    fn run(&self) -> io::Result<bool> {
+        let mut decoder = DynamicDecoder::with_registry(SchemaRegistry::with_builtin());
+        if let Some(path) = &self.schemas {
+            for item in Frames::new(&fs::read(path)?) {
+                if let Item::Frame { frame, .. } = item {
+                    decoder.learn(frame).map_err(|e| invalid_data(path, e))?;
+                }
+            }
+        }
+
+        let stdout = io::stdout();
+        let mut out = BufWriter::new(stdout.lock());
+        let ok = match self.command {
+            Command::Dump => dump(&read_input(&self.input)?, &mut decoder, self.json, &mut out)?,
+            Command::Validate => validate(&read_input(&self.input)?, &mut decoder, &mut out)?,
+            Command::Stats => stats(&read_input(&self.input)?, &mut decoder, &mut out)?,
+            Command::Pcap => {
+                let data = read_input(&self.input)?;
+                let capture = Capture::new(&data).map_err(|e| invalid_data("capture", e))?;
+                let extractor = match self.port {
+                    Some(port) => FrameExtractor::new(port),
+                    None => FrameExtractor::all_ports(),
+                };
+                pcap(&capture, extractor, &mut decoder, self.json, &mut out)?
+            }
+            Command::Encode => {
+                let input: Box<dyn BufRead> = match self.input.as_deref() {
+                    None | Some("-") => Box::new(BufReader::new(io::stdin())),
+                    Some(path) => Box::new(BufReader::new(fs::File::open(path)?)),
+                };
+                match &self.output {
+                    Some(path) => {
+                        let mut file = BufWriter::new(fs::File::create(path)?);
+                        let ok = encode(input, decoder.registry(), &mut file)?;
+                        file.flush()?;
+                        ok
+                    }
+                    None => encode(input, decoder.registry(), &mut out)?,
+                }
+            }
+        };
+        out.flush()?;
+        Ok(ok)
+    }
+}
+
+fn read_input(path: &Option<String>) -> io::Result<Vec<u8>> {
+    match path.as_deref() {
+        None | Some("-") => {
+            let mut data = Vec::new();
+            io::stdin().lock().read_to_end(&mut data)?;
+            Ok(data)
+        }
+        Some(path) => fs::read(path),
+    }
+}
+
+fn invalid_data(context: &str, err: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", context, err))
+}
+
+// ---------------------------------------------------------------------------
+// Frame scanning
+// ---------------------------------------------------------------------------
+
+/// One entry in a frame file
+#[derive(Debug, PartialEq)]
+enum Item<'a> {
+    /// A frame whose header decoded; `crc` is the checksum verdict
+    Frame {
+        offset: usize,
+        header: FrameHeader,
+        frame: &'a [u8],
+        crc: Result<(), Error>,
+    },
+    /// Bytes that do not form a frame; scanning resumes after `skipped`
+    Malformed {
+        offset: usize,
+        error: Error,
+        skipped: usize,
+    },
+}
+
+/// Iterator over the frames of a file, resynchronising on the magic number
+/// after corrupt bytes
+struct Frames<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Frames<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Whether a frame header decodes at `pos`, or the input ends there
+    /// (possibly after a torn header)
+    fn boundary_at(&self, pos: usize) -> bool {
+        let rest = &self.buf[pos..];
+        match FrameHeader::decode(rest) {
+            Ok(_) => true,
+            Err(Error::UnexpectedEof) => {
+                let magic = FRAME_MAGIC.to_le_bytes();
+                rest.starts_with(&magic[..rest.len().min(magic.len())])
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Offset of the next plausible frame header after `pos`
+    fn resync(&self, pos: usize) -> usize {
+        let magic = FRAME_MAGIC.to_le_bytes();
+        (pos + 1..self.buf.len())
+            .find(|&at| self.buf[at..].starts_with(&magic) && self.boundary_at(at))
+            .unwrap_or(self.buf.len())
+    }
+
+    fn malformed(&mut self, error: Error, next: usize) -> Item<'a> {
+        let item = Item::Malformed {
+            offset: self.pos,
+            error,
+            skipped: next - self.pos,
+        };
+        self.pos = next;
+        item
+    }
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = Item<'a>;
+
+    fn next(&mut self) -> Option<Item<'a>> {
+        if self.pos >= self.buf.len() {
+            return None;
+        }
+
+        let rest = &self.buf[self.pos..];
+        let header = match FrameHeader::decode(rest) {
+            Ok(header) => header,
+            Err(Error::UnexpectedEof) => {
+                return Some(self.malformed(Error::UnexpectedEof, self.buf.len()))
+            }
+            Err(error) => return Some(self.malformed(error, self.resync(self.pos))),
+        };
+
+        let total = header.total_size();
+        if rest.len() < total {
+            // Either a torn final frame or a corrupt length
+            let next = self.resync(self.pos);
+            return Some(self.malformed(Error::UnexpectedEof, next));
+        }
+
+        let frame = &rest[..total];
+        let crc = FrameDecoder::new(frame).verify_crc32c();
+        if crc.is_err() && !self.boundary_at(self.pos + total) {
+            // The length itself is suspect, so do not trust it to skip ahead
+            let next = self.resync(self.pos);
+            return Some(self.malformed(Error::CrcMismatch, next));
+        }
+
+        let item = Item::Frame {
+            offset: self.pos,
+            header,
+            frame,
+            crc,
+        };
+        self.pos += total;
+        Some(item)
+    }
+}
+
+/// `PRESENCE_BITMAP|TAGGED`, or `-` when no flag is set
+fn flag_names(flags: u8) -> String {
+    const NAMES: [(u8, &str); 5] = [
+        (FrameFlags::PRESENCE_BITMAP, "PRESENCE_BITMAP"),
+        (FrameFlags::COMPRESSED, "COMPRESSED"),
+        (FrameFlags::ENCRYPTED, "ENCRYPTED"),
+        (FrameFlags::DELTA, "DELTA"),
+        (FrameFlags::TAGGED, "TAGGED"),
+    ];
+    let names: Vec<&str> = NAMES
+        .iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|&(_, name)| name)
+        .collect();
+    if names.is_empty() {
+        "-".into()
+    } else {
+        names.join("|")
+    }
+}
+
+fn type_name(registry: &SchemaRegistry, msg_type: u16) -> &str {
+    match registry.latest(msg_type) {
+        Some(schema) => &schema.name,
+        None if msg_type == msg_types::SCHEMA => "Schema",
+        None => "?",
+    }
+}
+
+/// Learn from schema announcements, returning a note for the dump
+fn learn(decoder: &mut DynamicDecoder, header: &FrameHeader, frame: &[u8]) -> Option<String> {
+    if header.msg_type != msg_types::SCHEMA {
+        return None;
+    }
+    Some(match (schema::decode_schema(frame), decoder.learn(frame)) {
+        (Ok((_, announced)), Ok(_)) => format!(
+            "announces {} (msg_type {}, v{}, {} fields)",
+            announced.name,
+            announced.msg_type,
+            announced.version,
+            announced.fields.len()
+        ),
+        (Err(err), _) | (_, Err(err)) => format!("bad announcement: {}", err),
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Commands
+// ---------------------------------------------------------------------------
+
+fn dump(
+    data: &[u8],
+    decoder: &mut DynamicDecoder,
+    as_json: bool,
+    out: &mut impl Write,
+) -> io::Result<bool> {
+    let mut ok = true;
+    for item in Frames::new(data) {
+        let (offset, header, frame, crc) = match item {
+            Item::Frame {
+                offset,
+                header,
+                frame,
+                crc,
+            } => (offset, header, frame, crc),
+            Item::Malformed {
+                offset,
+                error,
+                skipped,
+            } => {
+                ok = false;
+                let line = format!(
+                    "@{}: malformed: {} ({} bytes skipped)",
+                    offset, error, skipped
+                );
+                // Keep stdout parseable as JSON lines
+                if as_json {
+                    eprintln!("{}", line);
+                } else {
+                    writeln!(out, "{}", line)?;
+                }
+                continue;
+            }
+        };
+        ok &= crc.is_ok();
+        print_frame(
+            &format!("@{}", offset),
+            &header,
+            frame,
+            crc,
+            decoder,
+            as_json,
+            out,
+        )?;
+    }
+    Ok(ok)
+}
+
+/// Print one frame for `dump`, labelled with `location`
+fn print_frame(
+    location: &str,
+    header: &FrameHeader,
+    frame: &[u8],
+    crc: Result<(), Error>,
+    decoder: &mut DynamicDecoder,
+    as_json: bool,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let note = learn(decoder, header, frame);
+    if as_json {
+        // Frames no schema can render are kept as hex, so `encode` gets
+        // every frame back
+        let line = json::to_json(decoder.registry(), frame)
+            .or_else(|_| json::raw_to_json(frame))
+            .map_err(|err| invalid_data(location, err))?;
+        writeln!(out, "{}", line)?;
+        return Ok(());
+    }
+
+    writeln!(
+        out,
+        "{} {} msg_type={} seq={} ver={} channel={} len={} flags={} crc={}",
+        location,
+        type_name(decoder.registry(), header.msg_type),
+        header.msg_type,
+        header.seq,
+        header.ver,
+        header.channel,
+        header.len,
+        flag_names(header.flags),
+        match crc {
+            Ok(()) => "ok",
+            Err(_) => "MISMATCH",
+        }
+    )?;
+    if let Some(note) = note {
+        writeln!(out, "    {}", note)?;
+    } else if crc.is_ok() && decoder.registry().latest(header.msg_type).is_some() {
+        match decoder.decode(frame) {
+            Ok((_, message)) => {
+                for (name, value) in message.fields() {
+                    writeln!(out, "    {} = {}", name, value)?;
+                }
+            }
+            Err(err) => writeln!(out, "    undecodable body: {}", err)?,
+        }
+    }
+    Ok(())
+}
+
+fn pcap(
+    capture: &Capture<'_>,
+    mut extractor: FrameExtractor,
+    decoder: &mut DynamicDecoder,
+    as_json: bool,
+    out: &mut impl Write,
+) -> io::Result<bool> {
+    let mut ok = true;
+    let mut last_ts = Timestamp::EPOCH;
+    let mut result = Ok(());
+    let mut on_event = |event: Event<'_>| {
+        if result.is_err() {
+            return;
+        }
+        let (location, problem) = match event {
+            Event::Frame {
+                ts,
+                src,
+                dst,
+                frame,
+            } => {
+                let location = format!("{} {} > {}", ts, src, dst);
+                // The extractor only hands over frames whose header decoded
+                let Ok(header) = FrameHeader::decode(frame) else {
+                    return;
+                };
+                let crc = FrameDecoder::new(frame).verify_crc32c();
+                ok &= crc.is_ok();
+                result = print_frame(&location, &header, frame, crc, decoder, as_json, out);
+                return;
+            }
+            Event::Malformed {
+                ts,
+                src,
+                dst,
+                error,
+                skipped,
+            } => (
+                format!("{} {} > {}", ts, src, dst),
+                format!("malformed: {} ({} bytes skipped)", error, skipped),
+            ),
+            Event::Gap {
+                ts,
+                src,
+                dst,
+                missing,
+            } => (
+                format!("{} {} > {}", ts, src, dst),
+                format!("TCP gap: {} bytes never captured", missing),
+            ),
+        };
+        ok = false;
+        let line = format!("{}: {}", location, problem);
+        result = if as_json {
+            eprintln!("{}", line);
+            Ok(())
+        } else {
+            writeln!(out, "{}", line)
+        };
+    };
+
+    for packet in capture.packets() {
+        let packet = packet.map_err(|e| invalid_data("capture", e))?;
+        last_ts = packet.ts;
+        extractor.push(&packet, &mut on_event);
+    }
+    extractor.finish(last_ts, &mut on_event);
+    result.map(|()| ok)
+}
+
+fn validate(data: &[u8], decoder: &mut DynamicDecoder, out: &mut impl Write) -> io::Result<bool> {
+    let (mut frames, mut problems) = (0usize, 0usize);
+    for item in Frames::new(data) {
+        match item {
+            Item::Malformed {
+                offset,
+                error,
+                skipped,
+            } => {
+                problems += 1;
+                writeln!(out, "@{}: {} ({} bytes skipped)", offset, error, skipped)?;
+            }
+            Item::Frame {
+                offset,
+                header,
+                frame,
+                crc,
+            } => {
+                frames += 1;
+                let verdict = crc.and_then(|()| {
+                    if header.msg_type == msg_types::SCHEMA {
+                        return decoder.learn(frame).map(|_| ());
+                    }
+                    let opaque = FrameFlags::COMPRESSED | FrameFlags::ENCRYPTED | FrameFlags::DELTA;
+                    match decoder.registry().latest(header.msg_type) {
+                        Some(_) if !header.has_flag(opaque) => decoder.decode(frame).map(|_| ()),
+                        _ => Ok(()),
+                    }
+                });
+                if let Err(err) = verdict {
+                    problems += 1;
+                    writeln!(
+                        out,
+                        "@{}: msg_type={} seq={}: {}",
+                        offset, header.msg_type, header.seq, err
+                    )?;
+                }
+            }
+        }
+    }
+    writeln!(out, "{} frames, {} problems", frames, problems)?;
+    Ok(problems == 0)
+}
+
+/// Power-of-two bucket (`lo..=hi`) holding a frame of `size` bytes
+fn size_bucket(size: usize) -> (usize, usize) {
+    let lo = if size == 0 { 0 } else { 1 << size.ilog2() };
+    (lo, (lo * 2).max(1) - 1)
+}
+
+fn stats(data: &[u8], decoder: &mut DynamicDecoder, out: &mut impl Write) -> io::Result<bool> {
+    let mut per_type: BTreeMap<u16, (usize, usize)> = BTreeMap::new();
+    let mut sizes: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+    let mut tracker = SeqTracker::new();
+    let mut gaps = Vec::new();
+    let (mut frames, mut bad_crc, mut malformed, mut malformed_bytes) = (0, 0, 0, 0);
+    let (mut missing, mut duplicates) = (0u64, 0usize);
+
+    for item in Frames::new(data) {
+        match item {
+            Item::Malformed { skipped, .. } => {
+                malformed += 1;
+                malformed_bytes += skipped;
+            }
+            Item::Frame {
+                offset,
+                header,
+                frame,
+                crc,
+            } => {
+                frames += 1;
+                if crc.is_err() {
+                    bad_crc += 1;
+                    continue;
+                }
+                learn(decoder, &header, frame);
+
+                let entry = per_type.entry(header.msg_type).or_default();
+                entry.0 += 1;
+                entry.1 += frame.len();
+                *sizes.entry(size_bucket(frame.len())).or_default() += 1;
+
+                match tracker.observe_header(&header) {
+                    status @ SeqStatus::Gap { expected, received } => {
+                        missing += status.missing() as u64;
+                        gaps.push((offset, header.channel, expected, received));
+                    }
+                    SeqStatus::Duplicate { .. } => duplicates += 1,
+                    SeqStatus::First | SeqStatus::InOrder => {}
+                }
+            }
+        }
+    }
+
+    writeln!(
+        out,
+        "{} bytes, {} frames ({} CRC mismatches), {} malformed regions ({} bytes)",
+        data.len(),
+        frames,
+        bad_crc,
+        malformed,
+        malformed_bytes
+    )?;
+
+    writeln!(out, "\nmessage types:")?;
+    for (msg_type, (count, bytes)) in &per_type {
+        writeln!(
+            out,
+            "  {:>6} {:<12} {:>10} frames {:>12} bytes",
+            msg_type,
+            type_name(decoder.registry(), *msg_type),
+            count,
+            bytes
+        )?;
+    }
+
+    writeln!(out, "\nframe sizes:")?;
+    let widest = sizes.values().copied().max().unwrap_or(1);
+    for ((lo, hi), count) in &sizes {
+        let bar = "#".repeat((count * 40).div_ceil(widest));
+        writeln!(out, "  {:>8}..={:<8} {:>10} {}", lo, hi, count, bar)?;
+    }
+
+    writeln!(
+        out,
+        "\nsequence: {} gaps ({} frames missing), {} duplicates",
+        gaps.len(),
+        missing,
+        duplicates
+    )?;
+    for (offset, channel, expected, received) in gaps.iter().take(MAX_LISTED_GAPS) {
+        writeln!(
+            out,
+            "  @{}: channel {} expected {} got {}",
+            offset, channel, expected, received
+        )?;
+    }
+    if gaps.len() > MAX_LISTED_GAPS {
+        writeln!(out, "  ... {} more", gaps.len() - MAX_LISTED_GAPS)?;
+    }
+    Ok(true)
+}
+
+fn encode(
+    input: impl BufRead,
+    registry: &SchemaRegistry,
+    out: &mut impl Write,
+) -> io::Result<bool> {
+    let mut buf = vec![0u8; MAX_FRAME_SIZE];
+    let mut ok = true;
+    for (line_no, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match json::from_json(registry, &line, &mut buf) {
+            Ok(len) => out.write_all(&buf[..len])?,
+            Err(err) => {
+                ok = false;
+                eprintln!("line {}: {}", line_no + 1, err);
+            }
+        }
+    }
+    Ok(ok)
+}
+
+// Shared with the library's tests, which use the rest of it
+#[cfg(test)]
+#[path = "test_util.rs"]
+#[allow(dead_code)]
+mod test_util;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::trade_frame;
+    use minibit::messages::quote_delta;
+
+    #[test]
+    fn test_scan_resyncs_after_corruption() {
+        let good = trade_frame(1);
+        let mut data = good.clone();
+        data.extend_from_slice(b"junk");
+        let second = data.len();
+        data.extend(trade_frame(2));
+        let mut bad = trade_frame(3);
+        bad[20] ^= 0xff; // body corruption: length still trusted
+        let third = data.len();
+        data.extend(&bad);
+        let torn = data.len();
+        data.extend(&good[..10]);
+
+        let items: Vec<_> = Frames::new(&data).collect();
+        assert_eq!(items.len(), 5);
+        assert!(matches!(
+            items[0],
+            Item::Frame {
+                offset: 0,
+                crc: Ok(()),
+                ..
+            }
+        ));
+        assert_eq!(
+            items[1],
+            Item::Malformed {
+                offset: good.len(),
+                error: Error::InvalidMagic,
+                skipped: 4
+            }
+        );
+        assert!(matches!(items[2], Item::Frame { offset, .. } if offset == second));
+        assert!(matches!(
+            items[3],
+            Item::Frame { offset, crc: Err(Error::CrcMismatch), .. } if offset == third
+        ));
+        assert_eq!(
+            items[4],
+            Item::Malformed {
+                offset: torn,
+                error: Error::UnexpectedEof,
+                skipped: 10
+            }
+        );
+    }
+
+    #[test]
+    fn test_corrupt_length_does_not_swallow_frames() {
+        let mut data = trade_frame(1);
+        data[10] = data[10].wrapping_add(3); // len field
+        let second = data.len();
+        data.extend(trade_frame(2));
+
+        let items: Vec<_> = Frames::new(&data).collect();
+        assert!(matches!(
+            items[0],
+            Item::Malformed { offset: 0, error: Error::CrcMismatch, skipped } if skipped == second
+        ));
+        assert!(matches!(items[1], Item::Frame { offset, crc: Ok(()), .. } if offset == second));
+    }
+
+    #[test]
+    fn test_encode_dump_roundtrip() {
+        let registry = SchemaRegistry::with_builtin();
+        let mut data = trade_frame(1);
+        data.extend(trade_frame(3));
+        // Delta frames have no JSON rendering and travel as raw hex
+        let mut delta = quote_delta::DeltaEncoder::new(0);
+        let mut buf = [0u8; 128];
+        for seq in 4..6 {
+            let quote = quote_delta::Quote {
+                instrument: 1,
+                ts_ns: seq as u64,
+                bid: 10,
+                ask: 11,
+                level: 1,
+                symbol: None,
+            };
+            let len = delta.encode(&mut buf, seq, &quote).unwrap();
+            data.extend(&buf[..len]);
+        }
+
+        let mut dumped = Vec::new();
+        let mut decoder = DynamicDecoder::with_registry(registry.clone());
+        assert!(dump(&data, &mut decoder, true, &mut dumped).unwrap());
+        assert!(String::from_utf8_lossy(&dumped).contains("\"raw\":"));
+
+        let mut encoded = Vec::new();
+        assert!(encode(&dumped[..], &registry, &mut encoded).unwrap());
+        assert_eq!(encoded, data);
+
+        let mut report = Vec::new();
+        stats(&data, &mut decoder, &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.contains("1 gaps (1 frames missing)"), "{}", report);
+        assert!(report.contains("channel 0 expected 2 got 3"), "{}", report);
+    }
+
+    #[test]
+    fn test_args() {
+        let parse = |args: &[&str]| Args::parse(args.iter().map(|s| s.to_string()));
+        let args = parse(&["encode", "-o", "out.bin", "in.json"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(args.command, Command::Encode);
+        assert_eq!(args.output.as_deref(), Some("out.bin"));
+        assert_eq!(args.input.as_deref(), Some("in.json"));
+
+        assert!(parse(&["--help"]).unwrap().is_none());
+        assert!(parse(&["frobnicate"]).is_err());
+        assert!(parse(&["stats", "--json"]).is_err());
+        assert!(parse(&["dump", "a", "b"]).is_err());
+
+        let args = parse(&["pcap", "--port", "9000", "--json"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (args.command, args.port, args.json),
+            (Command::Pcap, Some(9000), true)
+        );
+        assert!(parse(&["pcap", "--port", "http"]).is_err());
+    }
+
+    #[test]
+    fn test_pcap_dump() {
+        // Classic little-endian pcap, raw IPv4, one UDP datagram with two frames
+        let mut payload = trade_frame(1);
+        payload.extend(trade_frame(2));
+        let mut udp = [5000u16, 9000, 8 + payload.len() as u16, 0]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect::<Vec<_>>();
+        udp.extend(&payload);
+        let mut packet = vec![0x45, 0];
+        packet.extend((20 + udp.len() as u16).to_be_bytes());
+        packet.extend([0, 0, 0, 0, 64, 17, 0, 0, 127, 0, 0, 1, 127, 0, 0, 1]);
+        packet.extend(&udp);
+
+        let mut capture = Vec::new();
+        for field in [0xa1b2c3d4u32, 0x0004_0002, 0, 0, 65535, 101, 60, 250_000] {
+            capture.extend(field.to_le_bytes());
+        }
+        capture.extend((packet.len() as u32).to_le_bytes());
+        capture.extend((packet.len() as u32).to_le_bytes());
+        capture.extend(&packet);
+
+        let mut decoder = DynamicDecoder::with_registry(SchemaRegistry::with_builtin());
+        let mut out = Vec::new();
+        let capture = Capture::new(&capture).unwrap();
+        assert!(pcap(
+            &capture,
+            FrameExtractor::new(9000),
+            &mut decoder,
+            false,
+            &mut out
+        )
+        .unwrap());
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.starts_with("1970-01-01T00:01:00.250000000Z 127.0.0.1:5000 > 127.0.0.1:9000 Trade"),
+            "{}",
+            out
+        );
+        assert!(out.contains("seq=2"), "{}", out);
+
+        let mut out = Vec::new();
+        pcap(
+            &capture,
+            FrameExtractor::new(80),
+            &mut decoder,
+            false,
+            &mut out,
+        )
+        .unwrap();
+        assert!(out.is_empty());
+    }
 }
@@ -3,10 +3,14 @@
 //! This module provides convenient APIs for encoding and decoding specific
 //! message types with predefined schemas.
 
+use crate::bitmap::{BitmapSize, PresenceBitmap};
 use crate::decoder::FrameDecoder;
 use crate::encoder::FrameEncoder;
 use crate::error::{Error, Result};
 use crate::frame::{FrameFlags, FrameHeader};
+use crate::schema::{FieldType, MessageSchema, OptionalEncoding};
+use crate::tagged::TaggedValue;
+use crate::timestamp::Timestamp;
 
 /// Message type constants
 pub mod msg_types {
@@ -14,6 +18,24 @@ pub mod msg_types {
     pub const TRADE_V1: u16 = 1;
     /// Quote message v1  
     pub const QUOTE_V1: u16 = 2;
+    /// Per-instrument delta-encoded quote v1
+    pub const QUOTE_DELTA_V1: u16 = 3;
+    /// Version negotiation hello (session control range starts at 0xFF00)
+    pub const HELLO: u16 = 0xFF00;
+    /// Schema announcement describing another message type
+    pub const SCHEMA: u16 = 0xFF01;
+    /// TCP session logon request and reply
+    pub const LOGON: u16 = 0xFF02;
+    /// TCP session heartbeat
+    pub const HEARTBEAT: u16 = 0xFF03;
+    /// TCP session logout, with a reason
+    pub const LOGOUT: u16 = 0xFF04;
+
+    /// Check if `msg_type` lies in the session control range
+    #[inline]
+    pub const fn is_session_control(msg_type: u16) -> bool {
+        msg_type >= HELLO
+    }
 }
 
 /// Trade message utilities
@@ -28,6 +50,33 @@ pub mod trade {
         pub const NOTE: usize = 1;
     }
 
+    /// Schema minor version implemented here (1: accepts tagged optional fields)
+    pub const MINOR_VERSION: u8 = 1;
+
+    /// Presence bitmap layout on the wire (16-bit, as in the original v1 layout)
+    pub const BITMAP_SIZE: BitmapSize = BitmapSize::U16;
+
+    /// Schema describing the body layout implemented here
+    pub fn schema() -> MessageSchema {
+        MessageSchema::new(msg_types::TRADE_V1, "Trade", MINOR_VERSION)
+            .with_optional_encoding(OptionalEncoding::Bitmap(BITMAP_SIZE))
+            .required("ts_ns", FieldType::U64)
+            .required("price", FieldType::I64)
+            .required("qty", FieldType::U32)
+            .optional("symbol", FieldType::Bytes, fields::SYMBOL as u8)
+            .optional("note", FieldType::Bytes, fields::NOTE as u8)
+    }
+
+    /// Decoded Trade v1 message: (header, ts_ns, price, qty, symbol, note)
+    pub type Decoded<'a> = (
+        FrameHeader,
+        u64,
+        i64,
+        u32,
+        Option<&'a [u8]>,
+        Option<&'a [u8]>,
+    );
+
     /// Encode a Trade v1 message
     ///
     /// Fixed fields: ts_ns (u64), price (i64), qty (u32)
@@ -59,16 +108,16 @@ pub mod trade {
 
         // Write presence bitmap and optional fields if needed
         if symbol.is_some() || note.is_some() {
-            let mut bitmap = 0u16;
+            let mut bitmap = PresenceBitmap::new(BITMAP_SIZE);
 
             if symbol.is_some() {
-                bitmap |= 1 << fields::SYMBOL;
+                bitmap.set(fields::SYMBOL)?;
             }
             if note.is_some() {
-                bitmap |= 1 << fields::NOTE;
+                bitmap.set(fields::NOTE)?;
             }
 
-            encoder.put_bitmap(bitmap)?;
+            encoder.put_presence_bitmap(&bitmap)?;
 
             // Write optional fields in order
             if let Some(symbol_bytes) = symbol {
@@ -82,13 +131,65 @@ pub mod trade {
         encoder.finish_crc32c()
     }
 
+    /// Encode a Trade v1 message stamped with a [`Timestamp`]
+    ///
+    /// Same as [`encode`] with `ts_ns` taken from `ts`.
+    #[inline]
+    pub fn encode_at(
+        buf: &mut [u8],
+        seq: u32,
+        ts: Timestamp,
+        price: i64,
+        qty: u32,
+        symbol: Option<&[u8]>,
+        note: Option<&[u8]>,
+    ) -> Result<usize> {
+        encode(buf, seq, ts.as_nanos(), price, qty, symbol, note)
+    }
+
+    /// Encode a Trade v1 message with tagged optional fields
+    ///
+    /// Same fields as [`encode`], but `symbol` and `note` are written as
+    /// tagged fields (indices from [`fields`]) so that decoders can skip
+    /// fields added by later schema versions in any position.
+    #[inline]
+    pub fn encode_tagged(
+        buf: &mut [u8],
+        seq: u32,
+        ts_ns: u64,
+        price: i64,
+        qty: u32,
+        symbol: Option<&[u8]>,
+        note: Option<&[u8]>,
+    ) -> Result<usize> {
+        let mut encoder = FrameEncoder::new(buf);
+
+        let mut header = FrameHeader::new(msg_types::TRADE_V1, seq, 0);
+        header.set_flag(FrameFlags::TAGGED);
+        encoder.begin(&header)?;
+
+        encoder.put_u64(ts_ns)?;
+        encoder.put_i64(price)?;
+        encoder.put_u32(qty)?;
+
+        if let Some(symbol_bytes) = symbol {
+            encoder.put_tagged(fields::SYMBOL as u32, TaggedValue::Bytes(symbol_bytes))?;
+        }
+        if let Some(note_bytes) = note {
+            encoder.put_tagged(fields::NOTE as u32, TaggedValue::Bytes(note_bytes))?;
+        }
+
+        encoder.finish_crc32c()
+    }
+
     /// Decode a Trade v1 message
     ///
+    /// Accepts both bitmap-gated and tagged optional fields; unknown tagged
+    /// fields are skipped.
+    ///
     /// Returns (header, ts_ns, price, qty, symbol, note)
     #[inline]
-    pub fn decode(
-        buf: &[u8],
-    ) -> Result<(FrameHeader, u64, i64, u32, Option<&[u8]>, Option<&[u8]>)> {
+    pub fn decode(buf: &[u8]) -> Result<Decoded<'_>> {
         let decoder = FrameDecoder::new(buf);
         let header = decoder.header()?;
 
@@ -109,14 +210,26 @@ pub mod trade {
         let mut symbol = None;
         let mut note = None;
 
-        // Read optional fields if presence bitmap is set
-        if header.has_flag(FrameFlags::PRESENCE_BITMAP) {
-            let bitmap = body.get_bitmap()?;
+        if header.has_flag(FrameFlags::TAGGED) {
+            if header.has_flag(FrameFlags::PRESENCE_BITMAP) {
+                return Err(Error::FlagConflict);
+            }
+            for field in body.tagged_fields() {
+                let (field_idx, value) = field?;
+                match field_idx as usize {
+                    fields::SYMBOL => symbol = Some(value.as_bytes()?),
+                    fields::NOTE => note = Some(value.as_bytes()?),
+                    _ => {} // Added by a newer schema version
+                }
+            }
+        } else if header.has_flag(FrameFlags::PRESENCE_BITMAP) {
+            // Read optional fields if presence bitmap is set
+            let bitmap = body.get_presence_bitmap(BITMAP_SIZE)?;
 
-            if bitmap & (1 << fields::SYMBOL) != 0 {
+            if bitmap.is_set(fields::SYMBOL) {
                 symbol = Some(body.get_varbytes()?);
             }
-            if bitmap & (1 << fields::NOTE) != 0 {
+            if bitmap.is_set(fields::NOTE) {
                 note = Some(body.get_varbytes()?);
             }
         }
@@ -135,6 +248,26 @@ pub mod quote {
         pub const SYMBOL: usize = 0;
     }
 
+    /// Schema minor version implemented here (1: accepts tagged optional fields)
+    pub const MINOR_VERSION: u8 = 1;
+
+    /// Presence bitmap layout on the wire (16-bit, as in the original v1 layout)
+    pub const BITMAP_SIZE: BitmapSize = BitmapSize::U16;
+
+    /// Schema describing the body layout implemented here
+    pub fn schema() -> MessageSchema {
+        MessageSchema::new(msg_types::QUOTE_V1, "Quote", MINOR_VERSION)
+            .with_optional_encoding(OptionalEncoding::Bitmap(BITMAP_SIZE))
+            .required("ts_ns", FieldType::U64)
+            .required("bid", FieldType::I64)
+            .required("ask", FieldType::I64)
+            .required("level", FieldType::U8)
+            .optional("symbol", FieldType::Bytes, fields::SYMBOL as u8)
+    }
+
+    /// Decoded Quote v1 message: (header, ts_ns, bid, ask, level, symbol)
+    pub type Decoded<'a> = (FrameHeader, u64, i64, i64, u8, Option<&'a [u8]>);
+
     /// Encode a Quote v1 message  
     ///
     /// Fixed fields: ts_ns (u64), bid (i64), ask (i64), level (u8)
@@ -166,19 +299,71 @@ pub mod quote {
 
         // Write optional fields if needed
         if let Some(symbol_bytes) = symbol {
-            let bitmap = 1u16 << fields::SYMBOL;
-            encoder.put_bitmap(bitmap)?;
+            let mut bitmap = PresenceBitmap::new(BITMAP_SIZE);
+            bitmap.set(fields::SYMBOL)?;
+            encoder.put_presence_bitmap(&bitmap)?;
             encoder.put_varbytes(symbol_bytes)?;
         }
 
         encoder.finish_crc32c()
     }
 
+    /// Encode a Quote v1 message stamped with a [`Timestamp`]
+    ///
+    /// Same as [`encode`] with `ts_ns` taken from `ts`.
+    #[inline]
+    pub fn encode_at(
+        buf: &mut [u8],
+        seq: u32,
+        ts: Timestamp,
+        bid: i64,
+        ask: i64,
+        level: u8,
+        symbol: Option<&[u8]>,
+    ) -> Result<usize> {
+        encode(buf, seq, ts.as_nanos(), bid, ask, level, symbol)
+    }
+
+    /// Encode a Quote v1 message with tagged optional fields
+    ///
+    /// Same fields as [`encode`], but `symbol` is written as a tagged field so
+    /// that decoders can skip fields added by later schema versions.
+    #[inline]
+    pub fn encode_tagged(
+        buf: &mut [u8],
+        seq: u32,
+        ts_ns: u64,
+        bid: i64,
+        ask: i64,
+        level: u8,
+        symbol: Option<&[u8]>,
+    ) -> Result<usize> {
+        let mut encoder = FrameEncoder::new(buf);
+
+        let mut header = FrameHeader::new(msg_types::QUOTE_V1, seq, 0);
+        header.set_flag(FrameFlags::TAGGED);
+        encoder.begin(&header)?;
+
+        encoder.put_u64(ts_ns)?;
+        encoder.put_i64(bid)?;
+        encoder.put_i64(ask)?;
+        encoder.put_u8(level)?;
+
+        if let Some(symbol_bytes) = symbol {
+            encoder.put_tagged(fields::SYMBOL as u32, TaggedValue::Bytes(symbol_bytes))?;
+        }
+
+        encoder.finish_crc32c()
+    }
+
     /// Decode a Quote v1 message
     ///
+    /// Accepts both bitmap-gated and tagged optional fields; unknown tagged
+    /// fields are skipped.
+    ///
     /// Returns (header, ts_ns, bid, ask, level, symbol)
     #[inline]
-    pub fn decode(buf: &[u8]) -> Result<(FrameHeader, u64, i64, i64, u8, Option<&[u8]>)> {
+    pub fn decode(buf: &[u8]) -> Result<Decoded<'_>> {
         let decoder = FrameDecoder::new(buf);
         let header = decoder.header()?;
 
@@ -198,9 +383,19 @@ pub mod quote {
 
         let mut symbol = None;
 
-        if header.has_flag(FrameFlags::PRESENCE_BITMAP) {
-            let bitmap = body.get_bitmap()?;
-            if bitmap & (1 << fields::SYMBOL) != 0 {
+        if header.has_flag(FrameFlags::TAGGED) {
+            if header.has_flag(FrameFlags::PRESENCE_BITMAP) {
+                return Err(Error::FlagConflict);
+            }
+            for field in body.tagged_fields() {
+                let (field_idx, value) = field?;
+                if field_idx as usize == fields::SYMBOL {
+                    symbol = Some(value.as_bytes()?);
+                }
+            }
+        } else if header.has_flag(FrameFlags::PRESENCE_BITMAP) {
+            let bitmap = body.get_presence_bitmap(BITMAP_SIZE)?;
+            if bitmap.is_set(fields::SYMBOL) {
                 symbol = Some(body.get_varbytes()?);
             }
         }
@@ -209,6 +404,301 @@ pub mod quote {
     }
 }
 
+/// Delta-encoded quote stream
+///
+/// Consecutive quotes on the same instrument usually differ by a tick or two,
+/// so after a keyframe the encoder sends `ts_ns`, `bid` and `ask` as ZigZag
+/// varint deltas from the previous quote on that instrument and sets
+/// [`FrameFlags::DELTA`]. Keyframes carry the full values and are emitted for
+/// the first quote on an instrument, every `keyframe_interval` frames, and on
+/// request via [`DeltaEncoder::force_keyframe`].
+///
+/// The decoder tracks `FrameHeader::seq` per channel; on a gap it drops the
+/// per-instrument state of that channel and rejects delta frames with
+/// `Error::MissingDeltaBase` until the next keyframe for the instrument arrives.
+///
+/// Body layout:
+///
+/// ```text
+/// instrument (varint u32)
+/// keyframe: ts_ns u64, bid i64, ask i64, level u8
+/// delta:    ts_ns zigzag, bid zigzag, ask zigzag, level u8
+/// [bitmap var, symbol varbytes]   (if PRESENCE_BITMAP)
+/// ```
+pub mod quote_delta {
+    use super::*;
+    use crate::sequence::{SeqStatus, SeqTracker};
+    use alloc::collections::BTreeMap;
+
+    /// Field indices for presence bitmap
+    pub mod fields {
+        /// Symbol field index
+        pub const SYMBOL: usize = 0;
+    }
+
+    /// Schema minor version implemented here
+    pub const MINOR_VERSION: u8 = 0;
+
+    /// Presence bitmap layout on the wire
+    pub const BITMAP_SIZE: BitmapSize = BitmapSize::Var;
+
+    /// Default number of frames per instrument between keyframes
+    pub const DEFAULT_KEYFRAME_INTERVAL: u32 = 64;
+
+    /// Schema describing the keyframe body layout
+    ///
+    /// Delta frames are relative to earlier quotes and cannot be read through
+    /// a schema; decode them with [`DeltaDecoder`].
+    pub fn schema() -> MessageSchema {
+        MessageSchema::new(msg_types::QUOTE_DELTA_V1, "QuoteDelta", MINOR_VERSION)
+            .with_optional_encoding(OptionalEncoding::Bitmap(BITMAP_SIZE))
+            .required("instrument", FieldType::VarU64)
+            .required("ts_ns", FieldType::U64)
+            .required("bid", FieldType::I64)
+            .required("ask", FieldType::I64)
+            .required("level", FieldType::U8)
+            .optional("symbol", FieldType::Bytes, fields::SYMBOL as u8)
+    }
+
+    /// A decoded or to-be-encoded quote
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Quote<'a> {
+        /// Instrument identifier the delta state is keyed on
+        pub instrument: u32,
+        /// Timestamp in nanoseconds
+        pub ts_ns: u64,
+        /// Bid price (fixed-point)
+        pub bid: i64,
+        /// Ask price (fixed-point)
+        pub ask: i64,
+        /// Book level
+        pub level: u8,
+        /// Optional symbol
+        pub symbol: Option<&'a [u8]>,
+    }
+
+    impl Quote<'_> {
+        /// Quote timestamp as a [`Timestamp`]
+        #[inline]
+        pub fn timestamp(&self) -> Timestamp {
+            Timestamp::from_nanos(self.ts_ns)
+        }
+    }
+
+    /// Last quote seen on an instrument
+    #[derive(Debug, Clone, Copy)]
+    struct State {
+        ts_ns: u64,
+        bid: i64,
+        ask: i64,
+        since_keyframe: u32,
+    }
+
+    /// Stateful encoder for delta quote frames
+    #[derive(Debug)]
+    pub struct DeltaEncoder {
+        states: BTreeMap<u32, State>,
+        keyframe_interval: u32,
+        channel: u16,
+    }
+
+    impl DeltaEncoder {
+        /// Create encoder emitting a keyframe every `keyframe_interval` frames
+        /// per instrument (0 disables periodic keyframes)
+        #[inline]
+        pub fn new(keyframe_interval: u32) -> Self {
+            Self {
+                states: BTreeMap::new(),
+                keyframe_interval,
+                channel: 0,
+            }
+        }
+
+        /// Send frames on `channel` (uses a version 2 header when non-zero)
+        #[inline]
+        pub fn with_channel(mut self, channel: u16) -> Self {
+            self.channel = channel;
+            self
+        }
+
+        /// Send the next quote on `instrument` as a keyframe
+        #[inline]
+        pub fn force_keyframe(&mut self, instrument: u32) {
+            self.states.remove(&instrument);
+        }
+
+        /// Send the next quote on every instrument as a keyframe
+        #[inline]
+        pub fn reset(&mut self) {
+            self.states.clear();
+        }
+
+        /// Encode a quote, as a delta frame when state for its instrument exists
+        pub fn encode(&mut self, buf: &mut [u8], seq: u32, quote: &Quote<'_>) -> Result<usize> {
+            let prev = self.states.get(&quote.instrument).copied().filter(|state| {
+                self.keyframe_interval == 0 || state.since_keyframe < self.keyframe_interval
+            });
+
+            let mut encoder = FrameEncoder::new(buf);
+
+            let mut header = FrameHeader::new(msg_types::QUOTE_DELTA_V1, seq, 0);
+            if self.channel != 0 {
+                header = header.with_channel(self.channel);
+            }
+            if prev.is_some() {
+                header.set_flag(FrameFlags::DELTA);
+            }
+            if quote.symbol.is_some() {
+                header.set_flag(FrameFlags::PRESENCE_BITMAP);
+            }
+
+            encoder.begin(&header)?;
+            encoder.put_varint_u32(quote.instrument)?;
+
+            match prev {
+                Some(prev) => {
+                    encoder.put_varint_delta_i64(quote.ts_ns as i64, prev.ts_ns as i64)?;
+                    encoder.put_varint_delta_i64(quote.bid, prev.bid)?;
+                    encoder.put_varint_delta_i64(quote.ask, prev.ask)?;
+                }
+                None => {
+                    encoder.put_u64(quote.ts_ns)?;
+                    encoder.put_i64(quote.bid)?;
+                    encoder.put_i64(quote.ask)?;
+                }
+            }
+            encoder.put_u8(quote.level)?;
+
+            if let Some(symbol_bytes) = quote.symbol {
+                let mut bitmap = PresenceBitmap::new(BITMAP_SIZE);
+                bitmap.set(fields::SYMBOL)?;
+                encoder.put_presence_bitmap(&bitmap)?;
+                encoder.put_varbytes(symbol_bytes)?;
+            }
+
+            let size = encoder.finish_crc32c()?;
+
+            // Only commit state once the frame is fully written
+            self.states.insert(
+                quote.instrument,
+                State {
+                    ts_ns: quote.ts_ns,
+                    bid: quote.bid,
+                    ask: quote.ask,
+                    since_keyframe: prev.map_or(1, |p| p.since_keyframe + 1),
+                },
+            );
+
+            Ok(size)
+        }
+    }
+
+    impl Default for DeltaEncoder {
+        fn default() -> Self {
+            Self::new(DEFAULT_KEYFRAME_INTERVAL)
+        }
+    }
+
+    /// Stateful decoder for delta quote frames
+    #[derive(Debug, Default)]
+    pub struct DeltaDecoder {
+        states: BTreeMap<(u16, u32), State>,
+        seqs: SeqTracker,
+    }
+
+    impl DeltaDecoder {
+        /// Create decoder with no instrument state
+        #[inline]
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Forget all instrument state and expected sequence numbers
+        #[inline]
+        pub fn reset(&mut self) {
+            self.states.clear();
+            self.seqs.reset();
+        }
+
+        /// Decode a quote frame, applying deltas to the instrument's last quote
+        ///
+        /// Returns `Error::MissingDeltaBase` for a delta frame whose instrument
+        /// has no state, either because no keyframe was seen yet or because a
+        /// sequence gap invalidated it. A replayed or stale frame returns
+        /// `Error::DuplicateSeq` and leaves the instrument state untouched.
+        pub fn decode<'a>(&mut self, buf: &'a [u8]) -> Result<(FrameHeader, Quote<'a>)> {
+            let decoder = FrameDecoder::new(buf);
+            let header = decoder.header()?;
+
+            if header.msg_type != msg_types::QUOTE_DELTA_V1 {
+                return Err(Error::UnsupportedMsgType);
+            }
+
+            decoder.verify_crc32c()?;
+
+            // A lost frame may have carried a delta we never applied; a
+            // replayed one was already applied and must not be applied again
+            match self.seqs.observe_header(&header) {
+                SeqStatus::First | SeqStatus::InOrder => {}
+                SeqStatus::Gap { .. } => {
+                    self.states
+                        .retain(|&(channel, _), _| channel != header.channel);
+                }
+                SeqStatus::Duplicate { .. } => return Err(Error::DuplicateSeq),
+            }
+
+            let mut body = decoder.body()?;
+            let instrument = body.get_varint_u32()?;
+
+            let (ts_ns, bid, ask, since_keyframe) = if header.has_flag(FrameFlags::DELTA) {
+                let prev = self
+                    .states
+                    .get(&(header.channel, instrument))
+                    .ok_or(Error::MissingDeltaBase)?;
+                (
+                    body.get_varint_delta_i64(prev.ts_ns as i64)? as u64,
+                    body.get_varint_delta_i64(prev.bid)?,
+                    body.get_varint_delta_i64(prev.ask)?,
+                    prev.since_keyframe + 1,
+                )
+            } else {
+                (body.get_u64()?, body.get_i64()?, body.get_i64()?, 1)
+            };
+            let level = body.get_u8()?;
+
+            let mut symbol = None;
+            if header.has_flag(FrameFlags::PRESENCE_BITMAP) {
+                let bitmap = body.get_presence_bitmap(BITMAP_SIZE)?;
+                if bitmap.is_set(fields::SYMBOL) {
+                    symbol = Some(body.get_varbytes()?);
+                }
+            }
+
+            self.states.insert(
+                (header.channel, instrument),
+                State {
+                    ts_ns,
+                    bid,
+                    ask,
+                    since_keyframe,
+                },
+            );
+
+            Ok((
+                header,
+                Quote {
+                    instrument,
+                    ts_ns,
+                    bid,
+                    ask,
+                    level,
+                    symbol,
+                },
+            ))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -341,6 +831,36 @@ mod tests {
         assert_eq!(symbol, None);
     }
 
+    #[test]
+    fn test_encode_at_timestamp() {
+        let ts = Timestamp::from_secs_nanos(1_700_000_000, 123).unwrap();
+        let mut buf = [0u8; 256];
+        let mut expected = [0u8; 256];
+
+        let size = trade::encode_at(&mut buf, 1, ts, 50_000_000, 100, Some(b"AAPL"), None).unwrap();
+        let expected_size = trade::encode(
+            &mut expected,
+            1,
+            ts.as_nanos(),
+            50_000_000,
+            100,
+            Some(b"AAPL"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(buf[..size], expected[..expected_size]);
+        let (_, ts_ns, ..) = trade::decode(&buf[..size]).unwrap();
+        assert_eq!(Timestamp::from_nanos(ts_ns), ts);
+
+        let size = quote::encode_at(&mut buf, 2, ts, 100, 101, 1, None).unwrap();
+        let (_, ts_ns, ..) = quote::decode(&buf[..size]).unwrap();
+        assert_eq!(Timestamp::from_nanos(ts_ns), ts);
+
+        let mut quote = sample_quote(1, 0);
+        quote.ts_ns = ts.as_nanos();
+        assert_eq!(quote.timestamp(), ts);
+    }
+
     #[test]
     fn test_unsupported_message_type() {
         let mut buf = [0u8; 128];
@@ -363,4 +883,183 @@ mod tests {
             Error::UnsupportedMsgType
         );
     }
+
+    fn sample_quote(instrument: u32, i: i64) -> quote_delta::Quote<'static> {
+        quote_delta::Quote {
+            instrument,
+            ts_ns: 1_700_000_000_000_000_000 + i as u64 * 1_000,
+            bid: 100_000_000 + i % 3,
+            ask: 100_010_000 - i % 2,
+            level: 1,
+            symbol: None,
+        }
+    }
+
+    #[test]
+    fn test_quote_delta_roundtrip() {
+        let mut encoder = quote_delta::DeltaEncoder::new(4);
+        let mut decoder = quote_delta::DeltaDecoder::new();
+        let mut buf = [0u8; 128];
+
+        for i in 0..10 {
+            let instrument = (i % 2) as u32;
+            let mut quote = sample_quote(instrument, i);
+            if i == 0 {
+                quote.symbol = Some(b"EUR/USD");
+            }
+
+            let size = encoder.encode(&mut buf, i as u32, &quote).unwrap();
+            let (header, decoded) = decoder.decode(&buf[..size]).unwrap();
+
+            assert_eq!(decoded, quote);
+            // Each instrument starts with a keyframe and repeats one every 4 frames
+            let per_instrument = i / 2;
+            assert_eq!(header.has_flag(FrameFlags::DELTA), per_instrument % 4 != 0);
+        }
+    }
+
+    #[test]
+    fn test_quote_delta_is_smaller() {
+        let mut encoder = quote_delta::DeltaEncoder::default();
+        let mut buf = [0u8; 128];
+
+        let key_size = encoder.encode(&mut buf, 0, &sample_quote(7, 0)).unwrap();
+        let delta_size = encoder.encode(&mut buf, 1, &sample_quote(7, 1)).unwrap();
+
+        let mut quote_buf = [0u8; 128];
+        let full_size = quote::encode(&mut quote_buf, 1, 0, 0, 0, 1, None).unwrap();
+
+        assert!(delta_size < full_size);
+        assert!(delta_size < key_size);
+    }
+
+    #[test]
+    fn test_quote_delta_recovers_after_gap() {
+        let mut encoder = quote_delta::DeltaEncoder::new(0);
+        let mut decoder = quote_delta::DeltaDecoder::new();
+        let mut buf = [0u8; 128];
+
+        let size = encoder.encode(&mut buf, 0, &sample_quote(1, 0)).unwrap();
+        decoder.decode(&buf[..size]).unwrap();
+
+        // Frame 1 is lost
+        encoder.encode(&mut buf, 1, &sample_quote(1, 1)).unwrap();
+
+        let size = encoder.encode(&mut buf, 2, &sample_quote(1, 2)).unwrap();
+        assert_eq!(
+            decoder.decode(&buf[..size]).unwrap_err(),
+            Error::MissingDeltaBase
+        );
+
+        // A keyframe restores the stream
+        encoder.force_keyframe(1);
+        let size = encoder.encode(&mut buf, 3, &sample_quote(1, 3)).unwrap();
+        let (header, decoded) = decoder.decode(&buf[..size]).unwrap();
+        assert!(!header.has_flag(FrameFlags::DELTA));
+        assert_eq!(decoded, sample_quote(1, 3));
+
+        let size = encoder.encode(&mut buf, 4, &sample_quote(1, 4)).unwrap();
+        let (header, decoded) = decoder.decode(&buf[..size]).unwrap();
+        assert!(header.has_flag(FrameFlags::DELTA));
+        assert_eq!(decoded, sample_quote(1, 4));
+    }
+
+    #[test]
+    fn test_quote_delta_ignores_replayed_keyframe() {
+        let mut encoder = quote_delta::DeltaEncoder::new(0);
+        let mut decoder = quote_delta::DeltaDecoder::new();
+        let (mut key, mut buf) = ([0u8; 128], [0u8; 128]);
+
+        let key_size = encoder.encode(&mut key, 0, &sample_quote(1, 0)).unwrap();
+        decoder.decode(&key[..key_size]).unwrap();
+        let size = encoder.encode(&mut buf, 1, &sample_quote(1, 1)).unwrap();
+        decoder.decode(&buf[..size]).unwrap();
+
+        // The replayed keyframe must not rewind the state to quote 0
+        assert_eq!(
+            decoder.decode(&key[..key_size]).unwrap_err(),
+            Error::DuplicateSeq
+        );
+
+        let size = encoder.encode(&mut buf, 2, &sample_quote(1, 2)).unwrap();
+        let (header, decoded) = decoder.decode(&buf[..size]).unwrap();
+        assert!(header.has_flag(FrameFlags::DELTA));
+        assert_eq!(decoded, sample_quote(1, 2));
+    }
+
+    #[test]
+    fn test_quote_delta_ignores_replayed_delta() {
+        let mut encoder = quote_delta::DeltaEncoder::new(0);
+        let mut decoder = quote_delta::DeltaDecoder::new();
+        let (mut delta, mut buf) = ([0u8; 128], [0u8; 128]);
+
+        let size = encoder.encode(&mut buf, 0, &sample_quote(1, 0)).unwrap();
+        decoder.decode(&buf[..size]).unwrap();
+        let delta_size = encoder.encode(&mut delta, 1, &sample_quote(1, 1)).unwrap();
+        decoder.decode(&delta[..delta_size]).unwrap();
+
+        // Applying the delta twice would corrupt the state
+        assert_eq!(
+            decoder.decode(&delta[..delta_size]).unwrap_err(),
+            Error::DuplicateSeq
+        );
+
+        for i in 2..4 {
+            let size = encoder
+                .encode(&mut buf, i, &sample_quote(1, i as i64))
+                .unwrap();
+            let (_, decoded) = decoder.decode(&buf[..size]).unwrap();
+            assert_eq!(decoded, sample_quote(1, i as i64));
+        }
+    }
+
+    #[test]
+    fn test_quote_delta_without_keyframe() {
+        let mut encoder = quote_delta::DeltaEncoder::default();
+        let mut buf = [0u8; 128];
+
+        encoder.encode(&mut buf, 0, &sample_quote(1, 0)).unwrap();
+        let size = encoder.encode(&mut buf, 1, &sample_quote(1, 1)).unwrap();
+
+        // A decoder joining mid-stream cannot apply the delta
+        let mut decoder = quote_delta::DeltaDecoder::new();
+        assert_eq!(
+            decoder.decode(&buf[..size]).unwrap_err(),
+            Error::MissingDeltaBase
+        );
+    }
+
+    #[test]
+    fn test_quote_delta_channels_are_independent() {
+        let mut chan1 = quote_delta::DeltaEncoder::new(0).with_channel(1);
+        let mut chan2 = quote_delta::DeltaEncoder::new(0).with_channel(2);
+        let mut decoder = quote_delta::DeltaDecoder::new();
+        let mut buf = [0u8; 128];
+
+        // Same instrument id and seq space on two channels
+        for seq in 0..2 {
+            let size = chan1
+                .encode(&mut buf, seq, &sample_quote(5, seq as i64))
+                .unwrap();
+            decoder.decode(&buf[..size]).unwrap();
+            let size = chan2
+                .encode(&mut buf, seq, &sample_quote(5, 10 + seq as i64))
+                .unwrap();
+            decoder.decode(&buf[..size]).unwrap();
+        }
+
+        // A gap on channel 1 leaves channel 2 decodable
+        chan1.encode(&mut buf, 2, &sample_quote(5, 2)).unwrap();
+        let size = chan1.encode(&mut buf, 3, &sample_quote(5, 3)).unwrap();
+        assert_eq!(
+            decoder.decode(&buf[..size]).unwrap_err(),
+            Error::MissingDeltaBase
+        );
+
+        let size = chan2.encode(&mut buf, 2, &sample_quote(5, 12)).unwrap();
+        let (header, decoded) = decoder.decode(&buf[..size]).unwrap();
+        assert_eq!(header.channel, 2);
+        assert!(header.has_flag(FrameFlags::DELTA));
+        assert_eq!(decoded, sample_quote(5, 12));
+    }
 }
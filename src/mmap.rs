@@ -0,0 +1,400 @@
+//! Memory-mapped, zero-copy readers for recorded frames
+//!
+//! [`MappedFile`] maps a file read-only, either a plain concatenation of
+//! frames (as written by the encoders or `minibit encode`) or a
+//! [journal](crate::journal) file. Frames are decoded in place: every
+//! [`FrameDecoder`] borrows straight from the mapping.
+//!
+//! A [`FrameIndex`] records the offset of every frame so the file can be
+//! split into ranges and read by several threads at once; journal files
+//! shard by their own index blocks instead (see [`JournalView::shards`]).
+//!
+//! The file must not be truncated or rewritten while it is mapped. Frames
+//! appended afterwards are simply not visible.
+
+use core::ops::Range;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::vec::Vec;
+
+use memmap2::Mmap;
+
+use crate::decoder::FrameDecoder;
+use crate::error::{Error, Result};
+use crate::frame::FrameHeader;
+use crate::journal::JournalView;
+
+/// A read-only memory-mapped file
+#[derive(Debug)]
+pub struct MappedFile {
+    map: Mmap,
+}
+
+impl MappedFile {
+    /// Map the file at `path`
+    #[allow(unsafe_code)]
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is read-only and only ever exposed as `&[u8]`;
+        // callers are told not to truncate or rewrite the file while it is
+        // mapped, which is the remaining requirement of `Mmap::map`.
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(Self { map })
+    }
+
+    /// The mapped bytes
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+
+    /// Iterate over the frames of a plain frame file
+    pub fn frames(&self) -> FrameIter<'_> {
+        FrameIter::new(&self.map)
+    }
+
+    /// Record the offset of every frame of a plain frame file
+    pub fn index(&self) -> Result<FrameIndex> {
+        FrameIndex::build(&self.map)
+    }
+
+    /// Iterate over frames `range` of a plain frame file
+    pub fn range(&self, index: &FrameIndex, range: Range<usize>) -> FrameIter<'_> {
+        index.range(&self.map, range)
+    }
+
+    /// Split a plain frame file into up to `n` contiguous ranges of frames
+    pub fn shards(&self, index: &FrameIndex, n: usize) -> Vec<FrameIter<'_>> {
+        index
+            .shard_ranges(n)
+            .map(|range| index.range(&self.map, range))
+            .collect()
+    }
+
+    /// View the mapping as a journal file
+    pub fn journal(&self) -> io::Result<JournalView<'_>> {
+        JournalView::new(&self.map)
+    }
+}
+
+/// Iterator over back-to-back frames in a byte slice
+///
+/// Yields a decoder per frame whose header is valid (the CRC is left to the
+/// caller). Ends at a truncated final frame, reported by
+/// [`truncated`](Self::truncated), and after the first invalid header.
+#[derive(Debug, Clone)]
+pub struct FrameIter<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    end: usize,
+    truncated: Option<usize>,
+    failed: bool,
+}
+
+impl<'a> FrameIter<'a> {
+    /// Iterate over the frames of `buf`
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self {
+            buf,
+            pos: 0,
+            end: buf.len(),
+            truncated: None,
+            failed: false,
+        }
+    }
+
+    /// Offset of the next frame
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.pos
+    }
+
+    /// Offset of an incomplete final frame, once iteration has reached it
+    #[inline]
+    pub fn truncated(&self) -> Option<usize> {
+        self.truncated
+    }
+}
+
+impl<'a> Iterator for FrameIter<'a> {
+    type Item = Result<FrameDecoder<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.end || self.failed || self.truncated.is_some() {
+            return None;
+        }
+        let rest = &self.buf[self.pos..self.end];
+        match FrameHeader::decode(rest) {
+            Ok(header) if header.total_size() <= rest.len() => {
+                self.pos += header.total_size();
+                Some(Ok(FrameDecoder::new(&rest[..header.total_size()])))
+            }
+            Ok(_) | Err(Error::UnexpectedEof) => {
+                self.truncated = Some(self.pos);
+                None
+            }
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Offsets of the frames in a plain frame file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameIndex {
+    offsets: Vec<usize>,
+    end: usize,
+    truncated: Option<usize>,
+}
+
+impl FrameIndex {
+    /// Scan the frame headers of `buf`
+    ///
+    /// Fails on an invalid header; a truncated final frame is left out and
+    /// reported by [`truncated`](Self::truncated).
+    pub fn build(buf: &[u8]) -> Result<Self> {
+        let mut frames = FrameIter::new(buf);
+        let mut offsets = Vec::new();
+        loop {
+            let offset = frames.offset();
+            match frames.next() {
+                Some(frame) => {
+                    frame?;
+                    offsets.push(offset);
+                }
+                None => break,
+            }
+        }
+        Ok(Self {
+            offsets,
+            end: frames.offset(),
+            truncated: frames.truncated(),
+        })
+    }
+
+    /// Number of complete frames
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Whether there are no complete frames
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Offset of frame `i`
+    #[inline]
+    pub fn offset(&self, i: usize) -> Option<usize> {
+        self.offsets.get(i).copied()
+    }
+
+    /// Offset of an incomplete final frame
+    #[inline]
+    pub fn truncated(&self) -> Option<usize> {
+        self.truncated
+    }
+
+    /// Iterate over frames `range` of `buf`, the slice the index was built from
+    pub fn range<'a>(&self, buf: &'a [u8], range: Range<usize>) -> FrameIter<'a> {
+        let bound = |i: usize| self.offsets.get(i).copied().unwrap_or(self.end);
+        let end = bound(range.end).min(buf.len());
+        FrameIter {
+            buf,
+            pos: bound(range.start).min(end),
+            end,
+            truncated: None,
+            failed: false,
+        }
+    }
+
+    /// Split the frames into up to `n` contiguous, equally sized ranges
+    pub fn shard_ranges(&self, n: usize) -> impl Iterator<Item = Range<usize>> {
+        let len = self.len();
+        let per_shard = len.div_ceil(n.max(1)).max(1);
+        (0..len)
+            .step_by(per_shard)
+            .map(move |start| start..(start + per_shard).min(len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::JournalWriter;
+    use crate::test_util::trade_frame;
+    use crate::timestamp::Timestamp;
+    use std::{format, fs, thread};
+
+    /// Sum of frame seqs per shard, read on one thread each
+    fn shard_sums<'a, I>(shards: Vec<I>) -> Vec<(usize, u64)>
+    where
+        I: Iterator<Item = FrameDecoder<'a>> + Send,
+    {
+        thread::scope(|scope| {
+            let handles: Vec<_> = shards
+                .into_iter()
+                .map(|shard| {
+                    scope.spawn(move || {
+                        shard.fold((0, 0), |(count, sum), decoder| {
+                            decoder.verify_crc32c().unwrap();
+                            (count + 1, sum + decoder.header().unwrap().seq as u64)
+                        })
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        })
+    }
+
+    #[test]
+    fn test_mapped_frames_and_shards() {
+        let path = std::env::temp_dir().join(format!("minibit-mmap-{}.bin", std::process::id()));
+        let mut data: Vec<u8> = (0..100).flat_map(trade_frame).collect();
+        let torn = data.len();
+        data.extend(&trade_frame(100)[..30]);
+        fs::write(&path, &data).unwrap();
+
+        let mapped = MappedFile::open(&path).unwrap();
+        let mut frames = mapped.frames();
+        assert_eq!(frames.by_ref().count(), 100);
+        assert_eq!(frames.truncated(), Some(torn));
+
+        let index = mapped.index().unwrap();
+        assert_eq!((index.len(), index.truncated()), (100, Some(torn)));
+        let seqs: Vec<u32> = mapped
+            .range(&index, 10..13)
+            .map(|frame| frame.unwrap().header().unwrap().seq)
+            .collect();
+        assert_eq!(seqs, [10, 11, 12]);
+
+        let shards = mapped.shards(&index, 3);
+        assert_eq!(shards.len(), 3);
+        let sums = shard_sums(shards.into_iter().map(|s| s.map(|f| f.unwrap())).collect());
+        assert_eq!(
+            sums.iter().map(|&(count, _)| count).collect::<Vec<_>>(),
+            [34, 34, 32]
+        );
+        assert_eq!(
+            sums.iter().map(|&(_, sum)| sum).sum::<u64>(),
+            (0..100).sum::<u64>()
+        );
+
+        // A corrupt header ends iteration with an error
+        data[2] = 0x7f; // version byte of frame 0
+        fs::write(&path, &data).unwrap();
+        let mapped = MappedFile::open(&path).unwrap();
+        assert_eq!(
+            mapped.frames().next().unwrap().unwrap_err(),
+            Error::UnsupportedVersion
+        );
+        assert!(mapped.index().is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_mapped_journal_shards() {
+        let dir = std::env::temp_dir().join(format!("minibit-mmap-journal-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut writer = JournalWriter::create(&dir)
+            .unwrap()
+            .with_index_interval(500);
+        for seq in 0..50 {
+            writer
+                .append(&trade_frame(seq), Timestamp::from_nanos(seq as u64))
+                .unwrap();
+        }
+        let path = writer.current_path().unwrap().to_path_buf();
+        writer.close().unwrap();
+
+        let mapped = MappedFile::open(&path).unwrap();
+        let view = mapped.journal().unwrap();
+        let index = view.index().unwrap();
+        let shards: Vec<_> = view
+            .shards(&index, 4)
+            .into_iter()
+            .map(|shard| shard.map(|frame| frame.unwrap().decoder()))
+            .collect();
+        assert_eq!(shards.len(), 4);
+        let sums = shard_sums(shards);
+        assert_eq!(sums.iter().map(|&(count, _)| count).sum::<usize>(), 50);
+        assert_eq!(
+            sums.iter().map(|&(_, sum)| sum).sum::<u64>(),
+            (0..50).sum::<u64>()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_frame_index_edges() {
+        // Nothing, and less than a header
+        let empty = FrameIndex::build(&[]).unwrap();
+        assert_eq!((empty.len(), empty.truncated()), (0, None));
+        assert_eq!(empty.shard_ranges(4).count(), 0);
+        assert!(empty.range(&[], 0..3).next().is_none());
+        let short = FrameIndex::build(&trade_frame(0)[..5]).unwrap();
+        assert_eq!((short.len(), short.truncated()), (0, Some(0)));
+
+        let data: Vec<u8> = (0..10).flat_map(trade_frame).collect();
+        let index = FrameIndex::build(&data).unwrap();
+        let seqs = |frames: FrameIter<'_>| -> Vec<u32> {
+            frames
+                .map(|frame| frame.unwrap().header().unwrap().seq)
+                .collect()
+        };
+        // Ranges are clamped to the frames there are
+        assert_eq!(seqs(index.range(&data, 7..50)), [7, 8, 9]);
+        assert!(seqs(index.range(&data, 20..30)).is_empty());
+        #[allow(clippy::reversed_empty_ranges)]
+        let inverted = index.range(&data, 8..3);
+        assert!(seqs(inverted).is_empty());
+        // A slice shorter than the one indexed ends at a truncated frame
+        let mut cut = index.range(&data[..data.len() - 1], 8..10);
+        assert_eq!(cut.next().unwrap().unwrap().header().unwrap().seq, 8);
+        assert!(cut.next().is_none());
+
+        // At least one shard, and never an empty one
+        let mut ranges = index.shard_ranges(0);
+        assert_eq!((ranges.next(), ranges.next()), (Some(0..10), None));
+        assert_eq!(index.shard_ranges(20).count(), 10);
+        assert_eq!(
+            index.shard_ranges(4).collect::<Vec<_>>(),
+            [0..3, 3..6, 6..9, 9..10]
+        );
+
+        // A corrupt header mid-file yields the frames before it, then one error
+        let frame_len = trade_frame(0).len();
+        let mut corrupt = data.clone();
+        corrupt[3 * frame_len] ^= 0xff; // magic of frame 3
+        let mut frames = FrameIter::new(&corrupt);
+        assert_eq!(
+            frames
+                .by_ref()
+                .take(3)
+                .filter(|frame| frame.is_ok())
+                .count(),
+            3
+        );
+        assert!(frames.next().unwrap().is_err());
+        assert!(frames.next().is_none());
+        assert_eq!(frames.offset(), 3 * frame_len);
+        assert!(FrameIndex::build(&corrupt).is_err());
+    }
+
+    #[test]
+    fn test_map_empty_file() {
+        let path =
+            std::env::temp_dir().join(format!("minibit-mmap-empty-{}.bin", std::process::id()));
+        fs::write(&path, []).unwrap();
+        let mapped = MappedFile::open(&path).unwrap();
+        assert!(mapped.as_bytes().is_empty());
+        assert!(mapped.frames().next().is_none());
+        assert!(mapped.index().unwrap().is_empty());
+        assert!(mapped.journal().is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,1055 @@
+//! Packet capture (pcap and pcapng) reader for recorded traffic
+//!
+//! [`Capture`] walks the packets of a classic pcap or pcapng file held in
+//! memory, [`parse_segment`] strips the link, IP and UDP/TCP headers, and
+//! [`FrameExtractor`] turns the payloads for one port into frames: UDP
+//! datagrams are split into the frames they carry, TCP segments are
+//! reassembled per direction (reordering, retransmissions and lost
+//! segments) before frames are cut from the byte stream.
+//!
+//! Frames are handed out as verbatim byte slices, ready for
+//! [`FrameDecoder`](crate::FrameDecoder), together with the capture
+//! timestamp of the packet that completed them.
+//!
+//! Supported link types are Ethernet (with VLAN tags), BSD loopback, raw IP
+//! and Linux cooked captures (v1 and v2). IP fragments are skipped.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::error::{Error, Result};
+use crate::frame::FrameHeader;
+use crate::timestamp::{Timestamp, NANOS_PER_SEC};
+use crate::FRAME_MAGIC;
+
+/// Link-layer header types (`LINKTYPE_*` values)
+pub mod link_types {
+    /// BSD loopback, host-endian address family
+    pub const NULL: u16 = 0;
+    /// Ethernet II
+    pub const ETHERNET: u16 = 1;
+    /// Raw IP (some BSDs)
+    pub const RAW_BSD: u16 = 12;
+    /// Raw IP
+    pub const RAW: u16 = 101;
+    /// OpenBSD loopback, big-endian address family
+    pub const LOOP: u16 = 108;
+    /// Linux cooked capture v1
+    pub const LINUX_SLL: u16 = 113;
+    /// Raw IPv4
+    pub const IPV4: u16 = 228;
+    /// Raw IPv6
+    pub const IPV6: u16 = 229;
+    /// Linux cooked capture v2
+    pub const LINUX_SLL2: u16 = 276;
+}
+
+/// Default limit on out-of-order TCP bytes buffered per direction
+pub const DEFAULT_REORDER_LIMIT: usize = 1 << 20;
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const PCAP_HEADER_SIZE: usize = 24;
+const PCAP_RECORD_SIZE: usize = 16;
+
+const PCAPNG_SHB: u32 = 0x0a0d_0d0a;
+const PCAPNG_IDB: u32 = 1;
+const PCAPNG_SPB: u32 = 3;
+const PCAPNG_EPB: u32 = 6;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_OPT_END: u16 = 0;
+const PCAPNG_OPT_TSRESOL: u16 = 9;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+
+/// One captured packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet<'a> {
+    /// Capture timestamp
+    pub ts: Timestamp,
+    /// Link-layer header type of `data`
+    pub link_type: u16,
+    /// Captured bytes (possibly shorter than `orig_len` when snapped)
+    pub data: &'a [u8],
+    /// Length of the packet on the wire
+    pub orig_len: u32,
+}
+
+/// Bounds-checked reads in the capture's byte order
+#[derive(Debug, Clone, Copy)]
+struct Reader<'a> {
+    buf: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    #[inline]
+    fn bytes(&self, pos: usize, len: usize) -> Result<&'a [u8]> {
+        pos.checked_add(len)
+            .and_then(|end| self.buf.get(pos..end))
+            .ok_or(Error::UnexpectedEof)
+    }
+
+    #[inline]
+    fn u16(&self, pos: usize) -> Result<u16> {
+        let b: [u8; 2] = self
+            .bytes(pos, 2)?
+            .try_into()
+            .map_err(|_| Error::UnexpectedEof)?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    }
+
+    #[inline]
+    fn u32(&self, pos: usize) -> Result<u32> {
+        let b: [u8; 4] = self
+            .bytes(pos, 4)?
+            .try_into()
+            .map_err(|_| Error::UnexpectedEof)?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+}
+
+/// pcapng timestamp unit (`if_tsresol`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TsResolution {
+    /// 10^-n seconds
+    Decimal(u8),
+    /// 2^-n seconds
+    Binary(u8),
+}
+
+impl TsResolution {
+    fn from_option(value: u8) -> Self {
+        if value & 0x80 != 0 {
+            TsResolution::Binary(value & 0x7f)
+        } else {
+            TsResolution::Decimal(value)
+        }
+    }
+
+    fn to_timestamp(self, units: u64) -> Result<Timestamp> {
+        let nanos = match self {
+            TsResolution::Decimal(n) if n <= 9 => units.checked_mul(10u64.pow(9 - n as u32)),
+            TsResolution::Decimal(n) => 10u64
+                .checked_pow(n as u32 - 9)
+                .map(|div| units / div)
+                .or(Some(0)),
+            TsResolution::Binary(n) => {
+                let nanos = (units as u128 * NANOS_PER_SEC as u128)
+                    .checked_shr(n as u32)
+                    .unwrap_or(0);
+                u64::try_from(nanos).ok()
+            }
+        };
+        nanos.map(Timestamp::from_nanos).ok_or(Error::Overflow)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    link_type: u16,
+    resolution: TsResolution,
+}
+
+#[derive(Debug, Clone)]
+enum Format {
+    Pcap { nanos: bool, link_type: u16 },
+    PcapNg { interfaces: Vec<Interface> },
+}
+
+/// Packet capture file held in memory
+#[derive(Debug, Clone)]
+pub struct Capture<'a> {
+    reader: Reader<'a>,
+    format: Format,
+}
+
+impl<'a> Capture<'a> {
+    /// Recognise a pcap or pcapng file
+    ///
+    /// Returns `Error::InvalidCapture` for anything else.
+    pub fn new(buf: &'a [u8]) -> Result<Self> {
+        let le = Reader {
+            buf,
+            big_endian: false,
+        };
+        let magic = le.u32(0)?;
+
+        if magic == PCAPNG_SHB {
+            // The byte order is fixed by the section header's own magic
+            let big_endian = match le.u32(8)? {
+                PCAPNG_BYTE_ORDER_MAGIC => false,
+                m if m.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => true,
+                _ => return Err(Error::InvalidCapture),
+            };
+            return Ok(Self {
+                reader: Reader { buf, big_endian },
+                format: Format::PcapNg {
+                    interfaces: Vec::new(),
+                },
+            });
+        }
+
+        let (nanos, big_endian) = match magic {
+            PCAP_MAGIC_MICROS => (false, false),
+            PCAP_MAGIC_NANOS => (true, false),
+            m if m.swap_bytes() == PCAP_MAGIC_MICROS => (false, true),
+            m if m.swap_bytes() == PCAP_MAGIC_NANOS => (true, true),
+            _ => return Err(Error::InvalidCapture),
+        };
+        let reader = Reader { buf, big_endian };
+        let link_type = (reader.u32(20)? & 0xffff) as u16;
+        if buf.len() < PCAP_HEADER_SIZE {
+            return Err(Error::UnexpectedEof);
+        }
+        Ok(Self {
+            reader,
+            format: Format::Pcap { nanos, link_type },
+        })
+    }
+
+    /// Whether the file is pcapng rather than classic pcap
+    #[inline]
+    pub fn is_pcapng(&self) -> bool {
+        matches!(self.format, Format::PcapNg { .. })
+    }
+
+    /// Iterate over the captured packets
+    pub fn packets(&self) -> Packets<'a> {
+        let pos = match self.format {
+            Format::Pcap { .. } => PCAP_HEADER_SIZE,
+            Format::PcapNg { .. } => 0,
+        };
+        Packets {
+            reader: self.reader,
+            format: self.format.clone(),
+            pos,
+        }
+    }
+}
+
+/// Iterator over the packets of a [`Capture`]
+///
+/// Stops after the first error; a torn final record yields
+/// `Error::UnexpectedEof`.
+#[derive(Debug, Clone)]
+pub struct Packets<'a> {
+    reader: Reader<'a>,
+    format: Format,
+    pos: usize,
+}
+
+impl<'a> Packets<'a> {
+    fn next_pcap(&mut self, nanos: bool, link_type: u16) -> Result<Packet<'a>> {
+        let r = self.reader;
+        let secs = r.u32(self.pos)? as u64;
+        let frac = r.u32(self.pos + 4)? as u64;
+        let incl_len = r.u32(self.pos + 8)? as usize;
+        let orig_len = r.u32(self.pos + 12)?;
+        let data = r.bytes(self.pos + PCAP_RECORD_SIZE, incl_len)?;
+        self.pos += PCAP_RECORD_SIZE + incl_len;
+
+        let frac_nanos = if nanos { frac } else { frac * 1_000 };
+        Ok(Packet {
+            ts: Timestamp::from_nanos(secs * NANOS_PER_SEC + frac_nanos),
+            link_type,
+            data,
+            orig_len,
+        })
+    }
+
+    /// Next packet block, handling section and interface blocks on the way
+    fn next_pcapng(&mut self) -> Result<Option<Packet<'a>>> {
+        loop {
+            if self.pos >= self.reader.buf.len() {
+                return Ok(None);
+            }
+            let block_start = self.pos;
+
+            let le = Reader {
+                buf: self.reader.buf,
+                big_endian: false,
+            };
+            let block_type = le.u32(block_start)?;
+            if block_type == PCAPNG_SHB {
+                // New section: byte order and interfaces may change
+                self.reader.big_endian = match le.u32(block_start + 8)? {
+                    PCAPNG_BYTE_ORDER_MAGIC => false,
+                    m if m.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => true,
+                    _ => return Err(Error::InvalidCapture),
+                };
+                if let Format::PcapNg { interfaces } = &mut self.format {
+                    interfaces.clear();
+                }
+            }
+
+            let r = self.reader;
+            let block_type = r.u32(block_start)?;
+            let block_len = r.u32(block_start + 4)? as usize;
+            if block_len < 12 || !block_len.is_multiple_of(4) {
+                return Err(Error::InvalidCapture);
+            }
+            let block = r.bytes(block_start, block_len)?;
+            if r.u32(block_start + block_len - 4)? as usize != block_len {
+                return Err(Error::InvalidCapture);
+            }
+            self.pos += block_len;
+
+            let Format::PcapNg { interfaces } = &mut self.format else {
+                return Err(Error::InvalidCapture);
+            };
+            let body = Reader {
+                buf: &block[8..block_len - 4],
+                big_endian: r.big_endian,
+            };
+
+            match block_type {
+                PCAPNG_IDB => {
+                    let link_type = body.u16(0)?;
+                    let mut resolution = TsResolution::Decimal(6);
+                    let mut opt = 8;
+                    while opt + 4 <= body.buf.len() {
+                        let code = body.u16(opt)?;
+                        let len = body.u16(opt + 2)? as usize;
+                        if code == PCAPNG_OPT_END {
+                            break;
+                        }
+                        if code == PCAPNG_OPT_TSRESOL && len == 1 {
+                            resolution = TsResolution::from_option(body.bytes(opt + 4, 1)?[0]);
+                        }
+                        opt += 4 + len.next_multiple_of(4);
+                    }
+                    interfaces.push(Interface {
+                        link_type,
+                        resolution,
+                    });
+                }
+                PCAPNG_EPB => {
+                    let interface = *interfaces
+                        .get(body.u32(0)? as usize)
+                        .ok_or(Error::InvalidCapture)?;
+                    let units = (body.u32(4)? as u64) << 32 | body.u32(8)? as u64;
+                    let captured = body.u32(12)? as usize;
+                    return Ok(Some(Packet {
+                        ts: interface.resolution.to_timestamp(units)?,
+                        link_type: interface.link_type,
+                        data: body.bytes(20, captured)?,
+                        orig_len: body.u32(16)?,
+                    }));
+                }
+                PCAPNG_SPB => {
+                    // No timestamp; the captured length is implied by the block
+                    let interface = *interfaces.first().ok_or(Error::InvalidCapture)?;
+                    let orig_len = body.u32(0)?;
+                    let captured = (orig_len as usize).min(body.buf.len() - 4);
+                    return Ok(Some(Packet {
+                        ts: Timestamp::EPOCH,
+                        link_type: interface.link_type,
+                        data: body.bytes(4, captured)?,
+                        orig_len,
+                    }));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Packets<'a> {
+    type Item = Result<Packet<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.reader.buf.len() {
+            return None;
+        }
+        let result = match self.format {
+            Format::Pcap { nanos, link_type } => self.next_pcap(nanos, link_type).map(Some),
+            Format::PcapNg { .. } => self.next_pcapng(),
+        };
+        if result.is_err() {
+            self.pos = self.reader.buf.len();
+        }
+        result.transpose()
+    }
+}
+
+/// Transport protocol of a [`Segment`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// UDP datagram
+    Udp,
+    /// TCP segment
+    Tcp {
+        /// Sequence number of the first payload byte (or of the SYN)
+        seq: u32,
+        /// TCP flag bits (FIN 0x01, SYN 0x02, RST 0x04, ...)
+        flags: u8,
+    },
+}
+
+/// UDP or TCP payload with its endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment<'a> {
+    /// Sender address and port
+    pub src: SocketAddr,
+    /// Receiver address and port
+    pub dst: SocketAddr,
+    /// Protocol and TCP state
+    pub transport: Transport,
+    /// Application payload
+    pub payload: &'a [u8],
+}
+
+/// Strip link, IP and transport headers from a captured packet
+///
+/// Returns `None` for non-IP traffic, protocols other than UDP and TCP, IP
+/// fragments and packets truncated inside a header.
+pub fn parse_segment(link_type: u16, data: &[u8]) -> Option<Segment<'_>> {
+    let ip = match link_type {
+        link_types::ETHERNET => {
+            let mut ethertype = u16::from_be_bytes([*data.get(12)?, *data.get(13)?]);
+            let mut offset = 14;
+            // 802.1Q / 802.1ad tags
+            while ethertype == 0x8100 || ethertype == 0x88a8 {
+                ethertype = u16::from_be_bytes([*data.get(offset + 2)?, *data.get(offset + 3)?]);
+                offset += 4;
+            }
+            if ethertype != 0x0800 && ethertype != 0x86dd {
+                return None;
+            }
+            data.get(offset..)?
+        }
+        link_types::NULL | link_types::LOOP => data.get(4..)?,
+        link_types::RAW | link_types::RAW_BSD | link_types::IPV4 | link_types::IPV6 => data,
+        link_types::LINUX_SLL => data.get(16..)?,
+        link_types::LINUX_SLL2 => data.get(20..)?,
+        _ => return None,
+    };
+
+    let (src_ip, dst_ip, protocol, transport) = match ip.first()? >> 4 {
+        4 => {
+            let header_len = ((ip[0] & 0x0f) as usize) * 4;
+            let total_len = u16::from_be_bytes([*ip.get(2)?, *ip.get(3)?]) as usize;
+            let fragment = u16::from_be_bytes([*ip.get(6)?, *ip.get(7)?]);
+            if fragment & 0x3fff != 0 {
+                return None; // more-fragments bit or non-zero offset
+            }
+            let src: [u8; 4] = ip.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = ip.get(16..20)?.try_into().ok()?;
+            // Ethernet padding may follow the datagram
+            let end = total_len.min(ip.len());
+            (
+                IpAddr::V4(Ipv4Addr::from(src)),
+                IpAddr::V4(Ipv4Addr::from(dst)),
+                *ip.get(9)?,
+                ip.get(header_len..end)?,
+            )
+        }
+        6 => {
+            let payload_len = u16::from_be_bytes([*ip.get(4)?, *ip.get(5)?]) as usize;
+            let src: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+            let mut next_header = *ip.get(6)?;
+            let mut rest = ip.get(40..(40 + payload_len).min(ip.len()))?;
+            // Hop-by-hop, routing and destination options headers
+            while matches!(next_header, 0 | 43 | 60) {
+                let len = (*rest.get(1)? as usize + 1) * 8;
+                next_header = *rest.first()?;
+                rest = rest.get(len..)?;
+            }
+            (
+                IpAddr::V6(Ipv6Addr::from(src)),
+                IpAddr::V6(Ipv6Addr::from(dst)),
+                next_header,
+                rest,
+            )
+        }
+        _ => return None,
+    };
+
+    let src_port = u16::from_be_bytes([*transport.first()?, *transport.get(1)?]);
+    let dst_port = u16::from_be_bytes([*transport.get(2)?, *transport.get(3)?]);
+    let (transport_kind, payload) = match protocol {
+        17 => {
+            let len = u16::from_be_bytes([*transport.get(4)?, *transport.get(5)?]) as usize;
+            (Transport::Udp, transport.get(8..len.min(transport.len()))?)
+        }
+        6 => {
+            let seq = u32::from_be_bytes(transport.get(4..8)?.try_into().ok()?);
+            let data_offset = ((*transport.get(12)? >> 4) as usize) * 4;
+            let flags = *transport.get(13)?;
+            (Transport::Tcp { seq, flags }, transport.get(data_offset..)?)
+        }
+        _ => return None,
+    };
+
+    Some(Segment {
+        src: SocketAddr::new(src_ip, src_port),
+        dst: SocketAddr::new(dst_ip, dst_port),
+        transport: transport_kind,
+        payload,
+    })
+}
+
+/// Something found while extracting frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// A complete frame (header decoded, CRC not yet verified)
+    Frame {
+        /// Capture time of the packet that completed the frame
+        ts: Timestamp,
+        /// Sender
+        src: SocketAddr,
+        /// Receiver
+        dst: SocketAddr,
+        /// Verbatim frame bytes
+        frame: &'a [u8],
+    },
+    /// Bytes that do not form a frame
+    Malformed {
+        /// Capture time
+        ts: Timestamp,
+        /// Sender
+        src: SocketAddr,
+        /// Receiver
+        dst: SocketAddr,
+        /// Why the bytes were rejected
+        error: Error,
+        /// Number of bytes discarded
+        skipped: usize,
+    },
+    /// TCP bytes that were never captured; buffered partial data is dropped
+    Gap {
+        /// Capture time
+        ts: Timestamp,
+        /// Sender
+        src: SocketAddr,
+        /// Receiver
+        dst: SocketAddr,
+        /// Number of stream bytes missing
+        missing: u32,
+    },
+}
+
+/// Reassembly state of one TCP direction
+#[derive(Debug, Default)]
+struct TcpStream {
+    /// Sequence number of the next in-order byte
+    next_seq: Option<u32>,
+    /// Segments received ahead of `next_seq`
+    pending: Vec<(u32, Vec<u8>)>,
+    pending_bytes: usize,
+    /// In-order bytes not yet cut into frames
+    buf: Vec<u8>,
+}
+
+impl TcpStream {
+    /// Append the in-order part of a segment starting at `seq`
+    ///
+    /// Returns `false` if the segment lies entirely ahead of the stream.
+    fn append(&mut self, seq: u32, payload: &[u8]) -> bool {
+        let next = self.next_seq.unwrap_or(seq);
+        let ahead = seq.wrapping_sub(next) as i32;
+        if ahead > 0 {
+            return false;
+        }
+        let overlap = ahead.unsigned_abs() as usize;
+        if overlap < payload.len() {
+            self.buf.extend_from_slice(&payload[overlap..]);
+            self.next_seq = Some(next.wrapping_add((payload.len() - overlap) as u32));
+        } else {
+            self.next_seq = Some(next);
+        }
+        true
+    }
+
+    /// Move pending segments that have become contiguous into the buffer
+    fn drain_pending(&mut self) {
+        while let Some(i) = self.pending.iter().position(|&(seq, _)| {
+            let next = self.next_seq.unwrap_or(seq);
+            seq.wrapping_sub(next) as i32 <= 0
+        }) {
+            let (seq, data) = self.pending.swap_remove(i);
+            self.pending_bytes -= data.len();
+            self.append(seq, &data);
+        }
+    }
+
+    /// Skip to the earliest pending segment, returning the bytes skipped
+    fn skip_gap(&mut self) -> Option<u32> {
+        let next = self.next_seq?;
+        let earliest = self
+            .pending
+            .iter()
+            .map(|&(seq, _)| seq)
+            .min_by_key(|&seq| seq.wrapping_sub(next))?;
+        self.buf.clear();
+        self.next_seq = Some(earliest);
+        self.drain_pending();
+        Some(earliest.wrapping_sub(next))
+    }
+}
+
+/// Cut frames from `buf`, returning the number of bytes consumed
+///
+/// With `at_end`, a trailing partial frame is reported as malformed instead
+/// of being left for more data.
+fn split_frames(
+    buf: &[u8],
+    at_end: bool,
+    mut on_frame: impl FnMut(core::result::Result<&[u8], (Error, usize)>),
+) -> usize {
+    let magic = FRAME_MAGIC.to_le_bytes();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let rest = &buf[pos..];
+        match FrameHeader::decode(rest) {
+            Ok(header) if header.total_size() <= rest.len() => {
+                on_frame(Ok(&rest[..header.total_size()]));
+                pos += header.total_size();
+                continue;
+            }
+            Ok(_) | Err(Error::UnexpectedEof) if !at_end => break,
+            Ok(_) | Err(Error::UnexpectedEof) => {
+                on_frame(Err((Error::UnexpectedEof, rest.len())));
+                pos = buf.len();
+            }
+            Err(error) => {
+                // Resynchronise on the next magic number
+                let skip = (1..rest.len())
+                    .find(|&at| rest[at..].starts_with(&magic[..(rest.len() - at).min(2)]))
+                    .unwrap_or(rest.len());
+                on_frame(Err((error, skip)));
+                pos += skip;
+            }
+        }
+    }
+    pos
+}
+
+/// Extracts frames for one port from captured packets
+#[derive(Debug)]
+pub struct FrameExtractor {
+    port: Option<u16>,
+    reorder_limit: usize,
+    streams: BTreeMap<(SocketAddr, SocketAddr), TcpStream>,
+}
+
+impl FrameExtractor {
+    /// Extract traffic to or from `port`
+    pub fn new(port: u16) -> Self {
+        Self {
+            port: Some(port),
+            reorder_limit: DEFAULT_REORDER_LIMIT,
+            streams: BTreeMap::new(),
+        }
+    }
+
+    /// Extract UDP and TCP traffic on every port
+    pub fn all_ports() -> Self {
+        Self {
+            port: None,
+            ..Self::new(0)
+        }
+    }
+
+    /// Set how many out-of-order TCP bytes to buffer per direction before
+    /// declaring the missing bytes lost
+    pub fn with_reorder_limit(mut self, bytes: usize) -> Self {
+        self.reorder_limit = bytes;
+        self
+    }
+
+    /// Process one packet
+    pub fn push(&mut self, packet: &Packet<'_>, mut on_event: impl FnMut(Event<'_>)) {
+        let Some(segment) = parse_segment(packet.link_type, packet.data) else {
+            return;
+        };
+        if let Some(port) = self.port {
+            if segment.src.port() != port && segment.dst.port() != port {
+                return;
+            }
+        }
+
+        let (ts, src, dst) = (packet.ts, segment.src, segment.dst);
+        let emit = |on_event: &mut dyn FnMut(Event<'_>),
+                    item: core::result::Result<&[u8], (Error, usize)>| {
+            on_event(match item {
+                Ok(frame) => Event::Frame {
+                    ts,
+                    src,
+                    dst,
+                    frame,
+                },
+                Err((error, skipped)) => Event::Malformed {
+                    ts,
+                    src,
+                    dst,
+                    error,
+                    skipped,
+                },
+            })
+        };
+
+        let Transport::Tcp { seq, flags } = segment.transport else {
+            split_frames(segment.payload, true, |item| emit(&mut on_event, item));
+            return;
+        };
+
+        if flags & TCP_RST != 0 {
+            self.streams.remove(&(src, dst));
+            return;
+        }
+        let stream = self.streams.entry((src, dst)).or_default();
+        let mut seq = seq;
+        if flags & TCP_SYN != 0 {
+            *stream = TcpStream::default();
+            seq = seq.wrapping_add(1);
+            stream.next_seq = Some(seq);
+        }
+
+        if !segment.payload.is_empty() && !stream.append(seq, segment.payload) {
+            if !stream.pending.iter().any(|&(s, _)| s == seq) {
+                stream.pending.push((seq, segment.payload.to_vec()));
+                stream.pending_bytes += segment.payload.len();
+            }
+            while stream.pending_bytes > self.reorder_limit {
+                let Some(missing) = stream.skip_gap() else {
+                    break;
+                };
+                on_event(Event::Gap {
+                    ts,
+                    src,
+                    dst,
+                    missing,
+                });
+            }
+        }
+        stream.drain_pending();
+
+        let fin = flags & TCP_FIN != 0;
+        let consumed = split_frames(&stream.buf, fin, |item| emit(&mut on_event, item));
+        stream.buf.drain(..consumed);
+        if fin {
+            self.streams.remove(&(src, dst));
+        }
+    }
+
+    /// Report data left in streams that never completed
+    ///
+    /// Call once after the last packet.
+    pub fn finish(&mut self, ts: Timestamp, mut on_event: impl FnMut(Event<'_>)) {
+        for ((src, dst), mut stream) in core::mem::take(&mut self.streams) {
+            while let Some(missing) = stream.skip_gap() {
+                on_event(Event::Gap {
+                    ts,
+                    src,
+                    dst,
+                    missing,
+                });
+                let consumed = split_frames(&stream.buf, false, |item| {
+                    if let Ok(frame) = item {
+                        on_event(Event::Frame {
+                            ts,
+                            src,
+                            dst,
+                            frame,
+                        });
+                    }
+                });
+                stream.buf.drain(..consumed);
+            }
+            if !stream.buf.is_empty() {
+                on_event(Event::Malformed {
+                    ts,
+                    src,
+                    dst,
+                    error: Error::UnexpectedEof,
+                    skipped: stream.buf.len(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::trade_frame;
+    use std::vec;
+
+    const PORT: u16 = 9000;
+
+    /// Ethernet + IPv4 + UDP/TCP packet
+    fn ipv4_packet(tcp: Option<(u32, u8)>, src_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut transport = Vec::new();
+        transport.extend_from_slice(&src_port.to_be_bytes());
+        transport.extend_from_slice(&PORT.to_be_bytes());
+        let protocol = match tcp {
+            None => {
+                transport.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+                transport.extend_from_slice(&[0, 0]);
+                17
+            }
+            Some((seq, flags)) => {
+                transport.extend_from_slice(&seq.to_be_bytes());
+                transport.extend_from_slice(&[0; 4]);
+                transport.extend_from_slice(&[5 << 4, flags, 0xff, 0xff, 0, 0, 0, 0]);
+                6
+            }
+        };
+        transport.extend_from_slice(payload);
+
+        let mut packet = vec![0u8; 12];
+        packet.extend_from_slice(&[0x08, 0x00]);
+        packet.extend_from_slice(&[0x45, 0]);
+        packet.extend_from_slice(&((20 + transport.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0x40, 0, 64, protocol, 0, 0]);
+        packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        packet.extend_from_slice(&transport);
+        packet.extend_from_slice(&[0; 4]); // Ethernet padding
+        packet
+    }
+
+    fn pcap_file(packets: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut file = Vec::new();
+        file.extend_from_slice(&PCAP_MAGIC_NANOS.to_be_bytes());
+        file.extend_from_slice(&[0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff]);
+        file.extend_from_slice(&(link_types::ETHERNET as u32).to_be_bytes());
+        for (ts_ns, data) in packets {
+            file.extend_from_slice(&((ts_ns / NANOS_PER_SEC) as u32).to_be_bytes());
+            file.extend_from_slice(&((ts_ns % NANOS_PER_SEC) as u32).to_be_bytes());
+            file.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            file.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            file.extend_from_slice(data);
+        }
+        file
+    }
+
+    /// Collect (ts, seq) of frames and a count of other events
+    fn extract(
+        extractor: &mut FrameExtractor,
+        capture: &[u8],
+    ) -> (Vec<(u64, u32)>, Vec<Event<'static>>) {
+        let (mut frames, mut other) = (Vec::new(), Vec::new());
+        let mut last_ts = Timestamp::EPOCH;
+        let mut on_event = |event: Event<'_>| match event {
+            Event::Frame { ts, frame, .. } => {
+                let header = FrameHeader::decode(frame).unwrap();
+                crate::FrameDecoder::new(frame).verify_crc32c().unwrap();
+                frames.push((ts.as_nanos(), header.seq));
+            }
+            Event::Malformed {
+                ts,
+                src,
+                dst,
+                error,
+                skipped,
+            } => other.push(Event::Malformed {
+                ts,
+                src,
+                dst,
+                error,
+                skipped,
+            }),
+            Event::Gap {
+                ts,
+                src,
+                dst,
+                missing,
+            } => other.push(Event::Gap {
+                ts,
+                src,
+                dst,
+                missing,
+            }),
+        };
+        for packet in Capture::new(capture).unwrap().packets() {
+            let packet = packet.unwrap();
+            last_ts = packet.ts;
+            extractor.push(&packet, &mut on_event);
+        }
+        extractor.finish(last_ts, &mut on_event);
+        (frames, other)
+    }
+
+    #[test]
+    fn test_pcap_udp_batches_and_port_filter() {
+        let mut batch = trade_frame(1);
+        batch.extend(trade_frame(2));
+        let capture = pcap_file(&[
+            (1_000_000_001, ipv4_packet(None, 5000, &batch)),
+            (
+                2_000_000_000,
+                ipv4_packet(None, 5000, &trade_frame(3)[..20]),
+            ),
+        ]);
+
+        let (frames, other) = extract(&mut FrameExtractor::new(PORT), &capture);
+        assert_eq!(frames, [(1_000_000_001, 1), (1_000_000_001, 2)]);
+        assert!(matches!(
+            other[..],
+            [Event::Malformed {
+                error: Error::UnexpectedEof,
+                skipped: 20,
+                ..
+            }]
+        ));
+
+        let (frames, _) = extract(&mut FrameExtractor::new(PORT + 1), &capture);
+        assert!(frames.is_empty());
+    }
+
+    #[test]
+    fn test_tcp_reassembly() {
+        let mut stream = Vec::new();
+        for seq in 1..=3 {
+            stream.extend(trade_frame(seq));
+        }
+        let isn = u32::MAX - 10; // sequence numbers wrap mid-stream
+        let data = isn.wrapping_add(1);
+        let at = |offset: usize| data.wrapping_add(offset as u32);
+        let (a, b) = (30, 70);
+
+        let capture = pcap_file(&[
+            (1, ipv4_packet(Some((isn, TCP_SYN)), 5000, &[])),
+            (2, ipv4_packet(Some((at(b), 0x18)), 5000, &stream[b..])), // early
+            (3, ipv4_packet(Some((at(0), 0x18)), 5000, &stream[..a])),
+            (4, ipv4_packet(Some((at(0), 0x18)), 5000, &stream[..a])), // retransmit
+            (
+                5,
+                ipv4_packet(Some((at(a - 5), 0x18)), 5000, &stream[a - 5..b]),
+            ), // overlap
+            (6, ipv4_packet(Some((at(stream.len()), TCP_FIN)), 5000, &[])),
+        ]);
+
+        let (frames, other) = extract(&mut FrameExtractor::new(PORT), &capture);
+        assert_eq!(frames, [(5, 1), (5, 2), (5, 3)]);
+        assert!(other.is_empty(), "{:?}", other);
+    }
+
+    #[test]
+    fn test_tcp_lost_segment_reported_as_gap() {
+        let mut stream = Vec::new();
+        for seq in 1..=3 {
+            stream.extend(trade_frame(seq));
+        }
+        let frame_len = stream.len() / 3;
+
+        // Mid-stream capture start, then the middle of frame 2 is lost
+        let capture = pcap_file(&[
+            (
+                1,
+                ipv4_packet(Some((100, 0x18)), 5000, &stream[..frame_len + 5]),
+            ),
+            (
+                2,
+                ipv4_packet(
+                    Some((100 + 2 * frame_len as u32, 0x18)),
+                    5000,
+                    &stream[2 * frame_len..],
+                ),
+            ),
+        ]);
+
+        let (frames, other) = extract(
+            &mut FrameExtractor::new(PORT).with_reorder_limit(16),
+            &capture,
+        );
+        assert_eq!(frames, [(1, 1), (2, 3)]);
+        assert_eq!(
+            other
+                .iter()
+                .map(
+                    |e| matches!(e, Event::Gap { missing, .. } if *missing == frame_len as u32 - 5)
+                )
+                .collect::<Vec<_>>(),
+            [true]
+        );
+    }
+
+    #[test]
+    fn test_pcapng_and_ipv6() {
+        let frame = trade_frame(7);
+        let mut udp = Vec::new();
+        udp.extend_from_slice(&5000u16.to_be_bytes());
+        udp.extend_from_slice(&PORT.to_be_bytes());
+        udp.extend_from_slice(&((8 + frame.len()) as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(&frame);
+        let mut ip6 = vec![0x60, 0, 0, 0];
+        ip6.extend_from_slice(&(udp.len() as u16).to_be_bytes());
+        ip6.extend_from_slice(&[17, 64]);
+        ip6.extend_from_slice(&[0; 15]);
+        ip6.push(1);
+        ip6.extend_from_slice(&[0; 15]);
+        ip6.push(2);
+        ip6.extend_from_slice(&udp);
+
+        let block = |ty: u32, body: &[u8]| {
+            let len = (12 + body.len().next_multiple_of(4)) as u32;
+            let mut b = Vec::new();
+            b.extend_from_slice(&ty.to_le_bytes());
+            b.extend_from_slice(&len.to_le_bytes());
+            b.extend_from_slice(body);
+            b.resize(len as usize - 4, 0);
+            b.extend_from_slice(&len.to_le_bytes());
+            b
+        };
+        let mut file = block(
+            PCAPNG_SHB,
+            &[
+                0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            ],
+        );
+        // Raw IPv6 interface with nanosecond resolution
+        let mut idb = Vec::new();
+        idb.extend_from_slice(&link_types::IPV6.to_le_bytes());
+        idb.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        idb.extend_from_slice(&[PCAPNG_OPT_TSRESOL as u8, 0, 1, 0, 9, 0, 0, 0]);
+        idb.extend_from_slice(&[0, 0, 0, 0]);
+        file.extend(block(PCAPNG_IDB, &idb));
+        file.extend(block(0x0000_0bad, &[1, 2, 3, 4])); // custom block, skipped
+
+        let ts: u64 = 1_700_000_000_123_456_789;
+        let mut epb = Vec::new();
+        epb.extend_from_slice(&0u32.to_le_bytes());
+        epb.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        epb.extend_from_slice(&(ts as u32).to_le_bytes());
+        epb.extend_from_slice(&(ip6.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&(ip6.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&ip6);
+        file.extend(block(PCAPNG_EPB, &epb));
+
+        let capture = Capture::new(&file).unwrap();
+        assert!(capture.is_pcapng());
+        let packet = capture.packets().next().unwrap().unwrap();
+        let segment = parse_segment(packet.link_type, packet.data).unwrap();
+        assert_eq!(segment.src, "[::1]:5000".parse().unwrap());
+        assert_eq!(segment.transport, Transport::Udp);
+
+        let (frames, other) = extract(&mut FrameExtractor::all_ports(), &file);
+        assert_eq!(frames, [(ts, 7)]);
+        assert!(other.is_empty());
+
+        // A torn final block is an error, not a silent stop
+        let torn = &file[..file.len() - 3];
+        let results: Vec<_> = Capture::new(torn).unwrap().packets().collect();
+        assert_eq!(results, [Err(Error::UnexpectedEof)]);
+        assert_eq!(
+            Capture::new(b"not a capture").unwrap_err(),
+            Error::InvalidCapture
+        );
+    }
+}
@@ -0,0 +1,650 @@
+//! Time-scaled replay of recorded frames
+//!
+//! [`Replay`] merges one or more recordings into a single stream ordered by
+//! timestamp and hands each frame to a [`ReplaySink`], waiting between
+//! frames so that the recorded gaps are reproduced in real time, scaled, or
+//! skipped altogether ([`Speed`]). Timestamps come from journal records
+//! (receive time) or, for plain frame files, from a body field such as
+//! `ts_ns` (see [`ts_ns_field`]).
+//!
+//! A [`ReplayControl`] handle pauses, resumes, seeks, changes speed or stops
+//! a replay running on another thread. With seq rewriting enabled, every
+//! delivered frame is restamped from a [`Sequencer`] and its CRC
+//! recomputed, so merged recordings form one gap-free stream per channel.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use std::vec::Vec;
+use std::{io, thread};
+
+use crate::crc32c::crc32c;
+use crate::decoder::FrameDecoder;
+use crate::dynamic::{DynamicDecoder, Value};
+use crate::error::{Error, Result};
+use crate::frame::FrameHeader;
+use crate::journal::JournalView;
+use crate::sequence::Sequencer;
+use crate::timestamp::{Clock, MonotonicClock, Timestamp};
+
+/// Longest single wait, so control commands take effect promptly
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Playback pace
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    /// Reproduce the recorded gaps exactly
+    RealTime,
+    /// Play `n` times faster than recorded (`0.5` is half speed)
+    Scaled(f64),
+    /// Deliver frames as fast as the sink accepts them
+    Max,
+}
+
+impl Speed {
+    /// Wall-clock time for a recorded gap, or `None` to not wait at all
+    fn scale(self, recorded_nanos: u64) -> Option<u64> {
+        match self {
+            Speed::RealTime => Some(recorded_nanos),
+            Speed::Scaled(factor) if factor > 0.0 => Some((recorded_nanos as f64 / factor) as u64),
+            Speed::Scaled(_) | Speed::Max => None,
+        }
+    }
+}
+
+/// Receives replayed frames
+pub trait ReplaySink {
+    /// Deliver one frame with its recorded timestamp
+    fn deliver(&mut self, ts: Timestamp, frame: &[u8]) -> io::Result<()>;
+}
+
+impl<F: FnMut(Timestamp, &[u8]) -> io::Result<()>> ReplaySink for F {
+    #[inline]
+    fn deliver(&mut self, ts: Timestamp, frame: &[u8]) -> io::Result<()> {
+        self(ts, frame)
+    }
+}
+
+/// Time source that can also wait, so tests can replace real sleeping
+pub trait ReplayClock: Clock {
+    /// Block for `duration`
+    fn sleep(&mut self, duration: Duration);
+}
+
+impl ReplayClock for MonotonicClock {
+    #[inline]
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// Read a `ts_ns` body field through the schemas known to `decoder`
+///
+/// Accepts integer (nanoseconds) and timestamp fields; returns `None` for
+/// unknown message types and messages without the field.
+pub fn ts_ns_field(decoder: &DynamicDecoder, frame: &[u8]) -> Option<Timestamp> {
+    let (_, message) = decoder.decode(frame).ok()?;
+    match message.get("ts_ns")? {
+        Value::U64(nanos) => Some(Timestamp::from_nanos(*nanos)),
+        Value::Timestamp(ts) => Some(*ts),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum SeekTo {
+    Index(usize),
+    Time(Timestamp),
+}
+
+#[derive(Debug, Default)]
+struct ControlState {
+    paused: bool,
+    stopped: bool,
+    seek: Option<SeekTo>,
+    speed: Option<Speed>,
+}
+
+/// Handle for steering a [`Replay`], possibly from another thread
+#[derive(Debug, Clone, Default)]
+pub struct ReplayControl {
+    state: Arc<Mutex<ControlState>>,
+}
+
+impl ReplayControl {
+    fn lock(&self) -> MutexGuard<'_, ControlState> {
+        // The state stays consistent even if a holder panicked
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Hold delivery until [`resume`](Self::resume)
+    pub fn pause(&self) {
+        self.lock().paused = true;
+    }
+
+    /// Continue after a pause, pacing from the next frame
+    pub fn resume(&self) {
+        self.lock().paused = false;
+    }
+
+    /// Whether the replay is paused
+    pub fn is_paused(&self) -> bool {
+        self.lock().paused
+    }
+
+    /// Continue from the first frame recorded at or after `ts`
+    pub fn seek_time(&self, ts: Timestamp) {
+        self.lock().seek = Some(SeekTo::Time(ts));
+    }
+
+    /// Continue from the `index`-th frame of the merged stream
+    pub fn seek_index(&self, index: usize) {
+        self.lock().seek = Some(SeekTo::Index(index));
+    }
+
+    /// Change the playback pace
+    pub fn set_speed(&self, speed: Speed) {
+        self.lock().speed = Some(speed);
+    }
+
+    /// End the replay; the running [`Replay::run`] returns
+    pub fn stop(&self) {
+        self.lock().stopped = true;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry<'a> {
+    ts: Timestamp,
+    frame: &'a [u8],
+}
+
+/// Paced playback of one or more merged recordings
+///
+/// Frames borrow from the recordings (journal views, mapped files or
+/// buffers), which must outlive the replay.
+#[derive(Debug)]
+pub struct Replay<'a, C = MonotonicClock> {
+    entries: Vec<Entry<'a>>,
+    sorted: bool,
+    pos: usize,
+    speed: Speed,
+    clock: C,
+    control: ReplayControl,
+    paused: bool,
+    /// Clock reading and recorded time that pacing is measured from
+    anchor: Option<(Timestamp, Timestamp)>,
+    sequencer: Option<Sequencer>,
+    scratch: Vec<u8>,
+}
+
+impl<'a> Replay<'a> {
+    /// Create an empty real-time replay
+    pub fn new() -> Self {
+        Self::with_clock(MonotonicClock::new())
+    }
+}
+
+impl Default for Replay<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, C: ReplayClock> Replay<'a, C> {
+    /// Create an empty real-time replay paced by `clock`
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            entries: Vec::new(),
+            sorted: true,
+            pos: 0,
+            speed: Speed::RealTime,
+            clock,
+            control: ReplayControl::default(),
+            paused: false,
+            anchor: None,
+            sequencer: None,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Set the playback pace
+    pub fn with_speed(mut self, speed: Speed) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Restamp every delivered frame's seq from `sequencer`
+    ///
+    /// The CRC is recomputed for frames whose CRC was valid; frames that
+    /// arrive corrupt stay detectably corrupt.
+    pub fn with_seq_rewrite(mut self, sequencer: Sequencer) -> Self {
+        self.sequencer = Some(sequencer);
+        self
+    }
+
+    /// Handle for pausing, seeking and stopping this replay
+    pub fn control(&self) -> ReplayControl {
+        self.control.clone()
+    }
+
+    /// Add one frame recorded at `ts`
+    pub fn push(&mut self, ts: Timestamp, frame: &'a [u8]) {
+        self.sorted &= self.entries.last().is_none_or(|last| last.ts <= ts);
+        self.entries.push(Entry { ts, frame });
+    }
+
+    /// Add every frame of a journal file, timed by receive timestamp
+    ///
+    /// Returns the number of frames added; a torn tail is ignored.
+    pub fn add_journal(&mut self, view: &JournalView<'a>) -> io::Result<usize> {
+        let before = self.entries.len();
+        for frame in view.frames() {
+            let frame = frame?;
+            self.push(frame.ts, frame.frame);
+        }
+        Ok(self.entries.len() - before)
+    }
+
+    /// Add the frames of a plain frame file, timed by `ts_of`
+    ///
+    /// Frames for which `ts_of` returns `None` inherit the previous frame's
+    /// time. Returns the number of frames added; a truncated final frame is
+    /// ignored.
+    pub fn add_frames(
+        &mut self,
+        mut buf: &'a [u8],
+        mut ts_of: impl FnMut(&[u8]) -> Option<Timestamp>,
+    ) -> Result<usize> {
+        let mut count = 0;
+        let mut last = Timestamp::EPOCH;
+        while !buf.is_empty() {
+            let size = match FrameHeader::decode(buf) {
+                Ok(header) if header.total_size() <= buf.len() => header.total_size(),
+                Ok(_) | Err(Error::UnexpectedEof) => break,
+                Err(err) => return Err(err),
+            };
+            let (frame, rest) = buf.split_at(size);
+            last = ts_of(frame).unwrap_or(last);
+            self.push(last, frame);
+            count += 1;
+            buf = rest;
+        }
+        Ok(count)
+    }
+
+    /// Number of frames in the merged stream
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no frames
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Index of the next frame to deliver
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Continue from the first frame recorded at or after `ts`
+    pub fn seek_time(&mut self, ts: Timestamp) {
+        self.sort();
+        self.pos = self.entries.partition_point(|entry| entry.ts < ts);
+        self.anchor = None;
+    }
+
+    /// Continue from the `index`-th frame of the merged stream
+    pub fn seek_index(&mut self, index: usize) {
+        self.sort();
+        self.pos = index.min(self.entries.len());
+        self.anchor = None;
+    }
+
+    /// Wait for and deliver the next frame
+    ///
+    /// Returns `false` once the stream is exhausted or the replay was
+    /// stopped.
+    pub fn step(&mut self, sink: &mut impl ReplaySink) -> io::Result<bool> {
+        self.sort();
+        loop {
+            if !self.apply_commands() {
+                return Ok(false);
+            }
+            if self.paused {
+                self.clock.sleep(POLL_INTERVAL);
+                continue;
+            }
+            let Some(entry) = self.entries.get(self.pos).copied() else {
+                return Ok(false);
+            };
+            if let Some(wait) = self.wait_for(entry.ts) {
+                self.clock.sleep(wait.min(POLL_INTERVAL));
+                continue;
+            }
+
+            self.pos += 1;
+            let frame = match &mut self.sequencer {
+                Some(sequencer) => restamp(sequencer, entry.frame, &mut self.scratch)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+                None => entry.frame,
+            };
+            sink.deliver(entry.ts, frame)?;
+            return Ok(true);
+        }
+    }
+
+    /// Deliver frames until the stream is exhausted or the replay is
+    /// stopped, returning the number delivered
+    pub fn run(&mut self, sink: &mut impl ReplaySink) -> io::Result<u64> {
+        let mut delivered = 0;
+        while self.step(sink)? {
+            delivered += 1;
+        }
+        Ok(delivered)
+    }
+
+    /// Merge recordings into timestamp order, keeping each one's own order
+    fn sort(&mut self) {
+        if !self.sorted {
+            self.entries.sort_by_key(|entry| entry.ts);
+            self.sorted = true;
+        }
+    }
+
+    /// Apply pending control commands; `false` if stopped
+    fn apply_commands(&mut self) -> bool {
+        let (paused, stopped, seek, speed) = {
+            let mut state = self.control.lock();
+            (
+                state.paused,
+                state.stopped,
+                state.seek.take(),
+                state.speed.take(),
+            )
+        };
+        match seek {
+            Some(SeekTo::Index(index)) => self.seek_index(index),
+            Some(SeekTo::Time(ts)) => self.seek_time(ts),
+            None => {}
+        }
+        if let Some(speed) = speed {
+            self.speed = speed;
+            self.anchor = None;
+        }
+        if paused != self.paused {
+            self.paused = paused;
+            self.anchor = None;
+        }
+        !stopped
+    }
+
+    /// How long to wait before delivering a frame recorded at `ts`
+    fn wait_for(&mut self, ts: Timestamp) -> Option<Duration> {
+        let now = self.clock.now();
+        let (start, recorded) = *self.anchor.get_or_insert((now, ts));
+        let offset = self.speed.scale(ts.saturating_nanos_since(recorded))?;
+        let due = start.as_nanos().saturating_add(offset);
+        let wait = due.saturating_sub(now.as_nanos());
+        (wait > 0).then(|| Duration::from_nanos(wait))
+    }
+}
+
+/// Copy `frame` into `scratch` with the next seq for its channel
+fn restamp<'s>(
+    sequencer: &mut Sequencer,
+    frame: &[u8],
+    scratch: &'s mut Vec<u8>,
+) -> Result<&'s [u8]> {
+    let mut header = FrameHeader::decode(frame)?;
+    let crc_ok = FrameDecoder::new(frame).verify_crc32c().is_ok();
+    sequencer.stamp(&mut header);
+
+    scratch.clear();
+    scratch.extend_from_slice(frame);
+    header.encode(scratch)?;
+    if crc_ok {
+        let crc_at = scratch.len() - 4;
+        let crc = crc32c(&scratch[..crc_at]);
+        scratch[crc_at..].copy_from_slice(&crc.to_le_bytes());
+    }
+    Ok(scratch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::JournalWriter;
+    use crate::schema::SchemaRegistry;
+    use crate::test_util::trade_frame_at;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::{format, fs, vec};
+
+    /// Clock that only moves when slept on
+    #[derive(Debug, Clone, Default)]
+    struct FakeClock(Rc<Cell<u64>>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Timestamp {
+            Timestamp::from_nanos(self.0.get())
+        }
+    }
+
+    impl ReplayClock for FakeClock {
+        fn sleep(&mut self, duration: Duration) {
+            self.0.set(self.0.get() + duration.as_nanos() as u64);
+        }
+    }
+
+    /// Replay everything, recording (clock time, recorded ts, seq) per frame
+    fn play(replay: &mut Replay<'_, FakeClock>, clock: &FakeClock) -> Vec<(u64, u64, u32)> {
+        let mut out = Vec::new();
+        let mut sink = |ts: Timestamp, frame: &[u8]| {
+            FrameDecoder::new(frame).verify_crc32c().unwrap();
+            out.push((
+                clock.0.get(),
+                ts.as_nanos(),
+                FrameHeader::decode(frame).unwrap().seq,
+            ));
+            Ok(())
+        };
+        replay.run(&mut sink).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_pacing_and_merge_with_seq_rewrite() {
+        let ms = |n: u64| n * 1_000_000;
+        let a: Vec<u8> = [(7, ms(0)), (8, ms(30))]
+            .iter()
+            .flat_map(|&(seq, ts)| trade_frame_at(seq, ts))
+            .collect();
+        let b: Vec<u8> = [(100, ms(10)), (101, ms(40))]
+            .iter()
+            .flat_map(|&(seq, ts)| trade_frame_at(seq, ts))
+            .collect();
+        let decoder = DynamicDecoder::with_registry(SchemaRegistry::with_builtin());
+
+        let clock = FakeClock::default();
+        let mut replay =
+            Replay::with_clock(clock.clone()).with_seq_rewrite(Sequencer::starting_at(1));
+        assert_eq!(
+            replay.add_frames(&a, |f| ts_ns_field(&decoder, f)).unwrap(),
+            2
+        );
+        assert_eq!(
+            replay.add_frames(&b, |f| ts_ns_field(&decoder, f)).unwrap(),
+            2
+        );
+        assert_eq!(
+            play(&mut replay, &clock),
+            [
+                (0, 0, 1),
+                (ms(10), ms(10), 2),
+                (ms(30), ms(30), 3),
+                (ms(40), ms(40), 4)
+            ]
+        );
+
+        // Double speed halves the gaps; max speed removes them
+        let clock = FakeClock::default();
+        let mut replay = Replay::with_clock(clock.clone()).with_speed(Speed::Scaled(2.0));
+        replay.add_frames(&a, |f| ts_ns_field(&decoder, f)).unwrap();
+        assert_eq!(play(&mut replay, &clock), [(0, 0, 7), (ms(15), ms(30), 8)]);
+
+        let clock = FakeClock::default();
+        let mut replay = Replay::with_clock(clock.clone()).with_speed(Speed::Max);
+        replay.add_frames(&a, |f| ts_ns_field(&decoder, f)).unwrap();
+        assert_eq!(play(&mut replay, &clock), [(0, 0, 7), (0, ms(30), 8)]);
+    }
+
+    #[test]
+    fn test_journal_replay_with_pause_and_seek() {
+        let dir = std::env::temp_dir().join(format!("minibit-replay-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut writer = JournalWriter::create(&dir).unwrap();
+        for seq in 0..10 {
+            writer
+                .append(&trade_frame_at(seq, 0), Timestamp::from_secs(seq as u64))
+                .unwrap();
+        }
+        let path = writer.current_path().unwrap().to_path_buf();
+        writer.close().unwrap();
+        let data = fs::read(&path).unwrap();
+        let view = JournalView::new(&data).unwrap();
+
+        let clock = FakeClock::default();
+        let mut replay = Replay::with_clock(clock.clone());
+        assert_eq!(replay.add_journal(&view).unwrap(), 10);
+        let control = replay.control();
+
+        let mut seqs = vec![];
+        let mut sink = |_: Timestamp, frame: &[u8]| {
+            seqs.push(FrameHeader::decode(frame).unwrap().seq);
+            Ok(())
+        };
+        assert!(replay.step(&mut sink).unwrap());
+
+        // Seeking skips ahead without waiting for the skipped gap
+        control.seek_time(Timestamp::from_secs(6));
+        let before = clock.0.get();
+        assert!(replay.step(&mut sink).unwrap());
+        assert_eq!(clock.0.get(), before);
+
+        // Pausing holds delivery; stop ends it
+        control.pause();
+        control.stop();
+        assert!(!replay.step(&mut sink).unwrap());
+        assert!(control.is_paused());
+        assert_eq!(seqs, [0, 6]);
+        assert_eq!(replay.position(), 7);
+
+        let clock_before = clock.0.get();
+        let mut rest = Replay::with_clock(clock.clone()).with_speed(Speed::Max);
+        rest.add_journal(&view).unwrap();
+        rest.seek_index(8);
+        assert_eq!(play(&mut rest, &clock).len(), 2);
+        assert_eq!(clock.0.get(), clock_before);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_add_frames_edges() {
+        let mut buf: Vec<u8> = [(1, 100), (2, 0), (3, 300)]
+            .iter()
+            .flat_map(|&(seq, ts)| trade_frame_at(seq, ts))
+            .collect();
+        let frame_len = trade_frame_at(0, 0).len();
+        buf.extend(&trade_frame_at(4, 400)[..frame_len - 1]);
+
+        // A frame without a time inherits the previous one's; a truncated
+        // final frame is left out
+        let mut replay = Replay::with_clock(FakeClock::default()).with_speed(Speed::Max);
+        let ts_of = |frame: &[u8]| {
+            let nanos = FrameDecoder::new(frame).body().unwrap().get_u64().unwrap();
+            (nanos != 0).then(|| Timestamp::from_nanos(nanos))
+        };
+        assert_eq!(replay.add_frames(&buf, ts_of).unwrap(), 3);
+        let mut times = vec![];
+        let mut sink = |ts: Timestamp, _: &[u8]| {
+            times.push(ts.as_nanos());
+            Ok(())
+        };
+        replay.run(&mut sink).unwrap();
+        assert_eq!(times, [100, 100, 300]);
+
+        // An invalid header is an error, not the end of the recording
+        buf[frame_len] ^= 0xff;
+        let mut replay = Replay::with_clock(FakeClock::default());
+        assert_eq!(replay.add_frames(&buf, |_| None), Err(Error::InvalidMagic));
+
+        let mut empty = Replay::with_clock(FakeClock::default());
+        assert_eq!(empty.add_frames(&[], |_| None), Ok(0));
+        assert!(empty.is_empty());
+        assert_eq!(empty.run(&mut |_: Timestamp, _: &[u8]| Ok(())).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_replay_edges() {
+        let frames: Vec<Vec<u8>> = (0..4).map(|seq| trade_frame_at(seq, 0)).collect();
+        let clock = FakeClock::default();
+
+        // Times out of order within a recording are merged stably, and a
+        // non-positive scale does not wait
+        let mut replay = Replay::with_clock(clock.clone()).with_speed(Speed::Scaled(0.0));
+        for (frame, secs) in frames.iter().zip([3, 1, 1, 2]) {
+            replay.push(Timestamp::from_secs(secs), frame);
+        }
+        let seqs: Vec<u32> = play(&mut replay, &clock)
+            .into_iter()
+            .map(|(_, _, seq)| seq)
+            .collect();
+        assert_eq!(seqs, [1, 2, 3, 0]);
+        assert_eq!(clock.0.get(), 0);
+
+        // Seeking past the end leaves nothing to deliver
+        replay.seek_index(100);
+        assert_eq!(replay.position(), 4);
+        assert!(play(&mut replay, &clock).is_empty());
+        replay.seek_time(Timestamp::from_secs(10));
+        assert_eq!(replay.position(), 4);
+        replay.seek_time(Timestamp::EPOCH);
+        assert_eq!(replay.position(), 0);
+
+        // Restamped seqs wrap, and a corrupt frame stays corrupt
+        let mut corrupt = frames[1].clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xff;
+        let mut replay = Replay::with_clock(clock.clone())
+            .with_speed(Speed::Max)
+            .with_seq_rewrite(Sequencer::starting_at(u32::MAX));
+        replay.push(Timestamp::EPOCH, &frames[0]);
+        replay.push(Timestamp::EPOCH, &corrupt);
+        let mut out = vec![];
+        let mut sink = |_: Timestamp, frame: &[u8]| {
+            let decoder = FrameDecoder::new(frame);
+            out.push((
+                decoder.header().unwrap().seq,
+                decoder.verify_crc32c().is_ok(),
+            ));
+            Ok(())
+        };
+        replay.run(&mut sink).unwrap();
+        assert_eq!(out, [(u32::MAX, true), (0, false)]);
+
+        // A failing sink stops the run with its error
+        let mut replay = Replay::with_clock(clock.clone()).with_speed(Speed::Max);
+        replay.push(Timestamp::EPOCH, &frames[0]);
+        replay.push(Timestamp::EPOCH, &frames[1]);
+        let mut failing = |_: Timestamp, _: &[u8]| Err(io::Error::other("sink closed"));
+        let err = replay.run(&mut failing).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+        assert_eq!(replay.position(), 1);
+    }
+}
@@ -0,0 +1,1080 @@
+//! Message schemas and a registry with compatibility checking
+//!
+//! A [`MessageSchema`] describes the body layout of one message type:
+//! required fields in wire order, followed by optional fields that are either
+//! gated by a presence bitmap (written in bit order) or tagged (see
+//! [`tagged`](crate::tagged)). [`SchemaRegistry`] records schemas by
+//! `msg_type` and minor version, rejects conflicting ids, and reports whether
+//! a new version can be read by old decoders and vice versa.
+//!
+//! Schemas can also travel in-band: [`encode_schema`] writes a schema
+//! announcement frame (`msg_types::SCHEMA`) so that a reader without the
+//! writer's code can still decode its frames with
+//! [`dynamic`](crate::dynamic). Announcement bodies are:
+//!
+//! ```text
+//! msg_type u16 | version u8 | name varbytes | layout
+//!
+//! layout = optional encoding u8 | field count varint | field groups
+//! field group = name varbytes | type u8 | optional u8 | index u8 | params
+//! ```
+//!
+//! where `params` is the scale byte of a decimal or the nested layout of a
+//! group, and empty otherwise.
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::bitmap::BitmapSize;
+use crate::decimal::MAX_SCALE;
+use crate::decoder::{BodyCursor, FrameDecoder};
+use crate::encoder::{FrameEncoder, GROUP_LEN_SIZE};
+use crate::error::{Error, Result};
+use crate::frame::{FrameHeader, HEADER_V1};
+use crate::messages::msg_types;
+use crate::tagged::WireType;
+
+/// Deepest nesting of groups a schema may describe
+pub const MAX_GROUP_DEPTH: usize = 8;
+
+/// Most fields one layout (or group) in a schema announcement may declare
+pub const MAX_FIELDS: usize = 1024;
+
+/// Wire type of a schema field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    /// Unsigned 8-bit integer
+    U8,
+    /// Unsigned 16-bit integer (little-endian)
+    U16,
+    /// Unsigned 32-bit integer (little-endian)
+    U32,
+    /// Unsigned 64-bit integer (little-endian)
+    U64,
+    /// Signed 32-bit integer (little-endian)
+    I32,
+    /// Signed 64-bit integer (little-endian)
+    I64,
+    /// Unsigned varint
+    VarU64,
+    /// ZigZag signed varint
+    VarI64,
+    /// 8-byte nanosecond timestamp
+    Timestamp,
+    /// Length-prefixed bytes
+    Bytes,
+    /// 8-byte mantissa with the given number of fractional digits
+    Decimal(u8),
+    /// Length-prefixed nested group described by [`FieldDef::group`]
+    Group,
+}
+
+impl FieldType {
+    /// Short lowercase name used in diffs and dumps
+    #[inline]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            FieldType::U8 => "u8",
+            FieldType::U16 => "u16",
+            FieldType::U32 => "u32",
+            FieldType::U64 => "u64",
+            FieldType::I32 => "i32",
+            FieldType::I64 => "i64",
+            FieldType::VarU64 => "varu64",
+            FieldType::VarI64 => "vari64",
+            FieldType::Timestamp => "timestamp",
+            FieldType::Bytes => "bytes",
+            FieldType::Decimal(_) => "decimal",
+            FieldType::Group => "group",
+        }
+    }
+
+    /// Wire type used when the field is written as a tagged field
+    #[inline]
+    pub const fn wire_type(&self) -> WireType {
+        match self {
+            FieldType::U8 => WireType::Fixed8,
+            FieldType::U16 => WireType::Fixed16,
+            FieldType::U32 | FieldType::I32 => WireType::Fixed32,
+            FieldType::U64 | FieldType::I64 | FieldType::Timestamp | FieldType::Decimal(_) => {
+                WireType::Fixed64
+            }
+            FieldType::VarU64 | FieldType::VarI64 => WireType::Varint,
+            FieldType::Bytes | FieldType::Group => WireType::Bytes,
+        }
+    }
+
+    /// Code used in schema announcement frames (parameters follow separately)
+    #[inline]
+    const fn code(&self) -> u8 {
+        match self {
+            FieldType::U8 => 0,
+            FieldType::U16 => 1,
+            FieldType::U32 => 2,
+            FieldType::U64 => 3,
+            FieldType::I32 => 4,
+            FieldType::I64 => 5,
+            FieldType::VarU64 => 6,
+            FieldType::VarI64 => 7,
+            FieldType::Timestamp => 8,
+            FieldType::Bytes => 9,
+            FieldType::Decimal(_) => 10,
+            FieldType::Group => 11,
+        }
+    }
+
+    /// Parse a code from a schema announcement frame; decimals start at scale 0
+    #[inline]
+    const fn from_code(code: u8) -> Result<Self> {
+        match code {
+            0 => Ok(FieldType::U8),
+            1 => Ok(FieldType::U16),
+            2 => Ok(FieldType::U32),
+            3 => Ok(FieldType::U64),
+            4 => Ok(FieldType::I32),
+            5 => Ok(FieldType::I64),
+            6 => Ok(FieldType::VarU64),
+            7 => Ok(FieldType::VarI64),
+            8 => Ok(FieldType::Timestamp),
+            9 => Ok(FieldType::Bytes),
+            10 => Ok(FieldType::Decimal(0)),
+            11 => Ok(FieldType::Group),
+            _ => Err(Error::InvalidSchema),
+        }
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldType::Decimal(scale) => write!(f, "decimal({})", scale),
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+/// Whether a field is always present or optional
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Presence {
+    /// Always present, at a fixed position among the required fields
+    Required,
+    /// Present when its bitmap bit (or tag) is set
+    Optional {
+        /// Presence bitmap index, also used as the tag in tagged encoding
+        index: u8,
+    },
+}
+
+/// How optional fields are laid out after the required fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionalEncoding {
+    /// Presence bitmap followed by present fields in index order
+    Bitmap(BitmapSize),
+    /// Self-delimiting tagged fields (`FrameFlags::TAGGED`)
+    Tagged,
+}
+
+impl OptionalEncoding {
+    /// Code used in schema announcement frames
+    #[inline]
+    pub const fn code(&self) -> u8 {
+        match self {
+            OptionalEncoding::Bitmap(BitmapSize::U8) => 0,
+            OptionalEncoding::Bitmap(BitmapSize::U16) => 1,
+            OptionalEncoding::Bitmap(BitmapSize::Var) => 2,
+            OptionalEncoding::Tagged => 3,
+        }
+    }
+
+    /// Parse a code from a schema announcement frame
+    #[inline]
+    pub const fn from_code(code: u8) -> Result<Self> {
+        match code {
+            0 => Ok(OptionalEncoding::Bitmap(BitmapSize::U8)),
+            1 => Ok(OptionalEncoding::Bitmap(BitmapSize::U16)),
+            2 => Ok(OptionalEncoding::Bitmap(BitmapSize::Var)),
+            3 => Ok(OptionalEncoding::Tagged),
+            _ => Err(Error::InvalidSchema),
+        }
+    }
+}
+
+/// One field of a message schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDef {
+    /// Field name
+    pub name: String,
+    /// Wire type
+    pub ty: FieldType,
+    /// Required or optional (with bitmap index)
+    pub presence: Presence,
+    /// Layout of a [`FieldType::Group`] sub-body (its `msg_type`, `name` and
+    /// `version` are unused)
+    pub group: Option<Box<MessageSchema>>,
+}
+
+/// Body layout of one version of a message type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSchema {
+    /// Message type identifier
+    pub msg_type: u16,
+    /// Message name (must stay the same across versions)
+    pub name: String,
+    /// Schema minor version
+    pub version: u8,
+    /// Layout of the optional fields
+    pub optional_encoding: OptionalEncoding,
+    /// Fields in declaration order
+    pub fields: Vec<FieldDef>,
+}
+
+impl MessageSchema {
+    /// Create an empty schema using a 16-bit presence bitmap
+    pub fn new(msg_type: u16, name: &str, version: u8) -> Self {
+        Self {
+            msg_type,
+            name: String::from(name),
+            version,
+            optional_encoding: OptionalEncoding::Bitmap(BitmapSize::U16),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Set how optional fields are laid out
+    pub fn with_optional_encoding(mut self, encoding: OptionalEncoding) -> Self {
+        self.optional_encoding = encoding;
+        self
+    }
+
+    /// Create an empty layout for a nested group
+    ///
+    /// Groups with optional fields always carry their presence bitmap (or run
+    /// tagged fields to the end of the group), independent of header flags.
+    pub fn group() -> Self {
+        Self::new(0, "", 0)
+    }
+
+    /// Append a required field
+    pub fn required(self, name: &str, ty: FieldType) -> Self {
+        self.push(name, ty, Presence::Required, None)
+    }
+
+    /// Append an optional field at bitmap index `index`
+    pub fn optional(self, name: &str, ty: FieldType, index: u8) -> Self {
+        self.push(name, ty, Presence::Optional { index }, None)
+    }
+
+    /// Append a required nested group laid out as `group`
+    pub fn required_group(self, name: &str, group: MessageSchema) -> Self {
+        self.push(name, FieldType::Group, Presence::Required, Some(group))
+    }
+
+    /// Append an optional nested group at bitmap index `index`
+    pub fn optional_group(self, name: &str, group: MessageSchema, index: u8) -> Self {
+        let presence = Presence::Optional { index };
+        self.push(name, FieldType::Group, presence, Some(group))
+    }
+
+    fn push(
+        mut self,
+        name: &str,
+        ty: FieldType,
+        presence: Presence,
+        group: Option<MessageSchema>,
+    ) -> Self {
+        self.fields.push(FieldDef {
+            name: String::from(name),
+            ty,
+            presence,
+            group: group.map(Box::new),
+        });
+        self
+    }
+
+    /// Check if the layout has any optional fields
+    pub fn has_optional(&self) -> bool {
+        self.fields
+            .iter()
+            .any(|f| matches!(f.presence, Presence::Optional { .. }))
+    }
+
+    /// Required fields in wire order
+    pub fn required_fields(&self) -> impl Iterator<Item = &FieldDef> + '_ {
+        self.fields
+            .iter()
+            .filter(|f| f.presence == Presence::Required)
+    }
+
+    /// Optional fields sorted by index (their wire order)
+    pub fn optional_fields(&self) -> Vec<(u8, &FieldDef)> {
+        let mut optional: Vec<_> = self
+            .fields
+            .iter()
+            .filter_map(|f| match f.presence {
+                Presence::Optional { index } => Some((index, f)),
+                Presence::Required => None,
+            })
+            .collect();
+        optional.sort_by_key(|&(index, _)| index);
+        optional
+    }
+
+    /// Look up a field by name
+    pub fn field(&self, name: &str) -> Option<&FieldDef> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Check names are unique, optional indices are unique and in range,
+    /// decimal scales are supported and groups nest at most
+    /// [`MAX_GROUP_DEPTH`] deep
+    ///
+    /// Returns `Error::InvalidSchema` otherwise.
+    pub fn validate(&self) -> Result<()> {
+        self.validate_at(0)
+    }
+
+    fn validate_at(&self, depth: usize) -> Result<()> {
+        if depth > MAX_GROUP_DEPTH {
+            return Err(Error::InvalidSchema);
+        }
+
+        let max_index = match self.optional_encoding {
+            OptionalEncoding::Bitmap(size) => size.max_fields(),
+            OptionalEncoding::Tagged => crate::tagged::MAX_FIELD_IDX as usize + 1,
+        };
+
+        let mut names = BTreeSet::new();
+        // One bit per possible optional index
+        let mut indices = [0u64; 4];
+        for field in &self.fields {
+            if !names.insert(field.name.as_str()) {
+                return Err(Error::InvalidSchema);
+            }
+            if let Presence::Optional { index } = field.presence {
+                if index as usize >= max_index {
+                    return Err(Error::InvalidSchema);
+                }
+                let (word, bit) = (index as usize / 64, 1u64 << (index % 64));
+                if indices[word] & bit != 0 {
+                    return Err(Error::InvalidSchema);
+                }
+                indices[word] |= bit;
+            }
+            match (field.ty, &field.group) {
+                (FieldType::Group, Some(group)) => group.validate_at(depth + 1)?,
+                (FieldType::Group, None) | (_, Some(_)) => return Err(Error::InvalidSchema),
+                (FieldType::Decimal(scale), None) if scale > MAX_SCALE => {
+                    return Err(Error::InvalidSchema)
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One difference between two schema versions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaChange {
+    /// Field present only in the new schema
+    Added(FieldDef),
+    /// Field present only in the old schema
+    Removed(FieldDef),
+    /// Same position or index, different name
+    Renamed {
+        /// Name in the old schema
+        old: String,
+        /// Name in the new schema
+        new: String,
+    },
+    /// Same position or index, different type
+    TypeChanged {
+        /// Field name in the new schema
+        name: String,
+        /// Type in the old schema
+        old: FieldType,
+        /// Type in the new schema
+        new: FieldType,
+    },
+    /// Same position or index, nested group layout changed
+    GroupChanged {
+        /// Field name in the new schema
+        name: String,
+        /// Differences inside the group
+        changes: Vec<SchemaChange>,
+    },
+    /// Optional field layout changed
+    EncodingChanged {
+        /// Layout in the old schema
+        old: OptionalEncoding,
+        /// Layout in the new schema
+        new: OptionalEncoding,
+    },
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn presence(p: Presence) -> impl fmt::Display {
+            struct P(Presence);
+            impl fmt::Display for P {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    match self.0 {
+                        Presence::Required => f.write_str("required"),
+                        Presence::Optional { index } => write!(f, "optional #{}", index),
+                    }
+                }
+            }
+            P(p)
+        }
+
+        match self {
+            SchemaChange::Added(field) => write!(
+                f,
+                "+ {} {}: {}",
+                presence(field.presence),
+                field.name,
+                field.ty
+            ),
+            SchemaChange::Removed(field) => write!(
+                f,
+                "- {} {}: {}",
+                presence(field.presence),
+                field.name,
+                field.ty
+            ),
+            SchemaChange::Renamed { old, new } => write!(f, "~ {} renamed to {}", old, new),
+            SchemaChange::TypeChanged { name, old, new } => {
+                write!(f, "~ {}: {} -> {}", name, old, new)
+            }
+            SchemaChange::GroupChanged { name, changes } => {
+                write!(f, "~ {} {{", name)?;
+                for change in changes {
+                    write!(f, " {};", change)?;
+                }
+                f.write_str(" }")
+            }
+            SchemaChange::EncodingChanged { old, new } => {
+                write!(f, "~ optional encoding: {:?} -> {:?}", old, new)
+            }
+        }
+    }
+}
+
+/// Result of comparing two versions of a schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatReport {
+    /// Decoders built from the new schema can read frames written with the old one
+    pub backward: bool,
+    /// Decoders built from the old schema can read frames written with the new one
+    pub forward: bool,
+    /// Field-level differences, old to new
+    pub changes: Vec<SchemaChange>,
+}
+
+impl CompatReport {
+    /// Check if compatible in both directions
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.backward && self.forward
+    }
+}
+
+impl fmt::Display for CompatReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        writeln!(
+            f,
+            "backward compatible: {}, forward compatible: {}",
+            yes_no(self.backward),
+            yes_no(self.forward)
+        )?;
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check whether a decoder for `reader` can parse bodies written with `writer`
+fn can_read(reader: &MessageSchema, writer: &MessageSchema) -> bool {
+    if reader.optional_encoding != writer.optional_encoding {
+        return false;
+    }
+
+    // Required fields are positional and must match exactly
+    let reader_required: Vec<_> = reader.required_fields().collect();
+    let writer_required: Vec<_> = writer.required_fields().collect();
+    if reader_required.len() != writer_required.len()
+        || !reader_required
+            .iter()
+            .zip(&writer_required)
+            .all(|(r, w)| field_can_read(r, w))
+    {
+        return false;
+    }
+
+    let reader_optional = reader.optional_fields();
+    let writer_optional = writer.optional_fields();
+
+    // Shared optional indices must agree on type
+    for &(index, field) in &writer_optional {
+        if let Some(&(_, known)) = reader_optional.iter().find(|&&(i, _)| i == index) {
+            if !field_can_read(known, field) {
+                return false;
+            }
+        }
+    }
+
+    match reader.optional_encoding {
+        // Tagged fields can be skipped wherever they appear
+        OptionalEncoding::Tagged => true,
+        // Bitmap fields are read in index order, so unknown ones are only
+        // harmless after the last field the reader knows about
+        OptionalEncoding::Bitmap(_) => {
+            let last_known = reader_optional.last().map(|&(index, _)| index);
+            writer_optional.iter().all(|&(index, _)| {
+                reader_optional.iter().any(|&(i, _)| i == index)
+                    || last_known.is_none_or(|last| index > last)
+            })
+        }
+    }
+}
+
+fn field_can_read(reader: &FieldDef, writer: &FieldDef) -> bool {
+    reader.ty == writer.ty
+        && match (&reader.group, &writer.group) {
+            (Some(r), Some(w)) => can_read(r, w),
+            _ => true,
+        }
+}
+
+/// Compare two versions of a message schema
+pub fn check_compatibility(old: &MessageSchema, new: &MessageSchema) -> CompatReport {
+    let mut changes = Vec::new();
+
+    if old.optional_encoding != new.optional_encoding {
+        changes.push(SchemaChange::EncodingChanged {
+            old: old.optional_encoding,
+            new: new.optional_encoding,
+        });
+    }
+
+    // Required fields are matched by position
+    let old_required: Vec<_> = old.required_fields().collect();
+    let new_required: Vec<_> = new.required_fields().collect();
+    for i in 0..old_required.len().max(new_required.len()) {
+        match (old_required.get(i), new_required.get(i)) {
+            (Some(o), Some(n)) => diff_pair(o, n, &mut changes),
+            (Some(o), None) => changes.push(SchemaChange::Removed((*o).clone())),
+            (None, Some(n)) => changes.push(SchemaChange::Added((*n).clone())),
+            (None, None) => {}
+        }
+    }
+
+    // Optional fields are matched by index
+    let old_optional = old.optional_fields();
+    let new_optional = new.optional_fields();
+    for &(index, o) in &old_optional {
+        match new_optional.iter().find(|&&(i, _)| i == index) {
+            Some(&(_, n)) => diff_pair(o, n, &mut changes),
+            None => changes.push(SchemaChange::Removed(o.clone())),
+        }
+    }
+    for &(index, n) in &new_optional {
+        if !old_optional.iter().any(|&(i, _)| i == index) {
+            changes.push(SchemaChange::Added(n.clone()));
+        }
+    }
+
+    CompatReport {
+        backward: can_read(new, old),
+        forward: can_read(old, new),
+        changes,
+    }
+}
+
+fn diff_pair(old: &FieldDef, new: &FieldDef, changes: &mut Vec<SchemaChange>) {
+    if old.name != new.name {
+        changes.push(SchemaChange::Renamed {
+            old: old.name.clone(),
+            new: new.name.clone(),
+        });
+    }
+    if old.ty != new.ty {
+        changes.push(SchemaChange::TypeChanged {
+            name: new.name.clone(),
+            old: old.ty,
+            new: new.ty,
+        });
+    } else if let (Some(old_group), Some(new_group)) = (&old.group, &new.group) {
+        let nested = check_compatibility(old_group, new_group).changes;
+        if !nested.is_empty() {
+            changes.push(SchemaChange::GroupChanged {
+                name: new.name.clone(),
+                changes: nested,
+            });
+        }
+    }
+}
+
+/// Runtime registry of message schemas keyed by `msg_type` and version
+#[derive(Debug, Clone, Default)]
+pub struct SchemaRegistry {
+    schemas: BTreeMap<u16, BTreeMap<u8, MessageSchema>>,
+}
+
+impl SchemaRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry holding the built-in trade, quote and delta quote
+    /// schemas
+    pub fn with_builtin() -> Self {
+        let mut registry = Self::new();
+        for schema in [
+            crate::messages::trade::schema(),
+            crate::messages::quote::schema(),
+            crate::messages::quote_delta::schema(),
+        ] {
+            registry
+                .register(schema)
+                .expect("built-in schemas are valid and distinct");
+        }
+        registry
+    }
+
+    /// Register a schema version
+    ///
+    /// Returns `Error::InvalidSchema` if the schema fails validation or its
+    /// `msg_type` is in the session control range reserved by
+    /// [`msg_types`], and `Error::DuplicateMsgType` if its `msg_type` already
+    /// belongs to a message with a different name or this version is already
+    /// registered.
+    pub fn register(&mut self, schema: MessageSchema) -> Result<()> {
+        schema.validate()?;
+        if msg_types::is_session_control(schema.msg_type) {
+            return Err(Error::InvalidSchema);
+        }
+
+        let versions = self.schemas.entry(schema.msg_type).or_default();
+        let name_clash = versions.values().any(|s| s.name != schema.name);
+        if name_clash || versions.contains_key(&schema.version) {
+            return Err(Error::DuplicateMsgType);
+        }
+
+        versions.insert(schema.version, schema);
+        Ok(())
+    }
+
+    /// Register a schema version only if it is compatible with the latest one
+    ///
+    /// `backward` and `forward` select the directions that must hold. Returns
+    /// the comparison report on success and `Error::IncompatibleSchema`
+    /// otherwise; use [`check_compatibility`] to inspect a rejection.
+    pub fn register_compatible(
+        &mut self,
+        schema: MessageSchema,
+        backward: bool,
+        forward: bool,
+    ) -> Result<Option<CompatReport>> {
+        let report = self
+            .latest(schema.msg_type)
+            .map(|latest| check_compatibility(latest, &schema));
+        if let Some(report) = &report {
+            if (backward && !report.backward) || (forward && !report.forward) {
+                return Err(Error::IncompatibleSchema);
+            }
+        }
+        self.register(schema)?;
+        Ok(report)
+    }
+
+    /// Latest registered version of `msg_type`
+    pub fn latest(&self, msg_type: u16) -> Option<&MessageSchema> {
+        self.schemas
+            .get(&msg_type)
+            .and_then(|versions| versions.values().next_back())
+    }
+
+    /// Specific version of `msg_type`
+    pub fn get(&self, msg_type: u16, version: u8) -> Option<&MessageSchema> {
+        self.schemas
+            .get(&msg_type)
+            .and_then(|versions| versions.get(&version))
+    }
+
+    /// Latest schema registered under `name`
+    pub fn by_name(&self, name: &str) -> Option<&MessageSchema> {
+        self.schemas
+            .values()
+            .filter_map(|versions| versions.values().next_back())
+            .find(|s| s.name == name)
+    }
+
+    /// All registered versions of `msg_type`, oldest first
+    pub fn versions(&self, msg_type: u16) -> impl Iterator<Item = &MessageSchema> + '_ {
+        self.schemas
+            .get(&msg_type)
+            .into_iter()
+            .flat_map(|versions| versions.values())
+    }
+
+    /// Latest version of every registered message type, by `msg_type`
+    pub fn iter(&self) -> impl Iterator<Item = &MessageSchema> + '_ {
+        self.schemas
+            .values()
+            .filter_map(|versions| versions.values().next_back())
+    }
+}
+
+/// Encode a schema announcement frame describing `schema`
+///
+/// Like hello frames, announcements always use a version 1 header.
+pub fn encode_schema(buf: &mut [u8], seq: u32, schema: &MessageSchema) -> Result<usize> {
+    let mut encoder = FrameEncoder::new(buf);
+
+    let header = FrameHeader::new(msg_types::SCHEMA, seq, 0).with_version(HEADER_V1);
+    encoder.begin(&header)?;
+
+    encoder.put_u16(schema.msg_type)?;
+    encoder.put_u8(schema.version)?;
+    encoder.put_varbytes(schema.name.as_bytes())?;
+    put_layout(&mut encoder, schema)?;
+
+    encoder.finish_crc32c()
+}
+
+/// Write optional encoding, field count and one group per field
+fn put_layout(encoder: &mut FrameEncoder<'_>, schema: &MessageSchema) -> Result<()> {
+    encoder.put_u8(schema.optional_encoding.code())?;
+    encoder.put_varint_u32(u32::try_from(schema.fields.len()).map_err(|_| Error::Overflow)?)?;
+
+    for field in &schema.fields {
+        let group = encoder.begin_group()?;
+        encoder.put_varbytes(field.name.as_bytes())?;
+        encoder.put_u8(field.ty.code())?;
+        match field.presence {
+            Presence::Required => {
+                encoder.put_u8(0)?;
+                encoder.put_u8(0)?;
+            }
+            Presence::Optional { index } => {
+                encoder.put_u8(1)?;
+                encoder.put_u8(index)?;
+            }
+        }
+        match (field.ty, &field.group) {
+            (FieldType::Decimal(scale), _) => encoder.put_u8(scale)?,
+            (FieldType::Group, Some(layout)) => put_layout(encoder, layout)?,
+            (FieldType::Group, None) => return Err(Error::InvalidSchema),
+            _ => {}
+        }
+        encoder.end_group(group)?;
+    }
+    Ok(())
+}
+
+/// Decode a schema announcement frame
+///
+/// Field groups may carry trailing attributes added by newer writers; they
+/// are skipped. Returns `Error::InvalidSchema` for unknown codes, non-UTF-8
+/// names, a layout with more than [`MAX_FIELDS`] fields or a schema that
+/// fails [`MessageSchema::validate`].
+pub fn decode_schema(buf: &[u8]) -> Result<(FrameHeader, MessageSchema)> {
+    let decoder = FrameDecoder::new(buf);
+    let header = decoder.header()?;
+
+    if header.msg_type != msg_types::SCHEMA {
+        return Err(Error::UnsupportedMsgType);
+    }
+
+    decoder.verify_crc32c()?;
+
+    let mut body = decoder.body()?;
+    let msg_type = body.get_u16()?;
+    let version = body.get_u8()?;
+    let name = utf8(body.get_varbytes()?)?;
+
+    let schema = get_layout(&mut body, MessageSchema::new(msg_type, name, version), 0)?;
+    schema.validate()?;
+    Ok((header, schema))
+}
+
+/// Read what [`put_layout`] wrote into `schema`
+fn get_layout(
+    body: &mut BodyCursor<'_>,
+    schema: MessageSchema,
+    depth: usize,
+) -> Result<MessageSchema> {
+    if depth > MAX_GROUP_DEPTH {
+        return Err(Error::InvalidSchema);
+    }
+
+    let mut schema = schema.with_optional_encoding(OptionalEncoding::from_code(body.get_u8()?)?);
+
+    // Every field needs at least a group prefix, which bounds the allocation
+    let count = body.get_varint_u32()? as usize;
+    if count > MAX_FIELDS {
+        return Err(Error::InvalidSchema);
+    }
+    if count > body.remaining() / GROUP_LEN_SIZE {
+        return Err(Error::UnexpectedEof);
+    }
+
+    for _ in 0..count {
+        let mut field = body.get_group()?;
+        let name = utf8(field.get_varbytes()?)?;
+        let mut ty = FieldType::from_code(field.get_u8()?)?;
+        let presence = match (field.get_u8()?, field.get_u8()?) {
+            (0, _) => Presence::Required,
+            (1, index) => Presence::Optional { index },
+            _ => return Err(Error::InvalidSchema),
+        };
+        let group = match ty {
+            FieldType::Decimal(_) => {
+                ty = FieldType::Decimal(field.get_u8()?);
+                None
+            }
+            FieldType::Group => Some(get_layout(&mut field, MessageSchema::group(), depth + 1)?),
+            _ => None,
+        };
+        schema = schema.push(name, ty, presence, group);
+    }
+
+    Ok(schema)
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str> {
+    core::str::from_utf8(bytes).map_err(|_| Error::InvalidSchema)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{msg_types, trade};
+    use std::string::ToString;
+
+    fn trade_v2() -> MessageSchema {
+        let mut schema = trade::schema().optional("venue", FieldType::Bytes, 2);
+        schema.version += 1;
+        schema
+    }
+
+    #[test]
+    fn test_register_rejects_duplicates() {
+        let mut registry = SchemaRegistry::with_builtin();
+        assert_eq!(
+            registry.register(trade::schema()),
+            Err(Error::DuplicateMsgType)
+        );
+
+        // Same id, different message
+        let other = MessageSchema::new(msg_types::TRADE_V1, "Order", 9);
+        assert_eq!(registry.register(other), Err(Error::DuplicateMsgType));
+
+        registry.register(trade_v2()).unwrap();
+        assert_eq!(registry.latest(msg_types::TRADE_V1), Some(&trade_v2()));
+        assert_eq!(registry.versions(msg_types::TRADE_V1).count(), 2);
+        assert_eq!(
+            registry.by_name("Quote").unwrap().msg_type,
+            msg_types::QUOTE_V1
+        );
+
+        // Built-in ids are taken, and session control ids are reserved
+        assert_eq!(
+            registry.register(MessageSchema::new(msg_types::QUOTE_DELTA_V1, "Order", 0)),
+            Err(Error::DuplicateMsgType)
+        );
+        for msg_type in [
+            msg_types::HELLO,
+            msg_types::SCHEMA,
+            msg_types::HEARTBEAT,
+            msg_types::LOGON,
+            u16::MAX,
+        ] {
+            assert_eq!(
+                SchemaRegistry::new().register(MessageSchema::new(msg_type, "Order", 0)),
+                Err(Error::InvalidSchema)
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let dup_name = MessageSchema::new(50, "X", 0)
+            .required("a", FieldType::U8)
+            .optional("a", FieldType::Bytes, 0);
+        assert_eq!(dup_name.validate(), Err(Error::InvalidSchema));
+
+        let dup_index = MessageSchema::new(50, "X", 0)
+            .optional("a", FieldType::Bytes, 1)
+            .optional("b", FieldType::Bytes, 1);
+        assert_eq!(dup_index.validate(), Err(Error::InvalidSchema));
+
+        let out_of_range = MessageSchema::new(50, "X", 0)
+            .with_optional_encoding(OptionalEncoding::Bitmap(BitmapSize::U8))
+            .optional("a", FieldType::Bytes, 8);
+        assert_eq!(out_of_range.validate(), Err(Error::InvalidSchema));
+    }
+
+    #[test]
+    fn test_appended_optional_field_is_fully_compatible() {
+        let report = check_compatibility(&trade::schema(), &trade_v2());
+        assert!(report.is_full());
+        assert_eq!(report.changes.len(), 1);
+        assert_eq!(
+            report.to_string().lines().nth(1),
+            Some("+ optional #2 venue: bytes")
+        );
+    }
+
+    #[test]
+    fn test_inserted_optional_field_breaks_forward_compat() {
+        // Old schema knows bits 0 and 2; new one fills in bit 1
+        let old = MessageSchema::new(60, "Fill", 0)
+            .required("qty", FieldType::U32)
+            .optional("a", FieldType::Bytes, 0)
+            .optional("c", FieldType::Bytes, 2);
+        let new = MessageSchema::new(60, "Fill", 1)
+            .required("qty", FieldType::U32)
+            .optional("a", FieldType::Bytes, 0)
+            .optional("b", FieldType::U64, 1)
+            .optional("c", FieldType::Bytes, 2);
+
+        let report = check_compatibility(&old, &new);
+        assert!(report.backward);
+        assert!(!report.forward);
+
+        // The same change is harmless with tagged fields
+        let old = old.with_optional_encoding(OptionalEncoding::Tagged);
+        let new = new.with_optional_encoding(OptionalEncoding::Tagged);
+        assert!(check_compatibility(&old, &new).is_full());
+    }
+
+    #[test]
+    fn test_required_and_type_changes_are_breaking() {
+        let old = trade::schema();
+
+        let mut retyped = trade::schema();
+        retyped.fields[2].ty = FieldType::U64;
+        retyped.version += 1;
+        let report = check_compatibility(&old, &retyped);
+        assert!(!report.backward && !report.forward);
+        assert_eq!(report.changes[0].to_string(), "~ qty: u32 -> u64");
+
+        let mut extra = trade::schema().required("fee", FieldType::I64);
+        extra.version += 1;
+        let report = check_compatibility(&old, &extra);
+        assert!(!report.backward && !report.forward);
+
+        let mut registry = SchemaRegistry::with_builtin();
+        assert_eq!(
+            registry.register_compatible(extra, true, false),
+            Err(Error::IncompatibleSchema)
+        );
+        assert!(registry
+            .register_compatible(trade_v2(), true, true)
+            .unwrap()
+            .is_some_and(|r| r.is_full()));
+    }
+
+    #[test]
+    fn test_schema_frame_roundtrip() {
+        let schemas = [
+            trade::schema(),
+            crate::messages::quote::schema(),
+            MessageSchema::new(70, "Book", 3)
+                .with_optional_encoding(OptionalEncoding::Tagged)
+                .required("ts", FieldType::Timestamp)
+                .required("depth", FieldType::VarU64)
+                .optional("imbalance", FieldType::VarI64, 200),
+        ];
+
+        let mut buf = [0u8; 512];
+        for schema in schemas {
+            let size = encode_schema(&mut buf, 4, &schema).unwrap();
+            let (header, decoded) = decode_schema(&buf[..size]).unwrap();
+            assert_eq!(header.msg_type, msg_types::SCHEMA);
+            assert_eq!(header.ver, HEADER_V1);
+            assert_eq!(decoded, schema);
+        }
+    }
+
+    #[test]
+    fn test_schema_frame_field_limit() {
+        let wide = |count: usize| {
+            (0..count).fold(MessageSchema::new(71, "Wide", 0), |schema, i| {
+                schema.required(&alloc::format!("f{}", i), FieldType::U8)
+            })
+        };
+        let mut buf = alloc::vec![0u8; 32 * 1024];
+
+        let schema = wide(MAX_FIELDS);
+        let size = encode_schema(&mut buf, 0, &schema).unwrap();
+        assert_eq!(decode_schema(&buf[..size]).unwrap().1, schema);
+
+        let size = encode_schema(&mut buf, 0, &wide(MAX_FIELDS + 1)).unwrap();
+        assert_eq!(decode_schema(&buf[..size]), Err(Error::InvalidSchema));
+    }
+
+    #[test]
+    fn test_schema_frame_rejects_bad_codes() {
+        let mut buf = [0u8; 256];
+        let size = encode_schema(&mut buf, 0, &trade::schema()).unwrap();
+
+        // First field's type code: header, msg_type, version, "Trade",
+        // encoding, count, group length, "ts_ns"
+        let type_offset = FrameHeader::SIZE + 2 + 1 + 6 + 1 + 1 + 4 + 6;
+        buf[type_offset] = 0xEE;
+        let crc = crate::crc32c::crc32c(&buf[..size - 4]);
+        buf[size - 4..size].copy_from_slice(&crc.to_le_bytes());
+
+        assert_eq!(decode_schema(&buf[..size]), Err(Error::InvalidSchema));
+    }
+
+    #[test]
+    fn test_group_changes_are_nested_in_diff() {
+        let leg = MessageSchema::group().required("px", FieldType::Decimal(4));
+        let old = MessageSchema::new(80, "Spread", 0).required_group("leg", leg.clone());
+        let new = MessageSchema::new(80, "Spread", 1)
+            .required_group("leg", leg.optional("venue", FieldType::Bytes, 0));
+
+        let report = check_compatibility(&old, &new);
+        assert!(report.is_full());
+        assert_eq!(
+            report.changes[0].to_string(),
+            "~ leg { + optional #0 venue: bytes; }"
+        );
+
+        let retyped = MessageSchema::new(80, "Spread", 1).required_group(
+            "leg",
+            MessageSchema::group().required("px", FieldType::Decimal(2)),
+        );
+        assert!(!check_compatibility(&old, &retyped).backward);
+        assert_eq!(
+            MessageSchema::new(80, "Spread", 0)
+                .required("leg", FieldType::Group)
+                .validate(),
+            Err(Error::InvalidSchema)
+        );
+    }
+
+    #[test]
+    fn test_rename_is_compatible() {
+        let mut renamed = trade::schema();
+        renamed.fields[0].name = "timestamp".to_string();
+        renamed.version += 1;
+
+        let report = check_compatibility(&trade::schema(), &renamed);
+        assert!(report.is_full());
+        assert_eq!(
+            report.changes[0].to_string(),
+            "~ ts_ns renamed to timestamp"
+        );
+    }
+}
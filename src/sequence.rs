@@ -0,0 +1,221 @@
+//! Per-channel sequence numbering
+//!
+//! Each channel carried in [`FrameHeader::channel`] has its own sequence
+//! space. [`Sequencer`] assigns outbound sequence numbers and
+//! [`SeqTracker`] checks inbound ones, reporting gaps and duplicates.
+//!
+//! Sequence numbers wrap at `u32::MAX`. An inbound number up to 2^31 ahead of
+//! the expected one is treated as a gap; anything behind it is a duplicate.
+
+use alloc::collections::BTreeMap;
+
+use crate::frame::FrameHeader;
+
+/// Result of checking an inbound sequence number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqStatus {
+    /// First frame seen on the channel
+    First,
+    /// Exactly the expected sequence number
+    InOrder,
+    /// Frames `expected..received` were skipped
+    Gap {
+        /// First missing sequence number
+        expected: u32,
+        /// Sequence number actually received
+        received: u32,
+    },
+    /// Sequence number already seen (or older)
+    Duplicate {
+        /// Sequence number that was expected next
+        expected: u32,
+        /// Sequence number actually received
+        received: u32,
+    },
+}
+
+impl SeqStatus {
+    /// Number of frames missing before this one (0 unless a gap)
+    #[inline]
+    pub fn missing(&self) -> u32 {
+        match *self {
+            SeqStatus::Gap { expected, received } => received.wrapping_sub(expected),
+            _ => 0,
+        }
+    }
+
+    /// Check if the frame should be processed (not a duplicate)
+    #[inline]
+    pub fn is_new(&self) -> bool {
+        !matches!(self, SeqStatus::Duplicate { .. })
+    }
+}
+
+/// Assigns outbound sequence numbers, one counter per channel
+#[derive(Debug, Clone, Default)]
+pub struct Sequencer {
+    next: BTreeMap<u16, u32>,
+    initial: u32,
+}
+
+impl Sequencer {
+    /// Create sequencer whose channels start at 0
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create sequencer whose channels start at `initial`
+    #[inline]
+    pub fn starting_at(initial: u32) -> Self {
+        Self {
+            next: BTreeMap::new(),
+            initial,
+        }
+    }
+
+    /// Take the next sequence number for `channel`
+    #[inline]
+    pub fn next_seq(&mut self, channel: u16) -> u32 {
+        let next = self.next.entry(channel).or_insert(self.initial);
+        let seq = *next;
+        *next = seq.wrapping_add(1);
+        seq
+    }
+
+    /// Sequence number the next frame on `channel` will get
+    #[inline]
+    pub fn peek(&self, channel: u16) -> u32 {
+        self.next.get(&channel).copied().unwrap_or(self.initial)
+    }
+
+    /// Assign `header.seq` from the header's channel
+    #[inline]
+    pub fn stamp(&mut self, header: &mut FrameHeader) {
+        header.seq = self.next_seq(header.channel);
+    }
+}
+
+/// Tracks inbound sequence numbers, one expected value per channel
+#[derive(Debug, Clone, Default)]
+pub struct SeqTracker {
+    expected: BTreeMap<u16, u32>,
+}
+
+impl SeqTracker {
+    /// Create tracker with no channel history
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check `seq` on `channel` and advance the expected value
+    ///
+    /// Duplicates leave the expected value unchanged.
+    #[inline]
+    pub fn observe(&mut self, channel: u16, seq: u32) -> SeqStatus {
+        let Some(expected) = self.expected.get_mut(&channel) else {
+            self.expected.insert(channel, seq.wrapping_add(1));
+            return SeqStatus::First;
+        };
+
+        let ahead = seq.wrapping_sub(*expected);
+        let status = if ahead == 0 {
+            SeqStatus::InOrder
+        } else if ahead < 1 << 31 {
+            SeqStatus::Gap {
+                expected: *expected,
+                received: seq,
+            }
+        } else {
+            return SeqStatus::Duplicate {
+                expected: *expected,
+                received: seq,
+            };
+        };
+
+        *expected = seq.wrapping_add(1);
+        status
+    }
+
+    /// Check the sequence number of a decoded header
+    #[inline]
+    pub fn observe_header(&mut self, header: &FrameHeader) -> SeqStatus {
+        self.observe(header.channel, header.seq)
+    }
+
+    /// Next sequence number expected on `channel`
+    #[inline]
+    pub fn expected(&self, channel: u16) -> Option<u32> {
+        self.expected.get(&channel).copied()
+    }
+
+    /// Forget the history of `channel`
+    #[inline]
+    pub fn reset_channel(&mut self, channel: u16) {
+        self.expected.remove(&channel);
+    }
+
+    /// Forget the history of every channel
+    #[inline]
+    pub fn reset(&mut self) {
+        self.expected.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequencer_per_channel() {
+        let mut sequencer = Sequencer::starting_at(100);
+
+        let mut a = FrameHeader::new(1, 0, 0).with_channel(1);
+        let mut b = FrameHeader::new(1, 0, 0).with_channel(2);
+
+        sequencer.stamp(&mut a);
+        assert_eq!(a.seq, 100);
+        sequencer.stamp(&mut a);
+        assert_eq!(a.seq, 101);
+        sequencer.stamp(&mut b);
+        assert_eq!(b.seq, 100);
+
+        assert_eq!(sequencer.peek(1), 102);
+        assert_eq!(sequencer.peek(9), 100);
+    }
+
+    #[test]
+    fn test_tracker_gaps_and_duplicates() {
+        let mut tracker = SeqTracker::new();
+
+        assert_eq!(tracker.observe(1, 10), SeqStatus::First);
+        assert_eq!(tracker.observe(1, 11), SeqStatus::InOrder);
+
+        let gap = tracker.observe(1, 15);
+        assert_eq!(
+            gap,
+            SeqStatus::Gap {
+                expected: 12,
+                received: 15
+            }
+        );
+        assert_eq!(gap.missing(), 3);
+
+        let dup = tracker.observe(1, 13);
+        assert!(!dup.is_new());
+        assert_eq!(tracker.expected(1), Some(16));
+
+        // Other channels are independent
+        assert_eq!(tracker.observe(2, 0), SeqStatus::First);
+        assert_eq!(tracker.observe(2, 1), SeqStatus::InOrder);
+    }
+
+    #[test]
+    fn test_tracker_wraparound() {
+        let mut tracker = SeqTracker::new();
+        tracker.observe(0, u32::MAX);
+        assert_eq!(tracker.observe(0, 0), SeqStatus::InOrder);
+        assert_eq!(tracker.observe(0, 2).missing(), 1);
+    }
+}
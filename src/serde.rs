@@ -0,0 +1,1592 @@
+//! Serde `Serializer`/`Deserializer` mapping structs onto frame bodies
+//!
+//! Enabled by the `serde` feature. The top-level struct is laid out like the
+//! hand-written codecs in [`messages`](crate::messages):
+//!
+//! - non-`Option` fields are written in declaration order
+//! - `Option` fields get presence bitmap indices in declaration order; when
+//!   any is `Some`, the header gets `PRESENCE_BITMAP` and the bitmap plus the
+//!   present values follow the fixed fields
+//!
+//! Inside field values, integers are fixed-width little-endian (`bool`,
+//! `i8` and `i16` as their unsigned counterparts, floats as their bits),
+//! `str` and byte slices are varbytes and borrow from the frame when
+//! deserialized, sequences and maps carry a varint count, tuples and nested
+//! structs are inlined, nested `Option`s carry a 0/1 byte, and enums a varint
+//! variant index. A `Serialize` struct with `&[u8]`/`&str` fields therefore
+//! produces the same bytes as the matching `messages` encoder.
+//!
+//! The format is not self-describing, so `deserialize_any` and
+//! `deserialize_ignored_any` are unsupported.
+//!
+//! Decoding a frame with optional fields reads the bitmap where the first
+//! `Option` field is met, which is where the fixed fields end if every
+//! `Option` comes after them. A struct declaring fixed fields after an
+//! `Option` is decoded a second time: first with every option absent to find
+//! the end of the fixed fields, then with the bitmap applied. The same
+//! happens when decoding fails, so the error reported is that of the
+//! second pass.
+
+use ::serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use ::serde::ser::{self, Impossible, Serialize};
+use ::serde::Deserialize;
+use core::fmt::Display;
+
+use crate::bitmap::{BitmapSize, PresenceBitmap};
+use crate::decoder::{BodyCursor, FrameDecoder};
+use crate::encoder::FrameEncoder;
+use crate::error::{Error, Result};
+use crate::frame::{FrameFlags, FrameHeader};
+
+/// Presence bitmap layout used by [`to_frame`] and [`from_frame`]
+/// (16-bit, matching the built-in messages)
+pub const BITMAP_SIZE: BitmapSize = BitmapSize::U16;
+
+impl ser::Error for Error {
+    fn custom<T: Display>(_msg: T) -> Self {
+        Error::Serde
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(_msg: T) -> Self {
+        Error::Serde
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl de::StdError for Error {}
+
+/// Encode `value` as the body of a frame with `header`
+///
+/// The header's `PRESENCE_BITMAP` flag is set or cleared to match the
+/// `Option` fields. Returns `Error::FlagConflict` if `TAGGED` is set.
+pub fn to_frame<T: ?Sized + Serialize>(
+    buf: &mut [u8],
+    header: &FrameHeader,
+    value: &T,
+) -> Result<usize> {
+    to_frame_with(buf, header, value, BITMAP_SIZE)
+}
+
+/// Encode `value` as a frame body using a presence bitmap of `bitmap_size`
+pub fn to_frame_with<T: ?Sized + Serialize>(
+    buf: &mut [u8],
+    header: &FrameHeader,
+    value: &T,
+    bitmap_size: BitmapSize,
+) -> Result<usize> {
+    if header.has_flag(FrameFlags::TAGGED) {
+        return Err(Error::FlagConflict);
+    }
+
+    let mut encoder = FrameEncoder::new(buf);
+    let mut header = *header;
+
+    let bitmap = Body::run(&mut encoder, value, Pass::Probe, bitmap_size)?;
+    if bitmap.is_empty() {
+        header.clear_flag(FrameFlags::PRESENCE_BITMAP);
+    } else {
+        header.set_flag(FrameFlags::PRESENCE_BITMAP);
+    }
+
+    encoder.begin(&header)?;
+    Body::run(&mut encoder, value, Pass::Required, bitmap_size)?;
+    if !bitmap.is_empty() {
+        encoder.put_presence_bitmap(&bitmap)?;
+        Body::run(&mut encoder, value, Pass::Optional, bitmap_size)?;
+    }
+
+    encoder.finish_crc32c()
+}
+
+/// Decode a frame body written by [`to_frame`]
+pub fn from_frame<'de, T: Deserialize<'de>>(buf: &'de [u8]) -> Result<(FrameHeader, T)> {
+    from_frame_with(buf, BITMAP_SIZE)
+}
+
+/// Decode a frame body written by [`to_frame_with`]
+pub fn from_frame_with<'de, T: Deserialize<'de>>(
+    buf: &'de [u8],
+    bitmap_size: BitmapSize,
+) -> Result<(FrameHeader, T)> {
+    let decoder = FrameDecoder::new(buf);
+    let header = decoder.header()?;
+    decoder.verify_crc32c()?;
+
+    let value = from_body(&header, decoder.body()?, bitmap_size)?;
+    Ok((header, value))
+}
+
+/// Decode a body positioned at its first field
+///
+/// Returns `Error::FlagConflict` for tagged frames.
+pub fn from_body<'de, T: Deserialize<'de>>(
+    header: &FrameHeader,
+    body: BodyCursor<'de>,
+    bitmap_size: BitmapSize,
+) -> Result<T> {
+    if header.has_flag(FrameFlags::TAGGED) {
+        return Err(Error::FlagConflict);
+    }
+
+    let mut required = BodyCursor {
+        buf: body.buf,
+        pos: body.pos,
+    };
+
+    if !header.has_flag(FrameFlags::PRESENCE_BITMAP) {
+        return T::deserialize(&mut BodyDeserializer::new(&mut required, Optional::Absent));
+    }
+
+    // Options usually follow the fixed fields, and then a single pass finds
+    // the bitmap at the first of them
+    let lazy = Optional::AtFirstOption(bitmap_size);
+    if let Ok(value) = T::deserialize(&mut BodyDeserializer::new(&mut required, lazy)) {
+        return Ok(value);
+    }
+
+    // Fixed fields may be interleaved with options, so find where they end
+    // by decoding once with every option absent
+    let mut probe = BodyCursor {
+        buf: body.buf,
+        pos: body.pos,
+    };
+    T::deserialize(&mut BodyDeserializer::new(&mut probe, Optional::Absent))?;
+
+    let mut optional = probe;
+    let bitmap = optional.get_presence_bitmap(bitmap_size)?;
+    let mut required = BodyCursor {
+        buf: body.buf,
+        pos: body.pos,
+    };
+    T::deserialize(&mut BodyDeserializer::new(
+        &mut required,
+        Optional::Present(bitmap, optional),
+    ))
+}
+
+// ---------------------------------------------------------------------------
+// Serialization
+// ---------------------------------------------------------------------------
+
+/// Which part of the body a pass over the top-level struct writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pass {
+    /// Write nothing; collect the presence bitmap
+    Probe,
+    /// Write non-`Option` fields
+    Required,
+    /// Write the values of `Some` fields
+    Optional,
+}
+
+/// Value serializer; `skip` turns every write into a no-op
+struct Writer<'e, 'buf> {
+    encoder: &'e mut FrameEncoder<'buf>,
+    skip: bool,
+}
+
+impl<'buf> Writer<'_, 'buf> {
+    #[inline]
+    fn put(&mut self, write: impl FnOnce(&mut FrameEncoder<'buf>) -> Result<()>) -> Result<()> {
+        if self.skip {
+            return Ok(());
+        }
+        write(self.encoder)
+    }
+
+    #[inline]
+    fn put_len(&mut self, len: Option<usize>) -> Result<()> {
+        let len = len.ok_or(Error::UnsupportedType)?;
+        let len = u32::try_from(len).map_err(|_| Error::Overflow)?;
+        self.put(|e| e.put_varint_u32(len))
+    }
+}
+
+impl<'w, 'e, 'buf> ser::Serializer for &'w mut Writer<'e, 'buf> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.put(|e| e.put_u8(v as u8))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.put(|e| e.put_u8(v as u8))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.put(|e| e.put_u16(v as u16))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.put(|e| e.put_i32(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.put(|e| e.put_i64(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.put(|e| e.put_u8(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.put(|e| e.put_u16(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.put(|e| e.put_u32(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.put(|e| e.put_u64(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.put(|e| e.put_u32(v.to_bits()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.put(|e| e.put_u64(v.to_bits()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.put(|e| e.put_u32(v as u32))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.put(|e| e.put_varbytes(v.as_bytes()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.put(|e| e.put_varbytes(v))
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.put(|e| e.put_u8(0))
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<()> {
+        self.put(|e| e.put_u8(1))?;
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<()> {
+        self.put(|e| e.put_varint_u32(variant_index))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.put(|e| e.put_varint_u32(variant_index))?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self> {
+        self.put_len(len)?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self> {
+        self.put(|e| e.put_varint_u32(variant_index))?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self> {
+        self.put_len(len)?;
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self> {
+        self.put(|e| e.put_varint_u32(variant_index))?;
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl ser::SerializeSeq for &mut Writer<'_, '_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut Writer<'_, '_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut Writer<'_, '_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for &mut Writer<'_, '_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for &mut Writer<'_, '_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<()> {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut Writer<'_, '_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut Writer<'_, '_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Serializer for the top-level struct; accepts nothing else
+struct Body<'w, 'e, 'buf> {
+    writer: &'w mut Writer<'e, 'buf>,
+    pass: Pass,
+    bitmap: PresenceBitmap,
+    next_option: usize,
+}
+
+impl<'w, 'e, 'buf> Body<'w, 'e, 'buf> {
+    /// Run one pass over `value`, returning the bitmap collected by a probe
+    fn run<T: ?Sized + Serialize>(
+        encoder: &'e mut FrameEncoder<'buf>,
+        value: &T,
+        pass: Pass,
+        bitmap_size: BitmapSize,
+    ) -> Result<PresenceBitmap> {
+        let mut writer = Writer {
+            encoder,
+            skip: true,
+        };
+        let mut body = Body {
+            writer: &mut writer,
+            pass,
+            bitmap: PresenceBitmap::new(bitmap_size),
+            next_option: 0,
+        };
+        value.serialize(&mut body)?;
+        Ok(body.bitmap)
+    }
+
+    /// Handle the next top-level `Option` field
+    fn option<T: ?Sized + Serialize>(&mut self, value: Option<&T>) -> Result<()> {
+        let index = self.next_option;
+        self.next_option += 1;
+        if index >= self.bitmap.size().max_fields() {
+            return Err(Error::Overflow);
+        }
+
+        match (self.pass, value) {
+            (Pass::Probe, Some(_)) => self.bitmap.set(index),
+            (Pass::Optional, Some(value)) => {
+                self.writer.skip = false;
+                value.serialize(&mut *self.writer)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'x, 'w, 'e, 'buf> ser::Serializer for &'x mut Body<'w, 'e, 'buf> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self> {
+        Ok(self)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_char(self, _v: char) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<()> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(Error::UnsupportedType)
+    }
+}
+
+impl ser::SerializeStruct for &mut Body<'_, '_, '_> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(Field { body: self })
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Serializer for one top-level field: `Option`s go through the bitmap,
+/// everything else is written during the required pass only
+struct Field<'x, 'w, 'e, 'buf> {
+    body: &'x mut Body<'w, 'e, 'buf>,
+}
+
+impl<'x, 'e, 'buf> Field<'x, '_, 'e, 'buf> {
+    #[inline]
+    fn value(self) -> &'x mut Writer<'e, 'buf> {
+        self.body.writer.skip = self.body.pass != Pass::Required;
+        &mut *self.body.writer
+    }
+}
+
+macro_rules! forward_to_writer {
+    ($($method:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        $(
+            fn $method(self, $($arg: $ty),*) -> Result<$ret> {
+                self.value().$method($($arg),*)
+            }
+        )*
+    };
+}
+
+impl<'x, 'w, 'e, 'buf> ser::Serializer for Field<'x, 'w, 'e, 'buf> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = &'x mut Writer<'e, 'buf>;
+    type SerializeTuple = &'x mut Writer<'e, 'buf>;
+    type SerializeTupleStruct = &'x mut Writer<'e, 'buf>;
+    type SerializeTupleVariant = &'x mut Writer<'e, 'buf>;
+    type SerializeMap = &'x mut Writer<'e, 'buf>;
+    type SerializeStruct = &'x mut Writer<'e, 'buf>;
+    type SerializeStructVariant = &'x mut Writer<'e, 'buf>;
+
+    fn serialize_none(self) -> Result<()> {
+        self.body.option::<()>(None)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<()> {
+        self.body.option(Some(value))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.value().serialize_newtype_struct(name, value)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.value()
+            .serialize_newtype_variant(name, variant_index, variant, value)
+    }
+
+    forward_to_writer! {
+        serialize_bool(v: bool) -> ();
+        serialize_i8(v: i8) -> ();
+        serialize_i16(v: i16) -> ();
+        serialize_i32(v: i32) -> ();
+        serialize_i64(v: i64) -> ();
+        serialize_u8(v: u8) -> ();
+        serialize_u16(v: u16) -> ();
+        serialize_u32(v: u32) -> ();
+        serialize_u64(v: u64) -> ();
+        serialize_f32(v: f32) -> ();
+        serialize_f64(v: f64) -> ();
+        serialize_char(v: char) -> ();
+        serialize_str(v: &str) -> ();
+        serialize_bytes(v: &[u8]) -> ();
+        serialize_unit() -> ();
+        serialize_unit_struct(name: &'static str) -> ();
+        serialize_unit_variant(name: &'static str, variant_index: u32, variant: &'static str) -> ();
+        serialize_seq(len: Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(len: usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(name: &'static str, len: usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(
+            name: &'static str,
+            variant_index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> Self::SerializeTupleVariant;
+        serialize_map(len: Option<usize>) -> Self::SerializeMap;
+        serialize_struct(name: &'static str, len: usize) -> Self::SerializeStruct;
+        serialize_struct_variant(
+            name: &'static str,
+            variant_index: u32,
+            variant: &'static str,
+            len: usize
+        ) -> Self::SerializeStructVariant;
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Deserialization
+// ---------------------------------------------------------------------------
+
+/// Read a varint count, rejecting counts that cannot fit in the rest of the
+/// body (every element takes at least one byte)
+#[inline]
+fn get_len(cursor: &mut BodyCursor<'_>) -> Result<usize> {
+    let len = cursor.get_varint_u32()? as usize;
+    if len > cursor.remaining() {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok(len)
+}
+
+impl<'de> de::Deserializer<'de> for &mut BodyCursor<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.get_u8()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            _ => Err(Error::DecodeInvariant),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i8(self.get_u8()? as i8)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i16(self.get_u16()? as i16)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i32(self.get_i32()?)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i64(self.get_i64()?)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u8(self.get_u8()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u16(self.get_u16()?)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u32(self.get_u32()?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u64(self.get_u64()?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_f32(f32::from_bits(self.get_u32()?))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_f64(f64::from_bits(self.get_u64()?))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let c = char::from_u32(self.get_u32()?).ok_or(Error::DecodeInvariant)?;
+        visitor.visit_char(c)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let s = core::str::from_utf8(self.get_varbytes()?).map_err(|_| Error::DecodeInvariant)?;
+        visitor.visit_borrowed_str(s)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_bytes(self.get_varbytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.get_u8()? {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            _ => Err(Error::DecodeInvariant),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let remaining = get_len(self)?;
+        visitor.visit_seq(Elements {
+            cursor: self,
+            remaining,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Elements {
+            cursor: self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let remaining = get_len(self)?;
+        visitor.visit_map(Elements {
+            cursor: self,
+            remaining,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u32(self.get_varint_u32()?)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// Sequence, tuple, struct or map elements read from a cursor
+struct Elements<'r, 'de> {
+    cursor: &'r mut BodyCursor<'de>,
+    remaining: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for Elements<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.cursor).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Elements<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.cursor).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        seed.deserialize(&mut *self.cursor)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for &mut BodyCursor<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let index = self.get_varint_u32()?;
+        let value = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(index))?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for &mut BodyCursor<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
+
+/// Where the top-level `Option` fields come from
+enum Optional<'de> {
+    /// Every `Option` field reads as `None`
+    Absent,
+    /// The bitmap follows the fixed fields read before the first `Option`
+    /// field; reading a fixed field after it fails the pass
+    AtFirstOption(BitmapSize),
+    /// The bitmap and a cursor at the first present value
+    Present(PresenceBitmap, BodyCursor<'de>),
+}
+
+/// Deserializer for the top-level struct
+struct BodyDeserializer<'r, 'de> {
+    required: &'r mut BodyCursor<'de>,
+    optional: Optional<'de>,
+    next_option: usize,
+    /// The bitmap was read where the first `Option` field was met
+    fixed_ended: bool,
+}
+
+impl<'r, 'de> BodyDeserializer<'r, 'de> {
+    fn new(required: &'r mut BodyCursor<'de>, optional: Optional<'de>) -> Self {
+        Self {
+            required,
+            optional,
+            next_option: 0,
+            fixed_ended: false,
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut BodyDeserializer<'_, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(Error::UnsupportedType)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_seq(BodyFields {
+            body: self,
+            remaining: fields.len(),
+        })
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    ::serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct seq tuple tuple_struct map enum
+        identifier ignored_any
+    }
+}
+
+/// Top-level struct fields
+struct BodyFields<'b, 'r, 'de> {
+    body: &'b mut BodyDeserializer<'r, 'de>,
+    remaining: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for BodyFields<'_, '_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(FieldDeserializer {
+            body: &mut *self.body,
+        })
+        .map(Some)
+    }
+}
+
+/// Deserializer for one top-level field: `Option`s consult the bitmap,
+/// everything else reads the fixed fields in order
+struct FieldDeserializer<'b, 'r, 'de> {
+    body: &'b mut BodyDeserializer<'r, 'de>,
+}
+
+macro_rules! forward_to_cursor {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value> {
+                self.fixed()?.$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'b, 'de> FieldDeserializer<'b, '_, 'de> {
+    /// Cursor for a fixed field, failing if the bitmap was read before it
+    #[inline]
+    fn fixed(self) -> Result<&'b mut BodyCursor<'de>> {
+        if self.body.fixed_ended {
+            return Err(Error::DecodeInvariant);
+        }
+        Ok(&mut *self.body.required)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for FieldDeserializer<'_, '_, 'de> {
+    type Error = Error;
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let index = self.body.next_option;
+        self.body.next_option += 1;
+        if let Optional::AtFirstOption(size) = self.body.optional {
+            let required = &*self.body.required;
+            let mut cursor = BodyCursor {
+                buf: required.buf,
+                pos: required.pos,
+            };
+            let bitmap = cursor.get_presence_bitmap(size)?;
+            self.body.optional = Optional::Present(bitmap, cursor);
+            self.body.fixed_ended = true;
+        }
+        match &mut self.body.optional {
+            Optional::Present(bitmap, cursor) if bitmap.is_set(index) => visitor.visit_some(cursor),
+            _ => visitor.visit_none(),
+        }
+    }
+
+    forward_to_cursor! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{msg_types, quote, trade};
+    use ::serde::{Deserialize, Serialize};
+    use std::string::String;
+    use std::vec;
+    use std::vec::Vec;
+
+    const TS_NS: u64 = 1_700_000_000_000_000_000;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Trade<'a> {
+        ts_ns: u64,
+        price: i64,
+        qty: u32,
+        #[serde(borrow)]
+        symbol: Option<&'a [u8]>,
+        #[serde(borrow)]
+        note: Option<&'a [u8]>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Quote<'a> {
+        ts_ns: u64,
+        bid: i64,
+        ask: i64,
+        level: u8,
+        symbol: Option<&'a str>,
+    }
+
+    #[test]
+    fn test_matches_handwritten_trade_codec() {
+        let cases = [
+            (None, None),
+            (Some(&b"AAPL"[..]), None),
+            (None, Some(&b"note"[..])),
+            (Some(&b"MSFT"[..]), Some(&b""[..])),
+        ];
+
+        for (symbol, note) in cases {
+            let mut expected = [0u8; 128];
+            let expected_len =
+                trade::encode(&mut expected, 9, TS_NS, -1, 100, symbol, note).unwrap();
+
+            let value = Trade {
+                ts_ns: TS_NS,
+                price: -1,
+                qty: 100,
+                symbol,
+                note,
+            };
+            let mut buf = [0u8; 128];
+            let header = FrameHeader::new(msg_types::TRADE_V1, 9, 0);
+            let len = to_frame(&mut buf, &header, &value).unwrap();
+            assert_eq!(&buf[..len], &expected[..expected_len]);
+
+            let (_, decoded): (_, Trade<'_>) = from_frame(&buf[..len]).unwrap();
+            assert_eq!(decoded, value);
+        }
+    }
+
+    #[test]
+    fn test_quote_borrows_str_from_frame() {
+        let mut buf = [0u8; 128];
+        let len = quote::encode(&mut buf, 1, TS_NS, 10, 11, 2, Some(b"BTC/USD")).unwrap();
+
+        let (header, decoded): (_, Quote<'_>) = from_frame(&buf[..len]).unwrap();
+        assert_eq!(header.msg_type, msg_types::QUOTE_V1);
+        assert_eq!(decoded.symbol, Some("BTC/USD"));
+        let symbol_ptr = decoded.symbol.unwrap().as_ptr();
+        assert!(buf[..len].as_ptr_range().contains(&symbol_ptr));
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Side {
+        Buy,
+        Sell { venue: String },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: u32,
+        comment: Option<String>,
+        side: Side,
+        fills: Vec<(u32, f64)>,
+        tag: Option<Option<u8>>,
+        active: bool,
+    }
+
+    #[test]
+    fn test_interleaved_options_and_nested_types() {
+        let orders = [
+            Order {
+                id: 1,
+                comment: None,
+                side: Side::Buy,
+                fills: vec![],
+                tag: None,
+                active: true,
+            },
+            Order {
+                id: 2,
+                comment: Some(String::from("hedge")),
+                side: Side::Sell {
+                    venue: String::from("XLON"),
+                },
+                fills: vec![(5, 1.25), (7, -0.5)],
+                tag: Some(None),
+                active: false,
+            },
+        ];
+
+        for order in orders {
+            let mut buf = [0u8; 256];
+            let len = to_frame(&mut buf, &FrameHeader::new(50, 0, 0), &order).unwrap();
+            let (header, decoded): (_, Order) = from_frame(&buf[..len]).unwrap();
+            assert_eq!(decoded, order);
+            assert_eq!(
+                header.has_flag(FrameFlags::PRESENCE_BITMAP),
+                order.comment.is_some() || order.tag.is_some()
+            );
+        }
+    }
+
+    #[test]
+    fn test_unsupported_shapes() {
+        let mut buf = [0u8; 64];
+        let header = FrameHeader::new(50, 0, 0);
+        assert_eq!(
+            to_frame(&mut buf, &header, &42u32),
+            Err(Error::UnsupportedType)
+        );
+
+        let mut tagged = header;
+        tagged.set_flag(FrameFlags::TAGGED);
+        assert_eq!(to_frame(&mut buf, &tagged, &()), Err(Error::FlagConflict));
+
+        // 17 options do not fit the default 16-bit bitmap
+        #[derive(Default, Serialize)]
+        struct Wide {
+            a: Option<u8>,
+            b: Option<u8>,
+            c: Option<u8>,
+            d: Option<u8>,
+            e: Option<u8>,
+            f: Option<u8>,
+            g: Option<u8>,
+            h: Option<u8>,
+            i: Option<u8>,
+            j: Option<u8>,
+            k: Option<u8>,
+            l: Option<u8>,
+            m: Option<u8>,
+            n: Option<u8>,
+            o: Option<u8>,
+            p: Option<u8>,
+            q: Option<u8>,
+        }
+        let wide = Wide {
+            a: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(to_frame(&mut buf, &header, &wide), Err(Error::Overflow));
+        assert!(to_frame_with(&mut buf, &header, &wide, BitmapSize::Var).is_ok());
+    }
+
+    std::thread_local! {
+        static DECODES: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+    }
+
+    /// A `u32` that counts how often it is deserialized
+    #[derive(Debug, PartialEq, Serialize)]
+    struct Counted(u32);
+
+    impl<'de> Deserialize<'de> for Counted {
+        fn deserialize<D: de::Deserializer<'de>>(d: D) -> core::result::Result<Self, D::Error> {
+            DECODES.with(|n| n.set(n.get() + 1));
+            u32::deserialize(d).map(Counted)
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Trailing {
+        id: Counted,
+        note: Option<u8>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Leading {
+        note: Option<u8>,
+        id: Counted,
+    }
+
+    #[test]
+    fn test_single_pass_when_options_trail() {
+        let mut buf = [0u8; 64];
+        let header = FrameHeader::new(50, 0, 0);
+        let decodes = |frame: &[u8], leading: bool| {
+            DECODES.with(|n| n.set(0));
+            if leading {
+                let (_, value): (_, Leading) = from_frame(frame).unwrap();
+                assert_eq!(
+                    value,
+                    Leading {
+                        note: Some(3),
+                        id: Counted(7)
+                    }
+                );
+            } else {
+                let (_, value): (_, Trailing) = from_frame(frame).unwrap();
+                assert_eq!(
+                    value,
+                    Trailing {
+                        id: Counted(7),
+                        note: Some(3)
+                    }
+                );
+            }
+            DECODES.with(|n| n.get())
+        };
+
+        let trailing = Trailing {
+            id: Counted(7),
+            note: Some(3),
+        };
+        let len = to_frame(&mut buf, &header, &trailing).unwrap();
+        assert_eq!(decodes(&buf[..len], false), 1);
+
+        // A fixed field after an option ends the first pass: it is followed
+        // by a probe and a pass with the bitmap applied
+        let leading = Leading {
+            note: Some(3),
+            id: Counted(7),
+        };
+        let len = to_frame(&mut buf, &header, &leading).unwrap();
+        assert_eq!(decodes(&buf[..len], true), 3);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Level {
+        price: i64,
+        qty: u32,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Book<'a> {
+        venue: &'a str,
+        best: Level,
+        depth: Vec<Level>,
+        last: Option<Level>,
+        #[serde(borrow)]
+        raw: Option<&'a [u8]>,
+    }
+
+    #[test]
+    fn test_nested_structs() {
+        let books = [
+            Book {
+                venue: "XNAS",
+                best: Level { price: 10, qty: 1 },
+                depth: vec![],
+                last: None,
+                raw: None,
+            },
+            Book {
+                venue: "",
+                best: Level { price: -3, qty: 0 },
+                depth: vec![Level { price: 9, qty: 2 }, Level { price: 8, qty: 5 }],
+                last: Some(Level { price: 11, qty: 4 }),
+                raw: Some(&b"\x00\xff"[..]),
+            },
+        ];
+        for book in books {
+            let mut buf = [0u8; 256];
+            let len = to_frame(&mut buf, &FrameHeader::new(50, 0, 0), &book).unwrap();
+            let (_, decoded): (_, Book<'_>) = from_frame(&buf[..len]).unwrap();
+            assert_eq!(decoded, book);
+        }
+    }
+
+    type Write = fn(&mut FrameEncoder<'_>);
+
+    /// Frame of msg_type 50 with `fixed` fields, then a presence bitmap with
+    /// `options` set (if any) and the option `values`
+    fn frame_with(fixed: Write, options: &[usize], values: Write) -> Vec<u8> {
+        let mut buf = [0u8; 128];
+        let mut header = FrameHeader::new(50, 0, 0);
+        if !options.is_empty() {
+            header.set_flag(FrameFlags::PRESENCE_BITMAP);
+        }
+        let mut encoder = FrameEncoder::new(&mut buf);
+        encoder.begin(&header).unwrap();
+        fixed(&mut encoder);
+        if !options.is_empty() {
+            let mut bitmap = PresenceBitmap::new(BITMAP_SIZE);
+            for &index in options {
+                bitmap.set(index).unwrap();
+            }
+            encoder.put_presence_bitmap(&bitmap).unwrap();
+        }
+        values(&mut encoder);
+        let len = encoder.finish_crc32c().unwrap();
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn test_malformed_bodies() {
+        fn quote_fields(e: &mut FrameEncoder<'_>) {
+            e.put_u64(TS_NS).unwrap();
+            e.put_i64(10).unwrap();
+            e.put_i64(11).unwrap();
+            e.put_u8(2).unwrap();
+        }
+        fn trade_fields(e: &mut FrameEncoder<'_>) {
+            e.put_u64(TS_NS).unwrap();
+            e.put_i64(10).unwrap();
+            e.put_u32(100).unwrap();
+        }
+        fn oversized(e: &mut FrameEncoder<'_>) {
+            e.put_varint_u32(100).unwrap();
+            e.put_bytes(b"abc").unwrap();
+        }
+        fn none(_: &mut FrameEncoder<'_>) {}
+        let quote = |frame: &[u8]| from_frame::<Quote<'_>>(frame).map(|_| ());
+
+        // Truncated fixed fields
+        let frame = frame_with(|e| e.put_u64(TS_NS).unwrap(), &[], none);
+        assert_eq!(quote(&frame), Err(Error::UnexpectedEof));
+
+        // A bitmap promising a value that is missing
+        let frame = frame_with(quote_fields, &[0], none);
+        assert_eq!(quote(&frame), Err(Error::UnexpectedEof));
+
+        // Borrowed str and bytes longer than the body, and a str that is
+        // not UTF-8
+        let frame = frame_with(quote_fields, &[0], oversized);
+        assert_eq!(quote(&frame), Err(Error::UnexpectedEof));
+        let frame = frame_with(trade_fields, &[0], oversized);
+        let decoded = from_frame::<Trade<'_>>(&frame).map(|_| ());
+        assert_eq!(decoded, Err(Error::UnexpectedEof));
+        let frame = frame_with(quote_fields, &[0], |e| {
+            e.put_varbytes(&[0xff, 0xfe]).unwrap()
+        });
+        assert_eq!(quote(&frame), Err(Error::DecodeInvariant));
+
+        // A sequence count larger than the rest of the body
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Fills {
+            fills: Vec<u32>,
+        }
+        let frame = frame_with(|e| e.put_varint_u32(1000).unwrap(), &[], none);
+        let decoded = from_frame::<Fills>(&frame).map(|(_, f)| f);
+        assert_eq!(decoded, Err(Error::UnexpectedEof));
+
+        // Values cannot be skipped without knowing their type
+        #[derive(Debug, Deserialize)]
+        struct Skips {
+            _id: u32,
+            _rest: de::IgnoredAny,
+        }
+        let frame = frame_with(|e| e.put_u64(1).unwrap(), &[], none);
+        let decoded = from_frame::<Skips>(&frame).map(|_| ());
+        assert_eq!(decoded, Err(Error::UnsupportedType));
+    }
+}
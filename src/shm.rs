@@ -0,0 +1,1133 @@
+//! Shared-memory frame transport over a memory-mapped file
+//!
+//! A single-producer single-consumer ring: the [`Producer`] encodes frames
+//! straight into a slot of the mapping (with [`FrameEncoder`] or any of the
+//! message encoders) and the [`Consumer`] decodes them in place, usually
+//! from another process mapping the same file (e.g. under `/dev/shm`).
+//!
+//! File layout (little-endian, cursors on separate cache lines):
+//!
+//! ```text
+//! 0    magic "MBSPSC01" | capacity u64
+//! 64   write position u64 | frames written u64    (producer)
+//! 128  read position u64  | frames read u64       (consumer)
+//! 256  data: `capacity` bytes
+//! ```
+//!
+//! Positions count bytes since creation; `position % capacity` is the offset
+//! in the data area. Each record is a `u32` frame length, four reserved
+//! bytes and the frame, padded to 8 bytes. A record never wraps: when the
+//! end of the data area is too short, a padding marker sends the consumer
+//! back to the start, so frames are limited to half the capacity. The
+//! producer never overtakes the consumer; a full ring is backpressure
+//! ([`Producer::try_reserve`] returns `None`).
+//!
+//! For fan-out, a [`Publisher`] writes a broadcast ring that any number of
+//! [`Subscriber`]s follow with their own cursors. The publisher never waits:
+//! it overwrites the oldest frames and advances a tail position (stored at
+//! offset 128, where the SPSC ring keeps its read cursor) before reusing
+//! their bytes. A subscriber behind the tail has been lapped; it skips to
+//! the tail and reports the seqs it missed as an [`Overrun`]. Subscribers
+//! copy each frame out with atomic loads, since the publisher may be
+//! overwriting it, and check the tail again afterwards, so a frame torn by
+//! a concurrent overwrite is never delivered.
+//!
+//! [`FrameEncoder`]: crate::FrameEncoder
+
+use core::ops::Range;
+use core::ptr::NonNull;
+use core::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::vec::Vec;
+
+use memmap2::MmapMut;
+
+use crate::decoder::FrameDecoder;
+use crate::error::{Error, Result};
+use crate::frame::FrameHeader;
+
+/// Magic bytes at the start of a ring file
+pub const RING_MAGIC: [u8; 8] = *b"MBSPSC01";
+
+/// Magic bytes at the start of a broadcast ring file
+pub const BROADCAST_MAGIC: [u8; 8] = *b"MBSPMC01";
+
+/// Size of the control block before the data area
+pub const RING_HEADER_SIZE: usize = 256;
+
+/// Size of the length prefix in front of each frame
+pub const RECORD_PREFIX_SIZE: usize = 8;
+
+const WRITE_POS: usize = 64;
+const WRITTEN: usize = 72;
+const READ_POS: usize = 128;
+const READ: usize = 136;
+const TAIL: usize = 128;
+
+/// Length prefix of a padding record that skips to the start of the data
+const PAD: u32 = u32::MAX;
+
+/// Spins before yielding the thread while blocked
+const SPINS_BEFORE_YIELD: u32 = 64;
+
+#[inline]
+const fn record_size(frame_len: usize) -> usize {
+    (RECORD_PREFIX_SIZE + frame_len).next_multiple_of(8)
+}
+
+/// Largest frame a ring of `capacity` bytes takes
+///
+/// A record that does not fit before the end of the data area is written
+/// at the start of the next lap. Keeping records to half the capacity
+/// means that, with the ring drained, the skipped end plus the record
+/// always fit.
+#[inline]
+const fn max_frame_len(capacity: usize) -> usize {
+    capacity / 2 - RECORD_PREFIX_SIZE
+}
+
+/// How far the consumer is behind the producer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Lag {
+    /// Frames written but not yet consumed
+    pub frames: u64,
+    /// Ring bytes those frames occupy, padding included
+    pub bytes: u64,
+}
+
+/// A mapped ring file
+#[derive(Debug)]
+struct RingMap {
+    map: MmapMut,
+    base: NonNull<u8>,
+    capacity: usize,
+}
+
+// SAFETY: the mapping is plain shared memory. The control words are only
+// accessed atomically, and the producer and consumer only touch the data
+// ranges the cursor protocol gives them.
+#[allow(unsafe_code)]
+unsafe impl Send for RingMap {}
+
+#[allow(unsafe_code)]
+impl RingMap {
+    fn create(path: &Path, capacity: usize, magic: [u8; 8]) -> io::Result<Self> {
+        if !capacity.is_power_of_two() || capacity < 64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "ring capacity must be a power of two of at least 64",
+            ));
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        file.set_len((RING_HEADER_SIZE + capacity) as u64)?;
+        let mut map = Self::map(&file)?;
+        map[8..16].copy_from_slice(&(capacity as u64).to_le_bytes());
+        map[..8].copy_from_slice(&magic);
+        Self::new(map, magic)
+    }
+
+    fn open(path: &Path, magic: [u8; 8]) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Self::new(Self::map(&file)?, magic)
+    }
+
+    fn map(file: &File) -> io::Result<MmapMut> {
+        // SAFETY: the file is only accessed through this protocol; other
+        // writers (the peer process) are expected and synchronised by the
+        // atomic cursors.
+        unsafe { MmapMut::map_mut(file) }
+    }
+
+    fn new(mut map: MmapMut, magic: [u8; 8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg);
+        if map.len() < RING_HEADER_SIZE || map[..8] != magic {
+            return Err(invalid("not a minibit ring file of this kind"));
+        }
+        let capacity = u64::from_le_bytes(map[8..16].try_into().unwrap()) as usize;
+        if !capacity.is_power_of_two() || capacity < 64 || map.len() != RING_HEADER_SIZE + capacity
+        {
+            return Err(invalid("ring capacity does not match the file"));
+        }
+        let base = NonNull::new(map.as_mut_ptr()).ok_or_else(|| invalid("empty mapping"))?;
+        Ok(Self {
+            map,
+            base,
+            capacity,
+        })
+    }
+
+    /// Control word at `offset` (a multiple of 8 inside the header)
+    #[inline]
+    fn word(&self, offset: usize) -> &AtomicU64 {
+        debug_assert!(offset.is_multiple_of(8) && offset < RING_HEADER_SIZE);
+        // SAFETY: the mapping is page-aligned, so the offset is 8-aligned and
+        // in bounds; the word is only ever accessed atomically.
+        unsafe { &*(self.base.as_ptr().add(offset) as *const AtomicU64) }
+    }
+
+    /// Panics unless `at..at + len` lies in the data area
+    ///
+    /// Offsets and lengths come from shared memory, so a peer that breaks
+    /// the protocol must not be able to push them out of bounds.
+    #[inline]
+    fn check_range(&self, at: usize, len: usize) {
+        assert!(
+            at.checked_add(len).is_some_and(|end| end <= self.capacity),
+            "ring access out of bounds"
+        );
+    }
+
+    /// Data bytes `at..at + len`
+    #[inline]
+    fn data(&self, at: usize, len: usize) -> &[u8] {
+        self.check_range(at, len);
+        // SAFETY: in bounds of the data area, which the cursor protocol
+        // hands to the consumer read-only.
+        unsafe { core::slice::from_raw_parts(self.base.as_ptr().add(RING_HEADER_SIZE + at), len) }
+    }
+
+    /// Mutable data bytes `at..at + len`
+    #[inline]
+    fn data_mut(&mut self, at: usize, len: usize) -> &mut [u8] {
+        self.check_range(at, len);
+        // SAFETY: in bounds of the data area; the cursor protocol gives the
+        // producer exclusive use of the bytes between the write and read
+        // positions.
+        unsafe {
+            core::slice::from_raw_parts_mut(self.base.as_ptr().add(RING_HEADER_SIZE + at), len)
+        }
+    }
+
+    /// Frame length prefix at data offset `at`, possibly being overwritten
+    #[inline]
+    fn load_prefix(&self, at: usize) -> u32 {
+        self.check_range(at, 4);
+        // SAFETY: in bounds and 4-aligned (records start on 8-byte
+        // boundaries of a page-aligned mapping). The load is atomic, so a
+        // racing overwrite is not a data race; it can only yield a stale
+        // value, which the caller discards after checking the tail again.
+        let word = unsafe { &*(self.base.as_ptr().add(RING_HEADER_SIZE + at) as *const AtomicU32) };
+        u32::from_le(word.load(Ordering::Relaxed))
+    }
+
+    /// Copy data bytes `at..at + dst.len()`, possibly being overwritten
+    ///
+    /// `at` must be 8-aligned, as frames in a record are; the copy reads
+    /// whole words, including the record padding after the frame.
+    #[inline]
+    fn copy_out(&self, at: usize, dst: &mut [u8]) {
+        assert!(at.is_multiple_of(8), "unaligned ring copy");
+        self.check_range(at, dst.len().next_multiple_of(8));
+        // SAFETY: in bounds of the mapping, checked above
+        let src = unsafe { self.base.as_ptr().add(RING_HEADER_SIZE + at) };
+        for (i, chunk) in dst.chunks_mut(8).enumerate() {
+            // SAFETY: in bounds and 8-aligned. The publisher may be writing
+            // these bytes at the same time, so they are only read with
+            // relaxed atomic loads: a racing overwrite tears the copy
+            // without being undefined behaviour. The caller orders the
+            // loads with an acquire load of the write position before and
+            // an acquire fence and tail check after, and discards a copy
+            // the publisher may have overwritten.
+            let word = unsafe { &*(src.add(i * 8) as *const AtomicU64) };
+            chunk.copy_from_slice(&word.load(Ordering::Relaxed).to_ne_bytes()[..chunk.len()]);
+        }
+    }
+
+    fn lag(&self) -> Lag {
+        let read = self.word(READ).load(Ordering::Acquire);
+        let read_pos = self.word(READ_POS).load(Ordering::Acquire);
+        Lag {
+            frames: self
+                .word(WRITTEN)
+                .load(Ordering::Acquire)
+                .saturating_sub(read),
+            bytes: self
+                .word(WRITE_POS)
+                .load(Ordering::Acquire)
+                .saturating_sub(read_pos),
+        }
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.map.flush_async()
+    }
+}
+
+/// Wait a little longer each time while blocked on the peer
+fn backoff(spins: &mut u32) {
+    if *spins < SPINS_BEFORE_YIELD {
+        *spins += 1;
+        core::hint::spin_loop();
+    } else {
+        thread::yield_now();
+    }
+}
+
+/// Position of a reserved slot
+#[derive(Debug, Clone, Copy)]
+struct Reservation {
+    /// Write position of the record (after any padding)
+    pos: u64,
+    /// Whether a padding record must be written at the old position first
+    pad: bool,
+    max_len: usize,
+}
+
+/// Writing end of a ring
+#[derive(Debug)]
+pub struct Producer {
+    ring: RingMap,
+    write_pos: u64,
+}
+
+impl Producer {
+    /// Create the ring file at `path` with `capacity` data bytes
+    ///
+    /// `capacity` must be a power of two of at least 64. Fails with
+    /// `AlreadyExists` if the file exists: a consumer may still have it
+    /// mapped, so remove it once that consumer is gone.
+    pub fn create(path: impl AsRef<Path>, capacity: usize) -> io::Result<Self> {
+        Ok(Self {
+            ring: RingMap::create(path.as_ref(), capacity, RING_MAGIC)?,
+            write_pos: 0,
+        })
+    }
+
+    /// Data capacity in bytes
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity
+    }
+
+    /// Largest frame the ring can ever hold: half the capacity, less the
+    /// record prefix
+    #[inline]
+    pub fn max_frame_len(&self) -> usize {
+        max_frame_len(self.ring.capacity)
+    }
+
+    /// How far the consumer is behind
+    #[inline]
+    pub fn lag(&self) -> Lag {
+        self.ring.lag()
+    }
+
+    /// Reserve a slot for a frame of up to `max_len` bytes
+    ///
+    /// Returns `None` while the consumer has not made room (backpressure),
+    /// and `Error::ShortBuffer` if `max_len` could never fit.
+    pub fn try_reserve(&mut self, max_len: usize) -> Result<Option<Slot<'_>>> {
+        Ok(self.plan(max_len)?.map(|reservation| Slot {
+            producer: self,
+            reservation,
+        }))
+    }
+
+    /// Reserve a slot, waiting for the consumer to make room
+    pub fn reserve(&mut self, max_len: usize) -> Result<Slot<'_>> {
+        let mut spins = 0;
+        loop {
+            if let Some(reservation) = self.plan(max_len)? {
+                return Ok(Slot {
+                    producer: self,
+                    reservation,
+                });
+            }
+            backoff(&mut spins);
+        }
+    }
+
+    /// Ask the OS to write the mapping back to the file (not needed for
+    /// the consumer to see frames)
+    pub fn flush(&self) -> io::Result<()> {
+        self.ring.flush()
+    }
+
+    fn plan(&self, max_len: usize) -> Result<Option<Reservation>> {
+        let capacity = self.ring.capacity;
+        let need = record_size(max_len);
+        if max_len > self.max_frame_len() {
+            return Err(Error::ShortBuffer);
+        }
+        let read_pos = self.ring.word(READ_POS).load(Ordering::Acquire);
+        let offset = self.write_pos as usize & (capacity - 1);
+        let tail = capacity - offset;
+
+        let (pos, pad) = if need > tail {
+            (self.write_pos + tail as u64, true)
+        } else {
+            (self.write_pos, false)
+        };
+        if pos + need as u64 - read_pos > capacity as u64 {
+            return Ok(None);
+        }
+        Ok(Some(Reservation { pos, pad, max_len }))
+    }
+}
+
+/// A reserved slot in the ring; dropping it without
+/// [`commit`](Self::commit) abandons the frame
+#[derive(Debug)]
+pub struct Slot<'p> {
+    producer: &'p mut Producer,
+    reservation: Reservation,
+}
+
+impl Slot<'_> {
+    /// The slot's bytes, to encode one frame into
+    #[inline]
+    pub fn buf(&mut self) -> &mut [u8] {
+        let capacity = self.producer.ring.capacity;
+        let offset = self.reservation.pos as usize & (capacity - 1);
+        let len = self.reservation.max_len;
+        self.producer
+            .ring
+            .data_mut(offset + RECORD_PREFIX_SIZE, len)
+    }
+
+    /// Publish the first `len` bytes of [`buf`](Self::buf) as a frame
+    pub fn commit(self, len: usize) -> Result<()> {
+        let Reservation { pos, pad, max_len } = self.reservation;
+        if len > max_len {
+            return Err(Error::Overflow);
+        }
+        let ring = &mut self.producer.ring;
+        let mask = ring.capacity - 1;
+        if pad {
+            let at = self.producer.write_pos as usize & mask;
+            ring.data_mut(at, 4).copy_from_slice(&PAD.to_le_bytes());
+        }
+        let at = pos as usize & mask;
+        ring.data_mut(at, 4)
+            .copy_from_slice(&(len as u32).to_le_bytes());
+
+        let write_pos = pos + record_size(len) as u64;
+        self.producer.write_pos = write_pos;
+        ring.word(WRITE_POS).store(write_pos, Ordering::Release);
+        ring.word(WRITTEN).fetch_add(1, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Reading end of a ring
+#[derive(Debug)]
+pub struct Consumer {
+    ring: RingMap,
+    read_pos: u64,
+}
+
+impl Consumer {
+    /// Map the ring file created by a [`Producer`] at `path`
+    ///
+    /// Reading resumes where the previous consumer left off.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let ring = RingMap::open(path.as_ref(), RING_MAGIC)?;
+        let read_pos = ring.word(READ_POS).load(Ordering::Acquire);
+        Ok(Self { ring, read_pos })
+    }
+
+    /// How far this consumer is behind the producer
+    #[inline]
+    pub fn lag(&self) -> Lag {
+        self.ring.lag()
+    }
+
+    /// Take the next frame if one is ready
+    ///
+    /// The frame stays in the ring until the returned guard is dropped.
+    /// Fails with `Error::DecodeInvariant` if the ring holds a record that
+    /// cannot be valid.
+    pub fn try_recv(&mut self) -> Result<Option<Received<'_>>> {
+        Ok(self.poll()?.map(|(offset, len)| Received {
+            consumer: self,
+            offset,
+            len,
+        }))
+    }
+
+    /// Take the next frame, waiting for the producer
+    pub fn recv(&mut self) -> Result<Received<'_>> {
+        let mut spins = 0;
+        loop {
+            if let Some((offset, len)) = self.poll()? {
+                return Ok(Received {
+                    consumer: self,
+                    offset,
+                    len,
+                });
+            }
+            backoff(&mut spins);
+        }
+    }
+
+    /// Find the next record, skipping padding; (data offset, frame length)
+    fn poll(&mut self) -> Result<Option<(usize, usize)>> {
+        let capacity = self.ring.capacity;
+        loop {
+            let write_pos = self.ring.word(WRITE_POS).load(Ordering::Acquire);
+            if self.read_pos >= write_pos {
+                return Ok(None);
+            }
+            let offset = self.read_pos as usize & (capacity - 1);
+            let prefix = self.ring.data(offset, 4);
+            let len = u32::from_le_bytes(prefix.try_into().unwrap());
+            if len == PAD {
+                self.release(self.read_pos + (capacity - offset) as u64, 0);
+                continue;
+            }
+            if record_size(len as usize) > capacity - offset {
+                return Err(Error::DecodeInvariant);
+            }
+            return Ok(Some((offset + RECORD_PREFIX_SIZE, len as usize)));
+        }
+    }
+
+    fn release(&mut self, read_pos: u64, frames: u64) {
+        self.read_pos = read_pos;
+        self.ring.word(READ_POS).store(read_pos, Ordering::Release);
+        if frames > 0 {
+            self.ring.word(READ).fetch_add(frames, Ordering::Release);
+        }
+    }
+}
+
+/// A frame read in place; the slot is freed when this is dropped
+#[derive(Debug)]
+pub struct Received<'c> {
+    consumer: &'c mut Consumer,
+    offset: usize,
+    len: usize,
+}
+
+impl Received<'_> {
+    /// The frame bytes
+    #[inline]
+    pub fn frame(&self) -> &[u8] {
+        self.consumer.ring.data(self.offset, self.len)
+    }
+
+    /// Decoder over the frame
+    #[inline]
+    pub fn decoder(&self) -> FrameDecoder<'_> {
+        FrameDecoder::new(self.frame())
+    }
+}
+
+impl Drop for Received<'_> {
+    fn drop(&mut self) {
+        let next = self.consumer.read_pos + record_size(self.len) as u64;
+        self.consumer.release(next, 1);
+    }
+}
+
+/// Frames a subscriber missed because the publisher lapped it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overrun {
+    /// Seq after the last frame received, if any frame was received before
+    pub first: Option<u32>,
+    /// Seq of the frame received after the gap
+    pub resumed: u32,
+    /// Ring bytes skipped
+    pub bytes: u64,
+}
+
+impl Overrun {
+    /// The seqs lost, `first..resumed`
+    ///
+    /// `None` if the subscriber was lapped before receiving anything.
+    #[inline]
+    pub fn lost(&self) -> Option<Range<u32>> {
+        self.first.map(|first| first..self.resumed)
+    }
+}
+
+/// Writing end of a broadcast ring
+#[derive(Debug)]
+pub struct Publisher {
+    ring: RingMap,
+    write_pos: u64,
+    tail: u64,
+}
+
+impl Publisher {
+    /// Create the broadcast ring file at `path` with `capacity` data bytes
+    ///
+    /// `capacity` must be a power of two of at least 64. Fails with
+    /// `AlreadyExists` if the file exists: subscribers may still have it
+    /// mapped, so remove it once they are gone.
+    pub fn create(path: impl AsRef<Path>, capacity: usize) -> io::Result<Self> {
+        Ok(Self {
+            ring: RingMap::create(path.as_ref(), capacity, BROADCAST_MAGIC)?,
+            write_pos: 0,
+            tail: 0,
+        })
+    }
+
+    /// Data capacity in bytes
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity
+    }
+
+    /// Largest frame the ring can ever hold: half the capacity, less the
+    /// record prefix
+    ///
+    /// Larger records could reach back past the padding marker in front of
+    /// them, which would then be written over the new frame.
+    #[inline]
+    pub fn max_frame_len(&self) -> usize {
+        max_frame_len(self.ring.capacity)
+    }
+
+    /// Reserve a slot for a frame of up to `max_len` bytes, overwriting the
+    /// oldest frames as needed
+    ///
+    /// The overwritten frames are gone even if the slot is never committed.
+    /// Fails with `Error::ShortBuffer` if `max_len` could never fit.
+    pub fn reserve(&mut self, max_len: usize) -> Result<PublishSlot<'_>> {
+        if max_len > self.max_frame_len() {
+            return Err(Error::ShortBuffer);
+        }
+        let capacity = self.ring.capacity;
+        let need = record_size(max_len);
+        let tail_room = capacity - (self.write_pos as usize & (capacity - 1));
+        let (pos, pad) = if need > tail_room {
+            (self.write_pos + tail_room as u64, true)
+        } else {
+            (self.write_pos, false)
+        };
+        self.evict((pos + need as u64).saturating_sub(capacity as u64));
+        Ok(PublishSlot {
+            publisher: self,
+            reservation: Reservation { pos, pad, max_len },
+        })
+    }
+
+    /// Copy a complete frame into the ring
+    pub fn publish(&mut self, frame: &[u8]) -> Result<()> {
+        let mut slot = self.reserve(frame.len())?;
+        slot.buf().copy_from_slice(frame);
+        slot.commit(frame.len())
+    }
+
+    /// Ask the OS to write the mapping back to the file (not needed for
+    /// subscribers to see frames)
+    pub fn flush(&self) -> io::Result<()> {
+        self.ring.flush()
+    }
+
+    /// Move the tail past every record starting before `limit`, before
+    /// their bytes are reused
+    fn evict(&mut self, limit: u64) {
+        if self.tail >= limit {
+            return;
+        }
+        let mask = self.ring.capacity - 1;
+        while self.tail < limit && self.tail < self.write_pos {
+            let offset = self.tail as usize & mask;
+            let len = self.ring.load_prefix(offset);
+            self.tail += if len == PAD {
+                (self.ring.capacity - offset) as u64
+            } else {
+                record_size(len as usize) as u64
+            };
+        }
+        self.ring.word(TAIL).store(self.tail, Ordering::Relaxed);
+        // Subscribers that see any of the new bytes also see the new tail
+        fence(Ordering::Release);
+    }
+}
+
+/// A reserved slot in a broadcast ring; dropping it without
+/// [`commit`](Self::commit) abandons the frame
+#[derive(Debug)]
+pub struct PublishSlot<'p> {
+    publisher: &'p mut Publisher,
+    reservation: Reservation,
+}
+
+impl PublishSlot<'_> {
+    /// The slot's bytes, to encode one frame into
+    #[inline]
+    pub fn buf(&mut self) -> &mut [u8] {
+        let offset = self.reservation.pos as usize & (self.publisher.ring.capacity - 1);
+        self.publisher
+            .ring
+            .data_mut(offset + RECORD_PREFIX_SIZE, self.reservation.max_len)
+    }
+
+    /// Publish the first `len` bytes of [`buf`](Self::buf) as a frame
+    pub fn commit(self, len: usize) -> Result<()> {
+        let Reservation { pos, pad, max_len } = self.reservation;
+        if len > max_len {
+            return Err(Error::Overflow);
+        }
+        let ring = &mut self.publisher.ring;
+        let mask = ring.capacity - 1;
+        if pad {
+            let at = self.publisher.write_pos as usize & mask;
+            ring.data_mut(at, 4).copy_from_slice(&PAD.to_le_bytes());
+        }
+        ring.data_mut(pos as usize & mask, 4)
+            .copy_from_slice(&(len as u32).to_le_bytes());
+
+        let write_pos = pos + record_size(len) as u64;
+        self.publisher.write_pos = write_pos;
+        ring.word(WRITTEN).fetch_add(1, Ordering::Release);
+        ring.word(WRITE_POS).store(write_pos, Ordering::Release);
+        Ok(())
+    }
+}
+
+/// Reading end of a broadcast ring, one of any number
+#[derive(Debug)]
+pub struct Subscriber {
+    ring: RingMap,
+    read_pos: u64,
+    next_seq: Option<u32>,
+    skipped: u64,
+    overrun: Option<Overrun>,
+    frame: Vec<u8>,
+}
+
+impl Subscriber {
+    /// Map the broadcast ring file at `path`, starting with the next frame
+    /// published
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let ring = RingMap::open(path.as_ref(), BROADCAST_MAGIC)?;
+        let read_pos = ring.word(WRITE_POS).load(Ordering::Acquire);
+        Ok(Self {
+            ring,
+            read_pos,
+            next_seq: None,
+            skipped: 0,
+            overrun: None,
+            frame: Vec::new(),
+        })
+    }
+
+    /// Go back to the oldest frame still in the ring
+    pub fn rewind(&mut self) {
+        self.read_pos = self.ring.word(TAIL).load(Ordering::Acquire);
+        self.next_seq = None;
+        self.skipped = 0;
+    }
+
+    /// Ring bytes published but not yet read
+    #[inline]
+    pub fn lag_bytes(&self) -> u64 {
+        self.ring
+            .word(WRITE_POS)
+            .load(Ordering::Acquire)
+            .saturating_sub(self.read_pos)
+    }
+
+    /// Take the next frame if one is ready
+    ///
+    /// Fails with `Error::DecodeInvariant` if the ring holds a record that
+    /// cannot be valid.
+    pub fn try_recv(&mut self) -> Result<Option<Delivered<'_>>> {
+        Ok(self.poll()?.then(|| self.delivered()))
+    }
+
+    /// Take the next frame, waiting for the publisher
+    pub fn recv(&mut self) -> Result<Delivered<'_>> {
+        let mut spins = 0;
+        while !self.poll()? {
+            backoff(&mut spins);
+        }
+        Ok(self.delivered())
+    }
+
+    fn delivered(&mut self) -> Delivered<'_> {
+        Delivered {
+            frame: &self.frame,
+            overrun: self.overrun.take(),
+        }
+    }
+
+    /// Copy the next frame into `self.frame`, noting any overrun
+    fn poll(&mut self) -> Result<bool> {
+        let capacity = self.ring.capacity;
+        loop {
+            let tail = self.ring.word(TAIL).load(Ordering::Acquire);
+            if self.read_pos < tail {
+                self.skipped += tail - self.read_pos;
+                self.read_pos = tail;
+            }
+            if self.read_pos >= self.ring.word(WRITE_POS).load(Ordering::Acquire) {
+                return Ok(false);
+            }
+            let offset = self.read_pos as usize & (capacity - 1);
+            let len = self.ring.load_prefix(offset);
+            if !self.still_valid() {
+                continue;
+            }
+            if len == PAD {
+                self.read_pos += (capacity - offset) as u64;
+                continue;
+            }
+            if record_size(len as usize) > capacity - offset {
+                return Err(Error::DecodeInvariant);
+            }
+            self.frame.resize(len as usize, 0);
+            self.ring
+                .copy_out(offset + RECORD_PREFIX_SIZE, &mut self.frame);
+            if !self.still_valid() {
+                continue;
+            }
+            self.read_pos += record_size(len as usize) as u64;
+
+            if let Ok(header) = FrameHeader::decode(&self.frame) {
+                if self.skipped > 0 {
+                    self.overrun = Some(Overrun {
+                        first: self.next_seq,
+                        resumed: header.seq,
+                        bytes: core::mem::take(&mut self.skipped),
+                    });
+                }
+                self.next_seq = Some(header.seq.wrapping_add(1));
+            }
+            return Ok(true);
+        }
+    }
+
+    /// Whether the record at the read position was intact while it was read
+    #[inline]
+    fn still_valid(&self) -> bool {
+        fence(Ordering::Acquire);
+        self.ring.word(TAIL).load(Ordering::Relaxed) <= self.read_pos
+    }
+}
+
+/// A frame copied out of a broadcast ring
+#[derive(Debug)]
+pub struct Delivered<'s> {
+    frame: &'s [u8],
+    overrun: Option<Overrun>,
+}
+
+impl<'s> Delivered<'s> {
+    /// The frame bytes
+    #[inline]
+    pub fn frame(&self) -> &'s [u8] {
+        self.frame
+    }
+
+    /// Decoder over the frame
+    #[inline]
+    pub fn decoder(&self) -> FrameDecoder<'s> {
+        FrameDecoder::new(self.frame)
+    }
+
+    /// The frames missed just before this one, if the subscriber was lapped
+    #[inline]
+    pub fn overrun(&self) -> Option<Overrun> {
+        self.overrun
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::trade;
+    use crate::{FrameEncoder, FrameHeader};
+    use std::format;
+    use std::path::PathBuf;
+
+    fn ring_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("minibit-{}-{}.ring", name, std::process::id()));
+        // Left behind by an earlier run that failed
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn write_quote(buf: &mut [u8], seq: u32) {
+        let mut encoder = FrameEncoder::new(buf);
+        encoder.begin(&FrameHeader::new(9, seq, 0)).unwrap();
+        encoder.put_u64(seq as u64).unwrap();
+        encoder.put_u32(seq).unwrap();
+        assert_eq!(encoder.finish_crc32c().unwrap(), 32);
+    }
+
+    fn send_trade(producer: &mut Producer, seq: u32) {
+        let mut slot = producer.reserve(128).unwrap();
+        let len = trade::encode(slot.buf(), seq, seq as u64, 2, 3, Some(b"AAPL"), None).unwrap();
+        slot.commit(len).unwrap();
+    }
+
+    fn send_raw(producer: &mut Producer, frame: &[u8]) {
+        let mut slot = producer.reserve(frame.len()).unwrap();
+        slot.buf().copy_from_slice(frame);
+        slot.commit(frame.len()).unwrap();
+    }
+
+    #[test]
+    fn test_backpressure_wraparound_and_lag() {
+        let path = ring_path("spsc");
+        let mut producer = Producer::create(&path, 256).unwrap();
+        let mut consumer = Consumer::open(&path).unwrap();
+        assert!(consumer.try_recv().unwrap().is_none());
+        assert!(matches!(
+            producer.try_reserve(1024),
+            Err(Error::ShortBuffer)
+        ));
+
+        // 32-byte frames in 40-byte records: six fit, then the ring pushes back
+        let mut sent = 0;
+        while let Some(mut slot) = producer.try_reserve(32).unwrap() {
+            write_quote(slot.buf(), sent);
+            slot.commit(32).unwrap();
+            sent += 1;
+        }
+        assert_eq!(sent, 6);
+        assert_eq!(
+            producer.lag(),
+            Lag {
+                frames: 6,
+                bytes: 240
+            }
+        );
+        assert_eq!(consumer.lag(), producer.lag());
+
+        // Each frame consumed makes room for one more; records wrap behind
+        // a padding marker in the last 16 bytes of every lap
+        for seq in 0..200 {
+            {
+                let frame = consumer.recv().unwrap();
+                frame.decoder().verify_crc32c().unwrap();
+                assert_eq!(frame.decoder().header().unwrap().seq, seq);
+            }
+            let mut slot = producer
+                .try_reserve(32)
+                .unwrap()
+                .expect("room after consuming");
+            write_quote(slot.buf(), sent);
+            slot.commit(32).unwrap();
+            sent += 1;
+        }
+        assert_eq!(consumer.lag().frames, 6);
+        assert!(producer.try_reserve(32).unwrap().is_none());
+
+        // An abandoned slot publishes nothing
+        consumer.recv().unwrap();
+        assert!(producer.try_reserve(32).unwrap().is_some());
+        assert_eq!(consumer.lag().frames, 5);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_create_refuses_existing_ring() {
+        let path = ring_path("spsc-exists");
+        let mut producer = Producer::create(&path, 64).unwrap();
+        let mut consumer = Consumer::open(&path).unwrap();
+        send_raw(&mut producer, b"live");
+
+        let err = Producer::create(&path, 64).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        let err = Publisher::create(&path, 64).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+
+        // The live ring is untouched
+        assert_eq!(consumer.recv().unwrap().frame(), b"live");
+        std::fs::remove_file(&path).unwrap();
+        Producer::create(&path, 64).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_largest_frames_at_every_offset() {
+        let path = ring_path("spsc-largest");
+        let mut producer = Producer::create(&path, 64).unwrap();
+        let mut consumer = Consumer::open(&path).unwrap();
+        let max_len = producer.max_frame_len();
+        assert_eq!(max_len, 24);
+        assert!(matches!(
+            producer.try_reserve(max_len + 1),
+            Err(Error::ShortBuffer)
+        ));
+
+        // An empty frame moves the write position on by 8 bytes, so over two
+        // laps the largest frame starts at (or pads past) every offset
+        for round in 0..16u8 {
+            producer.try_reserve(0).unwrap().unwrap().commit(0).unwrap();
+            let mut slot = producer
+                .try_reserve(max_len)
+                .unwrap()
+                .expect("room in a drained ring");
+            slot.buf().fill(round);
+            slot.commit(max_len).unwrap();
+
+            assert!(consumer.recv().unwrap().frame().is_empty());
+            assert_eq!(consumer.recv().unwrap().frame(), [round; 24]);
+            assert!(consumer.try_recv().unwrap().is_none());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_two_threads() {
+        const FRAMES: u32 = 20_000;
+        let path = ring_path("spsc-threads");
+        let mut producer = Producer::create(&path, 4096).unwrap();
+        let mut consumer = Consumer::open(&path).unwrap();
+
+        let reader = std::thread::spawn(move || {
+            let mut max_lag = 0;
+            for seq in 0..FRAMES {
+                max_lag = max_lag.max(consumer.lag().frames);
+                let frame = consumer.recv().unwrap();
+                frame.decoder().verify_crc32c().unwrap();
+                assert_eq!(frame.decoder().header().unwrap().seq, seq);
+            }
+            assert!(consumer.try_recv().unwrap().is_none());
+            max_lag
+        });
+        for seq in 0..FRAMES {
+            send_trade(&mut producer, seq);
+        }
+        let max_lag = reader.join().unwrap();
+        // 4 KiB holds only a few dozen trades, so the producer was held back
+        assert!(max_lag <= 4096 / 48, "lag {}", max_lag);
+        assert_eq!(producer.lag(), Lag::default());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_broadcast_overrun() {
+        let path = ring_path("spmc");
+        let mut publisher = Publisher::create(&path, 256).unwrap();
+        let mut live = Subscriber::open(&path).unwrap();
+        let mut frame = [0u8; 32];
+        for seq in 0..3 {
+            write_quote(&mut frame, seq);
+            publisher.publish(&frame).unwrap();
+        }
+        let mut late = Subscriber::open(&path).unwrap();
+        assert!(late.try_recv().unwrap().is_none());
+        late.rewind();
+        for seq in 0..3 {
+            for subscriber in [&mut live, &mut late] {
+                let delivered = subscriber.recv().unwrap();
+                assert_eq!(delivered.decoder().header().unwrap().seq, seq);
+                assert!(delivered.overrun().is_none());
+            }
+        }
+
+        // 20 more frames lap both subscribers (six 40-byte records fit)
+        for seq in 3..23 {
+            write_quote(&mut frame, seq);
+            publisher.publish(&frame).unwrap();
+        }
+        assert_eq!(live.lag_bytes(), 20 * 40 + 3 * 16);
+        let delivered = live.recv().unwrap();
+        let overrun = delivered.overrun().unwrap();
+        let resumed = delivered.decoder().header().unwrap().seq;
+        assert_eq!(overrun.resumed, resumed);
+        assert_eq!(overrun.lost(), Some(3..resumed));
+        assert!(resumed > 3 && overrun.bytes > 0);
+        for seq in resumed + 1..23 {
+            let delivered = live.recv().unwrap();
+            delivered.decoder().verify_crc32c().unwrap();
+            assert_eq!(delivered.decoder().header().unwrap().seq, seq);
+            assert!(delivered.overrun().is_none());
+        }
+        assert!(live.try_recv().unwrap().is_none());
+
+        // A subscriber lapped before receiving anything knows no lost range
+        let mut fresh = Subscriber::open(&path).unwrap();
+        for seq in 23..33 {
+            write_quote(&mut frame, seq);
+            publisher.publish(&frame).unwrap();
+        }
+        let overrun = fresh.recv().unwrap().overrun().unwrap();
+        assert_eq!(overrun.lost(), None);
+        assert!(matches!(publisher.reserve(256), Err(Error::ShortBuffer)));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_broadcast_largest_frames_at_every_offset() {
+        let path = ring_path("spmc-largest");
+        let mut publisher = Publisher::create(&path, 64).unwrap();
+        let mut subscriber = Subscriber::open(&path).unwrap();
+        let max_len = publisher.max_frame_len();
+        assert_eq!(max_len, 24);
+        assert!(matches!(
+            publisher.reserve(max_len + 1),
+            Err(Error::ShortBuffer)
+        ));
+
+        // A 16-byte frame then the largest one, starting at every offset;
+        // the padding marker must never land inside the frame after it
+        for round in 0..16u8 {
+            let small = [!round; 16];
+            publisher.publish(&small[..round as usize % 9]).unwrap();
+            publisher.publish(&[round; 24]).unwrap();
+
+            let first = subscriber.recv().unwrap();
+            assert!(first.overrun().is_none());
+            assert_eq!(first.frame(), &small[..round as usize % 9]);
+            let second = subscriber.recv().unwrap();
+            assert!(second.overrun().is_none());
+            assert_eq!(second.frame(), [round; 24]);
+            assert!(subscriber.try_recv().unwrap().is_none());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_broadcast_threads() {
+        const FRAMES: u32 = 20_000;
+        let path = ring_path("spmc-threads");
+        let mut publisher = Publisher::create(&path, 2048).unwrap();
+        let subscribers: Vec<_> = (0..3).map(|_| Subscriber::open(&path).unwrap()).collect();
+
+        let readers: Vec<_> = subscribers
+            .into_iter()
+            .map(|mut subscriber| {
+                std::thread::spawn(move || {
+                    let (mut received, mut lost) = (0, 0);
+                    let mut next = 0;
+                    while next < FRAMES {
+                        let delivered = subscriber.recv().unwrap();
+                        // A torn copy would fail the CRC
+                        delivered.decoder().verify_crc32c().unwrap();
+                        let seq = delivered.decoder().header().unwrap().seq;
+                        match delivered.overrun() {
+                            Some(overrun) => {
+                                // Lapped before the first frame: no lost range
+                                let lost_seqs = overrun.lost().unwrap_or(0..seq);
+                                assert_eq!(lost_seqs, next..seq);
+                                lost += seq - next;
+                            }
+                            None => assert_eq!(seq, next),
+                        }
+                        received += 1;
+                        next = seq + 1;
+                    }
+                    (received, lost)
+                })
+            })
+            .collect();
+        for seq in 0..FRAMES {
+            let mut slot = publisher.reserve(128).unwrap();
+            let len =
+                trade::encode(slot.buf(), seq, seq as u64, 2, 3, Some(b"AAPL"), None).unwrap();
+            slot.commit(len).unwrap();
+        }
+        for reader in readers {
+            let (received, lost) = reader.join().unwrap();
+            assert_eq!(received + lost, FRAMES);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
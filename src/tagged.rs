@@ -0,0 +1,202 @@
+//! Tagged optional fields for forward compatibility
+//!
+//! With a presence bitmap a decoder must know the type of every present field
+//! to find the next one, so fields added by a newer schema can only be skipped
+//! when they happen to come last. Tagged fields instead prefix each value with
+//! a varint key carrying the field index and a [`WireType`]:
+//!
+//! ```text
+//! key = (field_idx << 3) | wire_type     (varint)
+//! value                                  (layout given by wire_type)
+//! ```
+//!
+//! The wire type alone determines the value's length, so a decoder can skip
+//! any field it does not recognise, in any position. Frames using this layout
+//! set [`FrameFlags::TAGGED`](crate::frame::FrameFlags::TAGGED) and place the
+//! tagged fields after the fixed fields, running to the end of the body.
+
+use crate::decoder::BodyCursor;
+use crate::error::{Error, Result};
+
+/// Number of key bits used by the wire type
+pub const WIRE_TYPE_BITS: u32 = 3;
+
+/// Largest field index that fits in a u32 key
+pub const MAX_FIELD_IDX: u32 = u32::MAX >> WIRE_TYPE_BITS;
+
+/// Value layout of a tagged field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum WireType {
+    /// Unsigned LEB128 varint
+    Varint = 0,
+    /// 8-byte little-endian value
+    Fixed64 = 1,
+    /// Varint length followed by that many bytes
+    Bytes = 2,
+    /// 4-byte little-endian value
+    Fixed32 = 3,
+    /// 2-byte little-endian value
+    Fixed16 = 4,
+    /// Single byte
+    Fixed8 = 5,
+}
+
+impl WireType {
+    /// Parse a wire type from the low bits of a key
+    #[inline]
+    pub const fn from_u8(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(WireType::Varint),
+            1 => Ok(WireType::Fixed64),
+            2 => Ok(WireType::Bytes),
+            3 => Ok(WireType::Fixed32),
+            4 => Ok(WireType::Fixed16),
+            5 => Ok(WireType::Fixed8),
+            _ => Err(Error::UnknownWireType),
+        }
+    }
+}
+
+/// Build the varint key for a tagged field
+#[inline]
+pub fn encode_key(field_idx: u32, wire_type: WireType) -> Result<u32> {
+    if field_idx > MAX_FIELD_IDX {
+        return Err(Error::Overflow);
+    }
+    Ok((field_idx << WIRE_TYPE_BITS) | wire_type as u32)
+}
+
+/// Split a varint key into field index and wire type
+#[inline]
+pub fn decode_key(key: u32) -> Result<(u32, WireType)> {
+    let wire_type = WireType::from_u8((key & ((1 << WIRE_TYPE_BITS) - 1)) as u8)?;
+    Ok((key >> WIRE_TYPE_BITS, wire_type))
+}
+
+/// Value of a tagged field, borrowing variable-length data from the frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaggedValue<'a> {
+    /// Varint value
+    Varint(u64),
+    /// 8-byte value
+    Fixed64(u64),
+    /// Length-prefixed bytes (zero-copy)
+    Bytes(&'a [u8]),
+    /// 4-byte value
+    Fixed32(u32),
+    /// 2-byte value
+    Fixed16(u16),
+    /// 1-byte value
+    Fixed8(u8),
+}
+
+impl<'a> TaggedValue<'a> {
+    /// Wire type used to encode this value
+    #[inline]
+    pub const fn wire_type(&self) -> WireType {
+        match self {
+            TaggedValue::Varint(_) => WireType::Varint,
+            TaggedValue::Fixed64(_) => WireType::Fixed64,
+            TaggedValue::Bytes(_) => WireType::Bytes,
+            TaggedValue::Fixed32(_) => WireType::Fixed32,
+            TaggedValue::Fixed16(_) => WireType::Fixed16,
+            TaggedValue::Fixed8(_) => WireType::Fixed8,
+        }
+    }
+
+    /// Bytes payload, or `Error::DecodeInvariant` for other wire types
+    #[inline]
+    pub fn as_bytes(&self) -> Result<&'a [u8]> {
+        match *self {
+            TaggedValue::Bytes(bytes) => Ok(bytes),
+            _ => Err(Error::DecodeInvariant),
+        }
+    }
+
+    /// Integer payload widened to u64, or `Error::DecodeInvariant` for bytes
+    #[inline]
+    pub fn as_u64(&self) -> Result<u64> {
+        match *self {
+            TaggedValue::Varint(v) | TaggedValue::Fixed64(v) => Ok(v),
+            TaggedValue::Fixed32(v) => Ok(v as u64),
+            TaggedValue::Fixed16(v) => Ok(v as u64),
+            TaggedValue::Fixed8(v) => Ok(v as u64),
+            TaggedValue::Bytes(_) => Err(Error::DecodeInvariant),
+        }
+    }
+}
+
+/// Iterator over the tagged fields remaining in a body
+///
+/// Yields `(field_idx, value)` pairs; stops after the first error.
+#[derive(Debug)]
+pub struct TaggedFields<'a> {
+    cursor: BodyCursor<'a>,
+    failed: bool,
+}
+
+impl<'a> TaggedFields<'a> {
+    /// Iterate over the tagged fields from the cursor's position to its end
+    #[inline]
+    pub fn new(cursor: BodyCursor<'a>) -> Self {
+        Self {
+            cursor,
+            failed: false,
+        }
+    }
+}
+
+impl<'a> Iterator for TaggedFields<'a> {
+    type Item = Result<(u32, TaggedValue<'a>)>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.cursor.is_at_end() {
+            return None;
+        }
+        let result = self.cursor.get_tagged();
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_roundtrip() {
+        for wire_type in [
+            WireType::Varint,
+            WireType::Fixed64,
+            WireType::Bytes,
+            WireType::Fixed32,
+            WireType::Fixed16,
+            WireType::Fixed8,
+        ] {
+            let key = encode_key(1234, wire_type).unwrap();
+            assert_eq!(decode_key(key).unwrap(), (1234, wire_type));
+        }
+
+        assert_eq!(
+            encode_key(MAX_FIELD_IDX + 1, WireType::Varint),
+            Err(Error::Overflow)
+        );
+        assert_eq!(decode_key(7), Err(Error::UnknownWireType));
+    }
+
+    #[test]
+    fn test_value_accessors() {
+        assert_eq!(TaggedValue::Fixed16(7).as_u64(), Ok(7));
+        assert_eq!(TaggedValue::Bytes(b"x").as_bytes(), Ok(&b"x"[..]));
+        assert_eq!(
+            TaggedValue::Varint(1).as_bytes(),
+            Err(Error::DecodeInvariant)
+        );
+        assert_eq!(
+            TaggedValue::Bytes(b"").as_u64(),
+            Err(Error::DecodeInvariant)
+        );
+    }
+}
@@ -0,0 +1,1199 @@
+//! Framed TCP sessions with logon, heartbeats and resumption
+//!
+//! Frames travel back to back over the stream. A session starts with a
+//! logon exchange: the client sends [`msg_types::LOGON`] with its identity,
+//! the heartbeat interval it wants and the seq it expects next; the server
+//! replies with a logon of its own carrying the seq it will send next (or a
+//! logout to refuse). Afterwards either side sends a heartbeat whenever it
+//! has been quiet for one interval, and a peer silent for
+//! [`MISSED_HEARTBEATS`] intervals is considered gone.
+//!
+//! [`Server`] accepts sessions and leaves it to the application which
+//! frames to send from the requested seq (e.g. from a
+//! [journal](crate::journal)). [`Client`] remembers the seq after the last
+//! application frame received and, when the connection drops, reconnects
+//! with exponential backoff and resumes from there.
+//!
+//! The transport is blocking: [`Connection::poll`] and [`Client::poll`]
+//! wait up to a timeout for the next frame, answering heartbeats meanwhile.
+//! With the `async` feature, `tcp_async` offers the same sessions over any
+//! `futures-io` stream.
+//!
+//! [`msg_types::LOGON`]: crate::messages::msg_types::LOGON
+
+use core::ops::Range;
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::string::String;
+use std::thread;
+use std::time::Instant;
+use std::vec::Vec;
+
+use crate::decoder::FrameDecoder;
+use crate::encoder::FrameEncoder;
+use crate::error::{Error, Result};
+use crate::frame::{FrameHeader, HEADER_V1};
+use crate::messages::msg_types;
+
+/// Default interval between heartbeats on a quiet session
+pub const DEFAULT_HEARTBEAT: Duration = Duration::from_secs(1);
+
+/// Heartbeat intervals without any data before the peer is considered gone
+pub const MISSED_HEARTBEATS: u32 = 3;
+
+/// Default time allowed for the logon exchange
+pub const DEFAULT_LOGON_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default first reconnection delay
+pub const DEFAULT_MIN_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Default cap on the reconnection delay
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Bytes read from the socket at a time
+const READ_CHUNK: usize = 64 * 1024;
+
+/// How often a server checks connections that are still logging on
+const LOGON_POLL: Duration = Duration::from_millis(1);
+
+/// Logon request (client) or reply (server)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Logon<'a> {
+    /// Heartbeat interval for the session
+    pub heartbeat: Duration,
+    /// Request: seq the client expects next on channel 0; reply: seq the
+    /// server sends next
+    pub next_seq: u32,
+    /// Who is logging on (empty in replies)
+    pub identity: &'a [u8],
+    /// Request: seq the client expects next on each other channel it has
+    /// received frames on (empty in replies)
+    pub channels: ChannelSeqs<'a>,
+}
+
+/// Size of one entry of [`ChannelSeqs`]: channel u16, seq u32
+const CHANNEL_SEQ_SIZE: usize = 6;
+
+/// (channel, seq) pairs packed as they travel in a logon frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChannelSeqs<'a>(&'a [u8]);
+
+impl<'a> ChannelSeqs<'a> {
+    /// View packed pairs; fails with `Error::DecodeInvariant` unless `raw`
+    /// holds whole entries
+    pub fn new(raw: &'a [u8]) -> Result<Self> {
+        if !raw.len().is_multiple_of(CHANNEL_SEQ_SIZE) {
+            return Err(Error::DecodeInvariant);
+        }
+        Ok(Self(raw))
+    }
+
+    /// Pack `seqs` into `out`
+    pub fn pack(seqs: impl IntoIterator<Item = (u16, u32)>, out: &mut Vec<u8>) {
+        out.clear();
+        for (channel, seq) in seqs {
+            out.extend_from_slice(&channel.to_le_bytes());
+            out.extend_from_slice(&seq.to_le_bytes());
+        }
+    }
+
+    /// The packed bytes
+    #[inline]
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+
+    /// Number of pairs
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len() / CHANNEL_SEQ_SIZE
+    }
+
+    /// Whether there are no pairs
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The (channel, seq) pairs
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u32)> + 'a {
+        self.0.chunks_exact(CHANNEL_SEQ_SIZE).map(|entry| {
+            (
+                u16::from_le_bytes([entry[0], entry[1]]),
+                u32::from_le_bytes([entry[2], entry[3], entry[4], entry[5]]),
+            )
+        })
+    }
+
+    /// Seq given for `channel`
+    pub fn get(&self, channel: u16) -> Option<u32> {
+        self.iter()
+            .find(|&(entry, _)| entry == channel)
+            .map(|(_, seq)| seq)
+    }
+}
+
+/// Encode a logon frame
+pub fn encode_logon(buf: &mut [u8], logon: &Logon<'_>) -> Result<usize> {
+    let mut encoder = FrameEncoder::new(buf);
+    encoder.begin(&FrameHeader::new(msg_types::LOGON, 0, 0).with_version(HEADER_V1))?;
+    let millis = u32::try_from(logon.heartbeat.as_millis()).map_err(|_| Error::Overflow)?;
+    encoder.put_u32(millis)?;
+    encoder.put_u32(logon.next_seq)?;
+    encoder.put_varbytes(logon.identity)?;
+    encoder.put_varbytes(logon.channels.as_bytes())?;
+    encoder.finish_crc32c()
+}
+
+/// Size of a logon frame with `identity` and `channels`, at most
+pub(crate) fn logon_size(identity: &[u8], channels: &[u8]) -> usize {
+    FrameHeader::SIZE + 4 + 4 + 5 + identity.len() + 5 + channels.len() + 4
+}
+
+/// Decode a logon frame
+///
+/// Logons from peers that predate per-channel seqs have no channels.
+pub fn decode_logon(buf: &[u8]) -> Result<Logon<'_>> {
+    let mut body = control_body(buf, msg_types::LOGON)?;
+    Ok(Logon {
+        heartbeat: Duration::from_millis(body.get_u32()? as u64),
+        next_seq: body.get_u32()?,
+        identity: body.get_varbytes()?,
+        channels: match body.remaining() {
+            0 => ChannelSeqs::default(),
+            _ => ChannelSeqs::new(body.get_varbytes()?)?,
+        },
+    })
+}
+
+/// Seqs after the last application frame received on each channel, which
+/// a client asks for when it resumes
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ResumeSeqs {
+    /// Channel 0, the only one of version 1 headers
+    pub(crate) next_seq: u32,
+    /// Every other channel seen
+    channels: BTreeMap<u16, u32>,
+    /// `channels` packed for the logon request
+    packed: Vec<u8>,
+}
+
+impl ResumeSeqs {
+    /// Note an application frame (its CRC already verified)
+    pub(crate) fn observe(&mut self, frame: &[u8]) {
+        let Ok(header) = FrameHeader::decode(frame) else {
+            return;
+        };
+        if msg_types::is_session_control(header.msg_type) {
+            return;
+        }
+        let next = header.seq.wrapping_add(1);
+        match header.channel {
+            0 => self.next_seq = next,
+            channel => {
+                self.channels.insert(channel, next);
+            }
+        }
+    }
+
+    /// Seq to ask for on `channel`, if any frame was received on it
+    pub(crate) fn get(&self, channel: u16) -> Option<u32> {
+        match channel {
+            0 => Some(self.next_seq),
+            channel => self.channels.get(&channel).copied(),
+        }
+    }
+
+    /// Seqs of the channels other than 0, packed for a logon
+    pub(crate) fn packed(&mut self) -> &[u8] {
+        ChannelSeqs::pack(
+            self.channels.iter().map(|(&channel, &seq)| (channel, seq)),
+            &mut self.packed,
+        );
+        &self.packed
+    }
+}
+
+/// Encode a heartbeat frame
+pub fn encode_heartbeat(buf: &mut [u8]) -> Result<usize> {
+    let mut encoder = FrameEncoder::new(buf);
+    encoder.begin(&FrameHeader::new(msg_types::HEARTBEAT, 0, 0).with_version(HEADER_V1))?;
+    encoder.finish_crc32c()
+}
+
+/// Encode a logout frame with a human-readable `reason`
+pub fn encode_logout(buf: &mut [u8], reason: &[u8]) -> Result<usize> {
+    let mut encoder = FrameEncoder::new(buf);
+    encoder.begin(&FrameHeader::new(msg_types::LOGOUT, 0, 0).with_version(HEADER_V1))?;
+    encoder.put_varbytes(reason)?;
+    encoder.finish_crc32c()
+}
+
+/// Decode a logout frame, returning the reason
+pub fn decode_logout(buf: &[u8]) -> Result<&[u8]> {
+    control_body(buf, msg_types::LOGOUT)?.get_varbytes()
+}
+
+fn control_body(buf: &[u8], msg_type: u16) -> Result<crate::decoder::BodyCursor<'_>> {
+    let decoder = FrameDecoder::new(buf);
+    if decoder.header()?.msg_type != msg_type {
+        return Err(Error::UnsupportedMsgType);
+    }
+    decoder.verify_crc32c()?;
+    decoder.body()
+}
+
+/// Seq of `channel` among (channel, seq) pairs
+pub(crate) fn channel_seq(channels: &[(u16, u32)], channel: u16) -> Option<u32> {
+    channels
+        .iter()
+        .find(|&&(entry, _)| entry == channel)
+        .map(|&(_, seq)| seq)
+}
+
+pub(crate) fn invalid_data(error: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Delay before the next logon after `failures` failed attempts in a row,
+/// doubling from `min` up to `max`
+pub(crate) fn backoff(min: Duration, max: Duration, failures: u32) -> Duration {
+    min.saturating_mul(1 << failures.saturating_sub(1).min(31))
+        .min(max)
+}
+
+/// Something that happened on a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// A session was (re)established; the server sends `next_seq` next
+    /// (client only)
+    Connected {
+        /// Seq of the next frame from the server
+        next_seq: u32,
+    },
+    /// An application frame (header decoded and CRC verified)
+    Frame(&'a [u8]),
+    /// Nothing arrived within the timeout
+    Idle,
+    /// The peer ended the session
+    LoggedOut {
+        /// Reason given by the peer
+        reason: &'a [u8],
+    },
+    /// The connection, or an attempt to reconnect, failed; the client tries
+    /// again on a later poll once its backoff delay has passed (client only)
+    Disconnected {
+        /// What went wrong
+        kind: io::ErrorKind,
+    },
+}
+
+/// An established session over one TCP connection
+#[derive(Debug)]
+pub struct Connection {
+    stream: TcpStream,
+    buf: Vec<u8>,
+    /// Start of unprocessed bytes in `buf`
+    pos: usize,
+    out: Vec<u8>,
+    heartbeat: Duration,
+    last_sent: Instant,
+    last_received: Instant,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, heartbeat: Duration) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        let now = Instant::now();
+        Ok(Self {
+            stream,
+            buf: Vec::new(),
+            pos: 0,
+            out: Vec::new(),
+            heartbeat: heartbeat.max(Duration::from_millis(1)),
+            last_sent: now,
+            last_received: now,
+        })
+    }
+
+    /// Address of the peer
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    /// Heartbeat interval agreed at logon
+    #[inline]
+    pub fn heartbeat(&self) -> Duration {
+        self.heartbeat
+    }
+
+    /// Send a complete frame
+    pub fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.stream.write_all(frame)?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    /// Encode a frame of up to `max_len` bytes and send it
+    ///
+    /// `encode` returns the frame length, as the message encoders do.
+    pub fn encode(
+        &mut self,
+        max_len: usize,
+        encode: impl FnOnce(&mut [u8]) -> Result<usize>,
+    ) -> io::Result<()> {
+        self.out.resize(max_len, 0);
+        let len =
+            encode(&mut self.out).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        self.stream.write_all(&self.out[..len])?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for the next frame, sending heartbeats meanwhile
+    ///
+    /// Returns [`Event::Frame`], [`Event::LoggedOut`] or [`Event::Idle`].
+    /// Fails with `TimedOut` once the peer has missed its heartbeats,
+    /// `UnexpectedEof` when it closes the connection and `InvalidData` on a
+    /// malformed stream or a frame failing its CRC.
+    pub fn poll(&mut self, timeout: Duration) -> io::Result<Event<'_>> {
+        let Some(range) = self.next_frame(Instant::now() + timeout)? else {
+            return Ok(Event::Idle);
+        };
+        let frame = &self.buf[range];
+        match FrameHeader::decode(frame).map_err(invalid_data)?.msg_type {
+            msg_types::LOGOUT => Ok(Event::LoggedOut {
+                reason: decode_logout(frame).map_err(invalid_data)?,
+            }),
+            msg_types::LOGON => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "logon within an established session",
+            )),
+            _ => {
+                FrameDecoder::new(frame)
+                    .verify_crc32c()
+                    .map_err(invalid_data)?;
+                Ok(Event::Frame(frame))
+            }
+        }
+    }
+
+    /// End the session, telling the peer why
+    pub fn logout(mut self, reason: &[u8]) -> io::Result<()> {
+        self.encode(FrameHeader::SIZE + 9 + reason.len(), |buf| {
+            encode_logout(buf, reason)
+        })
+    }
+
+    fn send_heartbeat(&mut self) -> io::Result<()> {
+        self.encode(FrameHeader::SIZE + 4, encode_heartbeat)
+    }
+
+    /// Read until a frame other than a heartbeat is complete, returning its
+    /// range in `buf`, or `None` at `deadline`
+    fn next_frame(&mut self, deadline: Instant) -> io::Result<Option<Range<usize>>> {
+        loop {
+            if let Some(range) = self.buffered_frame()? {
+                return Ok(Some(range));
+            }
+
+            let now = Instant::now();
+            let dead_at = self.last_received + self.heartbeat * MISSED_HEARTBEATS;
+            if now >= dead_at {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "peer missed its heartbeats",
+                ));
+            }
+            if now >= deadline {
+                return Ok(None);
+            }
+            if now >= self.last_sent + self.heartbeat {
+                self.send_heartbeat()?;
+            }
+            let wake = deadline.min(dead_at).min(self.last_sent + self.heartbeat);
+            self.stream
+                .set_read_timeout(Some((wake - now).max(Duration::from_millis(1))))?;
+            self.fill()?;
+        }
+    }
+
+    /// Take the next complete frame other than a heartbeat out of `buf`
+    fn buffered_frame(&mut self) -> io::Result<Option<Range<usize>>> {
+        loop {
+            let rest = &self.buf[self.pos..];
+            match FrameHeader::decode(rest) {
+                Ok(header) if header.total_size() <= rest.len() => {
+                    let start = self.pos;
+                    self.pos += header.total_size();
+                    if header.msg_type != msg_types::HEARTBEAT {
+                        return Ok(Some(start..self.pos));
+                    }
+                }
+                Ok(_) | Err(Error::UnexpectedEof) => return Ok(None),
+                Err(error) => return Err(invalid_data(error)),
+            }
+        }
+    }
+
+    /// Read once from the socket into `buf`; a read that would block or
+    /// times out reads nothing
+    fn fill(&mut self) -> io::Result<()> {
+        // Frames handed out earlier are no longer borrowed
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        let filled = self.buf.len();
+        self.buf.resize(filled + READ_CHUNK, 0);
+        let read = self.stream.read(&mut self.buf[filled..]);
+        self.buf.truncate(filled + read.as_ref().map_or(0, |&n| n));
+        match read {
+            Ok(0) => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed by peer",
+            )),
+            Ok(_) => {
+                self.last_received = Instant::now();
+                Ok(())
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::Interrupted
+                ) =>
+            {
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Accepts sessions on a TCP listener
+#[derive(Debug)]
+pub struct Server {
+    listener: TcpListener,
+    logon_timeout: Duration,
+    /// Connections whose logon request is still on its way, with the time
+    /// they are given up
+    arriving: Vec<(Connection, Instant)>,
+}
+
+impl Server {
+    /// Listen on `addr`
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            logon_timeout: DEFAULT_LOGON_TIMEOUT,
+            arriving: Vec::new(),
+        })
+    }
+
+    /// Set how long a new connection may take to log on
+    pub fn with_logon_timeout(mut self, timeout: Duration) -> Self {
+        self.logon_timeout = timeout;
+        self
+    }
+
+    /// Address the server listens on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Wait for a connection to send its logon request
+    ///
+    /// Connections log on concurrently: one that stays silent does not hold
+    /// up the others. Those that send anything but a logon, close or take
+    /// longer than the logon timeout are dropped.
+    pub fn accept(&mut self) -> io::Result<PendingLogon> {
+        loop {
+            if let Some(pending) = self.take_logon() {
+                return Ok(pending);
+            }
+            // Block for the next connection only while none is logging on
+            self.listener.set_nonblocking(!self.arriving.is_empty())?;
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(true)?;
+                    // No heartbeats before logon: the deadline comes first
+                    let conn = Connection::new(stream, self.logon_timeout)?;
+                    self.arriving
+                        .push((conn, Instant::now() + self.logon_timeout));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(LOGON_POLL),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Read what has arrived on the connections logging on and return the
+    /// first complete logon, dropping connections that failed or ran out
+    /// of time
+    fn take_logon(&mut self) -> Option<PendingLogon> {
+        let now = Instant::now();
+        let mut i = 0;
+        while i < self.arriving.len() {
+            let (conn, give_up_at) = &mut self.arriving[i];
+            let frame = match conn.buffered_frame() {
+                Ok(None) => conn.fill().and_then(|()| conn.buffered_frame()),
+                frame => frame,
+            };
+            match frame {
+                Ok(Some(range)) => {
+                    let (conn, _) = self.arriving.swap_remove(i);
+                    if let Ok(pending) = PendingLogon::new(conn, range) {
+                        return Some(pending);
+                    }
+                }
+                Ok(None) if now < *give_up_at => i += 1,
+                _ => {
+                    self.arriving.swap_remove(i);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// A connection whose logon request awaits an answer
+#[derive(Debug)]
+pub struct PendingLogon {
+    conn: Connection,
+    heartbeat: Duration,
+    next_seq: u32,
+    identity: Vec<u8>,
+    channels: Vec<(u16, u32)>,
+}
+
+impl PendingLogon {
+    /// Decode the logon request at `range` of the connection's buffer
+    fn new(conn: Connection, range: Range<usize>) -> io::Result<Self> {
+        conn.stream.set_nonblocking(false)?;
+        let logon = decode_logon(&conn.buf[range]).map_err(invalid_data)?;
+        Ok(Self {
+            heartbeat: logon.heartbeat,
+            next_seq: logon.next_seq,
+            identity: logon.identity.to_vec(),
+            channels: logon.channels.iter().collect(),
+            conn,
+        })
+    }
+
+    /// Who is logging on
+    #[inline]
+    pub fn identity(&self) -> &[u8] {
+        &self.identity
+    }
+
+    /// Seq the client expects next on channel 0
+    #[inline]
+    pub fn next_seq(&self) -> u32 {
+        self.next_seq
+    }
+
+    /// Seq the client expects next on `channel`, if it has received any
+    /// frame on it
+    pub fn channel_seq(&self, channel: u16) -> Option<u32> {
+        match channel {
+            0 => Some(self.next_seq),
+            channel => channel_seq(&self.channels, channel),
+        }
+    }
+
+    /// (channel, seq) the client expects next on the channels other than 0
+    #[inline]
+    pub fn channel_seqs(&self) -> &[(u16, u32)] {
+        &self.channels
+    }
+
+    /// Heartbeat interval the client asked for
+    #[inline]
+    pub fn heartbeat(&self) -> Duration {
+        self.heartbeat
+    }
+
+    /// Address of the client
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.conn.peer_addr()
+    }
+
+    /// Accept the logon, announcing the seq the server will send next
+    pub fn accept(mut self, next_seq: u32) -> io::Result<Connection> {
+        let reply = Logon {
+            heartbeat: self.heartbeat,
+            next_seq,
+            identity: &[],
+            channels: ChannelSeqs::default(),
+        };
+        self.conn.heartbeat = self.heartbeat.max(Duration::from_millis(1));
+        self.conn
+            .encode(logon_size(&[], &[]), |buf| encode_logon(buf, &reply))?;
+        self.conn.last_received = Instant::now();
+        Ok(self.conn)
+    }
+
+    /// Refuse the logon
+    pub fn reject(self, reason: &[u8]) -> io::Result<()> {
+        self.conn.logout(reason)
+    }
+}
+
+/// A session to a [`Server`] that survives disconnections
+#[derive(Debug)]
+pub struct Client {
+    addr: SocketAddr,
+    identity: Vec<u8>,
+    heartbeat: Duration,
+    logon_timeout: Duration,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    max_attempts: Option<u32>,
+    conn: Option<Connection>,
+    /// The connection failed and is dropped on the next poll
+    lost: bool,
+    /// Failed logon attempts in a row
+    failures: u32,
+    /// When the next logon may be attempted, after a failure
+    retry_at: Option<Instant>,
+    resume: ResumeSeqs,
+    sessions: u64,
+}
+
+impl Client {
+    /// Prepare a session to `addr` logging on as `identity`; nothing is
+    /// connected until the first [`poll`](Self::poll) or
+    /// [`connect`](Self::connect)
+    pub fn new(addr: SocketAddr, identity: &[u8]) -> Self {
+        Self {
+            addr,
+            identity: identity.to_vec(),
+            heartbeat: DEFAULT_HEARTBEAT,
+            logon_timeout: DEFAULT_LOGON_TIMEOUT,
+            min_backoff: DEFAULT_MIN_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            max_attempts: None,
+            conn: None,
+            lost: false,
+            failures: 0,
+            retry_at: None,
+            resume: ResumeSeqs::default(),
+            sessions: 0,
+        }
+    }
+
+    /// Set the heartbeat interval requested at logon
+    pub fn with_heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = interval;
+        self
+    }
+
+    /// Set how long the logon exchange may take
+    pub fn with_logon_timeout(mut self, timeout: Duration) -> Self {
+        self.logon_timeout = timeout;
+        self
+    }
+
+    /// Set the reconnection delay, doubling from `min` up to `max`
+    pub fn with_backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.min_backoff = min;
+        self.max_backoff = max.max(min);
+        self
+    }
+
+    /// Give up connecting after `attempts` failures in a row (default:
+    /// keep trying)
+    pub fn with_max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts.max(1));
+        self
+    }
+
+    /// Set the seq to ask for on channel 0 at the first logon
+    pub fn with_next_seq(mut self, seq: u32) -> Self {
+        self.resume.next_seq = seq;
+        self
+    }
+
+    /// Seq after the last application frame received on channel 0, asked
+    /// for on resume
+    #[inline]
+    pub fn next_seq(&self) -> u32 {
+        self.resume.next_seq
+    }
+
+    /// Seq after the last application frame received on `channel`, asked
+    /// for on resume; `None` for a channel no frame arrived on
+    #[inline]
+    pub fn channel_seq(&self, channel: u16) -> Option<u32> {
+        self.resume.get(channel)
+    }
+
+    /// Number of sessions established so far
+    #[inline]
+    pub fn sessions(&self) -> u64 {
+        self.sessions
+    }
+
+    /// Whether a session is up
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        self.conn.is_some() && !self.lost
+    }
+
+    /// Establish a session, retrying with backoff; returns the seq the
+    /// server sends next
+    ///
+    /// Blocks until connected unless [`with_max_attempts`](Self::with_max_attempts)
+    /// is set. A refused logon is not retried and fails with
+    /// `PermissionDenied`.
+    pub fn connect(&mut self) -> io::Result<u32> {
+        self.conn = None;
+        self.lost = false;
+        loop {
+            if let Event::Connected { next_seq } = self.reconnect(None)? {
+                return Ok(next_seq);
+            }
+        }
+    }
+
+    /// Wait up to `timeout` for the next event, making one logon attempt
+    /// first if there is no session
+    ///
+    /// Connection failures and failed logon attempts are reported as
+    /// [`Event::Disconnected`], and an attempt still waiting out its backoff
+    /// delay at the deadline as [`Event::Idle`]; the logon exchange is cut
+    /// short at the deadline too. Only running out of attempts or a refused
+    /// logon (see [`connect`](Self::connect)) is an error.
+    pub fn poll(&mut self, timeout: Duration) -> io::Result<Event<'_>> {
+        if self.lost {
+            self.conn = None;
+            self.lost = false;
+        }
+        if self.conn.is_none() {
+            return self.reconnect(Some(Instant::now() + timeout));
+        }
+        let conn = self.conn.as_mut().expect("connected above");
+        match conn.poll(timeout) {
+            Ok(Event::Frame(frame)) => {
+                self.resume.observe(frame);
+                Ok(Event::Frame(frame))
+            }
+            Ok(event @ Event::LoggedOut { .. }) => {
+                self.lost = true;
+                Ok(event)
+            }
+            Ok(event) => Ok(event),
+            Err(e) => {
+                self.lost = true;
+                Ok(Event::Disconnected { kind: e.kind() })
+            }
+        }
+    }
+
+    /// Send a complete frame on the current session
+    pub fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        let result = self.session()?.send(frame);
+        self.lost |= result.is_err();
+        result
+    }
+
+    /// Encode a frame of up to `max_len` bytes and send it on the current
+    /// session
+    pub fn encode(
+        &mut self,
+        max_len: usize,
+        encode: impl FnOnce(&mut [u8]) -> Result<usize>,
+    ) -> io::Result<()> {
+        let result = self.session()?.encode(max_len, encode);
+        self.lost |= result
+            .as_ref()
+            .is_err_and(|e| e.kind() != io::ErrorKind::InvalidInput);
+        result
+    }
+
+    /// End the current session, if any
+    pub fn logout(&mut self, reason: &[u8]) -> io::Result<()> {
+        match (self.conn.take(), self.lost) {
+            (Some(conn), false) => conn.logout(reason),
+            _ => Ok(()),
+        }
+    }
+
+    fn session(&mut self) -> io::Result<&mut Connection> {
+        match self.conn.as_mut() {
+            Some(conn) if !self.lost => Ok(conn),
+            _ => Err(io::Error::new(io::ErrorKind::NotConnected, "no session")),
+        }
+    }
+
+    /// Make one logon attempt once the backoff delay has passed
+    ///
+    /// Returns [`Event::Connected`], [`Event::Disconnected`] after a failure
+    /// that will be retried, or [`Event::Idle`] if `deadline` comes before
+    /// the attempt may be made.
+    fn reconnect(&mut self, deadline: Option<Instant>) -> io::Result<Event<'static>> {
+        if let Some(retry_at) = self.retry_at {
+            let wake = deadline.map_or(retry_at, |deadline| deadline.min(retry_at));
+            thread::sleep(wake.saturating_duration_since(Instant::now()));
+            if wake < retry_at {
+                return Ok(Event::Idle);
+            }
+        }
+        let timeout = match deadline {
+            Some(deadline) => self
+                .logon_timeout
+                .min(deadline.saturating_duration_since(Instant::now())),
+            None => self.logon_timeout,
+        };
+        match self.logon(timeout.max(Duration::from_millis(1))) {
+            Ok((conn, next_seq)) => {
+                self.conn = Some(conn);
+                self.sessions += 1;
+                self.failures = 0;
+                self.retry_at = None;
+                Ok(Event::Connected { next_seq })
+            }
+            Err(e) => {
+                self.failures += 1;
+                let exhausted = self.max_attempts.is_some_and(|max| self.failures >= max);
+                if exhausted || e.kind() == io::ErrorKind::PermissionDenied {
+                    self.failures = 0;
+                    self.retry_at = None;
+                    return Err(e);
+                }
+                self.retry_at = Some(
+                    Instant::now() + backoff(self.min_backoff, self.max_backoff, self.failures),
+                );
+                Ok(Event::Disconnected { kind: e.kind() })
+            }
+        }
+    }
+
+    fn logon(&mut self, timeout: Duration) -> io::Result<(Connection, u32)> {
+        let stream = TcpStream::connect_timeout(&self.addr, timeout)?;
+        let mut conn = Connection::new(stream, self.heartbeat)?;
+        let next_seq = self.resume.next_seq;
+        let channels = self.resume.packed();
+        let request = Logon {
+            heartbeat: self.heartbeat,
+            next_seq,
+            identity: &self.identity,
+            channels: ChannelSeqs::new(channels).map_err(invalid_data)?,
+        };
+        conn.encode(logon_size(&self.identity, channels), |buf| {
+            encode_logon(buf, &request)
+        })?;
+        let range = conn
+            .next_frame(Instant::now() + timeout)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "no logon reply"))?;
+        let frame = &conn.buf[range];
+        match FrameHeader::decode(frame).map_err(invalid_data)?.msg_type {
+            msg_types::LOGON => {
+                let reply = decode_logon(frame).map_err(invalid_data)?;
+                let next_seq = reply.next_seq;
+                conn.heartbeat = reply.heartbeat.max(Duration::from_millis(1));
+                Ok((conn, next_seq))
+            }
+            msg_types::LOGOUT => {
+                let reason = decode_logout(frame).map_err(invalid_data)?;
+                Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    String::from("logon refused: ") + &String::from_utf8_lossy(reason),
+                ))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected a logon reply",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::trade;
+    use crate::test_util::{channel_frame, trade_frame};
+    use std::format;
+    use std::string::ToString;
+
+    const HEARTBEAT: Duration = Duration::from_millis(40);
+    const WAIT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_logon_heartbeats_and_logout() {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let pending = server.accept().unwrap();
+            assert_eq!(pending.identity(), b"strategy-1");
+            assert_eq!((pending.next_seq(), pending.heartbeat()), (7, HEARTBEAT));
+            let mut conn = pending.accept(7).unwrap();
+            conn.send(&trade_frame(7)).unwrap();
+            // Quiet for several heartbeat intervals: both sides keep the
+            // session alive
+            assert_eq!(conn.poll(HEARTBEAT * 6).unwrap(), Event::Idle);
+            conn.encode(128, |buf| trade::encode(buf, 8, 1, 2, 3, None, None))
+                .unwrap();
+            match conn.poll(WAIT).unwrap() {
+                Event::Frame(frame) => assert_eq!(frame, trade_frame(100)),
+                other => panic!("{:?}", other),
+            }
+            conn.logout(b"end of day").unwrap();
+        });
+
+        let mut client = Client::new(addr, b"strategy-1")
+            .with_heartbeat(HEARTBEAT)
+            .with_next_seq(7);
+        assert_eq!(client.poll(WAIT).unwrap(), Event::Connected { next_seq: 7 });
+        let mut seqs = Vec::new();
+        while seqs.len() < 2 {
+            if let Event::Frame(frame) = client.poll(WAIT).unwrap() {
+                seqs.push(FrameHeader::decode(frame).unwrap().seq);
+            }
+        }
+        assert_eq!(seqs, [7, 8]);
+        assert_eq!(client.next_seq(), 9);
+        client.send(&trade_frame(100)).unwrap();
+        assert_eq!(
+            client.poll(WAIT).unwrap(),
+            Event::LoggedOut {
+                reason: b"end of day"
+            }
+        );
+        assert!(!client.is_connected());
+        assert!(client.send(&trade_frame(101)).is_err());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_reconnect_and_resume() {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let log: Vec<Vec<u8>> = (0..10).map(trade_frame).collect();
+        let handle = thread::spawn(move || {
+            let mut resumed = Vec::new();
+            // The first session breaks after five frames, without a logout
+            for end in [5, 10] {
+                let pending = server.accept().unwrap();
+                let from = pending.next_seq();
+                resumed.push(from);
+                let mut conn = pending.accept(from).unwrap();
+                for frame in &log[from as usize..end] {
+                    conn.send(frame).unwrap();
+                }
+                if end == 10 {
+                    conn.logout(b"done").unwrap();
+                }
+            }
+            resumed
+        });
+
+        let mut client = Client::new(addr, b"resumer")
+            .with_heartbeat(HEARTBEAT)
+            .with_backoff(Duration::from_millis(5), Duration::from_millis(50));
+        let mut seqs = Vec::new();
+        let mut events = Vec::new();
+        loop {
+            match client.poll(WAIT).unwrap() {
+                Event::Frame(frame) => seqs.push(FrameHeader::decode(frame).unwrap().seq),
+                Event::Connected { next_seq } => events.push(format!("connected {}", next_seq)),
+                Event::Disconnected { .. } => events.push("disconnected".into()),
+                Event::LoggedOut { .. } => break,
+                Event::Idle => {}
+            }
+        }
+        assert_eq!(seqs, (0..10).collect::<Vec<_>>());
+        assert_eq!(events, ["connected 0", "disconnected", "connected 5"]);
+        assert_eq!(client.sessions(), 2);
+        assert_eq!(handle.join().unwrap(), [0, 5]);
+    }
+
+    #[test]
+    fn test_backoff_rejection_and_silent_peer() {
+        // Nothing listening: give up after the allowed attempts
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut client = Client::new(addr, b"x")
+            .with_backoff(Duration::from_millis(1), Duration::from_millis(2))
+            .with_max_attempts(3);
+        let started = Instant::now();
+        assert!(client.connect().is_err());
+        assert!(started.elapsed() < WAIT);
+
+        // A refused logon is reported, not retried
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            server.accept().unwrap().reject(b"unknown user").unwrap();
+            // Then a peer that logs on and falls silent
+            let _conn = server.accept().unwrap().accept(0).unwrap();
+            thread::sleep(HEARTBEAT * 10);
+        });
+        let mut client = Client::new(addr, b"mallory").with_heartbeat(HEARTBEAT);
+        let error = client.connect().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert!(error.to_string().contains("unknown user"));
+
+        assert_eq!(client.poll(WAIT).unwrap(), Event::Connected { next_seq: 0 });
+        let mut kind = None;
+        while kind.is_none() {
+            if let Event::Disconnected { kind: k } = client.poll(WAIT).unwrap() {
+                kind = Some(k);
+            }
+        }
+        assert_eq!(kind, Some(io::ErrorKind::TimedOut));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_poll_bounded_while_reconnecting() {
+        // Nothing listening and no attempt limit: each poll still returns
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut client = Client::new(addr, b"x")
+            .with_backoff(Duration::from_millis(200), Duration::from_secs(1));
+        let timeout = Duration::from_millis(20);
+        let started = Instant::now();
+        assert!(matches!(
+            client.poll(timeout).unwrap(),
+            Event::Disconnected { .. }
+        ));
+        // The next attempt is 200ms away
+        assert_eq!(client.poll(timeout).unwrap(), Event::Idle);
+        assert_eq!(client.poll(timeout).unwrap(), Event::Idle);
+        assert!(started.elapsed() < Duration::from_millis(150));
+        thread::sleep(Duration::from_millis(200));
+        assert!(matches!(
+            client.poll(timeout).unwrap(),
+            Event::Disconnected { .. }
+        ));
+        assert!(!client.is_connected());
+    }
+
+    #[test]
+    fn test_silent_connection_does_not_hold_up_accept() {
+        let mut server = Server::bind("127.0.0.1:0")
+            .unwrap()
+            .with_logon_timeout(WAIT);
+        let addr = server.local_addr().unwrap();
+        // Connects but never logs on, then one that sends garbage
+        let _silent = TcpStream::connect(addr).unwrap();
+        let mut garbage = TcpStream::connect(addr).unwrap();
+        garbage.write_all(&[0xFF; 32]).unwrap();
+        let handle = thread::spawn(move || {
+            let mut client = Client::new(addr, b"prompt").with_heartbeat(HEARTBEAT);
+            client.connect().unwrap()
+        });
+
+        let started = Instant::now();
+        let pending = server.accept().unwrap();
+        assert_eq!(pending.identity(), b"prompt");
+        assert!(started.elapsed() < WAIT / 2);
+        let _conn = pending.accept(3).unwrap();
+        assert_eq!(handle.join().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_corrupt_frame_and_per_channel_resume() {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let pending = server.accept().unwrap();
+            assert!(pending.channel_seqs().is_empty());
+            let mut conn = pending.accept(0).unwrap();
+            conn.send(&trade_frame(0)).unwrap();
+            conn.send(&channel_frame(2, 10)).unwrap();
+            conn.send(&channel_frame(2, 11)).unwrap();
+            // A corrupt frame must not move the resume point
+            let mut corrupt = trade_frame(1);
+            corrupt[FrameHeader::SIZE] ^= 0x01;
+            conn.send(&corrupt).unwrap();
+
+            let pending = server.accept().unwrap();
+            let resume = (
+                pending.next_seq(),
+                pending.channel_seq(2),
+                pending.channel_seq(5),
+                pending.channel_seqs().to_vec(),
+            );
+            pending.accept(1).unwrap().logout(b"done").unwrap();
+            resume
+        });
+
+        let mut client = Client::new(addr, b"multi")
+            .with_heartbeat(HEARTBEAT)
+            .with_backoff(Duration::from_millis(5), Duration::from_millis(50));
+        let mut frames = Vec::new();
+        let mut kinds = Vec::new();
+        loop {
+            match client.poll(WAIT).unwrap() {
+                Event::Frame(frame) => {
+                    let header = FrameHeader::decode(frame).unwrap();
+                    frames.push((header.channel, header.seq));
+                }
+                Event::Disconnected { kind } => kinds.push(kind),
+                Event::LoggedOut { .. } => break,
+                _ => {}
+            }
+        }
+        assert_eq!(frames, [(0, 0), (2, 10), (2, 11)]);
+        assert_eq!(kinds, [io::ErrorKind::InvalidData]);
+        assert_eq!(
+            (
+                client.next_seq(),
+                client.channel_seq(2),
+                client.channel_seq(3)
+            ),
+            (1, Some(12), None)
+        );
+        assert_eq!(
+            handle.join().unwrap(),
+            (1, Some(12), None, std::vec![(2, 12)])
+        );
+    }
+
+    #[test]
+    fn test_logon_channel_seqs() {
+        let mut packed = Vec::new();
+        ChannelSeqs::pack([(2, 12), (7, u32::MAX)], &mut packed);
+        let request = Logon {
+            heartbeat: HEARTBEAT,
+            next_seq: 5,
+            identity: b"id",
+            channels: ChannelSeqs::new(&packed).unwrap(),
+        };
+        let mut buf = [0u8; 64];
+        let len = encode_logon(&mut buf, &request).unwrap();
+        assert!(len <= logon_size(b"id", &packed));
+        let decoded = decode_logon(&buf[..len]).unwrap();
+        assert_eq!(decoded, request);
+        assert_eq!(
+            decoded.channels.iter().collect::<Vec<_>>(),
+            [(2, 12), (7, u32::MAX)]
+        );
+        assert_eq!(decoded.channels.get(7), Some(u32::MAX));
+        assert_eq!(decoded.channels.get(0), None);
+        assert_eq!(ChannelSeqs::new(&packed[..5]), Err(Error::DecodeInvariant));
+
+        // A logon from a peer without per-channel seqs
+        let mut encoder = FrameEncoder::new(&mut buf);
+        encoder
+            .begin(&FrameHeader::new(msg_types::LOGON, 0, 0).with_version(HEADER_V1))
+            .unwrap();
+        encoder.put_u32(40).unwrap();
+        encoder.put_u32(5).unwrap();
+        encoder.put_varbytes(b"id").unwrap();
+        let len = encoder.finish_crc32c().unwrap();
+        let decoded = decode_logon(&buf[..len]).unwrap();
+        assert_eq!(decoded.next_seq, 5);
+        assert!(decoded.channels.is_empty());
+    }
+}
@@ -0,0 +1,881 @@
+//! Async framed TCP sessions, independent of any runtime
+//!
+//! The same protocol as [`tcp`](crate::tcp) — logon, heartbeats, resumption
+//! — over any stream implementing the `futures-io` [`AsyncRead`] and
+//! [`AsyncWrite`] traits. The application supplies time and sockets through
+//! [`Timer`] and [`Connector`], so the module works with tokio (through its
+//! compat layer), async-std, smol or a hand-written executor alike.
+//!
+//! [`Connection`] mirrors the blocking one; [`accept`] reads the logon of a
+//! stream the application accepted itself, and [`Client`] reconnects with
+//! backoff and resumes from the last received seq. Both ends speak to
+//! blocking peers too.
+
+use core::future::{poll_fn, Future};
+use core::ops::Range;
+use core::pin::{pin, Pin};
+use core::task::Poll;
+use core::time::Duration;
+use std::io;
+use std::net::SocketAddr;
+use std::string::String;
+use std::time::Instant;
+use std::vec::Vec;
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::decoder::FrameDecoder;
+use crate::error::{Error, Result};
+use crate::frame::FrameHeader;
+use crate::messages::msg_types;
+use crate::tcp::{
+    backoff, channel_seq, decode_logon, decode_logout, encode_heartbeat, encode_logon,
+    encode_logout, invalid_data, logon_size, ChannelSeqs, Event, Logon, ResumeSeqs,
+    DEFAULT_HEARTBEAT, DEFAULT_LOGON_TIMEOUT, DEFAULT_MAX_BACKOFF, DEFAULT_MIN_BACKOFF,
+    MISSED_HEARTBEATS,
+};
+
+/// Bytes read from the stream at a time
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Source of delays, provided by the runtime
+pub trait Timer {
+    /// Future completing after the delay
+    type Sleep: Future<Output = ()>;
+
+    /// Complete after `duration`
+    fn sleep(&self, duration: Duration) -> Self::Sleep;
+}
+
+/// Opens streams for a [`Client`], provided by the runtime
+///
+/// The client clones its connector into each connection as its timer.
+pub trait Connector: Timer + Clone {
+    /// Connected stream
+    type Stream: AsyncRead + AsyncWrite + Unpin;
+    /// Future resolving to a connected stream
+    type Connect: Future<Output = io::Result<Self::Stream>>;
+
+    /// Connect to `addr`
+    fn connect(&self, addr: SocketAddr) -> Self::Connect;
+}
+
+/// Run `future`, giving up with `None` once `sleep` completes
+async fn with_timeout<F: Future>(future: F, sleep: impl Future<Output = ()>) -> Option<F::Output> {
+    let (mut future, mut sleep) = (pin!(future), pin!(sleep));
+    poll_fn(|cx| match future.as_mut().poll(cx) {
+        Poll::Ready(output) => Poll::Ready(Some(output)),
+        Poll::Pending => sleep.as_mut().poll(cx).map(|()| None),
+    })
+    .await
+}
+
+async fn write_all<S: AsyncWrite + Unpin>(stream: &mut S, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        let written = poll_fn(|cx| Pin::new(&mut *stream).poll_write(cx, buf)).await?;
+        if written == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        buf = &buf[written..];
+    }
+    poll_fn(|cx| Pin::new(&mut *stream).poll_flush(cx)).await
+}
+
+/// An established session over one stream
+#[derive(Debug)]
+pub struct Connection<S, T> {
+    stream: S,
+    timer: T,
+    buf: Vec<u8>,
+    /// Start of unprocessed bytes in `buf`
+    pos: usize,
+    out: Vec<u8>,
+    heartbeat: Duration,
+    last_sent: Instant,
+    last_received: Instant,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin, T: Timer> Connection<S, T> {
+    fn new(stream: S, timer: T, heartbeat: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            stream,
+            timer,
+            buf: Vec::new(),
+            pos: 0,
+            out: Vec::new(),
+            heartbeat: heartbeat.max(Duration::from_millis(1)),
+            last_sent: now,
+            last_received: now,
+        }
+    }
+
+    /// Heartbeat interval agreed at logon
+    #[inline]
+    pub fn heartbeat(&self) -> Duration {
+        self.heartbeat
+    }
+
+    /// The underlying stream
+    #[inline]
+    pub fn stream(&self) -> &S {
+        &self.stream
+    }
+
+    /// Send a complete frame
+    pub async fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        write_all(&mut self.stream, frame).await?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    /// Encode a frame of up to `max_len` bytes and send it
+    ///
+    /// `encode` returns the frame length, as the message encoders do.
+    pub async fn encode(
+        &mut self,
+        max_len: usize,
+        encode: impl FnOnce(&mut [u8]) -> Result<usize>,
+    ) -> io::Result<()> {
+        self.out.resize(max_len, 0);
+        let len =
+            encode(&mut self.out).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        write_all(&mut self.stream, &self.out[..len]).await?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for the next frame, sending heartbeats meanwhile
+    ///
+    /// Behaves as [`tcp::Connection::poll`](crate::tcp::Connection::poll).
+    pub async fn poll(&mut self, timeout: Duration) -> io::Result<Event<'_>> {
+        let Some(range) = self.next_frame(Instant::now() + timeout).await? else {
+            return Ok(Event::Idle);
+        };
+        let frame = &self.buf[range];
+        match FrameHeader::decode(frame).map_err(invalid_data)?.msg_type {
+            msg_types::LOGOUT => Ok(Event::LoggedOut {
+                reason: decode_logout(frame).map_err(invalid_data)?,
+            }),
+            msg_types::LOGON => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "logon within an established session",
+            )),
+            _ => {
+                FrameDecoder::new(frame)
+                    .verify_crc32c()
+                    .map_err(invalid_data)?;
+                Ok(Event::Frame(frame))
+            }
+        }
+    }
+
+    /// End the session, telling the peer why
+    pub async fn logout(mut self, reason: &[u8]) -> io::Result<()> {
+        self.encode(FrameHeader::SIZE + 9 + reason.len(), |buf| {
+            encode_logout(buf, reason)
+        })
+        .await
+    }
+
+    /// Read until a frame other than a heartbeat is complete, returning its
+    /// range in `buf`, or `None` at `deadline`
+    async fn next_frame(&mut self, deadline: Instant) -> io::Result<Option<Range<usize>>> {
+        loop {
+            let rest = &self.buf[self.pos..];
+            match FrameHeader::decode(rest) {
+                Ok(header) if header.total_size() <= rest.len() => {
+                    let start = self.pos;
+                    self.pos += header.total_size();
+                    if header.msg_type != msg_types::HEARTBEAT {
+                        return Ok(Some(start..self.pos));
+                    }
+                    continue;
+                }
+                Ok(_) | Err(Error::UnexpectedEof) => {}
+                Err(error) => return Err(invalid_data(error)),
+            }
+
+            let now = Instant::now();
+            let dead_at = self.last_received + self.heartbeat * MISSED_HEARTBEATS;
+            if now >= dead_at {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "peer missed its heartbeats",
+                ));
+            }
+            if now >= deadline {
+                return Ok(None);
+            }
+            if now >= self.last_sent + self.heartbeat {
+                self.encode(FrameHeader::SIZE + 4, encode_heartbeat).await?;
+            }
+            let wake = deadline.min(dead_at).min(self.last_sent + self.heartbeat);
+            let sleep = self.timer.sleep((wake - now).max(Duration::from_millis(1)));
+
+            // Frames handed out earlier are no longer borrowed
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+            let filled = self.buf.len();
+            self.buf.resize(filled + READ_CHUNK, 0);
+            let (stream, chunk) = (&mut self.stream, &mut self.buf[filled..]);
+            let read = poll_fn(|cx| Pin::new(&mut *stream).poll_read(cx, chunk));
+            let read = with_timeout(read, sleep).await;
+            let len = match read {
+                Some(Ok(n)) => n,
+                _ => 0,
+            };
+            self.buf.truncate(filled + len);
+            match read {
+                Some(Ok(0)) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "connection closed by peer",
+                    ))
+                }
+                Some(Ok(_)) => self.last_received = Instant::now(),
+                Some(Err(e)) if e.kind() == io::ErrorKind::Interrupted => {}
+                Some(Err(e)) => return Err(e),
+                None => {}
+            }
+        }
+    }
+}
+
+/// Read the logon request on a freshly accepted `stream`
+///
+/// Fails with `TimedOut` if none arrives within `logon_timeout`.
+pub async fn accept<S: AsyncRead + AsyncWrite + Unpin, T: Timer>(
+    stream: S,
+    timer: T,
+    logon_timeout: Duration,
+) -> io::Result<PendingLogon<S, T>> {
+    // No heartbeats before logon: the deadline comes first
+    let mut conn = Connection::new(stream, timer, logon_timeout);
+    let range = conn
+        .next_frame(Instant::now() + logon_timeout)
+        .await?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "no logon received"))?;
+    let logon = decode_logon(&conn.buf[range]).map_err(invalid_data)?;
+    Ok(PendingLogon {
+        heartbeat: logon.heartbeat,
+        next_seq: logon.next_seq,
+        identity: logon.identity.to_vec(),
+        channels: logon.channels.iter().collect(),
+        conn,
+    })
+}
+
+/// A stream whose logon request awaits an answer
+#[derive(Debug)]
+pub struct PendingLogon<S, T> {
+    conn: Connection<S, T>,
+    heartbeat: Duration,
+    next_seq: u32,
+    identity: Vec<u8>,
+    channels: Vec<(u16, u32)>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin, T: Timer> PendingLogon<S, T> {
+    /// Who is logging on
+    #[inline]
+    pub fn identity(&self) -> &[u8] {
+        &self.identity
+    }
+
+    /// Seq the client expects next on channel 0
+    #[inline]
+    pub fn next_seq(&self) -> u32 {
+        self.next_seq
+    }
+
+    /// Seq the client expects next on `channel`, if it has received any
+    /// frame on it
+    pub fn channel_seq(&self, channel: u16) -> Option<u32> {
+        match channel {
+            0 => Some(self.next_seq),
+            channel => channel_seq(&self.channels, channel),
+        }
+    }
+
+    /// (channel, seq) the client expects next on the channels other than 0
+    #[inline]
+    pub fn channel_seqs(&self) -> &[(u16, u32)] {
+        &self.channels
+    }
+
+    /// Heartbeat interval the client asked for
+    #[inline]
+    pub fn heartbeat(&self) -> Duration {
+        self.heartbeat
+    }
+
+    /// Accept the logon, announcing the seq the server will send next
+    pub async fn accept(mut self, next_seq: u32) -> io::Result<Connection<S, T>> {
+        let reply = Logon {
+            heartbeat: self.heartbeat,
+            next_seq,
+            identity: &[],
+            channels: ChannelSeqs::default(),
+        };
+        self.conn.heartbeat = self.heartbeat.max(Duration::from_millis(1));
+        self.conn
+            .encode(logon_size(&[], &[]), |buf| encode_logon(buf, &reply))
+            .await?;
+        self.conn.last_received = Instant::now();
+        Ok(self.conn)
+    }
+
+    /// Refuse the logon
+    pub async fn reject(self, reason: &[u8]) -> io::Result<()> {
+        self.conn.logout(reason).await
+    }
+}
+
+/// A session that survives disconnections, as [`tcp::Client`](crate::tcp::Client)
+#[derive(Debug)]
+pub struct Client<C: Connector> {
+    connector: C,
+    addr: SocketAddr,
+    identity: Vec<u8>,
+    heartbeat: Duration,
+    logon_timeout: Duration,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    max_attempts: Option<u32>,
+    conn: Option<Connection<C::Stream, C>>,
+    /// The connection failed and is dropped on the next poll
+    lost: bool,
+    /// Failed logon attempts in a row
+    failures: u32,
+    /// When the next logon may be attempted, after a failure
+    retry_at: Option<Instant>,
+    resume: ResumeSeqs,
+    sessions: u64,
+}
+
+impl<C: Connector> Client<C> {
+    /// Prepare a session to `addr` through `connector`, logging on as
+    /// `identity`; nothing is connected until the first poll or connect
+    pub fn new(connector: C, addr: SocketAddr, identity: &[u8]) -> Self {
+        Self {
+            connector,
+            addr,
+            identity: identity.to_vec(),
+            heartbeat: DEFAULT_HEARTBEAT,
+            logon_timeout: DEFAULT_LOGON_TIMEOUT,
+            min_backoff: DEFAULT_MIN_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            max_attempts: None,
+            conn: None,
+            lost: false,
+            failures: 0,
+            retry_at: None,
+            resume: ResumeSeqs::default(),
+            sessions: 0,
+        }
+    }
+
+    /// Set the heartbeat interval requested at logon
+    pub fn with_heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = interval;
+        self
+    }
+
+    /// Set how long connecting and the logon exchange may take
+    pub fn with_logon_timeout(mut self, timeout: Duration) -> Self {
+        self.logon_timeout = timeout;
+        self
+    }
+
+    /// Set the reconnection delay, doubling from `min` up to `max`
+    pub fn with_backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.min_backoff = min;
+        self.max_backoff = max.max(min);
+        self
+    }
+
+    /// Give up connecting after `attempts` failures in a row (default:
+    /// keep trying)
+    pub fn with_max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts.max(1));
+        self
+    }
+
+    /// Set the seq to ask for on channel 0 at the first logon
+    pub fn with_next_seq(mut self, seq: u32) -> Self {
+        self.resume.next_seq = seq;
+        self
+    }
+
+    /// Seq after the last application frame received on channel 0, asked
+    /// for on resume
+    #[inline]
+    pub fn next_seq(&self) -> u32 {
+        self.resume.next_seq
+    }
+
+    /// Seq after the last application frame received on `channel`, asked
+    /// for on resume; `None` for a channel no frame arrived on
+    #[inline]
+    pub fn channel_seq(&self, channel: u16) -> Option<u32> {
+        self.resume.get(channel)
+    }
+
+    /// Number of sessions established so far
+    #[inline]
+    pub fn sessions(&self) -> u64 {
+        self.sessions
+    }
+
+    /// Whether a session is up
+    #[inline]
+    pub fn is_connected(&self) -> bool {
+        self.conn.is_some() && !self.lost
+    }
+
+    /// Establish a session, retrying with backoff; returns the seq the
+    /// server sends next
+    ///
+    /// Behaves as [`tcp::Client::connect`](crate::tcp::Client::connect).
+    pub async fn connect(&mut self) -> io::Result<u32> {
+        self.conn = None;
+        self.lost = false;
+        loop {
+            if let Event::Connected { next_seq } = self.reconnect(None).await? {
+                return Ok(next_seq);
+            }
+        }
+    }
+
+    /// Wait up to `timeout` for the next event, connecting first if there is
+    /// no session
+    ///
+    /// Behaves as [`tcp::Client::poll`](crate::tcp::Client::poll).
+    pub async fn poll(&mut self, timeout: Duration) -> io::Result<Event<'_>> {
+        if self.lost {
+            self.conn = None;
+            self.lost = false;
+        }
+        if self.conn.is_none() {
+            return self.reconnect(Some(Instant::now() + timeout)).await;
+        }
+        let conn = self.conn.as_mut().expect("connected above");
+        match conn.poll(timeout).await {
+            Ok(Event::Frame(frame)) => {
+                self.resume.observe(frame);
+                Ok(Event::Frame(frame))
+            }
+            Ok(event @ Event::LoggedOut { .. }) => {
+                self.lost = true;
+                Ok(event)
+            }
+            Ok(event) => Ok(event),
+            Err(e) => {
+                self.lost = true;
+                Ok(Event::Disconnected { kind: e.kind() })
+            }
+        }
+    }
+
+    /// Send a complete frame on the current session
+    pub async fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        let result = self.session()?.send(frame).await;
+        self.lost |= result.is_err();
+        result
+    }
+
+    /// Encode a frame of up to `max_len` bytes and send it on the current
+    /// session
+    pub async fn encode(
+        &mut self,
+        max_len: usize,
+        encode: impl FnOnce(&mut [u8]) -> Result<usize>,
+    ) -> io::Result<()> {
+        let result = self.session()?.encode(max_len, encode).await;
+        self.lost |= result
+            .as_ref()
+            .is_err_and(|e| e.kind() != io::ErrorKind::InvalidInput);
+        result
+    }
+
+    /// End the current session, if any
+    pub async fn logout(&mut self, reason: &[u8]) -> io::Result<()> {
+        match (self.conn.take(), self.lost) {
+            (Some(conn), false) => conn.logout(reason).await,
+            _ => Ok(()),
+        }
+    }
+
+    fn session(&mut self) -> io::Result<&mut Connection<C::Stream, C>> {
+        match self.conn.as_mut() {
+            Some(conn) if !self.lost => Ok(conn),
+            _ => Err(io::Error::new(io::ErrorKind::NotConnected, "no session")),
+        }
+    }
+
+    /// Make one logon attempt once the backoff delay has passed, as
+    /// [`tcp::Client`](crate::tcp::Client) does
+    async fn reconnect(&mut self, deadline: Option<Instant>) -> io::Result<Event<'static>> {
+        if let Some(retry_at) = self.retry_at {
+            let wake = deadline.map_or(retry_at, |deadline| deadline.min(retry_at));
+            self.connector
+                .sleep(wake.saturating_duration_since(Instant::now()))
+                .await;
+            if wake < retry_at {
+                return Ok(Event::Idle);
+            }
+        }
+        let timeout = match deadline {
+            Some(deadline) => self
+                .logon_timeout
+                .min(deadline.saturating_duration_since(Instant::now())),
+            None => self.logon_timeout,
+        };
+        match self.logon(timeout.max(Duration::from_millis(1))).await {
+            Ok((conn, next_seq)) => {
+                self.conn = Some(conn);
+                self.sessions += 1;
+                self.failures = 0;
+                self.retry_at = None;
+                Ok(Event::Connected { next_seq })
+            }
+            Err(e) => {
+                self.failures += 1;
+                let exhausted = self.max_attempts.is_some_and(|max| self.failures >= max);
+                if exhausted || e.kind() == io::ErrorKind::PermissionDenied {
+                    self.failures = 0;
+                    self.retry_at = None;
+                    return Err(e);
+                }
+                let delay = backoff(self.min_backoff, self.max_backoff, self.failures);
+                self.retry_at = Some(Instant::now() + delay);
+                Ok(Event::Disconnected { kind: e.kind() })
+            }
+        }
+    }
+
+    async fn logon(&mut self, timeout: Duration) -> io::Result<(Connection<C::Stream, C>, u32)> {
+        let connect = self.connector.connect(self.addr);
+        let stream = with_timeout(connect, self.connector.sleep(timeout))
+            .await
+            .unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::TimedOut, "connect timed out")))?;
+        let mut conn = Connection::new(stream, self.connector.clone(), self.heartbeat);
+        let next_seq = self.resume.next_seq;
+        let channels = self.resume.packed();
+        let request = Logon {
+            heartbeat: self.heartbeat,
+            next_seq,
+            identity: &self.identity,
+            channels: ChannelSeqs::new(channels).map_err(invalid_data)?,
+        };
+        conn.encode(logon_size(&self.identity, channels), |buf| {
+            encode_logon(buf, &request)
+        })
+        .await?;
+        let range = conn
+            .next_frame(Instant::now() + timeout)
+            .await?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "no logon reply"))?;
+        let frame = &conn.buf[range];
+        match FrameHeader::decode(frame).map_err(invalid_data)?.msg_type {
+            msg_types::LOGON => {
+                let reply = decode_logon(frame).map_err(invalid_data)?;
+                let next_seq = reply.next_seq;
+                conn.heartbeat = reply.heartbeat.max(Duration::from_millis(1));
+                Ok((conn, next_seq))
+            }
+            msg_types::LOGOUT => {
+                let reason = decode_logout(frame).map_err(invalid_data)?;
+                Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    String::from("logon refused: ") + &String::from_utf8_lossy(reason),
+                ))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected a logon reply",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::trade;
+    use crate::tcp;
+    use crate::test_util::trade_frame;
+    use core::task::{Context, Waker};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    const HEARTBEAT: Duration = Duration::from_millis(40);
+    const WAIT: Duration = Duration::from_secs(5);
+
+    /// Non-blocking std socket that asks to be polled again when not ready
+    #[derive(Debug)]
+    struct Stream(TcpStream);
+
+    fn retry<T>(cx: &mut Context<'_>, result: io::Result<T>) -> Poll<io::Result<T>> {
+        match result {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            result => Poll::Ready(result),
+        }
+    }
+
+    impl AsyncRead for Stream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            retry(cx, io::Read::read(&mut self.get_mut().0, buf))
+        }
+    }
+
+    impl AsyncWrite for Stream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            retry(cx, io::Write::write(&mut self.get_mut().0, buf))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(self.0.shutdown(std::net::Shutdown::Write))
+        }
+    }
+
+    /// Timer and connector of a busy-polling test runtime
+    #[derive(Debug, Clone)]
+    struct Spin;
+
+    struct Sleep(Instant);
+
+    impl Future for Sleep {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if Instant::now() >= self.0 {
+                return Poll::Ready(());
+            }
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    impl Timer for Spin {
+        type Sleep = Sleep;
+
+        fn sleep(&self, duration: Duration) -> Sleep {
+            Sleep(Instant::now() + duration)
+        }
+    }
+
+    impl Connector for Spin {
+        type Stream = Stream;
+        type Connect = core::future::Ready<io::Result<Stream>>;
+
+        fn connect(&self, addr: SocketAddr) -> Self::Connect {
+            core::future::ready(stream(TcpStream::connect(addr)))
+        }
+    }
+
+    fn stream(socket: io::Result<TcpStream>) -> io::Result<Stream> {
+        let socket = socket?;
+        socket.set_nonblocking(true)?;
+        Ok(Stream(socket))
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_client_resumes_against_blocking_server() {
+        let mut server = tcp::Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let log: Vec<Vec<u8>> = (0..6).map(trade_frame).collect();
+        let handle = thread::spawn(move || {
+            // The first session breaks after three frames, without a logout
+            for end in [3, 6] {
+                let pending = server.accept().unwrap();
+                assert_eq!(pending.identity(), b"async");
+                let from = pending.next_seq();
+                let mut conn = pending.accept(from).unwrap();
+                for frame in &log[from as usize..end] {
+                    conn.send(frame).unwrap();
+                }
+                if end == 6 {
+                    // Quiet long enough that only heartbeats keep it alive
+                    assert_eq!(conn.poll(HEARTBEAT * 5).unwrap(), tcp::Event::Idle);
+                    conn.logout(b"done").unwrap();
+                }
+            }
+        });
+
+        let mut client = Client::new(Spin, addr, b"async")
+            .with_heartbeat(HEARTBEAT)
+            .with_backoff(Duration::from_millis(5), Duration::from_millis(50));
+        let (mut seqs, mut events) = (Vec::new(), Vec::new());
+        block_on(async {
+            loop {
+                match client.poll(WAIT).await.unwrap() {
+                    Event::Frame(frame) => seqs.push(FrameHeader::decode(frame).unwrap().seq),
+                    Event::Connected { next_seq } => events.push(next_seq),
+                    Event::Disconnected { .. } => {}
+                    Event::LoggedOut { reason } => {
+                        assert_eq!(reason, b"done");
+                        break;
+                    }
+                    Event::Idle => {}
+                }
+            }
+        });
+        assert_eq!(seqs, (0..6).collect::<Vec<_>>());
+        assert_eq!(events, [0, 3]);
+        assert_eq!((client.sessions(), client.next_seq()), (2, 6));
+        assert!(!client.is_connected());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_accept_serves_blocking_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut client = tcp::Client::new(addr, b"mallory").with_heartbeat(HEARTBEAT);
+            let error = client.connect().unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+
+            let mut client = tcp::Client::new(addr, b"alice")
+                .with_heartbeat(HEARTBEAT)
+                .with_next_seq(4);
+            assert_eq!(client.connect().unwrap(), 4);
+            let mut seqs = Vec::new();
+            loop {
+                match client.poll(WAIT).unwrap() {
+                    tcp::Event::Frame(frame) => seqs.push(FrameHeader::decode(frame).unwrap().seq),
+                    tcp::Event::LoggedOut { .. } => break,
+                    _ => {}
+                }
+            }
+            client.send(&trade_frame(99)).unwrap_err();
+            seqs
+        });
+
+        block_on(async {
+            let accepted = stream(listener.accept().map(|(socket, _)| socket)).unwrap();
+            let pending = accept(accepted, Spin, WAIT).await.unwrap();
+            assert_eq!(pending.identity(), b"mallory");
+            pending.reject(b"unknown user").await.unwrap();
+
+            let accepted = stream(listener.accept().map(|(socket, _)| socket)).unwrap();
+            let pending = accept(accepted, Spin, WAIT).await.unwrap();
+            assert_eq!((pending.next_seq(), pending.heartbeat()), (4, HEARTBEAT));
+            let mut conn = pending.accept(4).await.unwrap();
+            conn.send(&trade_frame(4)).await.unwrap();
+            assert_eq!(conn.poll(HEARTBEAT * 5).await.unwrap(), Event::Idle);
+            conn.encode(128, |buf| trade::encode(buf, 5, 1, 2, 3, None, None))
+                .await
+                .unwrap();
+            conn.logout(b"end of day").await.unwrap();
+        });
+        assert_eq!(handle.join().unwrap(), [4, 5]);
+    }
+
+    #[test]
+    fn test_poll_bounded_while_reconnecting() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut client = Client::new(Spin, addr, b"x")
+            .with_backoff(Duration::from_millis(200), Duration::from_secs(1));
+        let timeout = Duration::from_millis(20);
+        let started = Instant::now();
+        block_on(async {
+            assert!(matches!(
+                client.poll(timeout).await.unwrap(),
+                Event::Disconnected { .. }
+            ));
+            assert_eq!(client.poll(timeout).await.unwrap(), Event::Idle);
+            assert_eq!(client.poll(timeout).await.unwrap(), Event::Idle);
+        });
+        assert!(started.elapsed() < Duration::from_millis(150));
+        assert!(!client.is_connected());
+    }
+
+    #[test]
+    fn test_error_paths() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let silent = TcpStream::connect(addr).unwrap();
+        let mut garbage = TcpStream::connect(addr).unwrap();
+        io::Write::write_all(&mut garbage, &[0xAB; 32]).unwrap();
+        block_on(async {
+            let accepted = stream(listener.accept().map(|(socket, _)| socket)).unwrap();
+            let error = accept(accepted, Spin, Duration::from_millis(20))
+                .await
+                .unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+
+            let accepted = stream(listener.accept().map(|(socket, _)| socket)).unwrap();
+            let error = accept(accepted, Spin, WAIT).await.unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        });
+        drop((silent, listener));
+
+        // Nobody listens any more: attempts run out
+        let mut client = Client::new(Spin, addr, b"x")
+            .with_backoff(Duration::from_millis(1), Duration::from_millis(5))
+            .with_max_attempts(2);
+        let error = block_on(client.connect()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+        assert_eq!(client.sessions(), 0);
+
+        // A frame failing its CRC drops the session without advancing the seq
+        let mut server = tcp::Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut conn = server.accept().unwrap().accept(0).unwrap();
+            conn.send(&trade_frame(0)).unwrap();
+            let mut corrupt = trade_frame(1);
+            corrupt[FrameHeader::SIZE] ^= 0xFF;
+            conn.send(&corrupt).unwrap();
+            // Hold the session open until the client has seen both frames
+            let _ = conn.poll(WAIT);
+        });
+        let mut client = Client::new(Spin, addr, b"x").with_heartbeat(HEARTBEAT);
+        block_on(async {
+            assert_eq!(client.connect().await.unwrap(), 0);
+            assert!(matches!(client.poll(WAIT).await.unwrap(), Event::Frame(_)));
+            assert_eq!(
+                client.poll(WAIT).await.unwrap(),
+                Event::Disconnected {
+                    kind: io::ErrorKind::InvalidData
+                }
+            );
+        });
+        assert_eq!(client.next_seq(), 1);
+        assert!(!client.is_connected());
+        drop(client);
+        handle.join().unwrap();
+    }
+}
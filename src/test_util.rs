@@ -0,0 +1,36 @@
+//! Frame fixtures shared by the unit tests
+//!
+//! Paths go through `minibit::` so the command-line tool's tests can include
+//! this file too.
+
+use std::vec::Vec;
+
+use minibit::crc32c::crc32c;
+use minibit::messages::{msg_types, trade};
+use minibit::FrameHeader;
+
+/// Trade frame with `seq` and `ts_ns`
+pub fn trade_frame_at(seq: u32, ts_ns: u64) -> Vec<u8> {
+    let mut buf = [0u8; 128];
+    let len = trade::encode(&mut buf, seq, ts_ns, 2, 3, Some(b"AAPL"), None).unwrap();
+    buf[..len].to_vec()
+}
+
+/// Trade frame with `seq`
+pub fn trade_frame(seq: u32) -> Vec<u8> {
+    trade_frame_at(seq, 1)
+}
+
+/// Trade frame with a version 2 header on `channel`
+pub fn channel_frame(channel: u16, seq: u32) -> Vec<u8> {
+    let mut frame = trade_frame(seq);
+    let body_len = frame.len() - FrameHeader::SIZE - 4;
+    FrameHeader::new(msg_types::TRADE_V1, seq, body_len as u32)
+        .with_channel(channel)
+        .encode(&mut frame)
+        .unwrap();
+    let crc_at = frame.len() - 4;
+    let crc = crc32c(&frame[..crc_at]);
+    frame[crc_at..].copy_from_slice(&crc.to_le_bytes());
+    frame
+}
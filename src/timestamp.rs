@@ -0,0 +1,605 @@
+//! Timestamp type with multiple wire resolutions and clock sources
+//!
+//! A [`Timestamp`] is a count of nanoseconds since the Unix epoch. It can be
+//! written to the wire in three forms:
+//!
+//! - **Full**: 8-byte little-endian nanoseconds
+//! - **Delta**: varint of the nanoseconds elapsed since a base timestamp
+//! - **Seconds + nanos**: 4-byte seconds followed by 4-byte sub-second nanos
+//!
+//! `Display` renders the timestamp as RFC 3339 in UTC with nanosecond
+//! precision, e.g. `2023-11-14T22:13:20.000000000Z`, and `FromStr` parses
+//! the same form back (with 0 to 9 fractional digits).
+
+use core::fmt;
+use core::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::varint;
+
+/// Nanoseconds per second
+pub const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Nanoseconds per millisecond
+pub const NANOS_PER_MILLI: u64 = 1_000_000;
+
+/// Nanoseconds per microsecond
+pub const NANOS_PER_MICRO: u64 = 1_000;
+
+/// Size of the full timestamp encoding in bytes
+pub const FULL_SIZE: usize = 8;
+
+/// Size of the seconds + nanos timestamp encoding in bytes
+pub const SECS_NANOS_SIZE: usize = 8;
+
+/// Nanoseconds since the Unix epoch (1970-01-01T00:00:00Z)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    /// The Unix epoch
+    pub const EPOCH: Self = Self(0);
+
+    /// Create from nanoseconds since the epoch
+    #[inline]
+    pub const fn from_nanos(nanos: u64) -> Self {
+        Self(nanos)
+    }
+
+    /// Create from microseconds since the epoch
+    #[inline]
+    pub const fn from_micros(micros: u64) -> Self {
+        Self(micros.saturating_mul(NANOS_PER_MICRO))
+    }
+
+    /// Create from milliseconds since the epoch
+    #[inline]
+    pub const fn from_millis(millis: u64) -> Self {
+        Self(millis.saturating_mul(NANOS_PER_MILLI))
+    }
+
+    /// Create from whole seconds since the epoch
+    #[inline]
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(secs.saturating_mul(NANOS_PER_SEC))
+    }
+
+    /// Create from seconds and sub-second nanoseconds
+    ///
+    /// Returns `Error::Overflow` if `nanos` is not below one second or the
+    /// result does not fit in 64 bits.
+    #[inline]
+    pub fn from_secs_nanos(secs: u64, nanos: u32) -> Result<Self> {
+        if nanos as u64 >= NANOS_PER_SEC {
+            return Err(Error::Overflow);
+        }
+        secs.checked_mul(NANOS_PER_SEC)
+            .and_then(|n| n.checked_add(nanos as u64))
+            .map(Self)
+            .ok_or(Error::Overflow)
+    }
+
+    /// Nanoseconds since the epoch
+    #[inline]
+    pub const fn as_nanos(&self) -> u64 {
+        self.0
+    }
+
+    /// Whole microseconds since the epoch (truncated)
+    #[inline]
+    pub const fn as_micros(&self) -> u64 {
+        self.0 / NANOS_PER_MICRO
+    }
+
+    /// Whole milliseconds since the epoch (truncated)
+    #[inline]
+    pub const fn as_millis(&self) -> u64 {
+        self.0 / NANOS_PER_MILLI
+    }
+
+    /// Whole seconds since the epoch (truncated)
+    #[inline]
+    pub const fn as_secs(&self) -> u64 {
+        self.0 / NANOS_PER_SEC
+    }
+
+    /// Sub-second part in nanoseconds
+    #[inline]
+    pub const fn subsec_nanos(&self) -> u32 {
+        (self.0 % NANOS_PER_SEC) as u32
+    }
+
+    /// Nanoseconds elapsed since `earlier`, or `None` if `earlier` is later
+    #[inline]
+    pub const fn checked_nanos_since(&self, earlier: Timestamp) -> Option<u64> {
+        self.0.checked_sub(earlier.0)
+    }
+
+    /// Nanoseconds elapsed since `earlier`, saturating at zero
+    #[inline]
+    pub const fn saturating_nanos_since(&self, earlier: Timestamp) -> u64 {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    /// Encode as 8-byte little-endian nanoseconds
+    #[inline]
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+        if buf.len() < FULL_SIZE {
+            return Err(Error::ShortBuffer);
+        }
+        buf[..FULL_SIZE].copy_from_slice(&self.0.to_le_bytes());
+        Ok(FULL_SIZE)
+    }
+
+    /// Decode from 8-byte little-endian nanoseconds
+    #[inline]
+    pub fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        if buf.len() < FULL_SIZE {
+            return Err(Error::UnexpectedEof);
+        }
+        let mut bytes = [0u8; FULL_SIZE];
+        bytes.copy_from_slice(&buf[..FULL_SIZE]);
+        Ok((Self(u64::from_le_bytes(bytes)), FULL_SIZE))
+    }
+
+    /// Encode as a varint delta from `base`
+    ///
+    /// Returns `Error::Overflow` if this timestamp is earlier than `base`.
+    #[inline]
+    pub fn encode_delta(&self, base: Timestamp, buf: &mut [u8]) -> Result<usize> {
+        let delta = self.checked_nanos_since(base).ok_or(Error::Overflow)?;
+        varint::encode_u64(delta, buf)
+    }
+
+    /// Decode a varint delta and add it to `base`
+    #[inline]
+    pub fn decode_delta(buf: &[u8], base: Timestamp) -> Result<(Self, usize)> {
+        let (delta, len) = varint::decode_u64(buf)?;
+        let nanos = base.0.checked_add(delta).ok_or(Error::Overflow)?;
+        Ok((Self(nanos), len))
+    }
+
+    /// Encode as 4-byte seconds followed by 4-byte sub-second nanos
+    ///
+    /// Returns `Error::Overflow` if the seconds do not fit in 32 bits
+    /// (after 2106-02-07).
+    #[inline]
+    pub fn encode_secs_nanos(&self, buf: &mut [u8]) -> Result<usize> {
+        let secs = u32::try_from(self.as_secs()).map_err(|_| Error::Overflow)?;
+        if buf.len() < SECS_NANOS_SIZE {
+            return Err(Error::ShortBuffer);
+        }
+        buf[0..4].copy_from_slice(&secs.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.subsec_nanos().to_le_bytes());
+        Ok(SECS_NANOS_SIZE)
+    }
+
+    /// Decode from 4-byte seconds followed by 4-byte sub-second nanos
+    #[inline]
+    pub fn decode_secs_nanos(buf: &[u8]) -> Result<(Self, usize)> {
+        if buf.len() < SECS_NANOS_SIZE {
+            return Err(Error::UnexpectedEof);
+        }
+        let secs = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let nanos = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+        if nanos as u64 >= NANOS_PER_SEC {
+            return Err(Error::DecodeInvariant);
+        }
+        let ts = Self::from_secs_nanos(secs as u64, nanos)?;
+        Ok((ts, SECS_NANOS_SIZE))
+    }
+
+    /// Current wall-clock time
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn now() -> Self {
+        SystemClock.now()
+    }
+}
+
+impl From<u64> for Timestamp {
+    #[inline]
+    fn from(nanos: u64) -> Self {
+        Self(nanos)
+    }
+}
+
+impl From<Timestamp> for u64 {
+    #[inline]
+    fn from(ts: Timestamp) -> Self {
+        ts.0
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Timestamp> for std::time::SystemTime {
+    #[inline]
+    fn from(ts: Timestamp) -> Self {
+        std::time::UNIX_EPOCH + std::time::Duration::from_nanos(ts.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl TryFrom<std::time::SystemTime> for Timestamp {
+    type Error = Error;
+
+    /// Fails with `Error::Overflow` for times before the epoch or after 2554
+    #[inline]
+    fn try_from(time: std::time::SystemTime) -> Result<Self> {
+        let since_epoch = time
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|_| Error::Overflow)?;
+        u64::try_from(since_epoch.as_nanos())
+            .map(Self)
+            .map_err(|_| Error::Overflow)
+    }
+}
+
+/// Convert days since the epoch to a proleptic Gregorian (year, month, day)
+///
+/// Howard Hinnant's `civil_from_days` algorithm restricted to non-negative days.
+const fn civil_from_days(days: u64) -> (u64, u32, u32) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Convert a proleptic Gregorian date to days since the epoch
+///
+/// Inverse of [`civil_from_days`]; `None` for dates before 1970.
+const fn days_from_civil(year: u64, month: u32, day: u32) -> Option<u64> {
+    let year = if month <= 2 {
+        year.checked_sub(1)
+    } else {
+        Some(year)
+    };
+    let Some(year) = year else {
+        return None;
+    };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 } as u64;
+    let doy = (153 * mp + 2) / 5 + day as u64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    (era * 146_097 + doe).checked_sub(719_468)
+}
+
+impl fmt::Display for Timestamp {
+    /// Format as RFC 3339 in UTC with nanosecond precision
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.as_secs();
+        let (year, month, day) = civil_from_days(secs / 86_400);
+        let sod = secs % 86_400;
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+            year,
+            month,
+            day,
+            sod / 3600,
+            (sod % 3600) / 60,
+            sod % 60,
+            self.subsec_nanos()
+        )
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = Error;
+
+    /// Parse `YYYY-MM-DDTHH:MM:SS[.fraction]Z` (UTC only)
+    fn from_str(s: &str) -> Result<Self> {
+        fn number(digits: &str) -> Result<u64> {
+            if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(Error::DecodeInvariant);
+            }
+            digits.parse().map_err(|_| Error::Overflow)
+        }
+
+        let s = s.strip_suffix('Z').ok_or(Error::DecodeInvariant)?;
+        let (date, time) = s.split_once('T').ok_or(Error::DecodeInvariant)?;
+        let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+
+        let mut date_parts = date.splitn(3, '-');
+        let mut time_parts = time.splitn(3, ':');
+        let next = |parts: &mut core::str::SplitN<'_, char>| {
+            number(parts.next().ok_or(Error::DecodeInvariant)?)
+        };
+        let (year, month, day) = (
+            next(&mut date_parts)?,
+            next(&mut date_parts)?,
+            next(&mut date_parts)?,
+        );
+        let (hour, minute, second) = (
+            next(&mut time_parts)?,
+            next(&mut time_parts)?,
+            next(&mut time_parts)?,
+        );
+
+        if !(1..=12).contains(&month)
+            || day == 0
+            || day > days_in_month(year, month as u32) as u64
+            || hour > 23
+            || minute > 59
+            || second > 59
+            || fraction.len() > 9
+            || (s.contains('.') && fraction.is_empty())
+        {
+            return Err(Error::DecodeInvariant);
+        }
+
+        let nanos = if fraction.is_empty() {
+            0
+        } else {
+            number(fraction)? * 10u64.pow(9 - fraction.len() as u32)
+        };
+
+        // Later years cannot be represented anyway, and capping them keeps
+        // the day arithmetic in range
+        if year > MAX_YEAR {
+            return Err(Error::Overflow);
+        }
+        let days = days_from_civil(year, month as u32, day as u32).ok_or(Error::Overflow)?;
+        days.checked_mul(86_400)
+            .and_then(|secs| secs.checked_add(hour * 3600 + minute * 60 + second))
+            .and_then(|secs| secs.checked_mul(NANOS_PER_SEC))
+            .and_then(|n| n.checked_add(nanos))
+            .map(Self)
+            .ok_or(Error::Overflow)
+    }
+}
+
+/// Last year a timestamp can fall in
+const MAX_YEAR: u64 = 2554;
+
+/// Number of days in `month` of `year`
+const fn days_in_month(year: u64, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Source of timestamps
+///
+/// Lets latency measurements and encoders be driven by a real clock in
+/// production and a deterministic one in tests.
+pub trait Clock {
+    /// Current time according to this clock
+    fn now(&self) -> Timestamp;
+}
+
+/// Wall-clock source backed by `std::time::SystemTime`
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> Timestamp {
+        Timestamp::try_from(std::time::SystemTime::now()).unwrap_or(Timestamp::EPOCH)
+    }
+}
+
+/// Monotonic clock anchored to the wall clock at construction
+///
+/// Readings never go backwards even if the system clock is adjusted, which
+/// keeps latency deltas between two readings non-negative.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    anchor: Timestamp,
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl MonotonicClock {
+    /// Create a clock anchored to the current wall-clock time
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            anchor: SystemClock.now(),
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for MonotonicClock {
+    #[inline]
+    fn now(&self) -> Timestamp {
+        let elapsed = self.start.elapsed().as_nanos() as u64;
+        Timestamp(self.anchor.0.saturating_add(elapsed))
+    }
+}
+
+/// Clock that always returns a fixed timestamp (useful for tests and replay)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub Timestamp);
+
+impl Clock for FixedClock {
+    #[inline]
+    fn now(&self) -> Timestamp {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn test_resolutions() {
+        let ts = Timestamp::from_nanos(1_700_000_000_123_456_789);
+        assert_eq!(ts.as_secs(), 1_700_000_000);
+        assert_eq!(ts.as_millis(), 1_700_000_000_123);
+        assert_eq!(ts.as_micros(), 1_700_000_000_123_456);
+        assert_eq!(ts.subsec_nanos(), 123_456_789);
+
+        assert_eq!(Timestamp::from_secs(2).as_nanos(), 2_000_000_000);
+        assert_eq!(Timestamp::from_millis(3).as_nanos(), 3_000_000);
+        assert_eq!(Timestamp::from_micros(4).as_nanos(), 4_000);
+        assert_eq!(
+            Timestamp::from_secs_nanos(1, 5).unwrap().as_nanos(),
+            1_000_000_005
+        );
+        assert_eq!(
+            Timestamp::from_secs_nanos(1, 1_000_000_000),
+            Err(Error::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_full_roundtrip() {
+        let ts = Timestamp::from_nanos(1_700_000_000_123_456_789);
+        let mut buf = [0u8; 8];
+        assert_eq!(ts.encode(&mut buf).unwrap(), 8);
+        assert_eq!(Timestamp::decode(&buf).unwrap(), (ts, 8));
+        assert_eq!(Timestamp::decode(&buf[..7]), Err(Error::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_delta_roundtrip() {
+        let base = Timestamp::from_secs(1_700_000_000);
+        let ts = Timestamp::from_nanos(base.as_nanos() + 1_500);
+        let mut buf = [0u8; varint::MAX_VARINT_U64_SIZE];
+        let len = ts.encode_delta(base, &mut buf).unwrap();
+        assert_eq!(len, 2);
+        assert_eq!(Timestamp::decode_delta(&buf, base).unwrap(), (ts, len));
+
+        // Timestamps before the base cannot be delta-encoded
+        assert_eq!(base.encode_delta(ts, &mut buf), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn test_secs_nanos_roundtrip() {
+        let ts = Timestamp::from_nanos(1_700_000_000_123_456_789);
+        let mut buf = [0u8; 8];
+        assert_eq!(ts.encode_secs_nanos(&mut buf).unwrap(), 8);
+        assert_eq!(Timestamp::decode_secs_nanos(&buf).unwrap(), (ts, 8));
+
+        // Out-of-range sub-second nanos are rejected
+        buf[4..8].copy_from_slice(&1_000_000_000u32.to_le_bytes());
+        assert_eq!(
+            Timestamp::decode_secs_nanos(&buf),
+            Err(Error::DecodeInvariant)
+        );
+
+        // Seconds beyond u32 cannot be represented
+        let far = Timestamp::from_secs(u32::MAX as u64 + 1);
+        assert_eq!(far.encode_secs_nanos(&mut buf), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(
+            Timestamp::EPOCH.to_string(),
+            "1970-01-01T00:00:00.000000000Z"
+        );
+        assert_eq!(
+            Timestamp::from_nanos(1_700_000_000_123_456_789).to_string(),
+            "2023-11-14T22:13:20.123456789Z"
+        );
+        // Leap day
+        assert_eq!(
+            Timestamp::from_secs(951_782_400).to_string(),
+            "2000-02-29T00:00:00.000000000Z"
+        );
+        assert_eq!(
+            Timestamp::from_nanos(u64::MAX).to_string(),
+            "2554-07-21T23:34:33.709551615Z"
+        );
+    }
+
+    #[test]
+    fn test_rfc3339_parse() {
+        for nanos in [
+            0,
+            951_782_400_000_000_000,
+            1_700_000_000_123_456_789,
+            u64::MAX,
+        ] {
+            let ts = Timestamp::from_nanos(nanos);
+            assert_eq!(ts.to_string().parse::<Timestamp>(), Ok(ts));
+        }
+        assert_eq!(
+            "2023-11-14T22:13:20.5Z".parse::<Timestamp>(),
+            Ok(Timestamp::from_nanos(1_700_000_000_500_000_000))
+        );
+        assert_eq!(
+            "2023-11-14T22:13:20Z".parse::<Timestamp>(),
+            Ok(Timestamp::from_secs(1_700_000_000))
+        );
+
+        for bad in [
+            "2023-11-14T22:13:20",
+            "2023-02-29T00:00:00Z",
+            "2023-11-14T24:00:00Z",
+            "2023-11-14T22:13:20.Z",
+            "2023-11-14 22:13:20Z",
+            "2023-11-+4T22:13:20Z",
+        ] {
+            assert_eq!(
+                bad.parse::<Timestamp>(),
+                Err(Error::DecodeInvariant),
+                "{}",
+                bad
+            );
+        }
+        assert_eq!(
+            "1969-12-31T23:59:59Z".parse::<Timestamp>(),
+            Err(Error::Overflow)
+        );
+        for huge in [
+            "2554-07-21T23:34:34Z",
+            "2555-01-01T00:00:00Z",
+            "1000000000000000-01-01T00:00:00Z",
+            "18446744073709551615-12-31T23:59:59Z",
+            "99999999999999999999-01-01T00:00:00Z",
+        ] {
+            assert_eq!(huge.parse::<Timestamp>(), Err(Error::Overflow), "{}", huge);
+        }
+    }
+
+    #[test]
+    fn test_system_time_conversion() {
+        let ts = Timestamp::from_nanos(1_700_000_000_123_456_789);
+        let time: std::time::SystemTime = ts.into();
+        assert_eq!(Timestamp::try_from(time).unwrap(), ts);
+
+        let before_epoch = std::time::UNIX_EPOCH - std::time::Duration::from_secs(1);
+        assert_eq!(Timestamp::try_from(before_epoch), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn test_clocks() {
+        let fixed = FixedClock(Timestamp::from_secs(42));
+        assert_eq!(fixed.now(), Timestamp::from_secs(42));
+
+        let mono = MonotonicClock::new();
+        let a = mono.now();
+        let b = mono.now();
+        assert!(b >= a);
+        assert!(SystemClock.now() > Timestamp::from_secs(1_600_000_000));
+    }
+}
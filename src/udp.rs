@@ -0,0 +1,491 @@
+//! Frames over UDP, unicast or multicast
+//!
+//! [`UdpPublisher`] packs back-to-back frames into datagrams no larger than
+//! a size budget (by default one Ethernet MTU), sending a datagram when the
+//! next frame would not fit or on [`flush`](UdpPublisher::flush).
+//! [`UdpSubscriber`] splits received datagrams back into frames and checks
+//! their seqs per channel, reporting gaps and dropping duplicates. A frame
+//! that arrives after its gap was reported is handed over as
+//! [`Event::Late`] rather than dropped.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::vec;
+use std::vec::Vec;
+
+use crate::error::{Error, Result};
+use crate::frame::FrameHeader;
+use crate::sequence::{SeqStatus, SeqTracker};
+
+/// Largest datagram that fits a 1500-byte Ethernet MTU over IPv4
+pub const DEFAULT_MAX_DATAGRAM: usize = 1472;
+
+/// Largest payload of a UDP datagram over IPv4
+pub const MAX_DATAGRAM: usize = 65507;
+
+/// Reported gaps per channel that late frames may still fill; older gaps
+/// are forgotten and their frames then count as duplicates
+pub const MAX_OPEN_GAPS: usize = 64;
+
+/// Counters kept by a [`UdpPublisher`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PublisherStats {
+    /// Datagrams sent
+    pub datagrams: u64,
+    /// Frames sent
+    pub frames: u64,
+    /// Bytes sent
+    pub bytes: u64,
+}
+
+/// Sends frames in batched datagrams
+#[derive(Debug)]
+pub struct UdpPublisher {
+    socket: UdpSocket,
+    dest: SocketAddr,
+    max_datagram: usize,
+    batch: Vec<u8>,
+    batched: u64,
+    stats: PublisherStats,
+}
+
+impl UdpPublisher {
+    /// Publish to `dest` through `socket`
+    pub fn new(socket: UdpSocket, dest: SocketAddr) -> Self {
+        Self {
+            socket,
+            dest,
+            max_datagram: DEFAULT_MAX_DATAGRAM,
+            batch: Vec::with_capacity(DEFAULT_MAX_DATAGRAM),
+            batched: 0,
+            stats: PublisherStats::default(),
+        }
+    }
+
+    /// Publish to multicast `group` from `interface` (unspecified for the
+    /// default route), with multicast loopback enabled
+    pub fn multicast(group: SocketAddrV4, interface: Ipv4Addr, ttl: u32) -> io::Result<Self> {
+        let socket = UdpSocket::bind((interface, 0))?;
+        socket.set_multicast_ttl_v4(ttl)?;
+        socket.set_multicast_loop_v4(true)?;
+        Ok(Self::new(socket, group.into()))
+    }
+
+    /// Set the datagram size budget (at most [`MAX_DATAGRAM`])
+    pub fn with_max_datagram(mut self, bytes: usize) -> Self {
+        self.max_datagram = bytes.clamp(FrameHeader::SIZE, MAX_DATAGRAM);
+        self
+    }
+
+    /// The underlying socket
+    #[inline]
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Counters so far
+    #[inline]
+    pub fn stats(&self) -> PublisherStats {
+        self.stats
+    }
+
+    /// Bytes batched but not yet sent
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.batch.len()
+    }
+
+    /// Add a complete frame to the batch
+    pub fn publish(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.encode(frame.len(), |buf| {
+            buf.copy_from_slice(frame);
+            Ok(frame.len())
+        })
+    }
+
+    /// Encode a frame of up to `max_len` bytes straight into the batch
+    ///
+    /// `encode` returns the frame length, as the message encoders do. Sends
+    /// the batch first if the frame might not fit.
+    pub fn encode(
+        &mut self,
+        max_len: usize,
+        encode: impl FnOnce(&mut [u8]) -> Result<usize>,
+    ) -> io::Result<()> {
+        if max_len > self.max_datagram {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame larger than the datagram budget",
+            ));
+        }
+        if self.batch.len() + max_len > self.max_datagram {
+            self.flush()?;
+        }
+        let start = self.batch.len();
+        self.batch.resize(start + max_len, 0);
+        match encode(&mut self.batch[start..]) {
+            Ok(len) if len <= max_len => {
+                self.batch.truncate(start + len);
+                self.batched += 1;
+                Ok(())
+            }
+            result => {
+                self.batch.truncate(start);
+                let error = result.err().unwrap_or(Error::Overflow);
+                Err(io::Error::new(io::ErrorKind::InvalidInput, error))
+            }
+        }
+    }
+
+    /// Send the batched frames, if any
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let sent = self.socket.send_to(&self.batch, self.dest)?;
+        self.stats.datagrams += 1;
+        self.stats.frames += self.batched;
+        self.stats.bytes += sent as u64;
+        self.batch.clear();
+        self.batched = 0;
+        Ok(())
+    }
+}
+
+/// Something found in a received datagram
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// A new frame (header decoded, CRC not yet verified)
+    Frame {
+        /// Sender
+        src: SocketAddr,
+        /// Verbatim frame bytes
+        frame: &'a [u8],
+    },
+    /// Frames `expected..received` on `channel` never arrived; reported just
+    /// before the frame with seq `received`
+    Gap {
+        /// Sender
+        src: SocketAddr,
+        /// Channel of the missing frames
+        channel: u16,
+        /// First missing seq
+        expected: u32,
+        /// Seq actually received
+        received: u32,
+    },
+    /// A frame that was missing from an earlier [`Event::Gap`] and arrived
+    /// out of order
+    Late {
+        /// Sender
+        src: SocketAddr,
+        /// Verbatim frame bytes
+        frame: &'a [u8],
+    },
+    /// The rest of a datagram that does not form a frame
+    Malformed {
+        /// Sender
+        src: SocketAddr,
+        /// Why the bytes were rejected
+        error: Error,
+        /// Number of bytes discarded
+        skipped: usize,
+    },
+}
+
+/// Counters kept by a [`UdpSubscriber`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubscriberStats {
+    /// Datagrams received
+    pub datagrams: u64,
+    /// New frames delivered
+    pub frames: u64,
+    /// Gaps detected
+    pub gaps: u64,
+    /// Frames missing across all gaps and not filled late
+    pub missing: u64,
+    /// Frames delivered late into a reported gap
+    pub late: u64,
+    /// Frames dropped as already seen
+    pub duplicates: u64,
+    /// Datagrams with bytes that do not form a frame
+    pub malformed: u64,
+}
+
+/// Receives batched frames and checks their seqs
+#[derive(Debug)]
+pub struct UdpSubscriber {
+    socket: UdpSocket,
+    buf: Vec<u8>,
+    tracker: SeqTracker,
+    /// Unfilled `expected..received` ranges per channel, oldest first
+    open_gaps: BTreeMap<u16, VecDeque<(u32, u32)>>,
+    stats: SubscriberStats,
+}
+
+/// Remove `seq` from the open gaps of a channel, returning whether it was
+/// missing
+fn fill_gap(open: &mut VecDeque<(u32, u32)>, seq: u32) -> bool {
+    let found = open
+        .iter()
+        .position(|&(start, end)| seq.wrapping_sub(start) < end.wrapping_sub(start));
+    let Some(index) = found else {
+        return false;
+    };
+    let (start, end) = open[index];
+    open.remove(index);
+    if seq.wrapping_add(1) != end {
+        open.insert(index, (seq.wrapping_add(1), end));
+    }
+    if seq != start {
+        open.insert(index, (start, seq));
+    }
+    while open.len() > MAX_OPEN_GAPS {
+        open.pop_front();
+    }
+    true
+}
+
+impl UdpSubscriber {
+    /// Receive on `socket`
+    pub fn new(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            buf: vec![0; MAX_DATAGRAM],
+            tracker: SeqTracker::new(),
+            open_gaps: BTreeMap::new(),
+            stats: SubscriberStats::default(),
+        }
+    }
+
+    /// Join multicast `group` on `interface` (unspecified to let the OS
+    /// choose), bound to the group's port
+    pub fn join_multicast(group: SocketAddrV4, interface: Ipv4Addr) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, group.port()))?;
+        socket.join_multicast_v4(group.ip(), &interface)?;
+        Ok(Self::new(socket))
+    }
+
+    /// The underlying socket, e.g. to set a read timeout
+    #[inline]
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Counters so far
+    #[inline]
+    pub fn stats(&self) -> SubscriberStats {
+        self.stats
+    }
+
+    /// Seq tracking state, one expected value per channel
+    #[inline]
+    pub fn tracker(&mut self) -> &mut SeqTracker {
+        &mut self.tracker
+    }
+
+    /// Wait for one datagram and report what it holds
+    ///
+    /// Returns the sender. Socket errors (including a read timeout) are
+    /// passed through.
+    pub fn recv(&mut self, mut on_event: impl FnMut(Event<'_>)) -> io::Result<SocketAddr> {
+        let (len, src) = self.socket.recv_from(&mut self.buf)?;
+        self.stats.datagrams += 1;
+        let mut rest = &self.buf[..len];
+        while !rest.is_empty() {
+            let header = match FrameHeader::decode(rest) {
+                Ok(header) if header.total_size() > rest.len() => Err(Error::UnexpectedEof),
+                result => result,
+            };
+            let header = match header {
+                Ok(header) => header,
+                Err(error) => {
+                    self.stats.malformed += 1;
+                    on_event(Event::Malformed {
+                        src,
+                        error,
+                        skipped: rest.len(),
+                    });
+                    break;
+                }
+            };
+            let (frame, tail) = rest.split_at(header.total_size());
+            rest = tail;
+            match self.tracker.observe_header(&header) {
+                SeqStatus::Duplicate { .. } => {
+                    let open = self.open_gaps.get_mut(&header.channel);
+                    if open.is_some_and(|open| fill_gap(open, header.seq)) {
+                        self.stats.late += 1;
+                        self.stats.missing -= 1;
+                        on_event(Event::Late { src, frame });
+                    } else {
+                        self.stats.duplicates += 1;
+                    }
+                    continue;
+                }
+                SeqStatus::Gap { expected, received } => {
+                    self.stats.gaps += 1;
+                    self.stats.missing += received.wrapping_sub(expected) as u64;
+                    let open = self.open_gaps.entry(header.channel).or_default();
+                    open.push_back((expected, received));
+                    if open.len() > MAX_OPEN_GAPS {
+                        open.pop_front();
+                    }
+                    on_event(Event::Gap {
+                        src,
+                        channel: header.channel,
+                        expected,
+                        received,
+                    });
+                }
+                SeqStatus::First | SeqStatus::InOrder => {}
+            }
+            self.stats.frames += 1;
+            on_event(Event::Frame { src, frame });
+        }
+        Ok(src)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::trade;
+    use crate::test_util::trade_frame;
+    use core::time::Duration;
+
+    fn loopback_pair() -> (UdpPublisher, UdpSubscriber) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let dest = socket.local_addr().unwrap();
+        let publisher = UdpPublisher::new(UdpSocket::bind("127.0.0.1:0").unwrap(), dest);
+        (publisher, UdpSubscriber::new(socket))
+    }
+
+    /// Seqs delivered in order and late, and gaps reported
+    #[derive(Default)]
+    struct Received {
+        seqs: Vec<u32>,
+        late: Vec<u32>,
+        gaps: Vec<(u32, u32)>,
+    }
+
+    /// Receive datagrams until `count` frames arrived, in order or late
+    fn receive(subscriber: &mut UdpSubscriber, count: usize) -> Received {
+        let mut received = Received::default();
+        let Received { seqs, late, gaps } = &mut received;
+        let seq = |frame: &[u8]| FrameHeader::decode(frame).unwrap().seq;
+        while seqs.len() + late.len() < count {
+            subscriber
+                .recv(|event| match event {
+                    Event::Frame { frame, .. } => seqs.push(seq(frame)),
+                    Event::Late { frame, .. } => late.push(seq(frame)),
+                    Event::Gap {
+                        expected, received, ..
+                    } => gaps.push((expected, received)),
+                    Event::Malformed { error, .. } => panic!("{}", error),
+                })
+                .unwrap();
+        }
+        received
+    }
+
+    #[test]
+    fn test_batching_under_budget() {
+        let (publisher, mut subscriber) = loopback_pair();
+        let mut publisher = publisher.with_max_datagram(500);
+        let frame_len = trade_frame(0).len();
+        for seq in 0..100 {
+            publisher
+                .encode(128, |buf| {
+                    trade::encode(buf, seq, 1, 2, 3, Some(b"AAPL"), None)
+                })
+                .unwrap();
+            assert!(publisher.pending() <= 500);
+        }
+        publisher.flush().unwrap();
+        assert!(publisher.publish(&[0; 501]).is_err());
+
+        // Up to 500 bytes per datagram, with 128 bytes kept free per frame
+        let per_datagram = (500 - 128) / frame_len + 1;
+        let stats = publisher.stats();
+        assert_eq!(stats.frames, 100);
+        assert_eq!(stats.datagrams, 100u64.div_ceil(per_datagram as u64));
+        assert_eq!(stats.bytes, 100 * frame_len as u64);
+
+        let received = receive(&mut subscriber, 100);
+        assert_eq!(received.seqs, (0..100).collect::<Vec<_>>());
+        assert!(received.gaps.is_empty());
+        assert_eq!(subscriber.stats().datagrams, stats.datagrams);
+    }
+
+    #[test]
+    fn test_gaps_duplicates_and_malformed() {
+        let (mut publisher, mut subscriber) = loopback_pair();
+        // 3 and 4 fill the first gap late; the second 3 is a true duplicate
+        for seq in [0, 1, 2, 5, 5, 6, 3, 9, 3, 4] {
+            publisher.publish(&trade_frame(seq)).unwrap();
+        }
+        publisher.flush().unwrap();
+        let received = receive(&mut subscriber, 8);
+        assert_eq!(received.seqs, [0, 1, 2, 5, 6, 9]);
+        assert_eq!(received.late, [3, 4]);
+        assert_eq!(received.gaps, [(3, 5), (7, 9)]);
+
+        let stats = subscriber.stats();
+        assert_eq!(
+            (stats.gaps, stats.missing, stats.late, stats.duplicates),
+            (2, 2, 2, 2)
+        );
+
+        // A frame followed by garbage
+        let mut datagram = trade_frame(10);
+        datagram.extend_from_slice(&[0xAB; 7]);
+        publisher.publish(&datagram[..datagram.len() - 7]).unwrap();
+        publisher.flush().unwrap();
+        publisher
+            .socket()
+            .send_to(&datagram, subscriber.socket().local_addr().unwrap())
+            .unwrap();
+        receive(&mut subscriber, 1);
+        let mut events = Vec::new();
+        subscriber
+            .recv(|event| {
+                events.push(match event {
+                    Event::Frame { .. } => "frame",
+                    Event::Gap { .. } => "gap",
+                    Event::Late { .. } => "late",
+                    Event::Malformed { skipped, .. } => {
+                        assert_eq!(skipped, 7);
+                        "malformed"
+                    }
+                })
+            })
+            .unwrap();
+        // seq 10 again is a duplicate; only the garbage is reported
+        assert_eq!(events, ["malformed"]);
+        assert_eq!(subscriber.stats().malformed, 1);
+    }
+
+    #[test]
+    fn test_multicast_loopback() {
+        let group = SocketAddrV4::new(Ipv4Addr::new(239, 255, 77, 1), 0);
+        let mut subscriber = UdpSubscriber::join_multicast(group, Ipv4Addr::LOCALHOST).unwrap();
+        let port = subscriber.socket().local_addr().unwrap().port();
+        subscriber
+            .socket()
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let group = SocketAddrV4::new(*group.ip(), port);
+        // TTL 0 keeps the datagrams on this host
+        let mut publisher = UdpPublisher::multicast(group, Ipv4Addr::LOCALHOST, 0).unwrap();
+        for seq in 0..3 {
+            publisher.publish(&trade_frame(seq)).unwrap();
+        }
+        publisher.flush().unwrap();
+        assert_eq!(receive(&mut subscriber, 3).seqs, [0, 1, 2]);
+    }
+}
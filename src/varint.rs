@@ -2,6 +2,9 @@
 //!
 //! This module provides fast varint encoding/decoding using the LEB128 format.
 //! Used for length prefixes in variable-length fields.
+//!
+//! Signed values use ZigZag encoding so that small negative numbers (such as
+//! price deltas) stay short: 0, -1, 1, -2, 2 map to 0, 1, 2, 3, 4.
 
 use crate::error::{Error, Result};
 
@@ -11,6 +14,9 @@ pub const MAX_VARINT_U32_SIZE: usize = 5;
 /// Maximum bytes needed for a u64 varint (10 bytes)  
 pub const MAX_VARINT_U64_SIZE: usize = 10;
 
+/// Maximum bytes needed for a u128 varint (19 bytes)
+pub const MAX_VARINT_U128_SIZE: usize = 19;
+
 /// Encode a u32 as varint into the given buffer
 ///
 /// Returns the number of bytes written, or Error::ShortBuffer if insufficient space.
@@ -121,6 +127,119 @@ pub fn decode_u64(buf: &[u8]) -> Result<(u64, usize)> {
     }
 }
 
+/// Encode a u128 as varint into the given buffer
+///
+/// Returns the number of bytes written, or Error::ShortBuffer if insufficient space.
+#[inline]
+pub fn encode_u128(value: u128, buf: &mut [u8]) -> Result<usize> {
+    let mut value = value;
+    let mut pos = 0;
+
+    loop {
+        if pos >= buf.len() {
+            return Err(Error::ShortBuffer);
+        }
+
+        if value < 0x80 {
+            buf[pos] = value as u8;
+            return Ok(pos + 1);
+        }
+
+        buf[pos] = (value as u8) | 0x80;
+        value >>= 7;
+        pos += 1;
+    }
+}
+
+/// Decode a u128 varint from the given buffer
+///
+/// Returns (value, bytes_consumed) or an error.
+#[inline]
+pub fn decode_u128(buf: &[u8]) -> Result<(u128, usize)> {
+    let mut result = 0u128;
+    let mut shift = 0;
+    let mut pos = 0;
+
+    loop {
+        if pos >= buf.len() {
+            return Err(Error::UnexpectedEof);
+        }
+
+        if shift >= 128 {
+            return Err(Error::Overflow);
+        }
+
+        let byte = buf[pos];
+        pos += 1;
+
+        result |= ((byte & 0x7F) as u128) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok((result, pos));
+        }
+
+        shift += 7;
+    }
+}
+
+/// Map a signed i32 onto an unsigned value using ZigZag encoding
+#[inline]
+pub const fn zigzag_encode_i32(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+/// Reverse ZigZag encoding for i32
+#[inline]
+pub const fn zigzag_decode_i32(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+/// Map a signed i64 onto an unsigned value using ZigZag encoding
+#[inline]
+pub const fn zigzag_encode_i64(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Reverse ZigZag encoding for i64
+#[inline]
+pub const fn zigzag_decode_i64(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// Encode an i32 as a ZigZag varint into the given buffer
+///
+/// Returns the number of bytes written, or Error::ShortBuffer if insufficient space.
+#[inline]
+pub fn encode_i32(value: i32, buf: &mut [u8]) -> Result<usize> {
+    encode_u32(zigzag_encode_i32(value), buf)
+}
+
+/// Decode a ZigZag varint i32 from the given buffer
+///
+/// Returns (value, bytes_consumed) or an error.
+#[inline]
+pub fn decode_i32(buf: &[u8]) -> Result<(i32, usize)> {
+    let (value, len) = decode_u32(buf)?;
+    Ok((zigzag_decode_i32(value), len))
+}
+
+/// Encode an i64 as a ZigZag varint into the given buffer
+///
+/// Returns the number of bytes written, or Error::ShortBuffer if insufficient space.
+#[inline]
+pub fn encode_i64(value: i64, buf: &mut [u8]) -> Result<usize> {
+    encode_u64(zigzag_encode_i64(value), buf)
+}
+
+/// Decode a ZigZag varint i64 from the given buffer
+///
+/// Returns (value, bytes_consumed) or an error.
+#[inline]
+pub fn decode_i64(buf: &[u8]) -> Result<(i64, usize)> {
+    let (value, len) = decode_u64(buf)?;
+    Ok((zigzag_decode_i64(value), len))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -153,6 +272,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_u128_roundtrip() {
+        let test_values = [0, 1, 127, 128, u64::MAX as u128, u128::MAX];
+
+        for &val in &test_values {
+            let mut buf = [0u8; MAX_VARINT_U128_SIZE];
+            let encoded_len = encode_u128(val, &mut buf).unwrap();
+            let (decoded_val, decoded_len) = decode_u128(&buf[..encoded_len]).unwrap();
+
+            assert_eq!(val, decoded_val);
+            assert_eq!(encoded_len, decoded_len);
+        }
+    }
+
     #[test]
     fn test_buffer_too_small() {
         let mut buf = [0u8; 2]; // Too small for large values
@@ -164,4 +297,53 @@ mod tests {
         let buf = [0x80]; // Incomplete varint
         assert_eq!(decode_u32(&buf), Err(Error::UnexpectedEof));
     }
+
+    #[test]
+    fn test_zigzag_mapping() {
+        let cases = [(0i64, 0u64), (-1, 1), (1, 2), (-2, 3), (2, 4)];
+        for (signed, unsigned) in cases {
+            assert_eq!(zigzag_encode_i64(signed), unsigned);
+            assert_eq!(zigzag_decode_i64(unsigned), signed);
+            assert_eq!(zigzag_encode_i32(signed as i32), unsigned as u32);
+        }
+        assert_eq!(zigzag_encode_i32(i32::MIN), u32::MAX);
+        assert_eq!(zigzag_encode_i64(i64::MIN), u64::MAX);
+    }
+
+    #[test]
+    fn test_small_negative_is_short() {
+        let mut buf = [0u8; MAX_VARINT_U64_SIZE];
+        assert_eq!(encode_i64(-1, &mut buf).unwrap(), 1);
+        assert_eq!(encode_i64(-64, &mut buf).unwrap(), 1);
+        assert_eq!(encode_i64(-65, &mut buf).unwrap(), 2);
+        // Without zigzag, -1 as u64 needs the full 10 bytes
+        assert_eq!(encode_u64(-1i64 as u64, &mut buf).unwrap(), 10);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn prop_i32_roundtrip(val: i32) {
+            let mut buf = [0u8; MAX_VARINT_U32_SIZE];
+            let encoded_len = encode_i32(val, &mut buf).unwrap();
+            let (decoded_val, decoded_len) = decode_i32(&buf[..encoded_len]).unwrap();
+            proptest::prop_assert_eq!(val, decoded_val);
+            proptest::prop_assert_eq!(encoded_len, decoded_len);
+        }
+
+        #[test]
+        fn prop_i64_roundtrip(val: i64) {
+            let mut buf = [0u8; MAX_VARINT_U64_SIZE];
+            let encoded_len = encode_i64(val, &mut buf).unwrap();
+            let (decoded_val, decoded_len) = decode_i64(&buf[..encoded_len]).unwrap();
+            proptest::prop_assert_eq!(val, decoded_val);
+            proptest::prop_assert_eq!(encoded_len, decoded_len);
+        }
+
+        #[test]
+        fn prop_zigzag_bounded_by_magnitude(val in -1_000_000i64..1_000_000) {
+            // zigzag(v) <= 2|v|, so encoded size tracks magnitude rather than sign
+            let magnitude = val.unsigned_abs();
+            proptest::prop_assert!(zigzag_encode_i64(val) <= magnitude * 2);
+        }
+    }
 }
@@ -0,0 +1,302 @@
+//! Protocol version negotiation
+//!
+//! On connect, each peer sends a hello frame advertising the range of header
+//! versions it can decode and the minor version it implements for every
+//! message type. Both peers then run [`negotiate`] on the two hellos; the
+//! computation is symmetric, so they agree without a further round trip:
+//!
+//! - the header version is the highest one both ranges contain
+//! - each message type both peers know is used at the lower of the two minors
+//! - message types only one peer knows are left out
+//!
+//! Hello frames are always written with a version 1 header so that any peer
+//! can read them, whatever it ends up negotiating.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::array::{ArrayView, FixedWidth};
+use crate::decoder::FrameDecoder;
+use crate::encoder::FrameEncoder;
+use crate::error::{Error, Result};
+use crate::frame::{FrameHeader, HEADER_V1};
+use crate::messages::{msg_types, quote, quote_delta, trade};
+use crate::{MAX_PROTOCOL_VERSION, MIN_PROTOCOL_VERSION};
+
+/// Inclusive range of header versions a peer can decode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionRange {
+    /// Oldest supported version
+    pub min: u8,
+    /// Newest supported version
+    pub max: u8,
+}
+
+impl VersionRange {
+    /// Versions supported by this crate
+    pub const SUPPORTED: Self = Self {
+        min: MIN_PROTOCOL_VERSION,
+        max: MAX_PROTOCOL_VERSION,
+    };
+
+    /// Create a range, rejecting `min > max`
+    #[inline]
+    pub const fn new(min: u8, max: u8) -> Result<Self> {
+        if min > max {
+            return Err(Error::UnsupportedVersion);
+        }
+        Ok(Self { min, max })
+    }
+
+    /// Check if `ver` lies within the range
+    #[inline]
+    pub const fn contains(&self, ver: u8) -> bool {
+        self.min <= ver && ver <= self.max
+    }
+
+    /// Versions supported by both ranges, if any
+    #[inline]
+    pub fn intersect(&self, other: &Self) -> Option<Self> {
+        let min = self.min.max(other.min);
+        let max = self.max.min(other.max);
+        (min <= max).then_some(Self { min, max })
+    }
+}
+
+/// Minor version a peer implements for one message type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MessageVersion {
+    /// Message type identifier
+    pub msg_type: u16,
+    /// Minor version of that message's schema
+    pub minor: u8,
+}
+
+impl FixedWidth for MessageVersion {
+    const SIZE: usize = 3;
+
+    #[inline]
+    fn encode_fixed(&self, buf: &mut [u8]) {
+        self.msg_type.encode_fixed(&mut buf[0..2]);
+        buf[2] = self.minor;
+    }
+
+    #[inline]
+    fn decode_fixed(buf: &[u8]) -> Self {
+        Self {
+            msg_type: u16::decode_fixed(&buf[0..2]),
+            minor: buf[2],
+        }
+    }
+}
+
+/// Message types and minor versions implemented by this crate
+pub const SUPPORTED_MESSAGES: &[MessageVersion] = &[
+    MessageVersion {
+        msg_type: msg_types::TRADE_V1,
+        minor: trade::MINOR_VERSION,
+    },
+    MessageVersion {
+        msg_type: msg_types::QUOTE_V1,
+        minor: quote::MINOR_VERSION,
+    },
+    MessageVersion {
+        msg_type: msg_types::QUOTE_DELTA_V1,
+        minor: quote_delta::MINOR_VERSION,
+    },
+];
+
+/// Outcome of a version negotiation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    /// Header version both peers will use
+    pub version: u8,
+    messages: Vec<MessageVersion>,
+}
+
+impl Negotiated {
+    /// Agreed minor version for `msg_type`, or `None` if a peer lacks it
+    #[inline]
+    pub fn minor(&self, msg_type: u16) -> Option<u8> {
+        self.messages
+            .binary_search_by_key(&msg_type, |m| m.msg_type)
+            .ok()
+            .map(|idx| self.messages[idx].minor)
+    }
+
+    /// Message types both peers support, sorted by `msg_type`
+    #[inline]
+    pub fn messages(&self) -> &[MessageVersion] {
+        &self.messages
+    }
+}
+
+/// Agree on a header version and per-message minor versions
+///
+/// Returns `Error::UnsupportedVersion` if the version ranges do not overlap.
+/// Duplicate entries for a message type keep the lowest minor.
+pub fn negotiate(
+    local: VersionRange,
+    local_messages: impl IntoIterator<Item = MessageVersion>,
+    remote: VersionRange,
+    remote_messages: impl IntoIterator<Item = MessageVersion>,
+) -> Result<Negotiated> {
+    let common = local.intersect(&remote).ok_or(Error::UnsupportedVersion)?;
+
+    let mut local_minors: BTreeMap<u16, u8> = BTreeMap::new();
+    for m in local_messages {
+        let minor = local_minors.entry(m.msg_type).or_insert(m.minor);
+        *minor = (*minor).min(m.minor);
+    }
+
+    let mut agreed: BTreeMap<u16, u8> = BTreeMap::new();
+    for m in remote_messages {
+        if let Some(&local_minor) = local_minors.get(&m.msg_type) {
+            let minor = agreed.entry(m.msg_type).or_insert(local_minor);
+            *minor = (*minor).min(m.minor);
+        }
+    }
+
+    Ok(Negotiated {
+        version: common.max,
+        messages: agreed
+            .into_iter()
+            .map(|(msg_type, minor)| MessageVersion { msg_type, minor })
+            .collect(),
+    })
+}
+
+/// Encode a hello frame advertising `versions` and `messages`
+#[inline]
+pub fn encode_hello(
+    buf: &mut [u8],
+    seq: u32,
+    versions: VersionRange,
+    messages: &[MessageVersion],
+) -> Result<usize> {
+    let mut encoder = FrameEncoder::new(buf);
+
+    let header = FrameHeader::new(msg_types::HELLO, seq, 0).with_version(HEADER_V1);
+    encoder.begin(&header)?;
+
+    encoder.put_u8(versions.min)?;
+    encoder.put_u8(versions.max)?;
+    encoder.put_array(messages)?;
+
+    encoder.finish_crc32c()
+}
+
+/// Decoded hello frame: (header, versions, messages)
+pub type Hello<'a> = (FrameHeader, VersionRange, ArrayView<'a, MessageVersion>);
+
+/// Decode a hello frame
+#[inline]
+pub fn decode_hello(buf: &[u8]) -> Result<Hello<'_>> {
+    let decoder = FrameDecoder::new(buf);
+    let header = decoder.header()?;
+
+    if header.msg_type != msg_types::HELLO {
+        return Err(Error::UnsupportedMsgType);
+    }
+
+    decoder.verify_crc32c()?;
+
+    let mut body = decoder.body()?;
+    let versions = VersionRange::new(body.get_u8()?, body.get_u8()?)?;
+    let messages = body.get_array()?;
+
+    Ok((header, versions, messages))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_range_intersect() {
+        let a = VersionRange::new(1, 3).unwrap();
+        let b = VersionRange::new(2, 5).unwrap();
+        assert_eq!(a.intersect(&b), Some(VersionRange { min: 2, max: 3 }));
+        assert_eq!(a.intersect(&VersionRange::new(4, 4).unwrap()), None);
+        assert_eq!(VersionRange::new(2, 1), Err(Error::UnsupportedVersion));
+        assert!(VersionRange::SUPPORTED.contains(crate::PROTOCOL_VERSION));
+    }
+
+    #[test]
+    fn test_negotiate_is_symmetric() {
+        let old_peer = VersionRange::new(1, 1).unwrap();
+        let old_messages = [
+            MessageVersion {
+                msg_type: msg_types::TRADE_V1,
+                minor: 0,
+            },
+            MessageVersion {
+                msg_type: 77,
+                minor: 4,
+            },
+        ];
+
+        let ours = negotiate(
+            VersionRange::SUPPORTED,
+            SUPPORTED_MESSAGES.iter().copied(),
+            old_peer,
+            old_messages,
+        )
+        .unwrap();
+        let theirs = negotiate(
+            old_peer,
+            old_messages,
+            VersionRange::SUPPORTED,
+            SUPPORTED_MESSAGES.iter().copied(),
+        )
+        .unwrap();
+
+        assert_eq!(ours, theirs);
+        assert_eq!(ours.version, 1);
+        assert_eq!(ours.minor(msg_types::TRADE_V1), Some(0));
+        assert_eq!(ours.minor(msg_types::QUOTE_V1), None);
+        assert_eq!(ours.minor(77), None);
+    }
+
+    #[test]
+    fn test_negotiate_picks_highest_common_version() {
+        let result = negotiate(
+            VersionRange::SUPPORTED,
+            SUPPORTED_MESSAGES.iter().copied(),
+            VersionRange::SUPPORTED,
+            SUPPORTED_MESSAGES.iter().copied(),
+        )
+        .unwrap();
+        assert_eq!(result.version, MAX_PROTOCOL_VERSION);
+        assert_eq!(result.messages(), SUPPORTED_MESSAGES);
+    }
+
+    #[test]
+    fn test_negotiate_without_overlap() {
+        let future = VersionRange::new(MAX_PROTOCOL_VERSION + 1, 9).unwrap();
+        assert_eq!(
+            negotiate(VersionRange::SUPPORTED, [], future, []).unwrap_err(),
+            Error::UnsupportedVersion
+        );
+    }
+
+    #[test]
+    fn test_hello_exchange() {
+        let mut buf = [0u8; 128];
+        let size = encode_hello(&mut buf, 0, VersionRange::SUPPORTED, SUPPORTED_MESSAGES).unwrap();
+
+        let (header, versions, messages) = decode_hello(&buf[..size]).unwrap();
+        assert_eq!(header.ver, HEADER_V1);
+        assert_eq!(versions, VersionRange::SUPPORTED);
+        assert!(messages.iter().eq(SUPPORTED_MESSAGES.iter().copied()));
+
+        let agreed = negotiate(
+            VersionRange::SUPPORTED,
+            SUPPORTED_MESSAGES.iter().copied(),
+            versions,
+            messages,
+        )
+        .unwrap();
+        assert_eq!(agreed.version, MAX_PROTOCOL_VERSION);
+    }
+}
@@ -0,0 +1,227 @@
+//! Forward-compatibility tests
+//!
+//! These tests hand-build frames as a hypothetical "v2" schema would write
+//! them, with tagged optional fields the v1 codecs have never heard of, and
+//! check that the v1 decoders skip them wherever they appear.
+
+use minibit::messages::{msg_types, quote, trade};
+use minibit::tagged::TaggedValue;
+use minibit::*;
+
+const TS_NS: u64 = 1_700_000_000_000_000_000;
+const PRICE: i64 = 50_000_000;
+const QTY: u32 = 100;
+
+/// Encode a trade whose tagged section is exactly `fields`, in order
+fn encode_future_trade(buf: &mut [u8], fields: &[(u32, TaggedValue<'_>)]) -> usize {
+    let mut encoder = FrameEncoder::new(buf);
+    let mut header = FrameHeader::new(msg_types::TRADE_V1, 7, 0);
+    header.set_flag(FrameFlags::TAGGED);
+    encoder.begin(&header).unwrap();
+
+    encoder.put_u64(TS_NS).unwrap();
+    encoder.put_i64(PRICE).unwrap();
+    encoder.put_u32(QTY).unwrap();
+
+    for &(field_idx, value) in fields {
+        encoder.put_tagged(field_idx, value).unwrap();
+    }
+
+    encoder.finish_crc32c().unwrap()
+}
+
+fn unknown_fields() -> [(u32, TaggedValue<'static>); 6] {
+    [
+        (2, TaggedValue::Varint(u64::MAX)),
+        (3, TaggedValue::Fixed64(0xDEAD_BEEF)),
+        (4, TaggedValue::Bytes(b"venue=XNAS")),
+        (5, TaggedValue::Fixed32(42)),
+        (6, TaggedValue::Fixed16(7)),
+        (1000, TaggedValue::Fixed8(1)),
+    ]
+}
+
+#[test]
+fn test_tagged_trade_roundtrip() {
+    let mut buf = [0u8; 256];
+    let size =
+        trade::encode_tagged(&mut buf, 1, TS_NS, PRICE, QTY, Some(b"AAPL"), Some(b"n")).unwrap();
+
+    let (header, ts_ns, price, qty, symbol, note) = trade::decode(&buf[..size]).unwrap();
+    assert!(header.has_flag(FrameFlags::TAGGED));
+    assert!(!header.has_flag(FrameFlags::PRESENCE_BITMAP));
+    assert_eq!((ts_ns, price, qty), (TS_NS, PRICE, QTY));
+    assert_eq!(symbol, Some(&b"AAPL"[..]));
+    assert_eq!(note, Some(&b"n"[..]));
+}
+
+#[test]
+fn test_unknown_fields_before_known() {
+    let mut fields: Vec<_> = unknown_fields().to_vec();
+    fields.push((trade::fields::SYMBOL as u32, TaggedValue::Bytes(b"TSLA")));
+    fields.push((trade::fields::NOTE as u32, TaggedValue::Bytes(b"late")));
+
+    let mut buf = [0u8; 256];
+    let size = encode_future_trade(&mut buf, &fields);
+
+    let (_, ts_ns, price, qty, symbol, note) = trade::decode(&buf[..size]).unwrap();
+    assert_eq!((ts_ns, price, qty), (TS_NS, PRICE, QTY));
+    assert_eq!(symbol, Some(&b"TSLA"[..]));
+    assert_eq!(note, Some(&b"late"[..]));
+}
+
+#[test]
+fn test_unknown_fields_interleaved() {
+    let unknown = unknown_fields();
+    let fields = [
+        unknown[0],
+        (trade::fields::NOTE as u32, TaggedValue::Bytes(b"first")),
+        unknown[2],
+        unknown[3],
+        (trade::fields::SYMBOL as u32, TaggedValue::Bytes(b"MSFT")),
+        unknown[5],
+    ];
+
+    let mut buf = [0u8; 256];
+    let size = encode_future_trade(&mut buf, &fields);
+
+    let (_, _, _, _, symbol, note) = trade::decode(&buf[..size]).unwrap();
+    assert_eq!(symbol, Some(&b"MSFT"[..]));
+    assert_eq!(note, Some(&b"first"[..]));
+}
+
+#[test]
+fn test_only_unknown_fields() {
+    let mut buf = [0u8; 256];
+    let size = encode_future_trade(&mut buf, &unknown_fields());
+
+    let (header, ts_ns, _, _, symbol, note) = trade::decode(&buf[..size]).unwrap();
+    assert_eq!(header.seq, 7);
+    assert_eq!(ts_ns, TS_NS);
+    assert_eq!(symbol, None);
+    assert_eq!(note, None);
+}
+
+#[test]
+fn test_future_quote_with_unknown_fields() {
+    let mut buf = [0u8; 256];
+    let mut encoder = FrameEncoder::new(&mut buf);
+    let mut header = FrameHeader::new(msg_types::QUOTE_V1, 9, 0);
+    header.set_flag(FrameFlags::TAGGED);
+    encoder.begin(&header).unwrap();
+    encoder.put_u64(TS_NS).unwrap();
+    encoder.put_i64(100).unwrap();
+    encoder.put_i64(101).unwrap();
+    encoder.put_u8(3).unwrap();
+    encoder.put_tagged(9, TaggedValue::Fixed64(500)).unwrap(); // bid_size
+    encoder
+        .put_tagged(quote::fields::SYMBOL as u32, TaggedValue::Bytes(b"ETH/USD"))
+        .unwrap();
+    encoder.put_tagged(10, TaggedValue::Fixed64(700)).unwrap(); // ask_size
+    let size = encoder.finish_crc32c().unwrap();
+
+    let (_, ts_ns, bid, ask, level, symbol) = quote::decode(&buf[..size]).unwrap();
+    assert_eq!((ts_ns, bid, ask, level), (TS_NS, 100, 101, 3));
+    assert_eq!(symbol, Some(&b"ETH/USD"[..]));
+}
+
+#[test]
+fn test_known_field_with_wrong_wire_type() {
+    let mut buf = [0u8; 256];
+    let size = encode_future_trade(
+        &mut buf,
+        &[(trade::fields::SYMBOL as u32, TaggedValue::Varint(1))],
+    );
+    assert_eq!(trade::decode(&buf[..size]), Err(Error::DecodeInvariant));
+}
+
+#[test]
+fn test_unknown_wire_type_is_rejected() {
+    let mut buf = [0u8; 256];
+    let mut encoder = FrameEncoder::new(&mut buf);
+    let mut header = FrameHeader::new(msg_types::TRADE_V1, 1, 0);
+    header.set_flag(FrameFlags::TAGGED);
+    encoder.begin(&header).unwrap();
+    encoder.put_u64(TS_NS).unwrap();
+    encoder.put_i64(PRICE).unwrap();
+    encoder.put_u32(QTY).unwrap();
+    encoder.put_varint_u32((2 << 3) | 7).unwrap(); // wire type 7 is undefined
+    encoder.put_u8(0).unwrap();
+    let size = encoder.finish_crc32c().unwrap();
+
+    assert_eq!(trade::decode(&buf[..size]), Err(Error::UnknownWireType));
+}
+
+#[test]
+fn test_truncated_tagged_field() {
+    let mut buf = [0u8; 256];
+    let mut encoder = FrameEncoder::new(&mut buf);
+    let mut header = FrameHeader::new(msg_types::TRADE_V1, 1, 0);
+    header.set_flag(FrameFlags::TAGGED);
+    encoder.begin(&header).unwrap();
+    encoder.put_u64(TS_NS).unwrap();
+    encoder.put_i64(PRICE).unwrap();
+    encoder.put_u32(QTY).unwrap();
+    encoder.put_varint_u32((3 << 3) | 1).unwrap(); // Fixed64 key ...
+    encoder.put_u32(0).unwrap(); // ... with only 4 bytes of value
+    let size = encoder.finish_crc32c().unwrap();
+
+    assert_eq!(trade::decode(&buf[..size]), Err(Error::UnexpectedEof));
+}
+
+#[test]
+fn test_tagged_and_bitmap_flags_conflict() {
+    let mut buf = [0u8; 256];
+    let size = trade::encode_tagged(&mut buf, 1, TS_NS, PRICE, QTY, None, None).unwrap();
+
+    // Set PRESENCE_BITMAP as well and re-seal the frame
+    buf[3] |= FrameFlags::PRESENCE_BITMAP;
+    let crc = crc32c::crc32c(&buf[..size - 4]);
+    buf[size - 4..size].copy_from_slice(&crc.to_le_bytes());
+
+    assert_eq!(trade::decode(&buf[..size]), Err(Error::FlagConflict));
+}
+
+#[test]
+fn test_tagged_fields_iterator() {
+    let fields = unknown_fields();
+    let mut buf = [0u8; 256];
+    let size = encode_future_trade(&mut buf, &fields);
+
+    let decoder = FrameDecoder::new(&buf[..size]);
+    let mut body = decoder.body().unwrap();
+    body.skip(8 + 8 + 4).unwrap();
+
+    let decoded: Vec<_> = body.tagged_fields().collect::<Result<_, _>>().unwrap();
+    assert_eq!(decoded, fields.to_vec());
+    assert!(body.is_at_end());
+}
+
+#[test]
+fn test_v2_header_decodes_with_v1_codecs() {
+    let mut buf = [0u8; 256];
+    let mut encoder = FrameEncoder::new(&mut buf);
+    let header = FrameHeader::new(msg_types::TRADE_V1, 3, 0).with_channel(12);
+    encoder.begin(&header).unwrap();
+    encoder.put_u64(TS_NS).unwrap();
+    encoder.put_i64(PRICE).unwrap();
+    encoder.put_u32(QTY).unwrap();
+    let size = encoder.finish_crc32c().unwrap();
+
+    let (header, ts_ns, _, _, _, _) = trade::decode(&buf[..size]).unwrap();
+    assert_eq!(header.ver, frame::HEADER_V2);
+    assert_eq!(header.channel, 12);
+    assert_eq!(ts_ns, TS_NS);
+}
+
+#[test]
+fn test_unsupported_future_header_version() {
+    let mut buf = [0u8; 256];
+    let size = trade::encode(&mut buf, 1, TS_NS, PRICE, QTY, None, None).unwrap();
+    buf[2] = MAX_PROTOCOL_VERSION + 1;
+
+    assert_eq!(
+        FrameDecoder::new(&buf[..size]).header(),
+        Err(Error::UnsupportedVersion)
+    );
+}